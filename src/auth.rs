@@ -0,0 +1,430 @@
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{Payload, ServiceRequest, ServiceResponse};
+use actix_web::http::{header, Method};
+use actix_web::middleware::Next;
+use actix_web::{web, Error, FromRequest, HttpMessage, HttpRequest};
+use anyhow::{anyhow, bail, Context};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::Utc;
+use ring::signature::{self, RsaPublicKeyComponents, UnparsedPublicKey};
+use serde::Deserialize;
+use std::future::{ready, Ready};
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+
+use crate::error::ApiError;
+use crate::signing::{self, UrlSigner};
+
+/// How long fetched signing keys are trusted before the JWKS is read again.
+const JWKS_TTL: Duration = Duration::from_secs(3600);
+/// Shortest gap between refetches prompted by an unknown key id, so forged
+/// tokens cannot make the server hammer the identity provider.
+const JWKS_MIN_REFRESH: Duration = Duration::from_secs(60);
+/// Allowed clock difference with the identity provider, in seconds.
+const LEEWAY_SECS: i64 = 60;
+
+/// Bearer JWTs from an external OpenID Connect identity provider. Off while
+/// `jwks_url` is empty.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OidcConfig {
+    /// Expected `iss` claim; empty accepts any issuer.
+    pub issuer: String,
+    /// Expected `aud` claim; empty accepts any audience.
+    pub audience: String,
+    /// Where the provider publishes its signing keys.
+    pub jwks_url: String,
+    /// Reject requests without a valid token, except `/health` and the
+    /// `/public` routes. Otherwise a token is only checked when one is sent.
+    pub required: bool,
+}
+
+impl OidcConfig {
+    pub fn enabled(&self) -> bool {
+        !self.jwks_url.is_empty()
+    }
+}
+
+/// The verified caller, available to handlers as an extractor. Handlers that
+/// also serve anonymous callers take `Option<AuthenticatedUser>`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuthenticatedUser {
+    /// The `sub` claim: the provider's stable identifier for the user.
+    pub subject: String,
+    pub claims: serde_json::Map<String, serde_json::Value>,
+}
+
+impl FromRequest for AuthenticatedUser {
+    type Error = ApiError;
+    type Future = Ready<Result<Self, ApiError>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(
+            req.extensions()
+                .get::<AuthenticatedUser>()
+                .cloned()
+                .ok_or_else(|| ApiError::Unauthorized("Bearer token required".to_string())),
+        )
+    }
+}
+
+/// Refuses callers without a verified bearer token whenever authentication
+/// is configured, even where tokens are otherwise optional; for endpoints
+/// that destroy or move files.
+pub fn require_user(req: &HttpRequest) -> Result<(), ApiError> {
+    let enabled = req
+        .app_data::<web::Data<Authenticator>>()
+        .is_some_and(|authenticator| authenticator.enabled());
+    if enabled && req.extensions().get::<AuthenticatedUser>().is_none() {
+        return Err(ApiError::Unauthorized("Bearer token required".to_string()));
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Jwk {
+    pub kty: String,
+    pub kid: Option<String>,
+    pub alg: Option<String>,
+    pub n: Option<String>,
+    pub e: Option<String>,
+    pub crv: Option<String>,
+    pub x: Option<String>,
+    pub y: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct JwkSet {
+    keys: Vec<Jwk>,
+}
+
+#[derive(Deserialize)]
+struct JwtHeader {
+    alg: String,
+    kid: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Audience {
+    One(String),
+    Many(Vec<String>),
+}
+
+#[derive(Deserialize)]
+struct RegisteredClaims {
+    sub: String,
+    exp: i64,
+    nbf: Option<i64>,
+    iss: Option<String>,
+    aud: Option<Audience>,
+}
+
+#[derive(Default)]
+struct KeySet {
+    keys: Vec<Jwk>,
+    fetched: Option<Instant>,
+}
+
+/// Verifies bearer tokens against the provider's published keys, fetched
+/// lazily and cached.
+pub struct Authenticator {
+    config: OidcConfig,
+    client: reqwest::Client,
+    keys: RwLock<KeySet>,
+    /// Keys were given up front and are never fetched.
+    pinned: bool,
+}
+
+impl Authenticator {
+    pub fn new(config: OidcConfig) -> Self {
+        Authenticator {
+            config,
+            client: reqwest::Client::new(),
+            keys: RwLock::new(KeySet::default()),
+            pinned: false,
+        }
+    }
+
+    /// An authenticator that never fetches, trusting only `keys`.
+    pub fn with_keys(config: OidcConfig, keys: Vec<Jwk>) -> Self {
+        Authenticator {
+            keys: RwLock::new(KeySet { keys, fetched: None }),
+            pinned: true,
+            ..Authenticator::new(config)
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.config.enabled()
+    }
+
+    async fn fetch_keys(&self) -> anyhow::Result<()> {
+        let set: JwkSet = self
+            .client
+            .get(&self.config.jwks_url)
+            .timeout(Duration::from_secs(10))
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status)
+            .with_context(|| format!("Failed to fetch JWKS from {}", self.config.jwks_url))?
+            .json()
+            .await
+            .context("Invalid JWKS")?;
+        *self.keys.write().unwrap() = KeySet {
+            keys: set.keys,
+            fetched: Some(Instant::now()),
+        };
+        Ok(())
+    }
+
+    fn find_key(&self, header: &JwtHeader) -> Option<Jwk> {
+        let keys = self.keys.read().unwrap();
+        keys.keys
+            .iter()
+            .find(|key| match &header.kid {
+                Some(kid) => key.kid.as_ref() == Some(kid),
+                None => key.alg.as_deref().is_none_or(|alg| alg == header.alg),
+            })
+            .cloned()
+    }
+
+    /// The signing key for `header`, refetching the key set when it is stale
+    /// or, at most once a minute, when the key id is unknown.
+    async fn key(&self, header: &JwtHeader) -> Result<Jwk, ApiError> {
+        if self.pinned {
+            return self
+                .find_key(header)
+                .ok_or_else(|| ApiError::Unauthorized("Unknown signing key".to_string()));
+        }
+        let age = self.keys.read().unwrap().fetched.map(|fetched| fetched.elapsed());
+        let key = match age {
+            Some(age) if age < JWKS_TTL => self.find_key(header),
+            _ => None,
+        };
+        if let Some(key) = key {
+            return Ok(key);
+        }
+        if age.is_none_or(|age| age >= JWKS_MIN_REFRESH) {
+            self.fetch_keys().await.map_err(ApiError::Storage)?;
+        }
+        self.find_key(header)
+            .ok_or_else(|| ApiError::Unauthorized("Unknown signing key".to_string()))
+    }
+
+    /// Checks the token's signature and registered claims.
+    pub async fn verify(&self, token: &str) -> Result<AuthenticatedUser, ApiError> {
+        let invalid = |e: anyhow::Error| ApiError::Unauthorized(format!("Invalid token: {}", e));
+        let (header, payload, signed, signature) = split(token).map_err(invalid)?;
+        let key = self.key(&header).await?;
+        verify_signature(&key, &header.alg, signed.as_bytes(), &signature).map_err(invalid)?;
+        self.check_claims(&payload).map_err(invalid)
+    }
+
+    fn check_claims(&self, payload: &[u8]) -> anyhow::Result<AuthenticatedUser> {
+        let registered: RegisteredClaims = serde_json::from_slice(payload).context("malformed claims")?;
+        let now = Utc::now().timestamp();
+        if registered.exp + LEEWAY_SECS < now {
+            bail!("expired");
+        }
+        if registered.nbf.is_some_and(|nbf| nbf - LEEWAY_SECS > now) {
+            bail!("not yet valid");
+        }
+        if !self.config.issuer.is_empty() && registered.iss.as_deref() != Some(self.config.issuer.as_str()) {
+            bail!("wrong issuer");
+        }
+        if !self.config.audience.is_empty() {
+            let audience = &self.config.audience;
+            let matches = match &registered.aud {
+                Some(Audience::One(aud)) => aud == audience,
+                Some(Audience::Many(auds)) => auds.contains(audience),
+                None => false,
+            };
+            if !matches {
+                bail!("wrong audience");
+            }
+        }
+        Ok(AuthenticatedUser {
+            subject: registered.sub,
+            claims: serde_json::from_slice(payload)?,
+        })
+    }
+}
+
+/// Splits a compact JWS into its header, payload, signed part and signature.
+fn split(token: &str) -> anyhow::Result<(JwtHeader, Vec<u8>, &str, Vec<u8>)> {
+    let (signed, signature) = token.rsplit_once('.').context("not a JWT")?;
+    let (header, payload) = signed.split_once('.').context("not a JWT")?;
+    let decode = |part: &str| URL_SAFE_NO_PAD.decode(part).context("bad base64");
+    let header: JwtHeader = serde_json::from_slice(&decode(header)?).context("malformed header")?;
+    Ok((header, decode(payload)?, signed, decode(signature)?))
+}
+
+fn verify_signature(key: &Jwk, alg: &str, message: &[u8], signature: &[u8]) -> anyhow::Result<()> {
+    let decode = |part: &Option<String>| {
+        part.as_deref()
+            .context("incomplete key")
+            .and_then(|part| URL_SAFE_NO_PAD.decode(part).context("bad key"))
+    };
+    if key.alg.as_deref().is_some_and(|key_alg| key_alg != alg) {
+        bail!("algorithm does not match key");
+    }
+    let verified = match (alg, key.kty.as_str()) {
+        ("RS256", "RSA") => RsaPublicKeyComponents { n: decode(&key.n)?, e: decode(&key.e)? }.verify(
+            &signature::RSA_PKCS1_2048_8192_SHA256,
+            message,
+            signature,
+        ),
+        ("ES256", "EC") if key.crv.as_deref() == Some("P-256") => {
+            let point = [vec![0x04], decode(&key.x)?, decode(&key.y)?].concat();
+            UnparsedPublicKey::new(&signature::ECDSA_P256_SHA256_FIXED, point).verify(message, signature)
+        }
+        _ => return Err(anyhow!("unsupported algorithm {}", alg)),
+    };
+    verified.map_err(|_| anyhow!("bad signature"))
+}
+
+/// Paths served without a token even when tokens are required.
+fn is_open(path: &str) -> bool {
+    path == "/health" || path == "/feed.json" || path.starts_with("/public/")
+}
+
+fn is_read(method: &Method) -> bool {
+    method == Method::GET || method == Method::HEAD
+}
+
+fn bearer_token(req: &ServiceRequest) -> Option<&str> {
+    let value = req.headers().get(header::AUTHORIZATION)?.to_str().ok()?;
+    let (scheme, token) = value.split_once(' ')?;
+    scheme.eq_ignore_ascii_case("bearer").then(|| token.trim())
+}
+
+/// Verifies any bearer token and attaches the [`AuthenticatedUser`] to the
+/// request; a bad token is refused even where tokens are optional. Where
+/// tokens are required, a URL signed by the [`UrlSigner`] stands in for one,
+/// but only to read: a signed link never authorizes a delete or an edit.
+pub async fn authenticate(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let authenticator = match req.app_data::<web::Data<Authenticator>>() {
+        Some(authenticator) if authenticator.enabled() => authenticator.clone(),
+        _ => return next.call(req).await.map(ServiceResponse::map_into_boxed_body),
+    };
+
+    match bearer_token(&req) {
+        Some(token) => match authenticator.verify(token).await {
+            Ok(user) => {
+                req.extensions_mut().insert(user);
+            }
+            Err(e) => return Ok(req.error_response(e)),
+        },
+        None if authenticator.config.required && !is_open(req.path()) => {
+            let signer = req.app_data::<web::Data<UrlSigner>>();
+            let error = match signer {
+                Some(signer) if is_read(req.method()) && signing::is_signed(req.query_string()) => {
+                    signer.verify(req.path(), req.query_string(), Utc::now()).err()
+                }
+                _ => Some(ApiError::Unauthorized("Bearer token required".to_string())),
+            };
+            if let Some(error) = error {
+                return Ok(req.error_response(error));
+            }
+        }
+        None => {}
+    }
+    next.call(req).await.map(ServiceResponse::map_into_boxed_body)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use ring::rand::SystemRandom;
+    use ring::signature::{EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_FIXED_SIGNING};
+
+    /// An ES256 signing key and its public JWK.
+    pub(crate) fn signing_key(kid: &str) -> (EcdsaKeyPair, Jwk) {
+        let rng = SystemRandom::new();
+        let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &rng).unwrap();
+        let pair = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, pkcs8.as_ref(), &rng).unwrap();
+        let point = pair.public_key().as_ref();
+        let jwk = Jwk {
+            kty: "EC".to_string(),
+            kid: Some(kid.to_string()),
+            alg: Some("ES256".to_string()),
+            n: None,
+            e: None,
+            crv: Some("P-256".to_string()),
+            x: Some(URL_SAFE_NO_PAD.encode(&point[1..33])),
+            y: Some(URL_SAFE_NO_PAD.encode(&point[33..])),
+        };
+        (pair, jwk)
+    }
+
+    pub(crate) fn sign(pair: &EcdsaKeyPair, kid: &str, claims: serde_json::Value) -> String {
+        let header = serde_json::json!({"alg": "ES256", "typ": "JWT", "kid": kid});
+        let signed = format!(
+            "{}.{}",
+            URL_SAFE_NO_PAD.encode(header.to_string()),
+            URL_SAFE_NO_PAD.encode(claims.to_string())
+        );
+        let signature = pair.sign(&SystemRandom::new(), signed.as_bytes()).unwrap();
+        format!("{}.{}", signed, URL_SAFE_NO_PAD.encode(signature.as_ref()))
+    }
+
+    #[actix_rt::test]
+    async fn verifies_signature_and_claims() {
+        let (pair, jwk) = signing_key("k1");
+        let config = OidcConfig {
+            issuer: "https://idp.example".to_string(),
+            audience: "images-api".to_string(),
+            jwks_url: "https://idp.example/jwks".to_string(),
+            required: false,
+        };
+        let authenticator = Authenticator::with_keys(config, vec![jwk]);
+        let exp = Utc::now().timestamp() + 300;
+        let claims = |iss: &str, aud: serde_json::Value, exp: i64| {
+            serde_json::json!({"sub": "user-1", "iss": iss, "aud": aud, "exp": exp, "name": "Sam"})
+        };
+
+        let token = sign(&pair, "k1", claims("https://idp.example", serde_json::json!(["other", "images-api"]), exp));
+        let user = authenticator.verify(&token).await.unwrap();
+        assert_eq!(user.subject, "user-1");
+        assert_eq!(user.claims["name"], "Sam");
+
+        for (token, error) in [
+            (sign(&pair, "k1", claims("https://idp.example", "images-api".into(), exp - 3600)), "expired"),
+            (sign(&pair, "k1", claims("https://evil.example", "images-api".into(), exp)), "wrong issuer"),
+            (sign(&pair, "k1", claims("https://idp.example", "other".into(), exp)), "wrong audience"),
+            (sign(&signing_key("k1").0, "k1", claims("https://idp.example", "images-api".into(), exp)), "bad signature"),
+        ] {
+            let error_message = authenticator.verify(&token).await.unwrap_err().to_string();
+            assert_eq!(error_message, format!("Invalid token: {}", error));
+        }
+        assert!(matches!(authenticator.verify("not.a-token").await, Err(ApiError::Unauthorized(_))));
+    }
+
+    #[actix_rt::test]
+    async fn fetches_and_caches_jwks() {
+        let server = wiremock::MockServer::start().await;
+        let (pair, jwk) = signing_key("k1");
+        let jwks = serde_json::json!({"keys": [{"kty": jwk.kty, "kid": jwk.kid, "alg": jwk.alg, "crv": jwk.crv, "x": jwk.x, "y": jwk.y}]});
+        wiremock::Mock::given(wiremock::matchers::path("/jwks"))
+            .respond_with(wiremock::ResponseTemplate::new(200).set_body_json(jwks))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let authenticator = Authenticator::new(OidcConfig {
+            jwks_url: format!("{}/jwks", server.uri()),
+            ..OidcConfig::default()
+        });
+
+        let token = sign(&pair, "k1", serde_json::json!({"sub": "user-1", "exp": Utc::now().timestamp() + 60}));
+        assert_eq!(authenticator.verify(&token).await.unwrap().subject, "user-1");
+        assert_eq!(authenticator.verify(&token).await.unwrap().subject, "user-1");
+        // An unknown key id right after a fetch does not fetch again
+        let token = sign(&pair, "k2", serde_json::json!({"sub": "user-1", "exp": Utc::now().timestamp() + 60}));
+        assert!(matches!(authenticator.verify(&token).await, Err(ApiError::Unauthorized(_))));
+    }
+}
@@ -0,0 +1,216 @@
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::{header, Uri};
+use actix_web::middleware::Next;
+use actix_web::{web, Error, ResponseError};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Instant;
+
+use crate::config::Config;
+use crate::error::ApiError;
+use crate::usage;
+
+/// Longest side served to a key over budget in [`OverBudget::Degrade`] mode.
+pub const DEGRADED_MAX_DIMENSION: u32 = 1024;
+/// Highest conversion quality served to a key over budget in degrade mode.
+pub const DEGRADED_QUALITY: u8 = 50;
+
+/// What happens to transform requests from a key that has spent its budget.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OverBudget {
+    /// Answer 429 until the next hour.
+    #[default]
+    Reject,
+    /// Serve smaller, lower-quality variants.
+    Degrade,
+}
+
+/// Size of the decoded source, attached to a transform response by the
+/// handler that did the work. The request is charged its megapixels times
+/// the seconds it took.
+#[derive(Debug, Clone, Copy)]
+pub struct TransformCost {
+    pub megapixels: f64,
+}
+
+impl TransformCost {
+    pub fn of(width: u32, height: u32) -> Self {
+        TransformCost {
+            megapixels: width as f64 * height as f64 / 1_000_000.0,
+        }
+    }
+}
+
+/// Megapixel-seconds spent per API key in the current clock hour.
+#[derive(Default)]
+pub struct TransformBudget {
+    spent: Mutex<HashMap<String, (i64, f64)>>,
+}
+
+fn hour_of(time: DateTime<Utc>) -> i64 {
+    time.timestamp().div_euclid(3600)
+}
+
+impl TransformBudget {
+    pub fn spent(&self, key: &str, now: DateTime<Utc>) -> f64 {
+        match self.spent.lock().unwrap().get(key) {
+            Some(&(hour, spent)) if hour == hour_of(now) => spent,
+            _ => 0.0,
+        }
+    }
+
+    pub fn charge(&self, key: &str, cost: f64, now: DateTime<Utc>) {
+        let hour = hour_of(now);
+        let mut spent = self.spent.lock().unwrap();
+        // Budgets reset each hour, so earlier hours are only dead weight
+        spent.retain(|_, (spent_hour, _)| *spent_hour == hour);
+        spent.entry(key.to_string()).or_insert((hour, 0.0)).1 += cost;
+    }
+}
+
+/// The key a request is budgeted under: its `X-API-Key` when the config gives
+/// that key a budget of its own, otherwise [`usage::ANONYMOUS`]. Unknown keys
+/// share one budget, so sending a fresh header value does not buy a fresh one.
+fn budget_key(req: &ServiceRequest, config: &Config) -> String {
+    let key = usage::api_key(req);
+    if config.transform_budgets.contains_key(&key) {
+        key
+    } else {
+        usage::ANONYMOUS.to_string()
+    }
+}
+
+/// Rewrites a transform query to the degraded limits: `w` and `h` capped at
+/// [`DEGRADED_MAX_DIMENSION`] and `quality` at [`DEGRADED_QUALITY`]. A resize
+/// without either side, or a conversion without a quality, gets the cap added;
+/// so does a preset, whose own size and quality may be larger.
+fn degrade(path: &str, query: &str) -> String {
+    let mut pairs: Vec<(String, String)> = query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (name.to_string(), value.to_string())
+        })
+        .collect();
+    let cap = |pairs: &mut Vec<(String, String)>, name: &str, max: u32| {
+        match pairs.iter_mut().find(|(n, _)| n == name) {
+            Some((_, value)) => {
+                if value.parse::<u32>().map_or(true, |v| v > max) {
+                    *value = max.to_string();
+                }
+            }
+            None => pairs.push((name.to_string(), max.to_string())),
+        }
+    };
+
+    let resizes = pairs.iter().any(|(name, _)| name == "w" || name == "h");
+    let preset = pairs.iter().any(|(name, _)| name == "preset");
+    if path.ends_with("/convert") {
+        cap(&mut pairs, "quality", DEGRADED_QUALITY.into());
+    } else if resizes {
+        for side in ["w", "h"] {
+            if pairs.iter().any(|(name, _)| name == side) {
+                cap(&mut pairs, side, DEGRADED_MAX_DIMENSION);
+            }
+        }
+    } else if path == "/transform" || preset {
+        // Uploads and presets come back at their own size unless bounded
+        cap(&mut pairs, "w", DEGRADED_MAX_DIMENSION);
+        cap(&mut pairs, "h", DEGRADED_MAX_DIMENSION);
+    }
+    if preset || (path == "/transform" && pairs.iter().any(|(name, _)| name == "format")) {
+        cap(&mut pairs, "quality", DEGRADED_QUALITY.into());
+    }
+    pairs
+        .iter()
+        .map(|(name, value)| format!("{}={}", name, value))
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+/// Holds each API key to its hourly transform budget, charging the cost
+/// handlers report through [`TransformCost`].
+pub async fn enforce(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let budget = req.app_data::<web::Data<TransformBudget>>().cloned();
+    let limit = req.app_data::<web::Data<Config>>().map(|config| {
+        let key = budget_key(&req, config);
+        (config.transform_budget_for(&key), config.over_budget, key)
+    });
+    let (Some(budget), Some((Some(limit), over_budget, key))) = (budget, limit) else {
+        return next.call(req).await.map(ServiceResponse::map_into_boxed_body);
+    };
+    if !usage::is_transform(&req) {
+        return next.call(req).await.map(ServiceResponse::map_into_boxed_body);
+    }
+
+    let now = Utc::now();
+    if budget.spent(&key, now) >= limit {
+        match over_budget {
+            OverBudget::Reject => {
+                let mut response = ApiError::RateLimited("Transform budget exhausted for this hour".to_string())
+                    .error_response();
+                let retry_after = 3600 - now.timestamp().rem_euclid(3600);
+                if let Ok(value) = retry_after.to_string().parse() {
+                    response.headers_mut().insert(header::RETRY_AFTER, value);
+                }
+                return Ok(req.into_response(response));
+            }
+            OverBudget::Degrade => {
+                let uri = format!("{}?{}", req.path(), degrade(req.path(), req.query_string()));
+                if let Ok(uri) = uri.parse::<Uri>() {
+                    req.head_mut().uri = uri;
+                }
+            }
+        }
+    }
+
+    let started = Instant::now();
+    let response = next.call(req).await?.map_into_boxed_body();
+    let cost = response.response().extensions().get::<TransformCost>().copied();
+    if let Some(cost) = cost {
+        budget.charge(&key, cost.megapixels * started.elapsed().as_secs_f64(), Utc::now());
+    }
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spending_resets_each_hour() {
+        let budget = TransformBudget::default();
+        let now = Utc::now();
+        budget.charge("key", 2.5, now);
+        budget.charge("key", 1.0, now);
+        assert_eq!(budget.spent("key", now), 3.5);
+        assert_eq!(budget.spent("other", now), 0.0);
+
+        let next_hour = now + chrono::Duration::hours(1);
+        assert_eq!(budget.spent("key", next_hour), 0.0);
+        budget.charge("key", 1.0, next_hour);
+        assert_eq!(budget.spent("key", next_hour), 1.0);
+        // Keys that spent nothing this hour are dropped
+        assert_eq!(budget.spent.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn degrades_dimensions_and_quality() {
+        assert_eq!(degrade("/images/a.jpg", "w=4000&fit=cover"), "w=1024&fit=cover");
+        assert_eq!(degrade("/images/a.jpg", "w=200&h=9000"), "w=200&h=1024");
+        assert_eq!(degrade("/images/a.jpg/convert", "format=webp&quality=90"), "format=webp&quality=50");
+        assert_eq!(degrade("/images/a.jpg/convert", "format=webp"), "format=webp&quality=50");
+        assert_eq!(degrade("/images/a.jpg/thumbnail", "size=256"), "size=256");
+        assert_eq!(degrade("/transform", "format=webp"), "format=webp&w=1024&h=1024&quality=50");
+        assert_eq!(degrade("/transform", "w=4000&fit=cover"), "w=1024&fit=cover");
+        assert_eq!(degrade("/images/a.jpg", "preset=web"), "preset=web&w=1024&h=1024&quality=50");
+    }
+}
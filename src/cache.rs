@@ -0,0 +1,333 @@
+use actix_web::web::Bytes;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::future::Future;
+use std::hash::Hash;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use crate::inflight::{self, Inflight};
+use crate::processor::{ConvertFormat, EncodedImage, FitMode};
+
+pub const DEFAULT_IMAGE_CACHE_BYTES: usize = 256 * 1024 * 1024;
+pub const DEFAULT_VARIANT_CACHE_BYTES: usize = 64 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub entries: usize,
+    pub bytes: usize,
+    pub max_bytes: usize,
+}
+
+struct Entry<V> {
+    value: V,
+    size: usize,
+    last_used: u64,
+}
+
+struct LruInner<K, V> {
+    entries: HashMap<K, Entry<V>>,
+    // last_used tick -> key, oldest first
+    recency: BTreeMap<u64, K>,
+    tick: u64,
+    bytes: usize,
+    hits: u64,
+    misses: u64,
+}
+
+/// Least-recently-used cache bounded by the total byte size of its values.
+pub struct LruCache<K, V> {
+    max_bytes: usize,
+    inner: Mutex<LruInner<K, V>>,
+}
+
+impl<K: Eq + Hash + Clone, V: Clone> LruCache<K, V> {
+    pub fn new(max_bytes: usize) -> Self {
+        LruCache {
+            max_bytes,
+            inner: Mutex::new(LruInner {
+                entries: HashMap::new(),
+                recency: BTreeMap::new(),
+                tick: 0,
+                bytes: 0,
+                hits: 0,
+                misses: 0,
+            }),
+        }
+    }
+
+    pub fn get(&self, key: &K) -> Option<V> {
+        self.get_valid(key, |_| true)
+    }
+
+    pub fn max_bytes(&self) -> usize {
+        self.max_bytes
+    }
+
+    /// Like [`get`](Self::get), but evicts and misses entries that fail `is_valid`.
+    pub fn get_valid(&self, key: &K, is_valid: impl FnOnce(&V) -> bool) -> Option<V> {
+        let mut inner = self.inner.lock().unwrap();
+        let inner = &mut *inner;
+
+        let valid = inner.entries.get(key).map(|entry| is_valid(&entry.value));
+        match valid {
+            Some(true) => {
+                inner.tick += 1;
+                let entry = inner.entries.get_mut(key).unwrap();
+                inner.recency.remove(&entry.last_used);
+                entry.last_used = inner.tick;
+                inner.recency.insert(inner.tick, key.clone());
+                inner.hits += 1;
+                Some(entry.value.clone())
+            }
+            Some(false) => {
+                Self::remove_entry(inner, key);
+                inner.misses += 1;
+                None
+            }
+            None => {
+                inner.misses += 1;
+                None
+            }
+        }
+    }
+
+    /// Stores `value`, evicting the least recently used entries to stay within budget.
+    /// Values larger than the whole budget are not cached.
+    pub fn insert(&self, key: K, value: V, size: usize) {
+        if size > self.max_bytes {
+            return;
+        }
+
+        let mut inner = self.inner.lock().unwrap();
+        let inner = &mut *inner;
+        Self::remove_entry(inner, &key);
+
+        while inner.bytes + size > self.max_bytes {
+            let Some((_, oldest)) = inner.recency.pop_first() else {
+                break;
+            };
+            if let Some(evicted) = inner.entries.remove(&oldest) {
+                inner.bytes -= evicted.size;
+            }
+        }
+
+        inner.tick += 1;
+        inner.recency.insert(inner.tick, key.clone());
+        inner.entries.insert(
+            key,
+            Entry {
+                value,
+                size,
+                last_used: inner.tick,
+            },
+        );
+        inner.bytes += size;
+    }
+
+    pub fn remove(&self, key: &K) {
+        Self::remove_entry(&mut self.inner.lock().unwrap(), key);
+    }
+
+    /// Drops every entry whose key fails `keep`.
+    pub fn retain(&self, keep: impl Fn(&K) -> bool) {
+        let mut inner = self.inner.lock().unwrap();
+        let doomed: Vec<K> = inner.entries.keys().filter(|key| !keep(key)).cloned().collect();
+        for key in &doomed {
+            Self::remove_entry(&mut inner, key);
+        }
+    }
+
+    fn remove_entry(inner: &mut LruInner<K, V>, key: &K) {
+        if let Some(entry) = inner.entries.remove(key) {
+            inner.recency.remove(&entry.last_used);
+            inner.bytes -= entry.size;
+        }
+    }
+
+    /// Keys and sizes of the entries whose key passes `select`, without
+    /// counting a lookup or refreshing their recency.
+    pub fn peek(&self, select: impl Fn(&K) -> bool) -> Vec<(K, usize)> {
+        let inner = self.inner.lock().unwrap();
+        inner
+            .entries
+            .iter()
+            .filter(|(key, _)| select(key))
+            .map(|(key, entry)| (key.clone(), entry.size))
+            .collect()
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        let inner = self.inner.lock().unwrap();
+        CacheStats {
+            hits: inner.hits,
+            misses: inner.misses,
+            entries: inner.entries.len(),
+            bytes: inner.bytes,
+            max_bytes: self.max_bytes,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CachedImage {
+    pub modified: Option<SystemTime>,
+    pub bytes: Bytes,
+}
+
+/// Original file contents keyed by filename, revalidated against the file's mtime.
+pub struct ImageCache(LruCache<String, CachedImage>);
+
+impl ImageCache {
+    pub fn new(max_bytes: usize) -> Self {
+        ImageCache(LruCache::new(max_bytes))
+    }
+
+    pub fn get(&self, filename: &str, modified: Option<SystemTime>) -> Option<Bytes> {
+        self.0
+            .get_valid(&filename.to_string(), |cached| modified.is_some() && cached.modified == modified)
+            .map(|cached| cached.bytes)
+    }
+
+    pub fn insert(&self, filename: &str, modified: Option<SystemTime>, bytes: Bytes) {
+        let size = bytes.len();
+        self.0.insert(filename.to_string(), CachedImage { modified, bytes }, size);
+    }
+
+    pub fn invalidate(&self, filename: &str) {
+        self.0.remove(&filename.to_string());
+    }
+
+    /// Bytes held for `filename`, whether or not they are still current.
+    pub fn cached_bytes(&self, filename: &str) -> Option<usize> {
+        self.0.peek(|key| key == filename).first().map(|(_, size)| *size)
+    }
+
+    /// Largest file worth reading into memory; bigger ones could never be cached.
+    pub fn max_bytes(&self) -> usize {
+        self.0.max_bytes()
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.0.stats()
+    }
+}
+
+impl Default for ImageCache {
+    fn default() -> Self {
+        ImageCache::new(DEFAULT_IMAGE_CACHE_BYTES)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct VariantKey {
+    pub filename: String,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub fit: FitMode,
+    pub respect_orientation: bool,
+    /// Target format and quality for converted variants; `None` keeps the source format.
+    pub convert: Option<(ConvertFormat, u8)>,
+}
+
+/// Result of rendering a variant, shared by every request waiting on it.
+pub type Rendered = Result<EncodedImage, Arc<anyhow::Error>>;
+
+/// Resized and converted renditions keyed by request parameters, dropped once the source file changes.
+pub struct VariantCache {
+    renditions: LruCache<VariantKey, (Option<SystemTime>, EncodedImage)>,
+    rendering: Inflight<VariantKey, Rendered>,
+}
+
+impl VariantCache {
+    pub fn new(max_bytes: usize) -> Self {
+        VariantCache {
+            renditions: LruCache::new(max_bytes),
+            rendering: Inflight::new(),
+        }
+    }
+
+    pub fn get(&self, key: &VariantKey, source_modified: Option<SystemTime>) -> Option<EncodedImage> {
+        self.renditions
+            .get_valid(key, |(modified, _)| source_modified.is_some() && *modified == source_modified)
+            .map(|(_, image)| image)
+    }
+
+    pub fn insert(&self, key: VariantKey, source_modified: Option<SystemTime>, image: EncodedImage) {
+        let size = image.bytes.len();
+        self.renditions.insert(key, (source_modified, image), size);
+    }
+
+    /// Runs `render` for `key`, or waits for an identical render already in
+    /// progress, so a burst of requests for one uncached variant decodes once.
+    pub async fn render<F, Fut>(&self, key: VariantKey, render: F) -> anyhow::Result<EncodedImage>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = anyhow::Result<EncodedImage>>,
+    {
+        self.rendering
+            .run(key, || async { render().await.map_err(Arc::new) })
+            .await
+            .map_err(|e| inflight::unshare(&e))
+    }
+
+    /// Drops every cached rendition of `filename`.
+    pub fn invalidate(&self, filename: &str) {
+        self.renditions.retain(|key| key.filename != filename);
+    }
+
+    /// Every rendition of `filename` held, with its size in bytes.
+    pub fn cached(&self, filename: &str) -> Vec<(VariantKey, usize)> {
+        self.renditions.peek(|key| key.filename == filename)
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.renditions.stats()
+    }
+}
+
+impl Default for VariantCache {
+    fn default() -> Self {
+        VariantCache::new(DEFAULT_VARIANT_CACHE_BYTES)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn evicts_least_recently_used_within_budget() {
+        let cache: LruCache<&str, u8> = LruCache::new(10);
+        cache.insert("a", 1, 4);
+        cache.insert("b", 2, 4);
+        assert_eq!(cache.get(&"a"), Some(1));
+
+        // "b" is now the least recently used and must go
+        cache.insert("c", 3, 4);
+        assert_eq!(cache.get(&"b"), None);
+        assert_eq!(cache.get(&"a"), Some(1));
+        assert_eq!(cache.get(&"c"), Some(3));
+
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses), (3, 1));
+        assert_eq!((stats.entries, stats.bytes), (2, 8));
+
+        // Peeking neither counts nor reorders
+        assert_eq!(cache.peek(|key| *key == "c"), [("c", 4)]);
+        assert_eq!(cache.stats().hits, 3);
+    }
+
+    #[test]
+    fn skips_oversized_values_and_drops_invalid_entries() {
+        let cache: LruCache<&str, u8> = LruCache::new(10);
+        cache.insert("huge", 1, 11);
+        assert_eq!(cache.stats().entries, 0);
+
+        cache.insert("a", 1, 4);
+        assert_eq!(cache.get_valid(&"a", |v| *v == 2), None);
+        assert_eq!(cache.stats().bytes, 0);
+    }
+}
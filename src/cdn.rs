@@ -0,0 +1,258 @@
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{HeaderName, HeaderValue};
+use actix_web::http::Method;
+use actix_web::middleware::Next;
+use actix_web::{web, Error};
+use anyhow::Context;
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use serde::Deserialize;
+use std::time::Duration;
+
+use crate::config::Config;
+
+// Keys are whitespace- or comma-separated in headers, so everything else is escaped
+const KEY: &AsciiSet = &NON_ALPHANUMERIC.remove(b'-').remove(b'.').remove(b'_');
+
+/// Carried by every listing, which any change to the library can affect.
+pub const GALLERY_KEY: &str = "gallery";
+
+/// Whose purge API is called when content changes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CdnProvider {
+    Fastly,
+    Cloudflare,
+}
+
+impl CdnProvider {
+    fn default_api_url(self) -> &'static str {
+        match self {
+            CdnProvider::Fastly => "https://api.fastly.com",
+            CdnProvider::Cloudflare => "https://api.cloudflare.com/client/v4",
+        }
+    }
+}
+
+/// Hints for a CDN in front of the API; the `[cdn]` table.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CdnConfig {
+    /// Tag image and listing responses with `Surrogate-Key` and `Cache-Tag`.
+    pub surrogate_keys: bool,
+    /// Purge changed keys through this provider's API; no purges while unset.
+    pub provider: Option<CdnProvider>,
+    /// Fastly service ID or Cloudflare zone ID.
+    pub service_id: String,
+    pub api_token: String,
+    /// Base URL of the purge API, for proxies and tests; defaults to the provider's.
+    pub api_url: Option<String>,
+}
+
+impl CdnConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.provider.is_some() && (self.service_id.is_empty() || self.api_token.is_empty()) {
+            return Err("cdn provider needs service_id and api_token".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// File names and tags both match case-insensitively, so their keys are
+/// lowercase: a request for `PHOTO.jpg` and a change to `photo.jpg` share one.
+pub fn image_key(filename: &str) -> String {
+    format!("image:{}", utf8_percent_encode(&filename.to_lowercase(), KEY))
+}
+
+pub fn tag_key(tag: &str) -> String {
+    format!("tag:{}", utf8_percent_encode(&tag.to_lowercase(), KEY))
+}
+
+fn is_listing(path: &str) -> bool {
+    path.starts_with("/gallery/") || matches!(path, "/public/images" | "/feed.json" | "/memories")
+}
+
+/// Keys of what a GET answered: the image a `{filename}` route serves, or
+/// the listing and any `tag` it was filtered by.
+pub fn keys_for(path: &str, query: &str, filename: Option<&str>) -> Vec<String> {
+    if let Some(filename) = filename {
+        return vec![image_key(filename)];
+    }
+    if !is_listing(path) {
+        return Vec::new();
+    }
+    let tags = query
+        .split('&')
+        .filter_map(|pair| pair.strip_prefix("tag="))
+        .map(|value| percent_decode_str(&value.replace('+', " ")).decode_utf8_lossy().into_owned())
+        .filter(|tag| !tag.is_empty())
+        .map(|tag| tag_key(&tag));
+    std::iter::once(GALLERY_KEY.to_string()).chain(tags).collect()
+}
+
+/// Keys a change made stale. Handlers attach it to their response, and
+/// [`surrogate`] purges them once the change has succeeded.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Purge(pub Vec<String>);
+
+impl Purge {
+    /// An image and every listing it may appear in.
+    pub fn image(filename: &str) -> Self {
+        Purge(vec![image_key(filename), GALLERY_KEY.to_string()])
+    }
+}
+
+/// Calls the configured provider's purge API.
+pub struct CdnPurger {
+    config: CdnConfig,
+    client: reqwest::Client,
+}
+
+impl CdnPurger {
+    pub fn new(config: CdnConfig) -> Self {
+        CdnPurger {
+            config,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.config.provider.is_some()
+    }
+
+    pub async fn purge(&self, keys: &[String]) -> anyhow::Result<()> {
+        let Some(provider) = self.config.provider else {
+            return Ok(());
+        };
+        let api_url = self.config.api_url.as_deref().unwrap_or(provider.default_api_url());
+        let api_url = api_url.trim_end_matches('/');
+        let request = match provider {
+            CdnProvider::Fastly => self
+                .client
+                .post(format!("{}/service/{}/purge", api_url, self.config.service_id))
+                .header("Fastly-Key", &self.config.api_token)
+                .header("Surrogate-Key", keys.join(" ")),
+            CdnProvider::Cloudflare => self
+                .client
+                .post(format!("{}/zones/{}/purge_cache", api_url, self.config.service_id))
+                .bearer_auth(&self.config.api_token)
+                .json(&serde_json::json!({ "tags": keys })),
+        };
+        request
+            .timeout(Duration::from_secs(10))
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status)
+            .with_context(|| format!("Failed to purge {} from the CDN", keys.join(" ")))?;
+        Ok(())
+    }
+}
+
+/// Adds `Surrogate-Key` and `Cache-Tag` to image and listing responses, and
+/// purges whatever a successful change reported through [`Purge`]. Purges run
+/// in the background; a failed one is only logged.
+pub async fn surrogate(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let tagging = req
+        .app_data::<web::Data<Config>>()
+        .is_some_and(|config| config.cdn.surrogate_keys);
+    let purger = req.app_data::<web::Data<CdnPurger>>().cloned();
+    let mut response = next.call(req).await?.map_into_boxed_body();
+
+    let status = response.status();
+    let readable = status.is_success() || status.as_u16() == 304;
+    let method = response.request().method().clone();
+    if tagging && readable && (method == Method::GET || method == Method::HEAD) {
+        let request = response.request();
+        let keys = keys_for(request.path(), request.query_string(), request.match_info().get("filename"));
+        if !keys.is_empty() {
+            for (name, value) in [("surrogate-key", keys.join(" ")), ("cache-tag", keys.join(","))] {
+                if let Ok(value) = HeaderValue::from_str(&value) {
+                    response.headers_mut().insert(HeaderName::from_static(name), value);
+                }
+            }
+        }
+    }
+
+    let purge = response.response().extensions().get::<Purge>().cloned();
+    if let (Some(purge), Some(purger)) = (purge, purger) {
+        if status.is_success() && purger.enabled() && !purge.0.is_empty() {
+            actix_web::rt::spawn(async move {
+                if let Err(e) = purger.purge(&purge.0).await {
+                    log::warn!("{:#}", e);
+                }
+            });
+        }
+    }
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_name_images_listings_and_tags() {
+        assert_eq!(keys_for("/images/A%20b.JPG/thumbnail", "size=256", Some("A b.JPG")), ["image:a%20b.jpg"]);
+        assert_eq!(keys_for("/gallery/images", "page=2", None), ["gallery"]);
+        assert_eq!(keys_for("/gallery/search", "q=x&tag=Red%2CBlue&tag=Summer+trip", None), [
+            "gallery",
+            "tag:red%2Cblue",
+            "tag:summer%20trip"
+        ]);
+        assert!(keys_for("/health", "", None).is_empty());
+        assert_eq!(Purge::image("a.jpg"), Purge(vec!["image:a.jpg".to_string(), "gallery".to_string()]));
+
+        let config = CdnConfig {
+            provider: Some(CdnProvider::Fastly),
+            ..CdnConfig::default()
+        };
+        assert!(config.validate().is_err());
+    }
+
+    #[actix_rt::test]
+    async fn purges_through_each_provider() {
+        use wiremock::matchers::{body_json, header, method, path};
+
+        let server = wiremock::MockServer::start().await;
+        wiremock::Mock::given(method("POST"))
+            .and(path("/service/svc/purge"))
+            .and(header("Fastly-Key", "secret"))
+            .and(header("Surrogate-Key", "image:a.jpg gallery"))
+            .respond_with(wiremock::ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&server)
+            .await;
+        wiremock::Mock::given(method("POST"))
+            .and(path("/zones/zone/purge_cache"))
+            .and(header("Authorization", "Bearer secret"))
+            .and(body_json(serde_json::json!({"tags": ["image:a.jpg", "gallery"]})))
+            .respond_with(wiremock::ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let keys = Purge::image("a.jpg").0;
+        for (provider, service_id) in [(CdnProvider::Fastly, "svc"), (CdnProvider::Cloudflare, "zone")] {
+            let purger = CdnPurger::new(CdnConfig {
+                provider: Some(provider),
+                service_id: service_id.to_string(),
+                api_token: "secret".to_string(),
+                api_url: Some(server.uri()),
+                ..CdnConfig::default()
+            });
+            purger.purge(&keys).await.unwrap();
+        }
+
+        let failing = CdnPurger::new(CdnConfig {
+            provider: Some(CdnProvider::Fastly),
+            service_id: "missing".to_string(),
+            api_token: "secret".to_string(),
+            api_url: Some(server.uri()),
+            ..CdnConfig::default()
+        });
+        assert!(failing.purge(&keys).await.is_err());
+    }
+}
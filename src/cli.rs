@@ -0,0 +1,80 @@
+use clap::{Parser, Subcommand};
+use std::path::PathBuf;
+
+use crate::config::Config;
+
+/// Command-line options. Flags override the config file and environment.
+#[derive(Debug, Parser)]
+#[command(name = "images-api", version, about = "Serve an images directory over HTTP")]
+pub struct Cli {
+    /// TOML config file (default: $CONFIG_FILE, then ./images-api.toml if present)
+    #[arg(long, global = true, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+
+    /// Port to listen on, keeping the configured bind hosts
+    #[arg(long, global = true)]
+    pub port: Option<u16>,
+
+    /// Directory the images are served from
+    #[arg(long, global = true, value_name = "DIR")]
+    pub images_dir: Option<PathBuf>,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Subcommand)]
+pub enum Command {
+    /// Run the HTTP server (the default)
+    Serve,
+    /// Apply the ingest rules to the whole library and write any missing
+    /// thumbnails, print a summary and exit
+    Index,
+}
+
+impl Cli {
+    pub fn command(&self) -> Command {
+        self.command.unwrap_or(Command::Serve)
+    }
+
+    /// Loads the config named by `--config` (or the usual places) and applies the flags.
+    pub fn load_config(&self) -> anyhow::Result<Config> {
+        Ok(self.apply(Config::load(self.config.as_deref())?))
+    }
+
+    pub fn apply(&self, mut config: Config) -> Config {
+        if let Some(images_dir) = &self.images_dir {
+            config.images_dir = images_dir.clone();
+        }
+        if let Some(port) = self.port {
+            for address in &mut config.bind_addresses {
+                let host = address.rsplit_once(':').map_or(address.as_str(), |(host, _)| host);
+                *address = format!("{}:{}", host, port);
+            }
+        }
+        config
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flags_override_config() {
+        let cli = Cli::try_parse_from(["images-api", "index", "--port", "9000", "--images-dir", "/srv/photos"]).unwrap();
+        assert_eq!(cli.command(), Command::Index);
+
+        let config = cli.apply(Config {
+            bind_addresses: vec!["0.0.0.0:8081".to_string(), "[::]:8081".to_string()],
+            ..Config::default()
+        });
+        assert_eq!(config.bind_addresses, vec!["0.0.0.0:9000", "[::]:9000"]);
+        assert_eq!(config.images_dir, PathBuf::from("/srv/photos"));
+
+        let cli = Cli::try_parse_from(["images-api"]).unwrap();
+        assert_eq!(cli.command(), Command::Serve);
+        assert_eq!(cli.apply(Config::default()).bind_addresses, vec!["127.0.0.1:8081"]);
+        assert!(Cli::try_parse_from(["images-api", "--port", "http"]).is_err());
+    }
+}
@@ -0,0 +1,222 @@
+use actix_web::http::header::{self, HeaderMap, HeaderValue, HttpDate};
+use actix_web::HttpRequest;
+use std::fs::Metadata;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Response validators for a file, used to answer conditional GETs with 304.
+#[derive(Debug, Clone)]
+pub struct Validators {
+    pub etag: String,
+    pub last_modified: Option<SystemTime>,
+}
+
+/// Weak ETag derived from size and mtime; `variant` distinguishes derived renditions.
+pub fn etag_for(metadata: &Metadata, variant: Option<&str>) -> String {
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|m| m.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_nanos())
+        .unwrap_or_default();
+    match variant {
+        Some(variant) => format!("W/\"{:x}-{:x}-{}\"", metadata.len(), modified, variant),
+        None => format!("W/\"{:x}-{:x}\"", metadata.len(), modified),
+    }
+}
+
+/// Whether `etag`, issued for the file or any rendition derived from it, still
+/// matches the file's current size and mtime.
+pub fn is_current(etag: &str, metadata: &Metadata) -> bool {
+    let current = etag_for(metadata, None);
+    let current = current.trim_start_matches("W/").trim_end_matches('"');
+    let etag = etag.trim().trim_start_matches("W/");
+    match etag.strip_prefix(current) {
+        Some(rest) => rest == "\"" || rest.starts_with('-'),
+        None => false,
+    }
+}
+
+impl Validators {
+    pub fn for_file(metadata: &Metadata, variant: Option<&str>) -> Self {
+        Validators {
+            etag: etag_for(metadata, variant),
+            last_modified: metadata.modified().ok(),
+        }
+    }
+
+    /// True when the client's cached copy is still current.
+    ///
+    /// `If-None-Match` wins over `If-Modified-Since`, as RFC 9110 requires.
+    pub fn is_not_modified(&self, req: &HttpRequest) -> bool {
+        let headers = req.headers();
+        if let Some(if_none_match) = headers.get(header::IF_NONE_MATCH).and_then(|v| v.to_str().ok()) {
+            return if_none_match
+                .split(',')
+                .map(str::trim)
+                .any(|tag| tag == "*" || weak_eq(tag, &self.etag));
+        }
+
+        let since = headers
+            .get(header::IF_MODIFIED_SINCE)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse::<HttpDate>().ok())
+            .map(SystemTime::from);
+        match (since, self.last_modified) {
+            (Some(since), Some(modified)) => whole_seconds(modified) <= whole_seconds(since),
+            _ => false,
+        }
+    }
+
+    pub fn apply(&self, headers: &mut HeaderMap, cache_control: &str) {
+        if let Ok(etag) = HeaderValue::from_str(&self.etag) {
+            headers.insert(header::ETAG, etag);
+        }
+        if let Some(modified) = self.last_modified {
+            if let Ok(value) = HeaderValue::from_str(&HttpDate::from(modified).to_string()) {
+                headers.insert(header::LAST_MODIFIED, value);
+            }
+        }
+        if let Ok(value) = HeaderValue::from_str(cache_control) {
+            headers.insert(header::CACHE_CONTROL, value);
+        }
+    }
+}
+
+/// `If-Match` and `If-Unmodified-Since` from a write request, parsed up front
+/// so they can be checked right before the write on a blocking thread.
+#[derive(Debug, Clone, Default)]
+pub struct Preconditions {
+    if_match: Option<Vec<String>>,
+    unmodified_since: Option<SystemTime>,
+}
+
+impl Preconditions {
+    pub fn from_request(req: &HttpRequest) -> Self {
+        let headers = req.headers();
+        Preconditions {
+            if_match: headers
+                .get(header::IF_MATCH)
+                .and_then(|v| v.to_str().ok())
+                .map(|v| v.split(',').map(|tag| tag.trim().to_string()).collect()),
+            unmodified_since: headers
+                .get(header::IF_UNMODIFIED_SINCE)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.parse::<HttpDate>().ok())
+                .map(SystemTime::from),
+        }
+    }
+
+    /// True when the file changed since the client read it, so the write must
+    /// be refused with 412.
+    ///
+    /// `If-Match` wins over `If-Unmodified-Since`, as RFC 9110 requires. ETags
+    /// here are all weak, so `If-Match` compares them weakly.
+    pub fn failed(&self, validators: &Validators) -> bool {
+        if let Some(if_match) = &self.if_match {
+            return !if_match.iter().any(|tag| tag == "*" || weak_eq(tag, &validators.etag));
+        }
+        match (self.unmodified_since, validators.last_modified) {
+            (Some(since), Some(modified)) => whole_seconds(modified) > whole_seconds(since),
+            _ => false,
+        }
+    }
+}
+
+fn weak_eq(a: &str, b: &str) -> bool {
+    a.trim_start_matches("W/") == b.trim_start_matches("W/")
+}
+
+// HTTP dates have one-second resolution
+fn whole_seconds(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+    use std::time::Duration;
+
+    fn validators() -> Validators {
+        Validators {
+            etag: "W/\"12-abc\"".to_string(),
+            last_modified: Some(UNIX_EPOCH + Duration::from_secs(1_700_000_000)),
+        }
+    }
+
+    #[test]
+    fn is_current_accepts_file_and_variant_etags() {
+        let file = assert_fs::NamedTempFile::new("a.jpg").unwrap();
+        std::fs::write(file.path(), b"content").unwrap();
+        let metadata = file.path().metadata().unwrap();
+
+        assert!(is_current(&etag_for(&metadata, None), &metadata));
+        assert!(is_current(&etag_for(&metadata, Some("thumb-300")), &metadata));
+        assert!(is_current(etag_for(&metadata, None).trim_start_matches("W/"), &metadata));
+
+        std::fs::write(file.path(), b"changed content").unwrap();
+        let changed = file.path().metadata().unwrap();
+        assert!(!is_current(&etag_for(&metadata, None), &changed));
+        assert!(!is_current("W/\"7\"", &metadata));
+        assert!(!is_current("garbage", &metadata));
+    }
+
+    #[test]
+    fn matches_if_none_match_weakly() {
+        let req = TestRequest::default()
+            .insert_header(("If-None-Match", "\"other\", \"12-abc\""))
+            .to_http_request();
+        assert!(validators().is_not_modified(&req));
+
+        let req = TestRequest::default()
+            .insert_header(("If-None-Match", "\"other\""))
+            // Ignored because If-None-Match is present
+            .insert_header(("If-Modified-Since", "Wed, 01 Jan 2031 00:00:00 GMT"))
+            .to_http_request();
+        assert!(!validators().is_not_modified(&req));
+    }
+
+    #[test]
+    fn compares_if_modified_since_to_the_second() {
+        let modified = HttpDate::from(validators().last_modified.unwrap()).to_string();
+        let req = TestRequest::default()
+            .insert_header(("If-Modified-Since", modified))
+            .to_http_request();
+        assert!(validators().is_not_modified(&req));
+
+        let req = TestRequest::default()
+            .insert_header(("If-Modified-Since", "Mon, 01 Jan 2001 00:00:00 GMT"))
+            .to_http_request();
+        assert!(!validators().is_not_modified(&req));
+    }
+
+    #[test]
+    fn if_match_wins_over_if_unmodified_since() {
+        let req = TestRequest::default()
+            .insert_header(("If-Match", "\"other\", W/\"12-abc\""))
+            .insert_header(("If-Unmodified-Since", "Mon, 01 Jan 2001 00:00:00 GMT"))
+            .to_http_request();
+        assert!(!Preconditions::from_request(&req).failed(&validators()));
+
+        let req = TestRequest::default().insert_header(("If-Match", "\"other\"")).to_http_request();
+        assert!(Preconditions::from_request(&req).failed(&validators()));
+
+        let req = TestRequest::default().insert_header(("If-Match", "*")).to_http_request();
+        assert!(!Preconditions::from_request(&req).failed(&validators()));
+    }
+
+    #[test]
+    fn fails_when_modified_after_if_unmodified_since() {
+        let modified = HttpDate::from(validators().last_modified.unwrap()).to_string();
+        let req = TestRequest::default()
+            .insert_header(("If-Unmodified-Since", modified))
+            .to_http_request();
+        assert!(!Preconditions::from_request(&req).failed(&validators()));
+
+        let req = TestRequest::default()
+            .insert_header(("If-Unmodified-Since", "Mon, 01 Jan 2001 00:00:00 GMT"))
+            .to_http_request();
+        assert!(Preconditions::from_request(&req).failed(&validators()));
+        assert!(!Preconditions::default().failed(&validators()));
+    }
+}
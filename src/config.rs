@@ -0,0 +1,538 @@
+use anyhow::Context;
+use percent_encoding::{utf8_percent_encode, AsciiSet, CONTROLS};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::auth::OidcConfig;
+use crate::budget::OverBudget;
+use crate::cache::{DEFAULT_IMAGE_CACHE_BYTES, DEFAULT_VARIANT_CACHE_BYTES};
+use crate::cdn::CdnConfig;
+use crate::fields::{self, Schema};
+use crate::ingest::{IngestRule, IngestRules};
+use crate::placeholder::PlaceholderConfig;
+use crate::policy::{CacheVisibility, ContentPolicy};
+use crate::presets::{self, Preset};
+use crate::video_roots;
+
+// Characters that cannot appear verbatim in a single URL path segment
+const PATH_SEGMENT: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'/')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'`')
+    .add(b'{')
+    .add(b'}');
+
+// Also escapes what separates query parameters
+const QUERY_VALUE: &AsciiSet = &PATH_SEGMENT.add(b'&').add(b'+').add(b'=');
+
+const DEFAULT_TRANSCODE_CACHE_BYTES: u64 = 2 * 1024 * 1024 * 1024;
+const DEFAULT_MIN_FREE_DISK_BYTES: u64 = 1024 * 1024 * 1024;
+const DEFAULT_TRANSFORM_MAX_UPLOAD_BYTES: usize = 32 * 1024 * 1024;
+
+/// Read from the working directory when `CONFIG_FILE` is not set.
+pub const DEFAULT_CONFIG_FILE: &str = "images-api.toml";
+
+/// Server settings: defaults, overlaid by an optional TOML file whose keys are
+/// the field names, overlaid in turn by environment variables.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Addresses the HTTP server listens on, e.g. `0.0.0.0:8081` and
+    /// `[::]:8081` for dual-stack. IPv6 listeners accept only IPv6.
+    pub bind_addresses: Vec<String>,
+    /// Addresses for a separate server carrying only the `/admin` endpoints,
+    /// e.g. `127.0.0.1:8082`. Empty serves them on `bind_addresses` instead.
+    pub admin_bind_addresses: Vec<String>,
+    /// PEM certificate chain; with `tls_key_path`, enables HTTPS.
+    pub tls_cert_path: Option<PathBuf>,
+    /// PEM private key for `tls_cert_path`.
+    pub tls_key_path: Option<PathBuf>,
+    /// Addresses the HTTPS listeners bind, when TLS is enabled.
+    pub tls_bind_addresses: Vec<String>,
+    /// Answer plain HTTP on `bind_addresses` with a redirect to HTTPS.
+    pub redirect_http: bool,
+    /// Directory the images are served from; created when missing.
+    pub images_dir: PathBuf,
+    /// Origins allowed to call the API from a browser; `*` allows any.
+    /// Empty sends no CORS headers.
+    pub cors_origins: Vec<String>,
+    /// Prefix for every URL the API hands out, e.g. `https://example.com/photos`
+    /// when served behind a reverse proxy. Empty means root-relative URLs.
+    pub public_base_url: String,
+    /// Title of the public JSON feed.
+    pub feed_title: String,
+    /// Per-response cap on bytes per second for original image downloads.
+    pub download_rate_limit: Option<u64>,
+    /// `max-age` sent in `Cache-Control` for served images.
+    pub cache_max_age_secs: u64,
+    /// Memory budget for cached original files.
+    pub image_cache_max_bytes: usize,
+    /// Memory budget for cached resized variants.
+    pub variant_cache_max_bytes: usize,
+    /// Build the library index in the background as the server starts.
+    pub index_on_startup: bool,
+    /// Watch the images directory and refresh caches and the index on changes.
+    pub watch_images_dir: bool,
+    /// Render the next gallery page's thumbnails in the background as each page is listed.
+    pub prefetch_thumbnails: bool,
+    /// Where `DELETE /images/{filename}` moves files; `.Trash` in the images
+    /// directory when unset.
+    pub trash_dir: Option<PathBuf>,
+    /// Disk budget for H.264 copies and HLS segments of videos.
+    pub transcode_cache_max_bytes: u64,
+    /// ffmpeg binary used for transcoding.
+    pub ffmpeg_path: String,
+    /// Free space on the images filesystem below which /health reports degraded.
+    pub min_free_disk_bytes: u64,
+    /// What the images directory exposes; the `[policy]` table in a config file.
+    pub policy: ContentPolicy,
+    /// Megapixel-seconds of resizing and conversion each API key may use per
+    /// hour. `None` leaves transforms unmetered.
+    pub transform_budget: Option<f64>,
+    /// Budgets for particular API keys, overriding `transform_budget`.
+    pub transform_budgets: HashMap<String, f64>,
+    /// How transform requests over budget are answered.
+    pub over_budget: OverBudget,
+    /// Largest image accepted by `POST /transform`.
+    pub transform_max_upload_bytes: usize,
+    pub oidc: OidcConfig,
+    /// Secret for signing expiring image URLs. Empty uses a random key per
+    /// process, so signed URLs stop working at restart.
+    pub url_signing_key: String,
+    /// Custom metadata fields settable per image; the `[fields]` table in a config file.
+    pub fields: Schema,
+    /// Folders served under `/videos/{name}/...`, by name; the `[video_roots]` table.
+    pub video_roots: HashMap<String, PathBuf>,
+    /// Tags added to files matching a pattern as the index picks them up;
+    /// `[[ingest_rules]]` entries in a config file.
+    pub ingest_rules: Vec<IngestRule>,
+    /// Stand-ins for missing or broken images; the `[placeholder]` table.
+    pub placeholder: PlaceholderConfig,
+    /// Named processing parameters for `?preset=`; `[presets.name]` tables.
+    pub presets: HashMap<String, Preset>,
+    /// Surrogate keys and purges for a CDN in front of the API; the `[cdn]` table.
+    pub cdn: CdnConfig,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            bind_addresses: vec!["127.0.0.1:8081".to_string()],
+            admin_bind_addresses: Vec::new(),
+            tls_cert_path: None,
+            tls_key_path: None,
+            tls_bind_addresses: vec!["127.0.0.1:8443".to_string()],
+            redirect_http: false,
+            images_dir: PathBuf::from("images"),
+            cors_origins: Vec::new(),
+            public_base_url: String::new(),
+            feed_title: "Images".to_string(),
+            download_rate_limit: None,
+            cache_max_age_secs: 86400,
+            image_cache_max_bytes: DEFAULT_IMAGE_CACHE_BYTES,
+            variant_cache_max_bytes: DEFAULT_VARIANT_CACHE_BYTES,
+            index_on_startup: false,
+            watch_images_dir: true,
+            prefetch_thumbnails: false,
+            trash_dir: None,
+            transcode_cache_max_bytes: DEFAULT_TRANSCODE_CACHE_BYTES,
+            ffmpeg_path: "ffmpeg".to_string(),
+            min_free_disk_bytes: DEFAULT_MIN_FREE_DISK_BYTES,
+            policy: ContentPolicy::default(),
+            transform_budget: None,
+            transform_budgets: HashMap::new(),
+            over_budget: OverBudget::Reject,
+            transform_max_upload_bytes: DEFAULT_TRANSFORM_MAX_UPLOAD_BYTES,
+            oidc: OidcConfig::default(),
+            url_signing_key: String::new(),
+            fields: Schema::new(),
+            video_roots: HashMap::new(),
+            ingest_rules: Vec::new(),
+            placeholder: PlaceholderConfig::default(),
+            presets: HashMap::new(),
+            cdn: CdnConfig::default(),
+        }
+    }
+}
+
+/// The value of `name` when set to something other than blank, for settings
+/// where a blank value can only mean "not set".
+fn env_value(name: &str) -> Option<String> {
+    env_string(name).filter(|value| !value.trim().is_empty())
+}
+
+fn env_number<T: std::str::FromStr>(name: &str) -> anyhow::Result<Option<T>> {
+    env_value(name).map(|value| parse_number(name, &value)).transpose()
+}
+
+fn parse_number<T: std::str::FromStr>(name: &str, value: &str) -> anyhow::Result<T> {
+    value
+        .trim()
+        .parse()
+        .map_err(|_| anyhow::anyhow!("Invalid {}={:?}: expected a number", name, value))
+}
+
+fn env_flag(name: &str, default: bool) -> anyhow::Result<bool> {
+    match env_value(name) {
+        Some(value) => parse_flag(name, &value),
+        None => Ok(default),
+    }
+}
+
+fn parse_flag(name: &str, value: &str) -> anyhow::Result<bool> {
+    match value.trim().to_ascii_lowercase().as_str() {
+        "1" | "true" | "yes" | "on" => Ok(true),
+        "0" | "false" | "no" | "off" => Ok(false),
+        _ => anyhow::bail!("Invalid {}={:?}: expected true or false", name, value),
+    }
+}
+
+/// The value of `name` when set, which must be one of `choices`.
+fn env_choice<T: Copy>(name: &str, choices: &[(&str, T)]) -> anyhow::Result<Option<T>> {
+    env_value(name).map(|value| parse_choice(name, &value, choices)).transpose()
+}
+
+fn parse_choice<T: Copy>(name: &str, value: &str, choices: &[(&str, T)]) -> anyhow::Result<T> {
+    choices
+        .iter()
+        .find(|(choice, _)| *choice == value.trim())
+        .map(|(_, choice)| *choice)
+        .ok_or_else(|| {
+            let names: Vec<&str> = choices.iter().map(|(choice, _)| *choice).collect();
+            anyhow::anyhow!("Invalid {}={:?}: expected one of {}", name, value, names.join(", "))
+        })
+}
+
+/// `VIDEO_ROOTS` entries, each written `name=/path`.
+fn parse_video_roots(value: &str) -> anyhow::Result<HashMap<String, PathBuf>> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|root| !root.is_empty())
+        .map(|root| match root.split_once('=') {
+            Some((name, dir)) => Ok((name.trim().to_string(), PathBuf::from(dir.trim()))),
+            None => anyhow::bail!("Invalid VIDEO_ROOTS entry {:?}: expected name=/path", root),
+        })
+        .collect()
+}
+
+fn env_string(name: &str) -> Option<String> {
+    std::env::var(name).ok()
+}
+
+fn env_list(name: &str) -> Option<Vec<String>> {
+    env_string(name).map(|list| {
+        list.split(',')
+            .map(str::trim)
+            .filter(|item| !item.is_empty())
+            .map(str::to_string)
+            .collect()
+    })
+}
+
+impl Config {
+    /// Defaults plus environment overrides, without reading any file.
+    pub fn from_env() -> anyhow::Result<Self> {
+        Config::default().with_env_overrides()
+    }
+
+    /// Loads `path`, or `CONFIG_FILE`, or [`DEFAULT_CONFIG_FILE`] when it
+    /// exists, then applies environment overrides. An explicitly named file
+    /// must exist.
+    pub fn load(path: Option<&Path>) -> anyhow::Result<Self> {
+        let explicit = path.map(Path::to_path_buf).or_else(|| env_string("CONFIG_FILE").map(PathBuf::from));
+        let config = match explicit {
+            Some(path) => Config::from_file(&path)?,
+            None if Path::new(DEFAULT_CONFIG_FILE).is_file() => Config::from_file(Path::new(DEFAULT_CONFIG_FILE))?,
+            None => Config::default(),
+        };
+        let config = config.with_env_overrides()?;
+        video_roots::validate(&config.video_roots)
+            .map_err(anyhow::Error::msg)
+            .context("Invalid video roots")?;
+        Ok(config)
+    }
+
+    pub fn from_file(path: &Path) -> anyhow::Result<Self> {
+        let contents = std::fs::read_to_string(path).with_context(|| format!("Failed to read {:?}", path))?;
+        let mut config: Config = toml::from_str(&contents).with_context(|| format!("Invalid config file {:?}", path))?;
+        config.public_base_url = config.public_base_url.trim_end_matches('/').to_string();
+        fields::validate_schema(&config.fields)
+            .map_err(anyhow::Error::msg)
+            .with_context(|| format!("Invalid config file {:?}", path))?;
+        IngestRules::compile(&config.ingest_rules)
+            .map_err(anyhow::Error::msg)
+            .with_context(|| format!("Invalid config file {:?}", path))?;
+        config
+            .placeholder
+            .validate()
+            .map_err(anyhow::Error::msg)
+            .with_context(|| format!("Invalid config file {:?}", path))?;
+        presets::validate(&config.presets)
+            .map_err(anyhow::Error::msg)
+            .with_context(|| format!("Invalid config file {:?}", path))?;
+        config
+            .cdn
+            .validate()
+            .map_err(anyhow::Error::msg)
+            .with_context(|| format!("Invalid config file {:?}", path))?;
+        Ok(config)
+    }
+
+    /// Replaces every setting whose environment variable is set. Fails on
+    /// a value that does not parse, naming the variable, rather than quietly
+    /// keeping the file's or the default setting.
+    pub fn with_env_overrides(self) -> anyhow::Result<Self> {
+        Ok(Config {
+            bind_addresses: env_list("BIND_ADDRESSES").unwrap_or(self.bind_addresses),
+            admin_bind_addresses: env_list("ADMIN_BIND_ADDRESSES").unwrap_or(self.admin_bind_addresses),
+            tls_cert_path: std::env::var_os("TLS_CERT").map(PathBuf::from).or(self.tls_cert_path),
+            tls_key_path: std::env::var_os("TLS_KEY").map(PathBuf::from).or(self.tls_key_path),
+            tls_bind_addresses: env_list("TLS_BIND_ADDRESSES").unwrap_or(self.tls_bind_addresses),
+            redirect_http: env_flag("REDIRECT_HTTP", self.redirect_http)?,
+            images_dir: std::env::var_os("IMAGES_DIR").map(PathBuf::from).unwrap_or(self.images_dir),
+            cors_origins: env_list("CORS_ORIGINS").unwrap_or(self.cors_origins),
+            public_base_url: env_string("PUBLIC_BASE_URL")
+                .map(|url| url.trim_end_matches('/').to_string())
+                .unwrap_or(self.public_base_url),
+            feed_title: env_string("FEED_TITLE").unwrap_or(self.feed_title),
+            download_rate_limit: match env_number::<u64>("DOWNLOAD_RATE_LIMIT")? {
+                Some(limit) => Some(limit).filter(|limit| *limit > 0),
+                None => self.download_rate_limit.filter(|limit| *limit > 0),
+            },
+            cache_max_age_secs: env_number("CACHE_MAX_AGE")?.unwrap_or(self.cache_max_age_secs),
+            image_cache_max_bytes: env_number("IMAGE_CACHE_MAX_BYTES")?.unwrap_or(self.image_cache_max_bytes),
+            variant_cache_max_bytes: env_number("VARIANT_CACHE_MAX_BYTES")?.unwrap_or(self.variant_cache_max_bytes),
+            index_on_startup: env_flag("INDEX_ON_STARTUP", self.index_on_startup)?,
+            watch_images_dir: env_flag("WATCH_IMAGES_DIR", self.watch_images_dir)?,
+            prefetch_thumbnails: env_flag("PREFETCH_THUMBNAILS", self.prefetch_thumbnails)?,
+            trash_dir: std::env::var_os("TRASH_DIR").map(PathBuf::from).or(self.trash_dir),
+            transcode_cache_max_bytes: env_number("TRANSCODE_CACHE_MAX_BYTES")?
+                .unwrap_or(self.transcode_cache_max_bytes),
+            ffmpeg_path: env_string("FFMPEG").unwrap_or(self.ffmpeg_path),
+            min_free_disk_bytes: env_number("MIN_FREE_DISK_BYTES")?.unwrap_or(self.min_free_disk_bytes),
+            policy: ContentPolicy {
+                allowed_extensions: env_list("ALLOWED_EXTENSIONS").unwrap_or(self.policy.allowed_extensions),
+                download_originals: env_flag("DOWNLOAD_ORIGINALS", self.policy.download_originals)?,
+                cache: env_choice(
+                    "CACHE_VISIBILITY",
+                    &[
+                        ("public", CacheVisibility::Public),
+                        ("private", CacheVisibility::Private),
+                        ("no-store", CacheVisibility::NoStore),
+                    ],
+                )?
+                .unwrap_or(self.policy.cache),
+                referenced_dirs: std::env::var_os("REFERENCED_DIRS")
+                    .map(|dirs| std::env::split_paths(&dirs).collect())
+                    .unwrap_or(self.policy.referenced_dirs),
+            },
+            transform_budget: match env_number::<f64>("TRANSFORM_BUDGET")? {
+                Some(budget) => Some(budget).filter(|budget| *budget > 0.0),
+                None => self.transform_budget.filter(|budget| *budget > 0.0),
+            },
+            transform_budgets: self.transform_budgets,
+            over_budget: env_choice("OVER_BUDGET", &[("reject", OverBudget::Reject), ("degrade", OverBudget::Degrade)])?
+                .unwrap_or(self.over_budget),
+            transform_max_upload_bytes: env_number("TRANSFORM_MAX_UPLOAD_BYTES")?
+                .unwrap_or(self.transform_max_upload_bytes),
+            oidc: OidcConfig {
+                issuer: env_string("OIDC_ISSUER").unwrap_or(self.oidc.issuer),
+                audience: env_string("OIDC_AUDIENCE").unwrap_or(self.oidc.audience),
+                jwks_url: env_string("OIDC_JWKS_URL").unwrap_or(self.oidc.jwks_url),
+                required: env_flag("AUTH_REQUIRED", self.oidc.required)?,
+            },
+            url_signing_key: env_string("URL_SIGNING_KEY").unwrap_or(self.url_signing_key),
+            fields: self.fields,
+            video_roots: match env_string("VIDEO_ROOTS") {
+                Some(roots) => parse_video_roots(&roots)?,
+                None => self.video_roots,
+            },
+            ingest_rules: self.ingest_rules,
+            placeholder: PlaceholderConfig {
+                enabled: env_flag("PLACEHOLDER_IMAGES", self.placeholder.enabled)?,
+                ..self.placeholder
+            },
+            presets: self.presets,
+            cdn: CdnConfig {
+                surrogate_keys: env_flag("SURROGATE_KEYS", self.cdn.surrogate_keys)?,
+                api_token: env_string("CDN_API_TOKEN").unwrap_or(self.cdn.api_token),
+                ..self.cdn
+            },
+        })
+    }
+
+    /// The rustls config for the HTTPS listeners, or `None` when TLS is off.
+    /// Fails when only one of the certificate and key is set, or on bad PEM.
+    pub fn tls(&self) -> anyhow::Result<Option<rustls::ServerConfig>> {
+        match (&self.tls_cert_path, &self.tls_key_path) {
+            (Some(cert), Some(key)) => crate::tls::server_config(cert, key).map(Some),
+            (None, None) if self.redirect_http => anyhow::bail!("redirect_http requires tls_cert_path and tls_key_path"),
+            (None, None) => Ok(None),
+            _ => anyhow::bail!("tls_cert_path and tls_key_path must be set together"),
+        }
+    }
+
+    /// Hourly transform budget for `key`, if transforms are metered for it.
+    pub fn transform_budget_for(&self, key: &str) -> Option<f64> {
+        self.transform_budgets.get(key).copied().or(self.transform_budget)
+    }
+
+    /// `Cache-Control` for served files, per the content policy.
+    pub fn cache_control(&self) -> String {
+        self.policy.cache_control(self.cache_max_age_secs)
+    }
+
+    /// Builds a public URL for an API path such as `/images/a.jpg`.
+    pub fn url(&self, path: &str) -> String {
+        format!("{}{}", self.public_base_url, path)
+    }
+
+    /// API path of an image, e.g. `/images/summer%20%231.jpg`, without the public base URL.
+    pub fn image_path(&self, filename: &str) -> String {
+        format!("/images/{}", utf8_percent_encode(filename, PATH_SEGMENT))
+    }
+
+    pub fn image_url(&self, filename: &str) -> String {
+        self.url(&self.image_path(filename))
+    }
+
+    pub fn thumbnail_url(&self, filename: &str, size: u32) -> String {
+        format!("{}/thumbnail?size={}", self.image_url(filename), size)
+    }
+
+    /// URL of the gallery filtered by `tag`.
+    pub fn tag_url(&self, tag: &str) -> String {
+        self.url(&format!("/gallery/images?tag={}", utf8_percent_encode(tag, QUERY_VALUE)))
+    }
+
+    /// URL of a published item under the unauthenticated `/public` routes.
+    pub fn public_image_url(&self, filename: &str) -> String {
+        self.url(&format!("/public/images/{}", utf8_percent_encode(filename, PATH_SEGMENT)))
+    }
+
+    /// URL of `path` (slash-separated, possibly empty) inside video root `root`.
+    pub fn video_url(&self, root: &str, path: &str) -> String {
+        let mut url = format!("/videos/{}", utf8_percent_encode(root, PATH_SEGMENT));
+        for segment in path.split('/').filter(|segment| !segment.is_empty()) {
+            url.push('/');
+            url.extend(utf8_percent_encode(segment, PATH_SEGMENT));
+        }
+        self.url(&url)
+    }
+
+    /// URL of endpoint `action` (e.g. `thumbnail?size=256`) of the video at
+    /// `path` inside video root `root`.
+    pub fn video_action_url(&self, root: &str, path: &str, action: &str) -> String {
+        format!("{}/{}", self.video_url(root, path), action)
+    }
+
+    pub fn video_thumbnail_url(&self, filename: &str, size: u32) -> String {
+        self.video_action_url(video_roots::LIBRARY, filename, &format!("thumbnail?size={}", size))
+    }
+
+    pub fn video_preview_url(&self, filename: &str, size: u32) -> String {
+        self.video_action_url(video_roots::LIBRARY, filename, &format!("preview?size={}", size))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn image_url_uses_base_and_encodes_filename() {
+        let config = Config {
+            public_base_url: "https://example.com/photos".to_string(),
+            ..Config::default()
+        };
+        assert_eq!(
+            config.image_url("summer #1.jpg"),
+            "https://example.com/photos/images/summer%20%231.jpg"
+        );
+        assert_eq!(Config::default().image_url("a.jpg"), "/images/a.jpg");
+    }
+
+    #[test]
+    fn file_settings_fill_in_defaults() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        let file = temp.path().join("images-api.toml");
+        std::fs::write(
+            &file,
+            r#"
+bind_addresses = ["0.0.0.0:9000", "[::]:9000"]
+images_dir = "/srv/photos"
+cors_origins = ["https://example.com"]
+public_base_url = "https://example.com/photos/"
+image_cache_max_bytes = 1024
+
+[policy]
+allowed_extensions = ["jpg", "mp4"]
+cache = "no-store"
+
+[fields]
+model_release = { type = "enum", values = ["signed", "pending"] }
+shot_on = { type = "date" }
+
+[[ingest_rules]]
+glob = "**/*Screenshot*"
+tags = ["screenshot"]
+"#,
+        )
+        .unwrap();
+
+        let config = Config::from_file(&file).unwrap();
+        assert_eq!(config.bind_addresses, vec!["0.0.0.0:9000", "[::]:9000"]);
+        assert_eq!(config.images_dir, PathBuf::from("/srv/photos"));
+        assert_eq!(config.cors_origins, vec!["https://example.com"]);
+        assert_eq!(config.public_base_url, "https://example.com/photos");
+        assert_eq!(config.image_cache_max_bytes, 1024);
+        assert_eq!(config.feed_title, "Images");
+        assert_eq!(config.policy.allowed_extensions, vec!["jpg", "mp4"]);
+        assert!(config.policy.download_originals);
+        assert_eq!(config.cache_control(), "no-store");
+        assert_eq!(config.fields["shot_on"], fields::FieldType::Date);
+        assert_eq!(
+            config.fields["model_release"],
+            fields::FieldType::Enum { values: vec!["signed".to_string(), "pending".to_string()] }
+        );
+        assert_eq!(config.ingest_rules[0].tags, ["screenshot"]);
+
+        std::fs::write(&file, "[[ingest_rules]]\nregex = \"(\"\ntags = [\"x\"]").unwrap();
+        assert!(format!("{:#}", Config::from_file(&file).unwrap_err()).contains("ingest_rules[0]"));
+
+        std::fs::write(&file, "image_cache_max_byte = 1024").unwrap();
+        let error = Config::from_file(&file).unwrap_err();
+        assert!(format!("{:#}", error).contains("unknown field"));
+        assert!(Config::from_file(&temp.path().join("missing.toml")).is_err());
+    }
+
+    #[test]
+    fn bad_environment_values_name_the_variable() {
+        assert_eq!(parse_number::<u64>("CACHE_MAX_AGE", " 60").unwrap(), 60);
+        let error = parse_number::<u64>("CACHE_MAX_AGE", "1h").unwrap_err();
+        assert_eq!(error.to_string(), "Invalid CACHE_MAX_AGE=\"1h\": expected a number");
+
+        assert!(parse_flag("INDEX_ON_STARTUP", "Yes").unwrap());
+        assert!(!parse_flag("INDEX_ON_STARTUP", "off").unwrap());
+        assert!(parse_flag("INDEX_ON_STARTUP", "ture").unwrap_err().to_string().contains("INDEX_ON_STARTUP=\"ture\""));
+
+        let choices = [("reject", OverBudget::Reject), ("degrade", OverBudget::Degrade)];
+        assert_eq!(parse_choice("OVER_BUDGET", "degrade", &choices).unwrap(), OverBudget::Degrade);
+        let error = parse_choice("OVER_BUDGET", "degraded", &choices).unwrap_err();
+        assert_eq!(error.to_string(), "Invalid OVER_BUDGET=\"degraded\": expected one of reject, degrade");
+
+        let roots = parse_video_roots("haley = /Volumes/Videos, models=/Volumes/New,").unwrap();
+        assert_eq!(roots["haley"], PathBuf::from("/Volumes/Videos"));
+        assert_eq!(roots["models"], PathBuf::from("/Volumes/New"));
+        let error = parse_video_roots("haley=/Volumes/Videos,/Volumes/New").unwrap_err();
+        assert_eq!(error.to_string(), "Invalid VIDEO_ROOTS entry \"/Volumes/New\": expected name=/path");
+    }
+}
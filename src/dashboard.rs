@@ -0,0 +1,227 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::cache::CacheStats;
+use crate::health::{DiskCheck, HealthStatus};
+use crate::indexer::{IndexProgress, IndexState};
+use crate::usage::Usage;
+
+/// Hours of traffic the error rate is taken over.
+pub const ERROR_PERIOD_HOURS: i64 = 24;
+/// A completed index younger than this is fresh; it scores zero at a week.
+const FRESH_INDEX_SECS: i64 = 24 * 3600;
+const STALE_INDEX_SECS: i64 = 7 * 24 * 3600;
+/// An error rate at or above this scores zero.
+const FAILING_ERROR_RATE: f64 = 0.1;
+
+/// Weight of each component in the overall score.
+const INDEX_WEIGHT: f64 = 0.3;
+const ERRORS_WEIGHT: f64 = 0.25;
+const CACHES_WEIGHT: f64 = 0.15;
+const STORAGE_WEIGHT: f64 = 0.3;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Dashboard {
+    /// Weighted average of the component scores, 0-100.
+    pub score: u8,
+    /// `healthy` from 80, `degraded` from 50, otherwise `unhealthy`.
+    pub status: HealthStatus,
+    pub generated_at: DateTime<Utc>,
+    pub index: IndexSummary,
+    pub errors: ErrorSummary,
+    pub caches: CacheSummary,
+    pub storage: StorageSummary,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct IndexSummary {
+    pub score: u8,
+    pub state: IndexState,
+    /// Files in the catalog readers currently see.
+    pub images: usize,
+    /// When the last scan finished, and how long ago.
+    pub finished_at: Option<DateTime<Utc>>,
+    pub age_secs: Option<i64>,
+    /// Whether the folder watcher keeps the catalog current between scans.
+    pub watching: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ErrorSummary {
+    pub score: u8,
+    pub period_hours: i64,
+    pub requests: u64,
+    pub server_errors: u64,
+    pub error_rate: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CacheSummary {
+    pub score: u8,
+    /// Hits over lookups across both memory caches; `None` before any lookup.
+    pub hit_rate: Option<f64>,
+    pub images: CacheStats,
+    pub variants: CacheStats,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StorageSummary {
+    pub score: u8,
+    #[serde(flatten)]
+    pub disk: DiskCheck,
+}
+
+fn percent(fraction: f64) -> u8 {
+    (fraction.clamp(0.0, 1.0) * 100.0).round() as u8
+}
+
+/// Full marks while watched or recently scanned, fading to zero over a week
+/// without either. A failed scan keeps the previous catalog, so it scores half
+/// of what that catalog would.
+fn index_score(progress: &IndexProgress, watching: bool, now: DateTime<Utc>) -> (u8, Option<i64>) {
+    let age = progress.finished_at.map(|finished| (now - finished).num_seconds().max(0));
+    let freshness = match age {
+        _ if watching && progress.finished_at.is_some() => 1.0,
+        Some(age) if age <= FRESH_INDEX_SECS => 1.0,
+        Some(age) => 1.0 - (age - FRESH_INDEX_SECS) as f64 / (STALE_INDEX_SECS - FRESH_INDEX_SECS) as f64,
+        None if progress.state == IndexState::Running => 0.5,
+        None => 0.0,
+    };
+    let score = match progress.state {
+        IndexState::Failed => freshness / 2.0,
+        _ => freshness,
+    };
+    (percent(score), age)
+}
+
+fn storage_score(disk: &DiskCheck) -> u8 {
+    match disk.free_bytes {
+        Some(_) if disk.min_free_bytes == 0 => 100,
+        // Full marks from twice the minimum, nothing below it
+        Some(free) => percent(free.saturating_sub(disk.min_free_bytes) as f64 / disk.min_free_bytes as f64),
+        None => 50,
+    }
+}
+
+impl Dashboard {
+    pub fn new(
+        progress: IndexProgress,
+        images: usize,
+        watching: bool,
+        traffic: Usage,
+        caches: (CacheStats, CacheStats),
+        disk: DiskCheck,
+        now: DateTime<Utc>,
+    ) -> Self {
+        let (index_score, age_secs) = index_score(&progress, watching, now);
+
+        let error_rate = match traffic.requests {
+            0 => 0.0,
+            requests => traffic.errors as f64 / requests as f64,
+        };
+        let errors = ErrorSummary {
+            score: percent(1.0 - error_rate / FAILING_ERROR_RATE),
+            period_hours: ERROR_PERIOD_HOURS,
+            requests: traffic.requests,
+            server_errors: traffic.errors,
+            error_rate,
+        };
+
+        let (image_stats, variant_stats) = caches;
+        let hits = image_stats.hits + variant_stats.hits;
+        let lookups = hits + image_stats.misses + variant_stats.misses;
+        let hit_rate = (lookups > 0).then(|| hits as f64 / lookups as f64);
+        let caches = CacheSummary {
+            score: percent(hit_rate.unwrap_or(1.0)),
+            hit_rate,
+            images: image_stats,
+            variants: variant_stats,
+        };
+
+        let storage = StorageSummary {
+            score: storage_score(&disk),
+            disk,
+        };
+
+        let score = percent(
+            (index_score as f64 * INDEX_WEIGHT
+                + errors.score as f64 * ERRORS_WEIGHT
+                + caches.score as f64 * CACHES_WEIGHT
+                + storage.score as f64 * STORAGE_WEIGHT)
+                / 100.0,
+        );
+        Dashboard {
+            score,
+            status: match score {
+                80.. => HealthStatus::Healthy,
+                50.. => HealthStatus::Degraded,
+                _ => HealthStatus::Unhealthy,
+            },
+            generated_at: now,
+            index: IndexSummary {
+                score: index_score,
+                state: progress.state,
+                images,
+                finished_at: progress.finished_at,
+                age_secs,
+                watching,
+                error: progress.error,
+            },
+            errors,
+            caches,
+            storage,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn disk(free_bytes: Option<u64>) -> DiskCheck {
+        DiskCheck {
+            status: HealthStatus::Healthy,
+            free_bytes,
+            total_bytes: Some(100),
+            min_free_bytes: 10,
+        }
+    }
+
+    #[test]
+    fn scores_each_component_and_weights_them() {
+        let now = Utc::now();
+        let completed = IndexProgress {
+            state: IndexState::Completed,
+            finished_at: Some(now - chrono::Duration::hours(1)),
+            ..IndexProgress::default()
+        };
+        let caches = CacheStats { hits: 3, misses: 1, ..CacheStats::default() };
+        let traffic = Usage { requests: 100, errors: 5, ..Usage::default() };
+
+        let dashboard = Dashboard::new(completed.clone(), 7, false, traffic, (caches, CacheStats::default()), disk(Some(15)), now);
+        assert_eq!(dashboard.index.score, 100);
+        assert_eq!(dashboard.errors.score, 50);
+        assert_eq!(dashboard.caches.hit_rate, Some(0.75));
+        assert_eq!(dashboard.storage.score, 50);
+        // 30 + 12.5 + 11.25 + 15
+        assert_eq!(dashboard.score, 69);
+        assert_eq!(dashboard.status, HealthStatus::Degraded);
+
+        let idle = Dashboard::new(IndexProgress::default(), 0, false, Usage::default(), Default::default(), disk(Some(20)), now);
+        assert_eq!((idle.index.score, idle.errors.score, idle.caches.score), (0, 100, 100));
+        assert_eq!(idle.score, 70);
+
+        let stale = IndexProgress {
+            finished_at: Some(now - chrono::Duration::days(4)),
+            ..completed
+        };
+        assert_eq!(index_score(&stale, false, now).0, 50);
+        assert_eq!(index_score(&stale, true, now).0, 100);
+        let failed = IndexProgress { state: IndexState::Failed, ..stale };
+        assert_eq!(index_score(&failed, false, now).0, 25);
+        assert_eq!(storage_score(&disk(Some(5))), 0);
+        assert_eq!(storage_score(&disk(None)), 50);
+    }
+}
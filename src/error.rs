@@ -0,0 +1,133 @@
+use actix_web::error::BlockingError;
+use actix_web::http::{header, StatusCode};
+use actix_web::{web, HttpResponse, ResponseError};
+use serde::Serialize;
+use std::fmt;
+
+/// Error returned by handlers, rendered as `{"error": <kind>, "message": <text>}`.
+///
+/// Storage and processing errors carry their full cause chain for the log, but
+/// only the outermost context (e.g. "Failed to read image") reaches the client.
+#[derive(Debug)]
+pub enum ApiError {
+    NotFound(String),
+    Unauthorized(String),
+    Forbidden(String),
+    Validation(String),
+    Unsupported(String),
+    RateLimited(String),
+    /// A request body over the size the endpoint accepts.
+    TooLarge(String),
+    /// The target of a move is already taken.
+    Conflict(String),
+    /// The file changed since the client read it (`If-Match`, `If-Unmodified-Since`).
+    PreconditionFailed(String),
+    /// A volume the library is served from is unmounted; carries its name.
+    VolumeOffline(String),
+    Storage(anyhow::Error),
+    Processing(anyhow::Error),
+}
+
+#[derive(Serialize)]
+struct ErrorBody {
+    error: &'static str,
+    message: String,
+}
+
+impl ApiError {
+    pub fn not_found(message: impl Into<String>) -> Self {
+        ApiError::NotFound(message.into())
+    }
+
+    pub fn validation(message: impl Into<String>) -> Self {
+        ApiError::Validation(message.into())
+    }
+
+    fn kind(&self) -> &'static str {
+        match self {
+            ApiError::NotFound(_) => "not_found",
+            ApiError::Unauthorized(_) => "unauthorized",
+            ApiError::Forbidden(_) => "forbidden",
+            ApiError::Validation(_) => "validation",
+            ApiError::Unsupported(_) => "unsupported_media_type",
+            ApiError::RateLimited(_) => "rate_limited",
+            ApiError::TooLarge(_) => "payload_too_large",
+            ApiError::Conflict(_) => "conflict",
+            ApiError::PreconditionFailed(_) => "precondition_failed",
+            ApiError::VolumeOffline(_) => "volume_offline",
+            ApiError::Storage(_) => "storage",
+            ApiError::Processing(_) => "processing",
+        }
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApiError::NotFound(message)
+            | ApiError::Unauthorized(message)
+            | ApiError::Forbidden(message)
+            | ApiError::Validation(message)
+            | ApiError::Unsupported(message)
+            | ApiError::RateLimited(message)
+            | ApiError::TooLarge(message)
+            | ApiError::Conflict(message)
+            | ApiError::PreconditionFailed(message) => f.write_str(message),
+            ApiError::VolumeOffline(volume) => write!(f, "Volume {} is offline", volume),
+            ApiError::Storage(e) | ApiError::Processing(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ApiError::NotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            ApiError::Forbidden(_) => StatusCode::FORBIDDEN,
+            ApiError::Validation(_) => StatusCode::BAD_REQUEST,
+            ApiError::Unsupported(_) => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            ApiError::RateLimited(_) => StatusCode::TOO_MANY_REQUESTS,
+            ApiError::TooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            ApiError::Conflict(_) => StatusCode::CONFLICT,
+            ApiError::PreconditionFailed(_) => StatusCode::PRECONDITION_FAILED,
+            ApiError::VolumeOffline(_) => StatusCode::SERVICE_UNAVAILABLE,
+            ApiError::Storage(_) | ApiError::Processing(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        if let ApiError::Storage(e) | ApiError::Processing(e) = self {
+            log::error!("{:#}", e);
+        }
+        let mut response = HttpResponse::build(self.status_code());
+        if let ApiError::Unauthorized(_) = self {
+            response.insert_header((header::WWW_AUTHENTICATE, "Bearer"));
+        }
+        response.json(ErrorBody {
+            error: self.kind(),
+            message: self.to_string(),
+        })
+    }
+}
+
+impl From<BlockingError> for ApiError {
+    fn from(e: BlockingError) -> Self {
+        ApiError::Processing(anyhow::Error::new(e).context("Background task failed"))
+    }
+}
+
+/// Reports malformed query strings as [`ApiError::Validation`] instead of plain text.
+pub fn query_config() -> web::QueryConfig {
+    web::QueryConfig::default().error_handler(|e, _| ApiError::Validation(e.to_string()).into())
+}
+
+// Room for bulk requests such as POST /validate with thousands of entries
+const MAX_JSON_BODY_BYTES: usize = 4 * 1024 * 1024;
+
+/// Reports malformed JSON bodies as [`ApiError::Validation`] instead of plain text.
+pub fn json_config() -> web::JsonConfig {
+    web::JsonConfig::default()
+        .limit(MAX_JSON_BODY_BYTES)
+        .error_handler(|e, _| ApiError::Validation(e.to_string()).into())
+}
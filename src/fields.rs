@@ -0,0 +1,160 @@
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::io;
+use std::path::Path;
+use std::sync::RwLock;
+
+/// Extended attribute holding an item's custom field values as a JSON object.
+#[cfg(target_os = "macos")]
+pub const FIELDS_XATTR: &str = "org.images-api.fields";
+
+// Unprivileged attributes must live in the user namespace on Linux.
+#[cfg(not(target_os = "macos"))]
+pub const FIELDS_XATTR: &str = "user.images-api.fields";
+
+/// What a custom field holds. Dates are `YYYY-MM-DD` strings.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
+pub enum FieldType {
+    Text,
+    Number,
+    Enum { values: Vec<String> },
+    Date,
+}
+
+/// Custom fields by name; the `[fields]` table in a config file.
+pub type Schema = BTreeMap<String, FieldType>;
+
+/// Checks that field names are simple identifiers and enums have values.
+pub fn validate_schema(schema: &Schema) -> Result<(), String> {
+    for (name, field) in schema {
+        if name.is_empty()
+            || !name
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+        {
+            return Err(format!(
+                "Field name {:?} must be lowercase letters, digits and underscores",
+                name
+            ));
+        }
+        if matches!(field, FieldType::Enum { values } if values.is_empty()) {
+            return Err(format!("Enum field {} needs at least one value", name));
+        }
+    }
+    Ok(())
+}
+
+/// The schema in effect, starting from the configured one and replaceable
+/// through `/admin/schema` until the next restart.
+#[derive(Default)]
+pub struct FieldRegistry {
+    schema: RwLock<Schema>,
+}
+
+impl FieldRegistry {
+    pub fn new(schema: Schema) -> Self {
+        FieldRegistry {
+            schema: RwLock::new(schema),
+        }
+    }
+
+    pub fn schema(&self) -> Schema {
+        self.schema.read().unwrap().clone()
+    }
+
+    pub fn replace(&self, schema: Schema) -> Result<(), String> {
+        validate_schema(&schema)?;
+        *self.schema.write().unwrap() = schema;
+        Ok(())
+    }
+
+    /// `value` for field `name` in the form it is stored, or why it does not fit.
+    pub fn check(&self, name: &str, value: Value) -> Result<Value, String> {
+        let schema = self.schema.read().unwrap();
+        let field = schema.get(name).ok_or_else(|| format!("Unknown field {}", name))?;
+        match (field, value) {
+            (FieldType::Text, Value::String(text)) => Ok(Value::String(text)),
+            (FieldType::Number, Value::Number(number)) => Ok(Value::Number(number)),
+            (FieldType::Enum { values }, Value::String(choice)) if values.contains(&choice) => {
+                Ok(Value::String(choice))
+            }
+            (FieldType::Enum { values }, _) => Err(format!("{} must be one of {:?}", name, values)),
+            (FieldType::Date, Value::String(date)) if NaiveDate::parse_from_str(&date, "%Y-%m-%d").is_ok() => {
+                Ok(Value::String(date))
+            }
+            (FieldType::Date, _) => Err(format!("{} must be a date (YYYY-MM-DD)", name)),
+            (FieldType::Text, _) => Err(format!("{} must be a string", name)),
+            (FieldType::Number, _) => Err(format!("{} must be a number", name)),
+        }
+    }
+}
+
+/// The custom field values stored on `path`; none when unset or unreadable.
+pub fn read(path: &Path) -> BTreeMap<String, Value> {
+    match xattr::get(path, FIELDS_XATTR) {
+        Ok(Some(bytes)) => serde_json::from_slice(&bytes).unwrap_or_else(|e| {
+            log::debug!("Ignoring malformed fields on {:?}: {}", path, e);
+            BTreeMap::new()
+        }),
+        Ok(None) => BTreeMap::new(),
+        Err(e) => {
+            log::debug!("Could not read fields for {:?}: {}", path, e);
+            BTreeMap::new()
+        }
+    }
+}
+
+/// Stores `values` on `path`; an empty map removes the attribute.
+pub fn write(path: &Path, values: &BTreeMap<String, Value>) -> io::Result<()> {
+    if values.is_empty() {
+        if xattr::get(path, FIELDS_XATTR)?.is_none() {
+            return Ok(());
+        }
+        return xattr::remove(path, FIELDS_XATTR);
+    }
+    let bytes = serde_json::to_vec(values).expect("JSON values always serialize");
+    xattr::set(path, FIELDS_XATTR, &bytes)
+}
+
+/// Whether `values` has field `name` equal to `expected` as written in a
+/// query string: strings compare exactly, numbers by value.
+pub fn matches(values: &BTreeMap<String, Value>, name: &str, expected: &str) -> bool {
+    match values.get(name) {
+        Some(Value::String(text)) => text == expected,
+        Some(Value::Number(number)) => expected
+            .parse::<f64>()
+            .is_ok_and(|expected| number.as_f64() == Some(expected)),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn checks_values_against_the_schema() {
+        let registry = FieldRegistry::new(Schema::from([
+            ("release".to_string(), FieldType::Enum { values: vec!["signed".to_string(), "pending".to_string()] }),
+            ("shot_on".to_string(), FieldType::Date),
+            ("rating".to_string(), FieldType::Number),
+        ]));
+        assert_eq!(registry.check("release", json!("signed")), Ok(json!("signed")));
+        assert!(registry.check("release", json!("lost")).is_err());
+        assert!(registry.check("shot_on", json!("2024-02-30")).is_err());
+        assert_eq!(registry.check("rating", json!(4)), Ok(json!(4)));
+        assert_eq!(registry.check("notes", json!("x")), Err("Unknown field notes".to_string()));
+
+        let values = BTreeMap::from([("rating".to_string(), json!(4)), ("release".to_string(), json!("signed"))]);
+        assert!(matches(&values, "rating", "4.0") && matches(&values, "release", "signed"));
+        assert!(!matches(&values, "release", "Signed") && !matches(&values, "notes", ""));
+
+        assert!(registry.replace(Schema::from([("Bad Name".to_string(), FieldType::Text)])).is_err());
+        assert!(registry.replace(Schema::from([("e".to_string(), FieldType::Enum { values: vec![] })])).is_err());
+        assert_eq!(registry.schema().len(), 3);
+    }
+}
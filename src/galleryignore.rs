@@ -0,0 +1,70 @@
+use ignore::gitignore::Gitignore;
+use std::path::Path;
+
+/// Per-folder file of gitignore-style patterns for entries to leave out.
+pub const FILE_NAME: &str = ".galleryignore";
+
+/// The `.galleryignore` files in effect inside one folder: its own and those
+/// of its parents up to the content root, each matching relative to its folder.
+#[derive(Default)]
+pub struct IgnoreRules(Vec<Gitignore>);
+
+impl IgnoreRules {
+    /// Loads the rules for `dir`, which must be `root` or inside it. Missing
+    /// files are skipped, and unreadable patterns are logged and ignored.
+    pub fn for_dir(root: &Path, dir: &Path) -> Self {
+        let mut rules = Vec::new();
+        for folder in dir.ancestors() {
+            let file = folder.join(FILE_NAME);
+            if file.is_file() {
+                let (gitignore, error) = Gitignore::new(&file);
+                if let Some(e) = error {
+                    log::warn!("Some patterns in {:?} were skipped: {}", file, e);
+                }
+                rules.push(gitignore);
+            }
+            if folder == root {
+                break;
+            }
+        }
+        IgnoreRules(rules)
+    }
+
+    /// Whether `path`, an entry inside the folder the rules were loaded for,
+    /// or one of its parent folders is excluded. The innermost file decides,
+    /// so a folder can re-include with `!pattern` what a parent excludes.
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        self.0
+            .iter()
+            .map(|gitignore| gitignore.matched_path_or_any_parents(path, is_dir))
+            .find(|matched| !matched.is_none())
+            .is_some_and(|matched| matched.is_ignore())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_fs::prelude::*;
+
+    #[test]
+    fn nested_files_match_relative_to_their_folder() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        temp.child(FILE_NAME).write_str("private/\n*.mov\n").unwrap();
+        temp.child("2024").create_dir_all().unwrap();
+        temp.child("2024").child(FILE_NAME).write_str("!keep.mov\n/draft-*\n").unwrap();
+
+        let root = IgnoreRules::for_dir(temp.path(), temp.path());
+        assert!(root.is_ignored(&temp.path().join("private"), true));
+        assert!(root.is_ignored(&temp.path().join("clip.mov"), false));
+        assert!(!root.is_ignored(&temp.path().join("clip.mp4"), false));
+
+        let nested = IgnoreRules::for_dir(temp.path(), &temp.path().join("2024"));
+        assert!(nested.is_ignored(&temp.path().join("2024/other.mov"), false));
+        assert!(!nested.is_ignored(&temp.path().join("2024/keep.mov"), false));
+        assert!(nested.is_ignored(&temp.path().join("2024/draft-1.mp4"), false));
+        assert!(!root.is_ignored(&temp.path().join("draft-1.mp4"), false));
+
+        assert!(!IgnoreRules::default().is_ignored(&temp.path().join("clip.mov"), false));
+    }
+}
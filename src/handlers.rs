@@ -1,14 +1,54 @@
-use actix_web::{get, web, HttpResponse, Responder};
-use chrono::Utc;
+use actix_web::http::header::{self, ContentDisposition, TryIntoHeaderValue};
+use actix_web::http::StatusCode;
+use actix_web::web::Bytes;
+use actix_web::{delete, get, post, put, routes, web, HttpRequest, HttpResponse, Responder};
+use anyhow::Context;
+use futures_util::StreamExt;
+use chrono::{DateTime, Datelike, NaiveDate, NaiveDateTime, Utc};
 use image::{GenericImageView, guess_format};
-use serde::Serialize;
-use std::path::PathBuf;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::{Read, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::auth::{self, AuthenticatedUser};
+use crate::budget::TransformCost;
+use crate::cache::{CacheStats, ImageCache, VariantCache, VariantKey};
+use crate::cdn::{self, Purge};
+use crate::conditional::{self, Preconditions, Validators};
+use crate::dashboard::{self, Dashboard};
+use crate::config::Config;
+use crate::error::ApiError;
+use crate::fields::{self, FieldRegistry};
+use crate::health::{self, CachesCheck, HealthChecks, HealthStatus};
+use crate::indexer::{self, LibraryIndex};
+use crate::maintenance::MaintenanceState;
+use crate::presets::{Operations, Preset};
+use crate::preview::PreviewFormat;
+use crate::processor::{ConvertFormat, EncodedImage, FitMode, ImageProcessor, DEFAULT_CONVERT_QUALITY, MAX_DIMENSION};
+use crate::range::{self, ByteRange, RangeRequest};
+use crate::rename::{self, RenamePlan};
+use crate::signing::{self, UrlSigner};
+use crate::stats::{ImageStats, ViewStats};
+use crate::suggest::{Suggestion, SuggestionKind, SuggestIndex};
+use crate::manifest::{self, ManifestEntry, ManifestFormat};
+use crate::media_types::MediaClass;
+use crate::policy::ContentPolicy;
+use crate::prefetch::ThumbnailPrefetcher;
+use crate::transcode::{TranscodeCache, HLS_PLAYLIST};
+use crate::trash;
+use crate::usage::{self, UsageReport, UsageTracker};
+use crate::video::{ProbeCache, VideoCodec};
+use crate::{housekeeping, library, media_types, metadata, paths, preview, publish, search, sprite, tags, thumbnails, throttle, video, video_roots, volumes};
 
 #[derive(Serialize)]
 pub struct HealthResponse {
-    pub status: String,
+    pub status: HealthStatus,
     pub timestamp: chrono::DateTime<Utc>,
     pub version: String,
+    pub checks: HealthChecks,
 }
 
 #[derive(Serialize)]
@@ -17,62 +57,3291 @@ pub struct ImageInfo {
     pub size_bytes: u64,
     pub format: Option<String>,
     pub dimensions: Option<(u32, u32)>,
+    pub stats: ImageStats,
+    /// Full path of a file served in place from a referenced folder.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub referenced_path: Option<PathBuf>,
+    /// Values of custom fields set on the file.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub fields: BTreeMap<String, serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+pub struct ServeImageQuery {
+    pub w: Option<u32>,
+    pub h: Option<u32>,
+    pub fit: Option<FitMode>,
+    /// Name of a configured preset; `w`, `h`, `fit` and `quality` override it.
+    pub preset: Option<String>,
+    /// Quality when the preset converts the image, 1-100.
+    pub quality: Option<u8>,
+    #[serde(default = "default_true")]
+    pub respect_orientation: bool,
+    /// Comma-separated video codecs the client cannot play, e.g. `hevc,prores`.
+    pub unsupported_codecs: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct ThumbnailQuery {
+    pub size: Option<u32>,
+    #[serde(default = "default_true")]
+    pub respect_orientation: bool,
+}
+
+#[derive(Deserialize)]
+pub struct KeyframesQuery {
+    /// Keyframes closer than this to the previous one listed are skipped.
+    #[serde(default = "default_min_keyframe_interval")]
+    pub min_interval: f64,
+    #[serde(default)]
+    pub thumbnails: bool,
+    pub size: Option<u32>,
+}
+
+fn default_min_keyframe_interval() -> f64 {
+    1.0
+}
+
+#[derive(Deserialize)]
+pub struct FrameQuery {
+    pub t: f64,
+    pub size: Option<u32>,
+}
+
+#[derive(Deserialize)]
+pub struct VideoThumbnailQuery {
+    /// Position such as `5s` or `1:05`; see [`video::parse_timestamp`].
+    pub at: Option<String>,
+    pub size: Option<u32>,
+    pub format: Option<ConvertFormat>,
+}
+
+#[derive(Deserialize)]
+pub struct VideoPreviewQuery {
+    pub size: Option<u32>,
+    #[serde(default)]
+    pub format: PreviewFormat,
+}
+
+/// Where video thumbnails are taken without `at`, unless the video is shorter.
+const DEFAULT_POSTER_SECS: f64 = 5.0;
+
+#[derive(Serialize)]
+pub struct Keyframe {
+    pub time_secs: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub thumbnail_url: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct KeyframesResponse {
+    pub filename: String,
+    pub duration_secs: Option<f64>,
+    pub keyframes: Vec<Keyframe>,
+}
+
+#[derive(Serialize)]
+pub struct VideoInfoResponse {
+    pub filename: String,
+    #[serde(flatten)]
+    pub info: video::VideoInfo,
+}
+
+#[derive(Deserialize)]
+pub struct ConvertQuery {
+    pub format: ConvertFormat,
+    pub quality: Option<u8>,
+    #[serde(default = "default_true")]
+    pub respect_orientation: bool,
+}
+
+#[derive(Deserialize)]
+pub struct TransformQuery {
+    pub w: Option<u32>,
+    pub h: Option<u32>,
+    pub fit: Option<FitMode>,
+    /// Re-encode as this format; otherwise the upload's own format is kept.
+    pub format: Option<ConvertFormat>,
+    /// Name of a configured preset; the other parameters override it.
+    pub preset: Option<String>,
+    pub quality: Option<u8>,
+    #[serde(default = "default_true")]
+    pub respect_orientation: bool,
+}
+
+#[derive(Deserialize)]
+pub struct MemoriesQuery {
+    pub date: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct RenditionInfo {
+    pub filename: String,
+    pub kind: library::RenditionKind,
+    pub url: String,
+    /// Full path of a file served in place from a referenced folder.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub referenced_path: Option<PathBuf>,
+}
+
+#[derive(Serialize)]
+pub struct MemoryImage {
+    pub filename: String,
+    pub url: String,
+    pub taken_at: NaiveDateTime,
+    pub renditions: Vec<RenditionInfo>,
+}
+
+#[derive(Serialize)]
+pub struct MemoryYear {
+    pub year: i32,
+    pub years_ago: i32,
+    pub images: Vec<MemoryImage>,
+}
+
+#[derive(Serialize)]
+pub struct MemoriesResponse {
+    pub date: NaiveDate,
+    pub years: Vec<MemoryYear>,
+}
+
+#[derive(Deserialize)]
+pub struct ActivityQuery {
+    pub year: Option<i32>,
+}
+
+#[derive(Serialize)]
+pub struct ActivityDay {
+    pub date: NaiveDate,
+    pub additions: u64,
+    pub views: u64,
+}
+
+#[derive(Serialize)]
+pub struct ActivityResponse {
+    pub year: i32,
+    pub total_additions: u64,
+    pub total_views: u64,
+    /// Days with any activity, oldest first.
+    pub days: Vec<ActivityDay>,
+}
+
+#[derive(Deserialize)]
+pub struct HousekeepingQuery {
+    #[serde(default)]
+    pub report: housekeeping::Report,
+    pub page: Option<usize>,
+    pub limit: Option<usize>,
+}
+
+#[derive(Serialize)]
+pub struct HousekeepingItem {
+    pub filename: String,
+    pub url: String,
+    pub size_bytes: u64,
+    pub modified: Option<DateTime<Utc>>,
+    pub views: u64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HousekeepingResponse {
+    pub items: Vec<HousekeepingItem>,
+    pub page: usize,
+    pub page_size: usize,
+    pub total: usize,
+    pub total_pages: usize,
+}
+
+#[derive(Deserialize)]
+pub struct SuggestQuery {
+    pub q: String,
+    pub limit: Option<usize>,
+}
+
+#[derive(Serialize)]
+pub struct SuggestResponse {
+    pub query: String,
+    pub suggestions: Vec<Suggestion>,
+}
+
+const DEFAULT_PAGE_SIZE: usize = 50;
+const MAX_PAGE_SIZE: usize = 500;
+
+#[derive(Deserialize)]
+pub struct ListImagesQuery {
+    pub page: Option<usize>,
+    pub limit: Option<usize>,
+    #[serde(default)]
+    pub sort: library::SortField,
+    #[serde(default)]
+    pub order: library::SortOrder,
+    /// Only stacks where some rendition carries this Finder tag, ignoring case.
+    pub tag: Option<String>,
+    /// Only stacks whose custom field has a value, as `name:value`.
+    pub field: Option<String>,
+    /// Only stacks of this kind: `image` or `video`.
+    #[serde(rename = "type")]
+    pub media_type: Option<MediaClass>,
+    /// Embed a tiny thumbnail per image as a `data:` URI.
+    #[serde(default)]
+    pub include_thumbnail: bool,
+}
+
+#[derive(Deserialize)]
+pub struct SearchQuery {
+    /// Words that must each appear in a file name or tag, ignoring case.
+    pub q: Option<String>,
+    pub tag: Option<String>,
+    #[serde(rename = "type")]
+    pub media_type: Option<MediaClass>,
+    pub min_size: Option<u64>,
+    pub max_size: Option<u64>,
+    /// Modified at or after / before, as RFC 3339 or `YYYY-MM-DD`.
+    pub after: Option<String>,
+    pub before: Option<String>,
+    pub page: Option<usize>,
+    pub limit: Option<usize>,
+    #[serde(default)]
+    pub sort: library::SortField,
+    #[serde(default)]
+    pub order: library::SortOrder,
+}
+
+#[derive(Deserialize)]
+pub struct StreamImagesQuery {
+    #[serde(default)]
+    pub sort: library::SortField,
+    #[serde(default)]
+    pub order: library::SortOrder,
+    pub tag: Option<String>,
+    pub field: Option<String>,
+    #[serde(rename = "type")]
+    pub media_type: Option<MediaClass>,
+}
+
+const MAX_VALIDATE_ENTRIES: usize = 20_000;
+
+#[derive(Serialize)]
+pub struct ValidateResponse {
+    /// Entries whose file changed since the ETag was issued.
+    pub stale: Vec<String>,
+    /// Entries whose file no longer exists (or never did).
+    pub missing: Vec<String>,
+    /// Entries not found while a volume is offline; they may be on it, so
+    /// check them again once it is back.
+    pub unavailable: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct TagsResponse {
+    pub filename: String,
+    pub tags: Vec<tags::Tag>,
+}
+
+#[derive(Serialize)]
+pub struct VideoFolderItem {
+    #[serde(flatten)]
+    pub entry: video_roots::FolderEntry,
+    pub url: String,
+}
+
+#[derive(Serialize)]
+pub struct VideoFolderResponse {
+    pub root: String,
+    pub path: String,
+    pub entries: Vec<VideoFolderItem>,
+}
+
+#[derive(Serialize)]
+pub struct FieldsResponse {
+    pub filename: String,
+    pub fields: BTreeMap<String, serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+pub struct SpriteQuery {
+    pub page: Option<usize>,
+    pub limit: Option<usize>,
+    pub size: Option<u32>,
+    #[serde(default)]
+    pub sort: library::SortField,
+    #[serde(default)]
+    pub order: library::SortOrder,
+    pub tag: Option<String>,
+    pub field: Option<String>,
+    #[serde(rename = "type")]
+    pub media_type: Option<MediaClass>,
+}
+
+#[derive(Serialize)]
+pub struct SpriteResponse {
+    /// The composited JPEG for this same page.
+    pub url: String,
+    #[serde(flatten)]
+    pub layout: sprite::SpriteLayout,
+}
+
+#[derive(Serialize)]
+pub struct GalleryImage {
+    pub filename: String,
+    pub media_type: MediaClass,
+    pub url: String,
+    pub size_bytes: u64,
+    pub modified: Option<DateTime<Utc>>,
+    pub renditions: Vec<RenditionInfo>,
+    /// Finder tags across every rendition, deduplicated by name.
+    pub tags: Vec<tags::Tag>,
+    /// Inline thumbnail, only with `include_thumbnail=true` and a decodable primary.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub thumbnail: Option<String>,
+    /// Videos only: running time from the MP4/QuickTime header.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duration_secs: Option<f64>,
+    /// Videos only: thumbnail of a still sharing the video's basename, or
+    /// else a frame taken from the video.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub poster_url: Option<String>,
+    /// Videos only: looping animation of frames across the video, for hover previews.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub preview_url: Option<String>,
+    /// Full path of a file served in place from a referenced folder.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub referenced_path: Option<PathBuf>,
+    /// Values of custom fields set on the primary file.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub fields: BTreeMap<String, serde_json::Value>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PaginatedImageResponse {
+    pub images: Vec<GalleryImage>,
+    pub page: usize,
+    pub page_size: usize,
+    pub total: usize,
+    pub total_pages: usize,
+}
+
+const DEFAULT_FEED_ITEMS: usize = 20;
+const MAX_FEED_ITEMS: usize = 100;
+const FEED_THUMBNAIL_SIZE: u32 = 512;
+
+#[derive(Deserialize)]
+pub struct PublicImagesQuery {
+    pub page: Option<usize>,
+    pub limit: Option<usize>,
+}
+
+/// A published item as listed under `/public`: nothing beyond what an
+/// embedding website needs, so no sizes, paths, tags or fields.
+#[derive(Serialize)]
+pub struct PublicImage {
+    pub filename: String,
+    pub media_type: MediaClass,
+    pub url: String,
+    pub thumbnail_url: String,
+    pub modified: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PublicImagesResponse {
+    pub images: Vec<PublicImage>,
+    pub page: usize,
+    pub page_size: usize,
+    pub total: usize,
+    pub total_pages: usize,
+}
+
+#[derive(Deserialize)]
+pub struct PublishRequest {
+    pub published: bool,
+}
+
+#[derive(Serialize)]
+pub struct PublishResponse {
+    pub filename: String,
+    pub published: bool,
+    /// Where the item can be reached without authentication, when published.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub public_url: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct DeleteImageQuery {
+    /// Remove the file for good instead of moving it to the trash.
+    #[serde(default)]
+    pub permanent: bool,
+    /// Only report what would happen. Defaults to true for permanent
+    /// deletes, which cannot be undone, and false for trashing.
+    pub dry_run: Option<bool>,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TrashStatus {
+    Trashed,
+    Deleted,
+    Restored,
+}
+
+#[derive(Serialize)]
+pub struct TrashResponse {
+    pub filename: String,
+    pub status: TrashStatus,
+    /// Name of the copy in the trash, for restoring this deletion in particular.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trashed_name: Option<String>,
+    /// Nothing was changed; `status` is what would have happened.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub dry_run: bool,
+}
+
+#[derive(Serialize)]
+pub struct TrashListResponse {
+    pub images: Vec<trash::Trashed>,
+}
+
+#[derive(Deserialize)]
+pub struct FeedQuery {
+    pub limit: Option<usize>,
+}
+
+/// An item of a [JSON Feed 1.1](https://jsonfeed.org/version/1.1) document.
+/// Only fields that are safe to publish: no sizes, paths or stats.
+#[derive(Serialize)]
+pub struct FeedItem {
+    pub id: String,
+    pub url: String,
+    pub title: String,
+    pub content_text: String,
+    /// Thumbnail of the image, or of a video's poster; absent for videos without one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub image: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub date_published: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize)]
+pub struct JsonFeed {
+    pub version: &'static str,
+    pub title: String,
+    pub home_page_url: String,
+    pub feed_url: String,
+    pub items: Vec<FeedItem>,
+}
+
+#[derive(Serialize)]
+pub struct CacheStatsResponse {
+    pub images: CacheStats,
+    pub variants: CacheStats,
+}
+
+/// Everything the server holds about one file, for `/admin/inspect`.
+#[derive(Serialize)]
+pub struct InspectResponse {
+    pub filename: String,
+    pub path: PathBuf,
+    pub referenced: bool,
+    pub stat: FileStat,
+    pub mime: Option<&'static str>,
+    pub metadata: InspectedMetadata,
+    /// Every extended attribute on the file: UTF-8 values verbatim, others
+    /// as `base64:...`.
+    pub xattrs: BTreeMap<String, String>,
+    /// The library index entry, if the file has been indexed.
+    pub index: Option<indexer::IndexedImage>,
+    pub stats: ImageStats,
+    pub cache: InspectedCaches,
+}
+
+#[derive(Serialize)]
+pub struct FileStat {
+    pub size_bytes: u64,
+    pub modified: Option<DateTime<Utc>>,
+    pub created: Option<DateTime<Utc>>,
+    pub accessed: Option<DateTime<Utc>>,
+    pub readonly: bool,
+}
+
+/// Metadata as the API parses it, read fresh from the file.
+#[derive(Serialize)]
+pub struct InspectedMetadata {
+    pub dimensions: Option<(u32, u32)>,
+    pub orientation: Option<u32>,
+    pub capture_date: Option<NaiveDateTime>,
+    pub camera: Option<String>,
+    pub tags: Vec<tags::Tag>,
+    pub fields: BTreeMap<String, serde_json::Value>,
+    pub published: bool,
+}
+
+#[derive(Serialize)]
+pub struct InspectedCaches {
+    /// Bytes of the original held in memory.
+    pub original_bytes: Option<usize>,
+    pub variants: Vec<CachedVariant>,
+    /// Sizes with a thumbnail on disk.
+    pub thumbnails: Vec<u32>,
+    pub transcoded: bool,
+    pub hls: bool,
+}
+
+#[derive(Serialize)]
+pub struct CachedVariant {
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub fit: FitMode,
+    pub respect_orientation: bool,
+    pub format: Option<ConvertFormat>,
+    pub quality: Option<u8>,
+    pub bytes: usize,
+}
+
+#[derive(Deserialize)]
+pub struct RenameRulesRequest {
+    /// File name template, e.g. `{yyyy}-{mm}-{dd}_{original}`.
+    pub template: String,
+    /// Only report what would be renamed.
+    #[serde(default = "default_true")]
+    pub dry_run: bool,
+}
+
+#[derive(Serialize)]
+pub struct RenameRulesResponse {
+    pub dry_run: bool,
+    #[serde(flatten)]
+    pub plan: RenamePlan,
+}
+
+#[derive(Deserialize)]
+pub struct MaintenanceRequest {
+    pub enabled: bool,
+    pub message: Option<String>,
+    pub retry_after_secs: Option<u64>,
+}
+
+/// Rejects formats we can serve but not decode (HEIC, RAW) with 415.
+fn ensure_decodable(path: &Path) -> Result<(), ApiError> {
+    match media_types::for_path(path) {
+        Some(media) if !media.decodable => Err(ApiError::Unsupported(format!(
+            "{} images cannot be resized, converted or thumbnailed",
+            media.mime
+        ))),
+        _ => Ok(()),
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// Keeps ETags of unrotated renditions distinct from the default upright ones.
+fn orientation_suffix(respect_orientation: bool) -> &'static str {
+    if respect_orientation {
+        ""
+    } else {
+        "-unoriented"
+    }
+}
+
+fn rendition_infos(stack: &library::ImageStack, config: &Config) -> Vec<RenditionInfo> {
+    stack
+        .renditions
+        .iter()
+        .map(|rendition| RenditionInfo {
+            filename: rendition.image.filename.clone(),
+            kind: rendition.kind,
+            url: config.image_url(&rendition.image.filename),
+            referenced_path: config
+                .policy
+                .is_referenced(&rendition.image.path)
+                .then(|| rendition.image.path.clone()),
+        })
+        .collect()
+}
+
+/// Page number and size from the query, defaulted and capped at `max`.
+fn page_bounds(
+    page: Option<usize>,
+    limit: Option<usize>,
+    default: usize,
+    max: usize,
+) -> Result<(usize, usize), ApiError> {
+    let page = page.unwrap_or(1);
+    let page_size = limit.unwrap_or(default);
+    if page == 0 || page_size == 0 {
+        return Err(ApiError::validation("page and limit must be at least 1"));
+    }
+    Ok((page, page_size.min(max)))
+}
+
+/// Scans the library into stacks, keeps those tagged `tag`, of `media_type`
+/// and with custom field `field` (`name:value`), if given, and sorts them.
+/// The filters read every file's extended attributes, so the whole scan runs
+/// off the async executor.
+async fn gallery_stacks(
+    config: &web::Data<Config>,
+    images_dir: &web::Data<PathBuf>,
+    sort: library::SortField,
+    order: library::SortOrder,
+    tag: Option<&str>,
+    media_type: Option<MediaClass>,
+    field: Option<&str>,
+) -> Result<Vec<library::ImageStack>, ApiError> {
+    let field = field
+        .map(|field| {
+            field
+                .split_once(':')
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .ok_or_else(|| ApiError::validation("field must be written as name:value"))
+        })
+        .transpose()?;
+    let (config, images_dir, tag) = (config.clone(), images_dir.clone(), tag.map(str::to_string));
+    web::block(move || {
+        let mut stacks = library::stack(
+            config
+                .policy
+                .scan(&images_dir)
+                .context("Failed to read images directory")
+                .map_err(ApiError::Storage)?,
+        );
+        if let Some(media_type) = media_type {
+            stacks.retain(|stack| stack.media_class() == media_type);
+        }
+        if let Some((name, value)) = &field {
+            stacks.retain(|stack| fields::matches(&fields::read(&stack.primary().path), name, value));
+        }
+        if let Some(tag) = &tag {
+            stacks.retain(|stack| {
+                stack
+                    .renditions
+                    .iter()
+                    .any(|rendition| tags::has_tag(&rendition.image.path, tag))
+            });
+        }
+        library::sort(&mut stacks, sort, order);
+        Ok(stacks)
+    })
+    .await?
+}
+
+fn gallery_image(stack: &library::ImageStack, config: &Config) -> GalleryImage {
+    let primary = stack.primary();
+    let media_type = stack.media_class();
+    let is_video = media_type == MediaClass::Video;
+    GalleryImage {
+        filename: primary.filename.clone(),
+        media_type,
+        url: config.image_url(&primary.filename),
+        size_bytes: primary.size_bytes,
+        modified: primary.modified,
+        renditions: rendition_infos(stack, config),
+        tags: stack_tags(stack),
+        thumbnail: None,
+        duration_secs: is_video.then(|| video::duration_secs(&primary.path)).flatten(),
+        poster_url: match &stack.poster {
+            Some(poster) => Some(config.thumbnail_url(poster, thumbnails::DEFAULT_SIZE)),
+            None => is_video.then(|| config.video_thumbnail_url(&primary.filename, thumbnails::DEFAULT_SIZE)),
+        },
+        preview_url: is_video.then(|| config.video_preview_url(&primary.filename, thumbnails::DEFAULT_SIZE)),
+        referenced_path: config.policy.is_referenced(&primary.path).then(|| primary.path.clone()),
+        fields: fields::read(&primary.path),
+    }
+}
+
+/// Best-effort inline thumbnail; formats that cannot be decoded get none.
+fn inline_thumbnail(images_dir: &Path, source: &Path) -> Option<String> {
+    if !media_types::for_path(source).is_some_and(|media| media.decodable) {
+        return None;
+    }
+    thumbnails::data_uri(images_dir, source, thumbnails::INLINE_SIZE)
+        .map_err(|e| log::warn!("No inline thumbnail for {:?}: {:#}", source, e))
+        .ok()
 }
 
+fn stack_tags(stack: &library::ImageStack) -> Vec<tags::Tag> {
+    let mut all: Vec<tags::Tag> = Vec::new();
+    for rendition in &stack.renditions {
+        for tag in tags::read(&rendition.image.path) {
+            if !all.iter().any(|seen| seen.name.eq_ignore_ascii_case(&tag.name)) {
+                all.push(tag);
+            }
+        }
+    }
+    all
+}
+
+/// Reports each dependency alongside an overall status; only an unhealthy
+/// service answers 503, so load balancers keep routing to degraded ones.
 #[get("/health")]
-pub async fn health_check() -> impl Responder {
+pub async fn health_check(
+    images_dir: web::Data<PathBuf>,
+    config: web::Data<Config>,
+    images: web::Data<ImageCache>,
+    variants: web::Data<VariantCache>,
+    index: web::Data<LibraryIndex>,
+) -> Result<HttpResponse, ApiError> {
+    let dir = images_dir.get_ref().clone();
+    let min_free_bytes = config.min_free_disk_bytes;
+    let (images_dir, disk) = web::block(move || {
+        (health::check_images_dir(&dir), health::check_disk(&dir, min_free_bytes))
+    })
+    .await?;
+    let checks = HealthChecks {
+        images_dir,
+        disk,
+        caches: CachesCheck {
+            images: images.stats(),
+            variants: variants.stats(),
+        },
+        index: health::check_index(index.progress()),
+    };
+
+    let status = checks.status();
     let response = HealthResponse {
-        status: "healthy".to_string(),
+        status,
         timestamp: Utc::now(),
         version: env!("CARGO_PKG_VERSION").to_string(),
+        checks,
+    };
+    Ok(match status {
+        HealthStatus::Unhealthy => HttpResponse::ServiceUnavailable().json(response),
+        HealthStatus::Healthy | HealthStatus::Degraded => HttpResponse::Ok().json(response),
+    })
+}
+
+#[derive(Serialize)]
+pub struct VolumesResponse {
+    pub status: HealthStatus,
+    /// The images directory first, then the referenced folders.
+    pub volumes: Vec<volumes::Volume>,
+}
+
+/// Whether each folder the library is served from is mounted. An offline
+/// images directory is unhealthy (503); an offline referenced folder only
+/// degrades the service, as its files answer 503 while the rest are served.
+#[get("/health/volumes")]
+pub async fn volume_health(
+    images_dir: web::Data<PathBuf>,
+    config: web::Data<Config>,
+) -> Result<HttpResponse, ApiError> {
+    let roots: Vec<PathBuf> = config.policy.roots(&images_dir).map(Path::to_path_buf).collect();
+    let volumes = web::block(move || roots.iter().map(|root| volumes::status(root)).collect::<Vec<_>>()).await?;
+    let status = match volumes.iter().position(|volume| !volume.online) {
+        None => HealthStatus::Healthy,
+        Some(0) => HealthStatus::Unhealthy,
+        Some(_) => HealthStatus::Degraded,
     };
-    HttpResponse::Ok().json(response)
+    let response = VolumesResponse { status, volumes };
+    Ok(match status {
+        HealthStatus::Unhealthy => HttpResponse::ServiceUnavailable().json(response),
+        HealthStatus::Healthy | HealthStatus::Degraded => HttpResponse::Ok().json(response),
+    })
+}
+
+#[derive(Serialize)]
+pub struct MeResponse {
+    pub subject: String,
+    /// Every claim of the caller's token, as the identity provider issued it.
+    pub claims: serde_json::Map<String, serde_json::Value>,
+}
+
+/// The caller's identity from their bearer token; 401 without one.
+#[get("/me")]
+pub async fn current_user(user: AuthenticatedUser) -> HttpResponse {
+    HttpResponse::Ok().json(MeResponse {
+        subject: user.subject,
+        claims: user.claims,
+    })
+}
+
+/// Answers a missing or undecodable image with the configured placeholder,
+/// when there is one; every other outcome passes through.
+fn or_placeholder(
+    result: Result<HttpResponse, ApiError>,
+    config: &Config,
+    width: Option<u32>,
+    height: Option<u32>,
+) -> Result<HttpResponse, ApiError> {
+    match result {
+        Err(e @ (ApiError::NotFound(_) | ApiError::Processing(_))) if config.placeholder.enabled => {
+            log::warn!("Serving placeholder: {:#}", e);
+            Ok(config.placeholder.response(width, height))
+        }
+        result => result,
+    }
+}
+
+/// The configured preset called `name`, if one was asked for.
+fn lookup_preset<'a>(config: &'a Config, name: Option<&str>) -> Result<Option<&'a Preset>, ApiError> {
+    name.map(|name| {
+        config
+            .presets
+            .get(name)
+            .ok_or_else(|| ApiError::validation(format!("Unknown preset {}", name)))
+    })
+    .transpose()
 }
 
 #[get("/images/{filename}")]
+#[allow(clippy::too_many_arguments)]
 pub async fn serve_image(
+    req: HttpRequest,
     filename: web::Path<String>,
+    query: web::Query<ServeImageQuery>,
     images_dir: web::Data<PathBuf>,
-) -> impl Responder {
-    let path = images_dir.join(filename.as_ref());
-    
-    if !path.exists() {
-        return HttpResponse::NotFound().body("Image not found");
-    }
-
-    match std::fs::read(&path) {
-        Ok(contents) => HttpResponse::Ok()
-            .content_type("image/jpeg") // You might want to make this dynamic based on the file type
-            .body(contents),
-        Err(_) => HttpResponse::InternalServerError().body("Failed to read image"),
+    config: web::Data<Config>,
+    stats: web::Data<ViewStats>,
+    images: web::Data<ImageCache>,
+    variants: web::Data<VariantCache>,
+    transcodes: web::Data<TranscodeCache>,
+) -> Result<HttpResponse, ApiError> {
+    if query.quality.is_some_and(|quality| !(1..=100).contains(&quality)) {
+        return Err(ApiError::validation("quality must be between 1 and 100"));
     }
+    let preset = lookup_preset(&config, query.preset.as_deref())?;
+    let ops = Operations::resolve(preset, query.w, query.h, query.fit, None, query.quality);
+    let side = |side: Option<u32>| side.map(|side| side.clamp(1, MAX_DIMENSION));
+    let (width, height) = (side(ops.width), side(ops.height));
+    let result = image_response(req, filename, query, ops, images_dir, config.clone(), stats, images, variants, transcodes).await;
+    or_placeholder(result, &config, width, height)
 }
 
-#[get("/images/{filename}/info")]
-pub async fn image_info(
+#[allow(clippy::too_many_arguments)]
+async fn image_response(
+    req: HttpRequest,
     filename: web::Path<String>,
+    query: web::Query<ServeImageQuery>,
+    ops: Operations,
     images_dir: web::Data<PathBuf>,
-) -> impl Responder {
-    let path = images_dir.join(filename.as_ref());
+    config: web::Data<Config>,
+    stats: web::Data<ViewStats>,
+    images: web::Data<ImageCache>,
+    variants: web::Data<VariantCache>,
+    transcodes: web::Data<TranscodeCache>,
+) -> Result<HttpResponse, ApiError> {
+    let path = config.policy.resolve(&images_dir, &filename)?;
     
     if !path.exists() {
-        return HttpResponse::NotFound().body("Image not found");
+        return Err(ApiError::not_found("Image not found"));
     }
+    let filename = paths::file_name(&path);
+    stats.record_view(&filename);
 
-    let metadata = match std::fs::metadata(&path) {
-        Ok(m) => m,
-        Err(_) => return HttpResponse::InternalServerError().body("Failed to read image metadata"),
+    let metadata = std::fs::metadata(&path)
+        .context("Failed to read image metadata")
+        .map_err(ApiError::Storage)?;
+    let resize = ops.transforms();
+    if resize {
+        ensure_decodable(&path)?;
+    } else {
+        config.policy.ensure_originals()?;
+    }
+    let transcode = if resize { None } else { transcode_reason(&req, &query, &path) };
+    let variant = if resize {
+        Some(
+            format!(
+                "{}x{}-{:?}{}{}",
+                ops.width.unwrap_or_default(),
+                ops.height.unwrap_or_default(),
+                ops.fit,
+                ops.convert
+                    .map(|(format, quality)| format!("-{:?}-q{}", format, quality))
+                    .unwrap_or_default(),
+                orientation_suffix(query.respect_orientation)
+            )
+            .to_lowercase(),
+        )
+    } else {
+        transcode.map(|_| "h264".to_string())
     };
+    let validators = Validators::for_file(&metadata, variant.as_deref());
 
-    let format = guess_format(&std::fs::read(&path).unwrap_or_default()).ok();
-    let dimensions = image::open(&path).ok().map(|img| img.dimensions());
+    let transcoded = match transcode {
+        Some(reason) if !validators.is_not_modified(&req) => {
+            let (source, name) = (path.clone(), filename.clone());
+            let result = web::block(move || transcodes.get_or_create(&source, &name)).await?;
+            match (result, reason) {
+                (Ok(transcoded), _) => Some(transcoded),
+                (Err(e), Transcode::Requested) => {
+                    return Err(ApiError::Processing(e.context("Failed to transcode video")));
+                }
+                (Err(e), Transcode::Guessed) => {
+                    log::warn!("Serving {} untranscoded: {:#}", filename, e);
+                    None
+                }
+            }
+        }
+        _ => None,
+    };
 
-    let info = ImageInfo {
-        filename: filename.to_string(),
-        size_bytes: metadata.len(),
-        format: format.map(|f| format!("{:?}", f)),
-        dimensions,
+    let mut response = if validators.is_not_modified(&req) {
+        HttpResponse::NotModified().finish()
+    } else if let Some(transcoded) = transcoded {
+        serve_file(&req, &transcoded, "video/mp4", &config).await?
+    } else if resize {
+        let key = VariantKey {
+            filename,
+            width: ops.width,
+            height: ops.height,
+            fit: ops.fit,
+            respect_orientation: query.respect_orientation,
+            convert: ops.convert,
+        };
+        serve_resized(path, key, variants).await?
+    } else if metadata.len() > images.max_bytes() as u64 {
+        // Originals too big to cache are streamed; variants are always rendered
+        serve_file(&req, &path, sniff_content_type(&path), &config).await?
+    } else {
+        serve_original(&req, &path, &filename, metadata.modified().ok(), &config, &images)?
+    };
+
+    if response.status().is_success() || response.status() == StatusCode::NOT_MODIFIED {
+        validators.apply(response.headers_mut(), &config.cache_control());
+    }
+    Ok(response)
+}
+
+/// Why a video is served as an H.264 copy instead of the original.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Transcode {
+    /// The client listed the video's codec in `unsupported_codecs`.
+    Requested,
+    /// The User-Agent belongs to a browser known not to play the codec.
+    Guessed,
+}
+
+fn transcode_reason(req: &HttpRequest, query: &ServeImageQuery, path: &Path) -> Option<Transcode> {
+    if !library::is_video_file(path) {
+        return None;
+    }
+    let codec = video::codec(path)?;
+    if codec == VideoCodec::H264 {
+        return None;
+    }
+
+    if let Some(unsupported) = &query.unsupported_codecs {
+        return unsupported
+            .split(',')
+            .filter_map(VideoCodec::from_name)
+            .any(|name| name == codec)
+            .then_some(Transcode::Requested);
+    }
+
+    let user_agent = req
+        .headers()
+        .get(actix_web::http::header::USER_AGENT)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default();
+    let guessed = match codec {
+        VideoCodec::Hevc => user_agent.contains("Firefox/"),
+        // No browser plays ProRes
+        VideoCodec::ProRes => user_agent.starts_with("Mozilla/"),
+        _ => false,
+    };
+    guessed.then_some(Transcode::Guessed)
+}
+
+/// `Content-Type` for a file too large to read whole: by extension, then by its first bytes.
+fn sniff_content_type(path: &Path) -> &'static str {
+    let mut head = Vec::with_capacity(64);
+    if let Ok(file) = std::fs::File::open(path) {
+        let _ = file.take(64).read_to_end(&mut head);
+    }
+    media_types::content_type(path, &head)
+}
+
+/// Streams a file (or the requested range of it) from disk rather than memory.
+async fn serve_file(
+    req: &HttpRequest,
+    path: &Path,
+    content_type: &'static str,
+    config: &Config,
+) -> Result<HttpResponse, ApiError> {
+    let mut file = tokio::fs::File::open(path)
+        .await
+        .context("Failed to open file")
+        .map_err(ApiError::Storage)?;
+    let total = file
+        .metadata()
+        .await
+        .context("Failed to read file metadata")
+        .map_err(ApiError::Storage)?
+        .len();
+
+    let range = match range::from_request(req, total) {
+        RangeRequest::Unsatisfiable => return Ok(range::unsatisfiable(total)),
+        RangeRequest::Partial(range) => Some(range),
+        RangeRequest::Full => None,
+    };
+    if let Some(range) = range {
+        tokio::io::AsyncSeekExt::seek(&mut file, SeekFrom::Start(range.start))
+            .await
+            .context("Failed to seek file")
+            .map_err(ApiError::Storage)?;
+    }
+    let length = range.map_or(total, |range| range.length());
+
+    // Streams are sent chunked unless told their length up front
+    let mut response = range::response(range, total);
+    response.content_type(content_type).no_chunking(length);
+    Ok(response.streaming(throttle::file(file, length, config.download_rate_limit)))
+}
+
+fn serve_original(
+    req: &HttpRequest,
+    path: &Path,
+    filename: &str,
+    modified: Option<SystemTime>,
+    config: &Config,
+    images: &ImageCache,
+) -> Result<HttpResponse, ApiError> {
+    let contents = match images.get(filename, modified) {
+        Some(contents) => contents,
+        None => {
+            let contents = Bytes::from(
+                std::fs::read(path)
+                    .context("Failed to read image")
+                    .map_err(ApiError::Storage)?,
+            );
+            images.insert(filename, modified, contents.clone());
+            contents
+        }
+    };
+
+    let content_type = media_types::content_type(path, &contents);
+    let total = contents.len() as u64;
+    let (range, contents) = match range::from_request(req, total) {
+        RangeRequest::Unsatisfiable => return Ok(range::unsatisfiable(total)),
+        RangeRequest::Partial(range) => (Some(range), contents.slice(range.start as usize..=range.end as usize)),
+        RangeRequest::Full => (None, contents),
+    };
+
+    let mut response = range::response(range, total);
+    response.content_type(content_type);
+    Ok(match config.download_rate_limit {
+        Some(limit) => response
+            .no_chunking(contents.len() as u64)
+            .streaming(throttle::throttled(contents, limit)),
+        None => response.body(contents),
+    })
+}
+
+async fn serve_resized(
+    path: PathBuf,
+    key: VariantKey,
+    variants: web::Data<VariantCache>,
+) -> Result<HttpResponse, ApiError> {
+    let valid = |side: Option<u32>| side.is_none_or(|side| (1..=MAX_DIMENSION).contains(&side));
+    if !valid(key.width) || !valid(key.height) {
+        return Err(ApiError::validation(format!("w and h must be between 1 and {}", MAX_DIMENSION)));
+    }
+
+    let source_modified = std::fs::metadata(&path).and_then(|m| m.modified()).ok();
+    if let Some(cached) = variants.get(&key, source_modified) {
+        return Ok(HttpResponse::Ok().content_type(cached.content_type()).body(cached.bytes));
+    }
+
+    // Only the request that does the decode is charged for it
+    let mut cost = None;
+    let (width, height, fit, respect_orientation, convert) =
+        (key.width, key.height, key.fit, key.respect_orientation, key.convert);
+    let encoded = variants
+        .render(key.clone(), || async {
+            let (encoded, decoded) = web::block(move || {
+                let (img, format) = ImageProcessor::open(&path, respect_orientation)?;
+                let resized = ImageProcessor::resize_image(&img, width, height, fit);
+                let encoded = match convert {
+                    Some((format, quality)) => ImageProcessor::convert(&resized, format, quality)?,
+                    None => ImageProcessor::encode(&resized, format)?,
+                };
+                anyhow::Ok((encoded, TransformCost::of(img.width(), img.height())))
+            })
+            .await??;
+            variants.insert(key.clone(), source_modified, encoded.clone());
+            cost = Some(decoded);
+            Ok(encoded)
+        })
+        .await
+        .with_context(|| format!("Failed to resize {}", key.filename))
+        .map_err(ApiError::Processing)?;
+
+    let mut response = HttpResponse::Ok().content_type(encoded.content_type()).body(encoded.bytes);
+    if let Some(cost) = cost {
+        response.extensions_mut().insert(cost);
+    }
+    Ok(response)
+}
+
+#[get("/images/{filename}/thumbnail")]
+pub async fn image_thumbnail(
+    filename: web::Path<String>,
+    query: web::Query<ThumbnailQuery>,
+    images_dir: web::Data<PathBuf>,
+    config: web::Data<Config>,
+    variants: web::Data<VariantCache>,
+) -> Result<HttpResponse, ApiError> {
+    let size = query.size.unwrap_or(thumbnails::DEFAULT_SIZE);
+    if !thumbnails::SIZES.contains(&size) {
+        return Err(ApiError::validation(format!("size must be one of {:?}", thumbnails::SIZES)));
+    }
+    let result = thumbnail_response(filename, size, query.respect_orientation, images_dir, &config, variants).await;
+    or_placeholder(result, &config, Some(size), Some(size))
+}
+
+async fn thumbnail_response(
+    filename: web::Path<String>,
+    size: u32,
+    respect_orientation: bool,
+    images_dir: web::Data<PathBuf>,
+    config: &Config,
+    variants: web::Data<VariantCache>,
+) -> Result<HttpResponse, ApiError> {
+    let source = config.policy.resolve(&images_dir, &filename)?;
+    if !source.is_file() {
+        return Err(ApiError::not_found("Image not found"));
+    }
+    ensure_decodable(&source)?;
+
+    let filename = paths::file_name(&source);
+    let dir = images_dir.get_ref().clone();
+    // Only upright thumbnails are cached on disk
+    let key = VariantKey {
+        filename: filename.clone(),
+        width: Some(size),
+        height: Some(size),
+        fit: FitMode::Contain,
+        respect_orientation,
+        convert: None,
+    };
+    let thumbnail = variants
+        .render(key, || async {
+            web::block(move || {
+                if respect_orientation {
+                    thumbnails::get_or_create(&dir, &source, size)
+                } else {
+                    thumbnails::render(&source, size, false)
+                }
+            })
+            .await?
+        })
+        .await
+        .with_context(|| format!("Failed to create thumbnail for {}", filename))
+        .map_err(ApiError::Processing)?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(thumbnail.content_type())
+        .body(thumbnail.bytes))
+}
+
+/// A video addressed as `/videos/{root}/{path}`: a file inside a configured
+/// video root, or a library video under [`video_roots::LIBRARY`].
+struct VideoSource {
+    path: PathBuf,
+    root: String,
+    /// Slash-separated path inside the root; the file name for library videos.
+    relative: String,
+    /// Names the video's cached posters, previews and HLS segments.
+    cache_name: String,
+}
+
+/// What a request to an endpoint below `/videos/{root}/{path}` addresses.
+enum VideoTarget {
+    Video(VideoSource),
+    /// `{path}` is no video, so the request named an entry of the root (a
+    /// folder may well hold one called `info`); this is the response for it.
+    Entry(HttpResponse),
+}
+
+/// Resolves the video for `endpoint` (e.g. `info`) below `/videos/{root}/{path}`.
+/// Files have no children, so when `{path}` is not a video the request is
+/// answered as the root entry `{path}/{endpoint}` instead.
+async fn video_target(
+    req: &HttpRequest,
+    images_dir: &Path,
+    config: &Config,
+    endpoint: &str,
+) -> Result<VideoTarget, ApiError> {
+    let root = req.match_info().query("root");
+    let relative = req.match_info().query("path").trim_matches('/');
+    let path = match root {
+        video_roots::LIBRARY if relative.contains('/') => None,
+        video_roots::LIBRARY => Some(config.policy.resolve(images_dir, relative)?),
+        root => Some(video_roots::resolve(&config.video_roots, root, relative)?),
+    };
+    match path {
+        Some(path) if path.is_file() && library::is_video_file(&path) => {
+            let (relative, cache_name) = match root {
+                video_roots::LIBRARY => (paths::file_name(&path), paths::file_name(&path)),
+                root => (relative.to_string(), video_roots::cache_name(root, relative)),
+            };
+            Ok(VideoTarget::Video(VideoSource { path, root: root.to_string(), relative, cache_name }))
+        }
+        _ => video_entry(req, images_dir, config, root, &format!("{}/{}", relative, endpoint))
+            .await
+            .map(VideoTarget::Entry),
+    }
+}
+
+/// Parses the query string once a handler knows the request is its own; see
+/// [`crate::error::query_config`].
+fn parse_query<T: DeserializeOwned>(req: &HttpRequest) -> Result<T, ApiError> {
+    web::Query::<T>::from_query(req.query_string())
+        .map(web::Query::into_inner)
+        .map_err(|e| ApiError::Validation(e.to_string()))
+}
+
+fn thumbnail_size(size: Option<u32>) -> Result<u32, ApiError> {
+    let size = size.unwrap_or(thumbnails::DEFAULT_SIZE);
+    if !thumbnails::SIZES.contains(&size) {
+        return Err(ApiError::validation(format!("size must be one of {:?}", thumbnails::SIZES)));
+    }
+    Ok(size)
+}
+
+/// Keyframe timestamps of an MP4 or QuickTime video, read from its sample
+/// tables, for building scrubbers. With `thumbnails=true` each keyframe links
+/// to its frame.
+#[get("/videos/{root}/{path:.*}/keyframes")]
+pub async fn video_keyframes(
+    req: HttpRequest,
+    images_dir: web::Data<PathBuf>,
+    config: web::Data<Config>,
+) -> Result<HttpResponse, ApiError> {
+    let video = match video_target(&req, &images_dir, &config, "keyframes").await? {
+        VideoTarget::Video(video) => video,
+        VideoTarget::Entry(response) => return Ok(response),
+    };
+    let query: KeyframesQuery = parse_query(&req)?;
+    if !query.min_interval.is_finite() || query.min_interval < 0.0 {
+        return Err(ApiError::validation("min_interval must be a non-negative number of seconds"));
+    }
+    let size = thumbnail_size(query.size)?;
+    let filename = paths::file_name(&video.path);
+
+    let source = video.path.clone();
+    let (times, duration_secs) = web::block(move || (video::keyframes(&source), video::duration_secs(&source))).await?;
+    let times = times.ok_or_else(|| ApiError::validation("Keyframes are only available for MP4 and QuickTime videos"))?;
+
+    let mut keyframes: Vec<Keyframe> = Vec::new();
+    for time_secs in times {
+        if keyframes.last().is_some_and(|last| time_secs - last.time_secs < query.min_interval) {
+            continue;
+        }
+        keyframes.push(Keyframe {
+            time_secs,
+            thumbnail_url: query.thumbnails.then(|| {
+                config.video_action_url(&video.root, &video.relative, &format!("frame?t={}&size={}", time_secs, size))
+            }),
+        });
+    }
+
+    Ok(HttpResponse::Ok().json(KeyframesResponse {
+        filename,
+        duration_secs,
+        keyframes,
+    }))
+}
+
+/// One frame of a video as a JPEG thumbnail, extracted with ffmpeg.
+#[get("/videos/{root}/{path:.*}/frame")]
+pub async fn video_frame(
+    req: HttpRequest,
+    images_dir: web::Data<PathBuf>,
+    config: web::Data<Config>,
+    transcodes: web::Data<TranscodeCache>,
+) -> Result<HttpResponse, ApiError> {
+    let path = match video_target(&req, &images_dir, &config, "frame").await? {
+        VideoTarget::Video(video) => video.path,
+        VideoTarget::Entry(response) => return Ok(response),
+    };
+    let query: FrameQuery = parse_query(&req)?;
+    if !query.t.is_finite() || query.t < 0.0 {
+        return Err(ApiError::validation("t must be a non-negative number of seconds"));
+    }
+    let size = thumbnail_size(query.size)?;
+
+    let metadata = std::fs::metadata(&path)
+        .context("Failed to read video metadata")
+        .map_err(ApiError::Storage)?;
+    let validators = Validators::for_file(&metadata, Some(&format!("frame-{}-{}", query.t, size)));
+    if validators.is_not_modified(&req) {
+        let mut response = HttpResponse::NotModified().finish();
+        validators.apply(response.headers_mut(), &config.cache_control());
+        return Ok(response);
+    }
+
+    let at_secs = query.t;
+    let frame = web::block(move || transcodes.frame(&path, at_secs, size))
+        .await?
+        .context("Failed to extract video frame")
+        .map_err(ApiError::Processing)?;
+
+    let mut response = HttpResponse::Ok().content_type(frame.content_type()).body(frame.bytes);
+    validators.apply(response.headers_mut(), &config.cache_control());
+    Ok(response)
+}
+
+/// A still from a video, extracted with ffmpeg and cached on disk, so videos
+/// without a poster image can still be previewed.
+#[get("/videos/{root}/{path:.*}/thumbnail")]
+pub async fn video_thumbnail(
+    req: HttpRequest,
+    images_dir: web::Data<PathBuf>,
+    config: web::Data<Config>,
+    transcodes: web::Data<TranscodeCache>,
+) -> Result<HttpResponse, ApiError> {
+    let video = match video_target(&req, &images_dir, &config, "thumbnail").await? {
+        VideoTarget::Video(video) => video,
+        VideoTarget::Entry(response) => return Ok(response),
+    };
+    let query: VideoThumbnailQuery = parse_query(&req)?;
+    let size = thumbnail_size(query.size)?;
+    let format = query.format.unwrap_or(ConvertFormat::Jpeg);
+    let at = query
+        .at
+        .as_deref()
+        .map(|at| video::parse_timestamp(at).ok_or_else(|| ApiError::validation("at must be a time such as 5s or 1:05")))
+        .transpose()?;
+    let at_millis = poster_millis(&video.path, at).await?;
+
+    let metadata = std::fs::metadata(&video.path)
+        .context("Failed to read video metadata")
+        .map_err(ApiError::Storage)?;
+    let validators = Validators::for_file(&metadata, Some(&format!("thumbnail-{}-{}-{:?}", at_millis, size, format)));
+    if validators.is_not_modified(&req) {
+        let mut response = HttpResponse::NotModified().finish();
+        validators.apply(response.headers_mut(), &config.cache_control());
+        return Ok(response);
+    }
+
+    let thumbnail = video_poster(&images_dir, video.path, &video.cache_name, size, at_millis, format, transcodes).await?;
+    let mut response = HttpResponse::Ok().content_type(thumbnail.content_type()).body(thumbnail.bytes);
+    validators.apply(response.headers_mut(), &config.cache_control());
+    Ok(response)
+}
+
+/// Where to take a video's still, in milliseconds: `at`, which must fall
+/// before the end, or [`DEFAULT_POSTER_SECS`] capped at half the running time.
+async fn poster_millis(path: &Path, at: Option<f64>) -> Result<u64, ApiError> {
+    let source = path.to_path_buf();
+    let duration_secs = web::block(move || video::duration_secs(&source)).await?;
+    let at_secs = match at {
+        Some(at) if duration_secs.is_some_and(|duration| at >= duration) => {
+            return Err(ApiError::validation("at is past the end of the video"));
+        }
+        Some(at) => at,
+        None => duration_secs.map_or(DEFAULT_POSTER_SECS, |duration| DEFAULT_POSTER_SECS.min(duration / 2.0)),
+    };
+    Ok((at_secs * 1000.0).round() as u64)
+}
+
+/// The still of video `path` at `at_millis`, from the disk cache (as `name`) or ffmpeg.
+async fn video_poster(
+    images_dir: &Path,
+    path: PathBuf,
+    name: &str,
+    size: u32,
+    at_millis: u64,
+    format: ConvertFormat,
+    transcodes: web::Data<TranscodeCache>,
+) -> Result<EncodedImage, ApiError> {
+    let dir = images_dir.to_path_buf();
+    let name = name.to_string();
+    web::block(move || {
+        thumbnails::get_or_create_poster(&dir, &path, &name, size, at_millis, format, || {
+            transcodes.frame(&path, at_millis as f64 / 1000.0, size)
+        })
+    })
+    .await?
+    .context("Failed to create video thumbnail")
+    .map_err(ApiError::Processing)
+}
+
+/// A short looping animation of frames spread evenly across a video, for
+/// hover previews on gallery cards. Frames are taken with ffmpeg and the
+/// result is cached on disk until the video changes.
+#[get("/videos/{root}/{path:.*}/preview")]
+pub async fn video_preview(
+    req: HttpRequest,
+    images_dir: web::Data<PathBuf>,
+    config: web::Data<Config>,
+    transcodes: web::Data<TranscodeCache>,
+) -> Result<HttpResponse, ApiError> {
+    let VideoSource { path, cache_name, .. } = match video_target(&req, &images_dir, &config, "preview").await? {
+        VideoTarget::Video(video) => video,
+        VideoTarget::Entry(response) => return Ok(response),
+    };
+    let query: VideoPreviewQuery = parse_query(&req)?;
+    let size = thumbnail_size(query.size)?;
+    let format = query.format;
+
+    let metadata = std::fs::metadata(&path)
+        .context("Failed to read video metadata")
+        .map_err(ApiError::Storage)?;
+    let validators = Validators::for_file(&metadata, Some(&format!("preview-{}-{}", size, format.extension())));
+    if validators.is_not_modified(&req) {
+        let mut response = HttpResponse::NotModified().finish();
+        validators.apply(response.headers_mut(), &config.cache_control());
+        return Ok(response);
+    }
+
+    let dir = images_dir.get_ref().clone();
+    let animation = web::block(move || {
+        thumbnails::get_or_create_preview(&dir, &path, &cache_name, size, format, || {
+            let duration_secs = video::duration_secs(&path)
+                .context("Previews need a running time from an MP4 or QuickTime header")?;
+            let frames = preview::frame_times(duration_secs, preview::FRAME_COUNT)
+                .into_iter()
+                .map(|at_secs| {
+                    let frame = transcodes.frame(&path, at_secs, size)?;
+                    image::load_from_memory(&frame.bytes).context("Failed to decode video frame")
+                })
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            preview::encode(&frames, format)
+        })
+    })
+    .await?
+    .context("Failed to create video preview")
+    .map_err(ApiError::Processing)?;
+
+    let mut response = HttpResponse::Ok().content_type(animation.content_type()).body(animation.bytes);
+    validators.apply(response.headers_mut(), &config.cache_control());
+    Ok(response)
+}
+
+/// Container format, size, running time, coded resolution, codec and average
+/// bitrate of a video, read from its headers and cached until it changes.
+#[get("/videos/{root}/{path:.*}/info")]
+pub async fn video_info(
+    req: HttpRequest,
+    images_dir: web::Data<PathBuf>,
+    config: web::Data<Config>,
+    probes: web::Data<ProbeCache>,
+) -> Result<HttpResponse, ApiError> {
+    let path = match video_target(&req, &images_dir, &config, "info").await? {
+        VideoTarget::Video(video) => video.path,
+        VideoTarget::Entry(response) => return Ok(response),
+    };
+    let filename = paths::file_name(&path);
+    let info = web::block(move || probes.probe(&path))
+        .await?
+        .context("Failed to read video")
+        .map_err(ApiError::Storage)?;
+    Ok(HttpResponse::Ok().json(VideoInfoResponse { filename, info }))
+}
+
+/// HLS playlist of a video, for players on slow links. The video is split
+/// into segments with ffmpeg on first request and cached on disk.
+#[get("/videos/{root}/{path:.*}/playlist.m3u8")]
+pub async fn video_playlist(
+    req: HttpRequest,
+    images_dir: web::Data<PathBuf>,
+    config: web::Data<Config>,
+    transcodes: web::Data<TranscodeCache>,
+) -> Result<HttpResponse, ApiError> {
+    let video = match video_target(&req, &images_dir, &config, HLS_PLAYLIST).await? {
+        VideoTarget::Video(video) => video,
+        VideoTarget::Entry(response) => return Ok(response),
+    };
+    serve_hls(&req, video, HLS_PLAYLIST, "application/vnd.apple.mpegurl", &config, transcodes).await
+}
+
+/// One MPEG-TS segment named in a video's HLS playlist.
+#[get("/videos/{root}/{path:.*}/segments/{segment}")]
+pub async fn video_segment(
+    req: HttpRequest,
+    images_dir: web::Data<PathBuf>,
+    config: web::Data<Config>,
+    transcodes: web::Data<TranscodeCache>,
+) -> Result<HttpResponse, ApiError> {
+    let segment = req.match_info().query("segment").to_string();
+    let video = match video_target(&req, &images_dir, &config, &format!("segments/{}", segment)).await? {
+        VideoTarget::Video(video) => video,
+        VideoTarget::Entry(response) => return Ok(response),
+    };
+    let is_segment = segment
+        .strip_prefix("seg")
+        .and_then(|rest| rest.strip_suffix(".ts"))
+        .is_some_and(|number| !number.is_empty() && number.bytes().all(|b| b.is_ascii_digit()));
+    if !is_segment {
+        return Err(ApiError::not_found("Segment not found"));
+    }
+    serve_hls(&req, video, &segment, "video/mp2t", &config, transcodes).await
+}
+
+/// Serves `name` from the HLS folder of `video`, segmenting the video first
+/// when its folder is missing or stale.
+async fn serve_hls(
+    req: &HttpRequest,
+    video: VideoSource,
+    name: &str,
+    content_type: &'static str,
+    config: &Config,
+    transcodes: web::Data<TranscodeCache>,
+) -> Result<HttpResponse, ApiError> {
+    let VideoSource { path, cache_name, .. } = video;
+    let metadata = std::fs::metadata(&path)
+        .context("Failed to read video metadata")
+        .map_err(ApiError::Storage)?;
+    let validators = Validators::for_file(&metadata, Some(&format!("hls-{}", name)));
+    if validators.is_not_modified(req) {
+        let mut response = HttpResponse::NotModified().finish();
+        validators.apply(response.headers_mut(), &config.cache_control());
+        return Ok(response);
+    }
+
+    let dir = web::block(move || transcodes.hls(&path, &cache_name))
+        .await?
+        .context("Failed to segment video")
+        .map_err(ApiError::Processing)?;
+    let file = dir.join(name);
+    if !file.is_file() {
+        return Err(ApiError::not_found("Segment not found"));
+    }
+    let mut response = serve_file(req, &file, content_type, config).await?;
+    validators.apply(response.headers_mut(), &config.cache_control());
+    Ok(response)
+}
+
+/// A file or folder inside one of the configured video roots, or a library
+/// video by file name under [`video_roots::LIBRARY`]. Files are streamed with
+/// `Range` support and conditional requests; folders list their subfolders
+/// and media files.
+#[routes]
+#[get("/videos/{root}")]
+#[get("/videos/{root}/{path:.*}")]
+pub async fn video_root(
+    req: HttpRequest,
+    images_dir: web::Data<PathBuf>,
+    config: web::Data<Config>,
+) -> Result<HttpResponse, ApiError> {
+    let root = req.match_info().query("root");
+    let relative = req.match_info().query("path");
+    video_entry(&req, &images_dir, &config, root, relative).await
+}
+
+/// The response for the entry at `relative` inside video root `root`.
+async fn video_entry(
+    req: &HttpRequest,
+    images_dir: &Path,
+    config: &Config,
+    root: &str,
+    relative: &str,
+) -> Result<HttpResponse, ApiError> {
+    let path = if root == video_roots::LIBRARY {
+        let filename = relative.trim_matches('/');
+        if filename.contains('/') {
+            return Err(ApiError::not_found("Not found"));
+        }
+        let path = config.policy.resolve(images_dir, filename)?;
+        if !path.is_file() || !library::is_video_file(&path) {
+            return Err(ApiError::not_found("Video not found"));
+        }
+        config.policy.ensure_originals()?;
+        path
+    } else {
+        video_roots::resolve(&config.video_roots, root, relative)?
+    };
+    let metadata = std::fs::metadata(&path).map_err(|_| ApiError::not_found("Not found"))?;
+
+    if metadata.is_dir() {
+        let entries = video_roots::list(&config.video_roots[root], &path)
+            .context("Failed to read folder")
+            .map_err(ApiError::Storage)?
+            .into_iter()
+            .map(|entry| VideoFolderItem {
+                url: config.video_url(root, &format!("{}/{}", relative, entry.name)),
+                entry,
+            })
+            .collect();
+        return Ok(HttpResponse::Ok().json(VideoFolderResponse {
+            root: root.to_string(),
+            path: relative.trim_matches('/').to_string(),
+            entries,
+        }));
+    }
+    if !metadata.is_file() || !library::is_media_file(&path) {
+        return Err(ApiError::not_found("Not found"));
+    }
+
+    let validators = Validators::for_file(&metadata, None);
+    let mut response = if validators.is_not_modified(req) {
+        HttpResponse::NotModified().finish()
+    } else {
+        serve_file(req, &path, sniff_content_type(&path), config).await?
+    };
+    validators.apply(response.headers_mut(), &config.cache_control());
+    Ok(response)
+}
+
+#[get("/images/{filename}/convert")]
+pub async fn convert_image(
+    req: HttpRequest,
+    filename: web::Path<String>,
+    query: web::Query<ConvertQuery>,
+    images_dir: web::Data<PathBuf>,
+    config: web::Data<Config>,
+    variants: web::Data<VariantCache>,
+) -> Result<HttpResponse, ApiError> {
+    let quality = query.quality.unwrap_or(DEFAULT_CONVERT_QUALITY);
+    if !(1..=100).contains(&quality) {
+        return Err(ApiError::validation("quality must be between 1 and 100"));
+    }
+
+    let path = config.policy.resolve(&images_dir, &filename)?;
+    let metadata = match std::fs::metadata(&path) {
+        Ok(m) if m.is_file() => m,
+        _ => return Err(ApiError::not_found("Image not found")),
+    };
+    ensure_decodable(&path)?;
+    let filename = paths::file_name(&path);
+
+    let variant = format!(
+        "{:?}-q{}{}",
+        query.format,
+        quality,
+        orientation_suffix(query.respect_orientation)
+    )
+    .to_lowercase();
+    let validators = Validators::for_file(&metadata, Some(&variant));
+    if validators.is_not_modified(&req) {
+        let mut response = HttpResponse::NotModified().finish();
+        validators.apply(response.headers_mut(), &config.cache_control());
+        return Ok(response);
+    }
+
+    let key = VariantKey {
+        filename,
+        width: None,
+        height: None,
+        fit: FitMode::default(),
+        respect_orientation: query.respect_orientation,
+        convert: Some((query.format, quality)),
+    };
+    let source_modified = metadata.modified().ok();
+    let (encoded, cost) = match variants.get(&key, source_modified) {
+        Some(cached) => (cached, None),
+        None => {
+            let mut cost = None;
+            let (format, respect_orientation) = (query.format, query.respect_orientation);
+            let encoded = variants
+                .render(key.clone(), || async {
+                    let (encoded, decoded) = web::block(move || {
+                        let (img, _) = ImageProcessor::open(&path, respect_orientation)?;
+                        anyhow::Ok((ImageProcessor::convert(&img, format, quality)?, TransformCost::of(img.width(), img.height())))
+                    })
+                    .await??;
+                    variants.insert(key.clone(), source_modified, encoded.clone());
+                    cost = Some(decoded);
+                    Ok(encoded)
+                })
+                .await
+                .with_context(|| format!("Failed to convert {}", key.filename))
+                .map_err(ApiError::Processing)?;
+            (encoded, cost)
+        }
+    };
+
+    let mut response = HttpResponse::Ok().content_type(encoded.content_type()).body(encoded.bytes);
+    validators.apply(response.headers_mut(), &config.cache_control());
+    if let Some(cost) = cost {
+        response.extensions_mut().insert(cost);
+    }
+    Ok(response)
+}
+
+/// Resizes and/or converts an image sent as the request body and returns
+/// the result, keeping nothing, for callers using the API as a stateless
+/// image processor.
+#[post("/transform")]
+pub async fn transform_upload(
+    mut payload: web::Payload,
+    query: web::Query<TransformQuery>,
+    config: web::Data<Config>,
+) -> Result<HttpResponse, ApiError> {
+    let valid = |side: Option<u32>| side.is_none_or(|side| (1..=MAX_DIMENSION).contains(&side));
+    if !valid(query.w) || !valid(query.h) {
+        return Err(ApiError::validation(format!("w and h must be between 1 and {}", MAX_DIMENSION)));
+    }
+    if query.quality.is_some_and(|quality| !(1..=100).contains(&quality)) {
+        return Err(ApiError::validation("quality must be between 1 and 100"));
+    }
+    let preset = lookup_preset(&config, query.preset.as_deref())?;
+    let ops = Operations::resolve(preset, query.w, query.h, query.fit, query.format, query.quality);
+
+    let mut body = web::BytesMut::new();
+    while let Some(chunk) = payload.next().await {
+        let chunk = chunk.map_err(|e| ApiError::validation(format!("Failed to read upload: {}", e)))?;
+        if body.len() + chunk.len() > config.transform_max_upload_bytes {
+            return Err(ApiError::TooLarge(format!(
+                "Uploads are limited to {} bytes",
+                config.transform_max_upload_bytes
+            )));
+        }
+        body.extend_from_slice(&chunk);
+    }
+    if body.is_empty() {
+        return Err(ApiError::validation("Send the image as the request body"));
+    }
+    if guess_format(&body).is_err() {
+        return Err(ApiError::Unsupported("Unrecognized image format".to_string()));
+    }
+
+    let respect_orientation = query.respect_orientation;
+    let (img, source_format) = web::block(move || ImageProcessor::decode(&body, respect_orientation))
+        .await?
+        .map_err(|e| ApiError::validation(format!("{:#}", e)))?;
+    let cost = TransformCost::of(img.width(), img.height());
+    let encoded = web::block(move || {
+        let resized = ImageProcessor::resize_image(&img, ops.width, ops.height, ops.fit);
+        match ops.convert {
+            Some((format, quality)) => ImageProcessor::convert(&resized, format, quality),
+            None => ImageProcessor::encode(&resized, source_format),
+        }
+    })
+    .await?
+    .context("Failed to transform upload")
+    .map_err(ApiError::Processing)?;
+
+    let mut response = HttpResponse::Ok()
+        .content_type(encoded.content_type())
+        .insert_header((actix_web::http::header::CACHE_CONTROL, "no-store"))
+        .body(encoded.bytes);
+    response.extensions_mut().insert(cost);
+    Ok(response)
+}
+
+#[get("/images/{filename}/download")]
+pub async fn download_image(
+    req: HttpRequest,
+    filename: web::Path<String>,
+    images_dir: web::Data<PathBuf>,
+    config: web::Data<Config>,
+    stats: web::Data<ViewStats>,
+) -> Result<HttpResponse, ApiError> {
+    let path = config.policy.resolve(&images_dir, &filename)?;
+    config.policy.ensure_originals()?;
+
+    let metadata = tokio::fs::metadata(&path)
+        .await
+        .map_err(|_| ApiError::not_found("Image not found"))?;
+    if !metadata.is_file() {
+        return Err(ApiError::not_found("Image not found"));
+    }
+    let filename = paths::file_name(&path);
+
+    // Resumed transfers fetch later ranges; only count the start of a download
+    if let RangeRequest::Full | RangeRequest::Partial(ByteRange { start: 0, .. }) =
+        range::from_request(&req, metadata.len())
+    {
+        stats.record_download(&filename);
+    }
+
+    let content_type = media_types::for_path(&path).map_or("application/octet-stream", |media| media.mime);
+    let mut response = serve_file(&req, &path, content_type, &config).await?;
+    if let Ok(value) = ContentDisposition::attachment(filename.as_str()).try_into_value() {
+        response.headers_mut().insert(header::CONTENT_DISPOSITION, value);
+    }
+    Ok(response)
+}
+
+#[derive(Deserialize)]
+pub struct SignedUrlQuery {
+    /// Seconds the URL stays valid, up to 7 days.
+    pub expires_in: Option<u64>,
+    /// Sign a link to the thumbnail of this size instead of the image.
+    pub thumbnail_size: Option<u32>,
+    pub w: Option<u32>,
+    pub h: Option<u32>,
+}
+
+#[derive(Serialize)]
+pub struct SignedUrlResponse {
+    pub url: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// An expiring link to the image, or to its thumbnail or a resized copy,
+/// that works without credentials where authentication is required; for
+/// embedding in emails or sharing.
+#[get("/images/{filename}/signed-url")]
+pub async fn signed_url(
+    filename: web::Path<String>,
+    query: web::Query<SignedUrlQuery>,
+    images_dir: web::Data<PathBuf>,
+    config: web::Data<Config>,
+    signer: web::Data<UrlSigner>,
+) -> Result<HttpResponse, ApiError> {
+    let expires_in = query.expires_in.unwrap_or(signing::DEFAULT_EXPIRES_SECS);
+    if !(1..=signing::MAX_EXPIRES_SECS).contains(&expires_in) {
+        return Err(ApiError::validation(format!(
+            "expires_in must be between 1 and {}",
+            signing::MAX_EXPIRES_SECS
+        )));
+    }
+    let path = config.policy.resolve(&images_dir, &filename)?;
+    if !path.is_file() {
+        return Err(ApiError::not_found("Image not found"));
+    }
+
+    let image_path = config.image_path(&paths::file_name(&path));
+    let target = match (query.thumbnail_size, query.w, query.h) {
+        (Some(size), _, _) => format!("{}/thumbnail?size={}", image_path, thumbnail_size(Some(size))?),
+        (None, None, None) => image_path,
+        (None, w, h) => {
+            let sides: Vec<String> = [("w", w), ("h", h)]
+                .into_iter()
+                .filter_map(|(name, side)| side.map(|side| format!("{}={}", name, side)))
+                .collect();
+            format!("{}?{}", image_path, sides.join("&"))
+        }
+    };
+    let expires_at = Utc::now() + chrono::Duration::seconds(expires_in as i64);
+    Ok(HttpResponse::Ok().json(SignedUrlResponse {
+        url: config.url(&signer.sign(&target, expires_at)),
+        expires_at,
+    }))
+}
+
+#[get("/images/{filename}/info")]
+pub async fn image_info(
+    filename: web::Path<String>,
+    images_dir: web::Data<PathBuf>,
+    config: web::Data<Config>,
+    stats: web::Data<ViewStats>,
+) -> Result<HttpResponse, ApiError> {
+    let path = config.policy.resolve(&images_dir, &filename)?;
+    
+    if !path.exists() {
+        return Err(ApiError::not_found("Image not found"));
+    }
+    let filename = paths::file_name(&path);
+
+    let metadata = std::fs::metadata(&path)
+        .context("Failed to read image metadata")
+        .map_err(ApiError::Storage)?;
+
+    let format = guess_format(&std::fs::read(&path).unwrap_or_default()).ok();
+    let dimensions = image::open(&path).ok().map(|img| img.dimensions());
+
+    let info = ImageInfo {
+        filename: filename.to_string(),
+        size_bytes: metadata.len(),
+        format: format.map(|f| format!("{:?}", f)),
+        dimensions,
+        stats: stats.get(&filename),
+        fields: fields::read(&path),
+        referenced_path: config.policy.is_referenced(&path).then_some(path),
+    };
+
+    Ok(HttpResponse::Ok().json(info))
+}
+
+/// Replaces an image's Finder tags with the JSON array in the body. Names are
+/// trimmed and deduplicated case-insensitively; `[]` clears them. `If-Match`
+/// or `If-Unmodified-Since` answer 412 when the image changed since it was read.
+#[put("/images/{filename}/tags")]
+pub async fn set_image_tags(
+    req: HttpRequest,
+    filename: web::Path<String>,
+    body: web::Json<Vec<String>>,
+    images_dir: web::Data<PathBuf>,
+    config: web::Data<Config>,
+    suggestions: web::Data<SuggestIndex>,
+    index: web::Data<LibraryIndex>,
+) -> Result<HttpResponse, ApiError> {
+    let mut names: Vec<String> = Vec::new();
+    for name in body.into_inner() {
+        let name = name.trim();
+        if name.is_empty() || name.contains('\n') {
+            return Err(ApiError::validation("Tags must be non-empty single-line strings"));
+        }
+        if !names.iter().any(|existing| existing.eq_ignore_ascii_case(name)) {
+            names.push(name.to_string());
+        }
+    }
+
+    // Extended attributes and the index entry are file I/O
+    let preconditions = Preconditions::from_request(&req);
+    let written = names.clone();
+    let (filename, previous, current) = web::block(move || -> Result<_, ApiError> {
+        let path = config.policy.resolve(&images_dir, &filename)?;
+        if !path.is_file() {
+            return Err(ApiError::not_found("Image not found"));
+        }
+        check_preconditions(&path, &preconditions)?;
+        let previous = tags::read(&path);
+        tags::write(&path, &written)
+            .context("Failed to write tags")
+            .map_err(ApiError::Storage)?;
+        let filename = paths::file_name(&path);
+        index.refresh_edited(&images_dir, &config.policy.referenced_dirs, &filename);
+        Ok((filename, previous, tags::read(&path)))
+    })
+    .await??;
+    suggestions.invalidate();
+
+    // Listings filtered by a tag the image lost or gained are stale too
+    let mut purge = Purge::image(&filename);
+    let mut tag_keys: Vec<String> = previous
+        .iter()
+        .map(|tag| tag.name.as_str())
+        .chain(names.iter().map(String::as_str))
+        .map(cdn::tag_key)
+        .collect();
+    tag_keys.sort();
+    tag_keys.dedup();
+    purge.0.extend(tag_keys);
+    let mut response = HttpResponse::Ok().json(TagsResponse { filename, tags: current });
+    response.extensions_mut().insert(purge);
+    Ok(response)
+}
+
+/// Refuses a write with 412 when `path` changed since the client read it.
+fn check_preconditions(path: &Path, preconditions: &Preconditions) -> Result<(), ApiError> {
+    let metadata = std::fs::metadata(path)
+        .context("Failed to read image metadata")
+        .map_err(ApiError::Storage)?;
+    if preconditions.failed(&Validators::for_file(&metadata, None)) {
+        return Err(ApiError::PreconditionFailed(format!(
+            "{} changed since it was read",
+            paths::file_name(path)
+        )));
+    }
+    Ok(())
+}
+
+/// Sets custom fields on an image from the JSON object in the body, checked
+/// against the schema. Fields not mentioned keep their value; `null` clears one.
+/// Preconditions are checked as for tags.
+#[put("/images/{filename}/fields")]
+pub async fn set_image_fields(
+    req: HttpRequest,
+    filename: web::Path<String>,
+    body: web::Json<BTreeMap<String, serde_json::Value>>,
+    images_dir: web::Data<PathBuf>,
+    config: web::Data<Config>,
+    registry: web::Data<FieldRegistry>,
+    index: web::Data<LibraryIndex>,
+) -> Result<HttpResponse, ApiError> {
+    let mut changes = Vec::new();
+    for (name, value) in body.into_inner() {
+        let value = match value {
+            serde_json::Value::Null => None,
+            value => Some(registry.check(&name, value).map_err(ApiError::Validation)?),
+        };
+        changes.push((name, value));
+    }
+
+    // Extended attributes and the index entry are file I/O
+    let preconditions = Preconditions::from_request(&req);
+    let (filename, values) = web::block(move || -> Result<_, ApiError> {
+        let path = config.policy.resolve(&images_dir, &filename)?;
+        if !path.is_file() {
+            return Err(ApiError::not_found("Image not found"));
+        }
+        check_preconditions(&path, &preconditions)?;
+        let mut values = fields::read(&path);
+        for (name, value) in changes {
+            match value {
+                Some(value) => values.insert(name, value),
+                None => values.remove(&name),
+            };
+        }
+        fields::write(&path, &values)
+            .context("Failed to write fields")
+            .map_err(ApiError::Storage)?;
+        let filename = paths::file_name(&path);
+        index.refresh_edited(&images_dir, &config.policy.referenced_dirs, &filename);
+        Ok((filename, values))
+    })
+    .await??;
+
+    let mut response = HttpResponse::Ok().json(FieldsResponse {
+        filename: filename.clone(),
+        fields: values,
+    });
+    response.extensions_mut().insert(Purge::image(&filename));
+    Ok(response)
+}
+
+/// Drops every cached trace of `filename` and brings its index entry in line
+/// with the disk, after the file was deleted, trashed or restored.
+async fn forget_image(
+    filename: &str,
+    images_dir: &web::Data<PathBuf>,
+    config: &web::Data<Config>,
+    images: &ImageCache,
+    variants: &VariantCache,
+    index: &web::Data<LibraryIndex>,
+) -> Result<(), ApiError> {
+    images.invalidate(filename);
+    variants.invalidate(filename);
+    let (filename, images_dir, config, index) = (filename.to_string(), images_dir.clone(), config.clone(), index.clone());
+    web::block(move || {
+        if let Err(e) = thumbnails::remove(&images_dir, &filename) {
+            log::warn!("Failed to remove thumbnails of {}: {}", filename, e);
+        }
+        index.refresh(&images_dir, &config.policy.referenced_dirs, &filename);
+    })
+    .await?;
+    Ok(())
+}
+
+/// Moves an image to the trash, or with `permanent=true&dry_run=false`
+/// removes it outright. A trashed file keeps its tags, custom fields and
+/// published flag. Takes a bearer token whenever authentication is set up.
+#[delete("/images/{filename}")]
+#[allow(clippy::too_many_arguments)]
+pub async fn delete_image(
+    req: HttpRequest,
+    filename: web::Path<String>,
+    query: web::Query<DeleteImageQuery>,
+    images_dir: web::Data<PathBuf>,
+    config: web::Data<Config>,
+    images: web::Data<ImageCache>,
+    variants: web::Data<VariantCache>,
+    index: web::Data<LibraryIndex>,
+) -> Result<HttpResponse, ApiError> {
+    auth::require_user(&req)?;
+    let path = config.policy.resolve(&images_dir, &filename)?;
+    if !path.is_file() {
+        return Err(ApiError::not_found("Image not found"));
+    }
+    if !path.starts_with(images_dir.as_path()) {
+        return Err(ApiError::Forbidden("Files in referenced folders cannot be deleted".to_string()));
+    }
+    let filename = paths::file_name(&path);
+
+    let dry_run = query.dry_run.unwrap_or(query.permanent);
+    if dry_run {
+        let status = if query.permanent { TrashStatus::Deleted } else { TrashStatus::Trashed };
+        return Ok(HttpResponse::Ok().json(TrashResponse {
+            filename,
+            status,
+            trashed_name: None,
+            dry_run,
+        }));
+    }
+
+    let (status, trashed_name) = if query.permanent {
+        web::block(move || std::fs::remove_file(&path))
+            .await?
+            .context("Failed to delete image")
+            .map_err(ApiError::Storage)?;
+        (TrashStatus::Deleted, None)
+    } else {
+        let trash_dir = trash::dir(&images_dir, config.trash_dir.as_deref());
+        let trashed = web::block(move || trash::put(&trash_dir, &path))
+            .await?
+            .context("Failed to move image to the trash")
+            .map_err(ApiError::Storage)?;
+        (TrashStatus::Trashed, Some(paths::file_name(&trashed)))
+    };
+    forget_image(&filename, &images_dir, &config, &images, &variants, &index).await?;
+
+    let purge = Purge::image(&filename);
+    let mut response = HttpResponse::Ok().json(TrashResponse {
+        filename,
+        status,
+        trashed_name,
+        dry_run,
+    });
+    response.extensions_mut().insert(purge);
+    Ok(response)
+}
+
+/// Files in the trash, most recently deleted first.
+#[get("/trash")]
+pub async fn trashed_images(
+    images_dir: web::Data<PathBuf>,
+    config: web::Data<Config>,
+) -> Result<HttpResponse, ApiError> {
+    let trash_dir = trash::dir(&images_dir, config.trash_dir.as_deref());
+    let images = web::block(move || trash::list(&trash_dir))
+        .await?
+        .context("Failed to read the trash")
+        .map_err(ApiError::Storage)?;
+    Ok(HttpResponse::Ok().json(TrashListResponse { images }))
+}
+
+/// Moves a trashed file back into the images directory under its old name,
+/// unless that name has been taken since. `{filename}` is a trashed name, or
+/// an original name for its latest deletion. Takes a bearer token whenever
+/// authentication is set up.
+#[post("/trash/{filename}/restore")]
+#[allow(clippy::too_many_arguments)]
+pub async fn restore_image(
+    req: HttpRequest,
+    filename: web::Path<String>,
+    images_dir: web::Data<PathBuf>,
+    config: web::Data<Config>,
+    images: web::Data<ImageCache>,
+    variants: web::Data<VariantCache>,
+    index: web::Data<LibraryIndex>,
+) -> Result<HttpResponse, ApiError> {
+    auth::require_user(&req)?;
+    let trash_dir = trash::dir(&images_dir, config.trash_dir.as_deref());
+    let trashed = trash::find(&trash_dir, &filename)
+        .context("Failed to read the trash")
+        .map_err(ApiError::Storage)?
+        .ok_or_else(|| ApiError::not_found("Not in the trash"))?;
+    let (filename, trashed) = (trashed.filename, trashed.path);
+    let restored = images_dir.join(&filename);
+    let taken = || ApiError::Conflict(format!("An image named {} already exists", filename));
+    if library::open_in(&images_dir, &config.policy.referenced_dirs, &filename).is_some() {
+        return Err(taken());
+    }
+
+    // A file created under the name since the check above is never replaced
+    match web::block(move || trash::move_file(&trashed, &restored)).await? {
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => return Err(taken()),
+        result => result.context("Failed to restore image").map_err(ApiError::Storage)?,
+    }
+    forget_image(&filename, &images_dir, &config, &images, &variants, &index).await?;
+
+    let purge = Purge::image(&filename);
+    let mut response = HttpResponse::Ok().json(TrashResponse {
+        filename,
+        status: TrashStatus::Restored,
+        trashed_name: None,
+        dry_run: false,
+    });
+    response.extensions_mut().insert(purge);
+    Ok(response)
+}
+
+/// Checks a client's cached ETags in bulk. The body maps file names to the
+/// ETag the client holds for the image or any rendition of it (thumbnail,
+/// resize, conversion); entries still current are omitted from the response.
+/// Names not found while a volume is offline are reported as unavailable
+/// rather than missing, so clients keep what they cached from it.
+#[post("/validate")]
+pub async fn validate_etags(
+    body: web::Json<BTreeMap<String, String>>,
+    images_dir: web::Data<PathBuf>,
+    config: web::Data<Config>,
+) -> Result<HttpResponse, ApiError> {
+    let entries = body.into_inner();
+    if entries.len() > MAX_VALIDATE_ENTRIES {
+        return Err(ApiError::validation(format!(
+            "At most {} entries can be validated per request",
+            MAX_VALIDATE_ENTRIES
+        )));
+    }
+
+    // Up to one stat per entry, so the loop runs off the executor
+    let response = web::block(move || {
+        let mut response = ValidateResponse { stale: Vec::new(), missing: Vec::new(), unavailable: Vec::new() };
+        for (filename, etag) in entries {
+            let path = match config.policy.resolve(&images_dir, &filename) {
+                Ok(path) => path,
+                Err(ApiError::VolumeOffline(_)) => {
+                    response.unavailable.push(filename);
+                    continue;
+                }
+                Err(_) => {
+                    response.missing.push(filename);
+                    continue;
+                }
+            };
+            match std::fs::metadata(path).ok().filter(|metadata| metadata.is_file()) {
+                Some(metadata) if conditional::is_current(&etag, &metadata) => {}
+                Some(_) => response.stale.push(filename),
+                None => response.missing.push(filename),
+            }
+        }
+        response
+    })
+    .await?;
+
+    Ok(HttpResponse::Ok().json(response))
+}
+
+#[get("/memories")]
+pub async fn memories(
+    query: web::Query<MemoriesQuery>,
+    images_dir: web::Data<PathBuf>,
+    config: web::Data<Config>,
+) -> Result<HttpResponse, ApiError> {
+    let date = match &query.date {
+        Some(raw) => NaiveDate::parse_from_str(raw, "%Y-%m-%d")
+            .map_err(|_| ApiError::validation("Invalid date, expected YYYY-MM-DD"))?,
+        None => Utc::now().date_naive(),
+    };
+
+    // Reading every file's EXIF blocks, so the scan runs off the executor
+    let (images_dir, scan_config) = (images_dir.clone(), config.clone());
+    let by_year = web::block(move || -> Result<_, ApiError> {
+        let images = scan_config.policy.scan(&images_dir)
+            .context("Failed to read images directory")
+            .map_err(ApiError::Storage)?;
+
+        // Same calendar day in earlier years, newest year first
+        let mut by_year: BTreeMap<i32, Vec<MemoryImage>> = BTreeMap::new();
+        for stack in library::stack(images) {
+            let Some(taken_at) = stack
+                .renditions
+                .iter()
+                .find_map(|rendition| metadata::capture_date(&rendition.image.path))
+            else {
+                continue;
+            };
+            if taken_at.month() == date.month()
+                && taken_at.day() == date.day()
+                && taken_at.year() < date.year()
+            {
+                by_year.entry(taken_at.year()).or_default().push(MemoryImage {
+                    filename: stack.primary().filename.clone(),
+                    url: scan_config.image_url(&stack.primary().filename),
+                    taken_at,
+                    renditions: rendition_infos(&stack, &scan_config),
+                });
+            }
+        }
+        Ok(by_year)
+    })
+    .await??;
+
+    let years = by_year
+        .into_iter()
+        .rev()
+        .map(|(year, mut images)| {
+            images.sort_by_key(|image| image.taken_at);
+            MemoryYear {
+                year,
+                years_ago: date.year() - year,
+                images,
+            }
+        })
+        .collect();
+
+    Ok(HttpResponse::Ok().json(MemoriesResponse { date, years }))
+}
+
+/// Additions and views per day of one year, for a calendar heatmap. An item
+/// counts as added on the day its primary file was last modified; views are
+/// counted since the server started.
+#[get("/stats/activity")]
+pub async fn library_activity(
+    query: web::Query<ActivityQuery>,
+    images_dir: web::Data<PathBuf>,
+    config: web::Data<Config>,
+    stats: web::Data<ViewStats>,
+) -> Result<HttpResponse, ApiError> {
+    let year = query.year.unwrap_or_else(|| Utc::now().year());
+    if NaiveDate::from_yo_opt(year, 1).is_none() {
+        return Err(ApiError::validation("Invalid year"));
+    }
+
+    let images = web::block(move || config.policy.scan(&images_dir))
+        .await?
+        .context("Failed to read images directory")
+        .map_err(ApiError::Storage)?;
+
+    let mut days: BTreeMap<NaiveDate, (u64, u64)> = BTreeMap::new();
+    for stack in library::stack(images) {
+        if let Some(added) = stack.primary().modified.map(|modified| modified.date_naive()) {
+            if added.year() == year {
+                days.entry(added).or_default().0 += 1;
+            }
+        }
+    }
+    for (day, views) in stats.views_by_day(year) {
+        days.entry(day).or_default().1 += views;
+    }
+
+    let days: Vec<ActivityDay> = days
+        .into_iter()
+        .map(|(date, (additions, views))| ActivityDay { date, additions, views })
+        .collect();
+    Ok(HttpResponse::Ok().json(ActivityResponse {
+        year,
+        total_additions: days.iter().map(|day| day.additions).sum(),
+        total_views: days.iter().map(|day| day.views).sum(),
+        days,
+    }))
+}
+
+/// Files worth a look when cleaning up the library: the largest, the oldest,
+/// those never viewed, untagged or without an EXIF date, as chosen by `report`.
+#[get("/reports/housekeeping")]
+pub async fn housekeeping_report(
+    query: web::Query<HousekeepingQuery>,
+    images_dir: web::Data<PathBuf>,
+    config: web::Data<Config>,
+    stats: web::Data<ViewStats>,
+) -> Result<HttpResponse, ApiError> {
+    let (page, page_size) = page_bounds(query.page, query.limit, DEFAULT_PAGE_SIZE, MAX_PAGE_SIZE)?;
+    let (report, views, scan_config) = (query.report, stats.clone(), config.clone());
+    let selected = web::block(move || -> Result<_, ApiError> {
+        let images = scan_config.policy.scan(&images_dir)
+            .context("Failed to read images directory")
+            .map_err(ApiError::Storage)?;
+        Ok(report.select(images, |filename| views.get(filename).views))
+    })
+    .await??;
+
+    let total = selected.len();
+    let items = selected
+        .into_iter()
+        .skip((page - 1).saturating_mul(page_size))
+        .take(page_size)
+        .map(|image| HousekeepingItem {
+            url: config.image_url(&image.filename),
+            views: stats.get(&image.filename).views,
+            filename: image.filename,
+            size_bytes: image.size_bytes,
+            modified: image.modified,
+        })
+        .collect();
+
+    Ok(HttpResponse::Ok().json(HousekeepingResponse {
+        items,
+        page,
+        page_size,
+        total,
+        total_pages: total.div_ceil(page_size),
+    }))
+}
+
+#[get("/suggest")]
+pub async fn suggest(
+    query: web::Query<SuggestQuery>,
+    images_dir: web::Data<PathBuf>,
+    index: web::Data<SuggestIndex>,
+    config: web::Data<Config>,
+) -> Result<HttpResponse, ApiError> {
+    let q = query.q.trim();
+    if q.is_empty() {
+        return Err(ApiError::validation("Query parameter q must not be empty"));
+    }
+    let limit = query.limit.unwrap_or(10).clamp(1, 50);
+
+    // Built from what the policy lets through, so nothing is dropped after the
+    // limit. A rebuild reads every file's tags, so it runs off the executor.
+    let policy_config = config.clone();
+    let index = web::block(move || index.get(&images_dir, &policy_config.policy))
+        .await?
+        .context("Failed to read images directory")
+        .map_err(ApiError::Storage)?;
+
+    let suggestions = index
+        .lookup(q, limit)
+        .into_iter()
+        .map(|mut suggestion| {
+            suggestion.url = Some(match suggestion.kind {
+                SuggestionKind::Filename => config.image_url(&suggestion.value),
+                SuggestionKind::Tag => config.tag_url(&suggestion.value),
+            });
+            suggestion
+        })
+        .collect();
+
+    Ok(HttpResponse::Ok().json(SuggestResponse {
+        query: q.to_string(),
+        suggestions,
+    }))
+}
+
+#[get("/admin/schema")]
+pub async fn field_schema(registry: web::Data<FieldRegistry>) -> impl Responder {
+    HttpResponse::Ok().json(registry.schema())
+}
+
+/// Replaces the custom field schema until the next restart. Values already
+/// stored on files are left alone.
+#[put("/admin/schema")]
+pub async fn set_field_schema(
+    body: web::Json<fields::Schema>,
+    registry: web::Data<FieldRegistry>,
+) -> Result<HttpResponse, ApiError> {
+    registry.replace(body.into_inner()).map_err(ApiError::Validation)?;
+    Ok(HttpResponse::Ok().json(registry.schema()))
+}
+
+#[get("/admin/maintenance")]
+pub async fn maintenance_status(state: web::Data<MaintenanceState>) -> impl Responder {
+    HttpResponse::Ok().json(state.status())
+}
+
+#[put("/admin/maintenance")]
+pub async fn set_maintenance(
+    body: web::Json<MaintenanceRequest>,
+    state: web::Data<MaintenanceState>,
+) -> impl Responder {
+    let body = body.into_inner();
+    if body.enabled {
+        log::warn!("Maintenance mode enabled: {:?}", body.message);
+        state.enable(body.message, body.retry_after_secs);
+    } else {
+        log::info!("Maintenance mode disabled");
+        state.disable();
+    }
+    HttpResponse::Ok().json(state.status())
+}
+
+/// Renames the images directory's files after a template, dated by EXIF
+/// capture date or modification time. A dry run (the default) only reports
+/// the plan. Renaming keeps tags, custom fields and timestamps, which belong
+/// to the file; cached renditions and index entries follow the new name.
+/// Takes a bearer token whenever authentication is set up. Preconditions are
+/// checked against every file to be renamed before any is, so `If-Match` lists
+/// the ETag of each.
+#[post("/admin/rename-rules/apply")]
+#[allow(clippy::too_many_arguments)]
+pub async fn apply_rename_rules(
+    req: HttpRequest,
+    body: web::Json<RenameRulesRequest>,
+    images_dir: web::Data<PathBuf>,
+    config: web::Data<Config>,
+    images: web::Data<ImageCache>,
+    variants: web::Data<VariantCache>,
+    index: web::Data<LibraryIndex>,
+    stats: web::Data<ViewStats>,
+) -> Result<HttpResponse, ApiError> {
+    auth::require_user(&req)?;
+    let template = rename::Template::parse(&body.template).map_err(ApiError::Validation)?;
+    let dry_run = body.dry_run;
+
+    // Scanning, renaming and refreshing the index are all file I/O
+    let preconditions = Preconditions::from_request(&req);
+    let plan = web::block(move || -> Result<_, ApiError> {
+        let mut files = library::scan(&images_dir)
+            .context("Failed to read images directory")
+            .map_err(ApiError::Storage)?;
+        files.retain(|image| config.policy.permits(&image.path));
+        let mut plan = rename::plan(&library::stack(files), &template);
+        if dry_run {
+            return Ok(plan);
+        }
+        // All or nothing: one changed file refuses the whole batch
+        for rename in &plan.renames {
+            check_preconditions(&images_dir.join(&rename.from), &preconditions)?;
+        }
+
+        let mut renamed = Vec::new();
+        for rename in std::mem::take(&mut plan.renames) {
+            let (from, to) = (images_dir.join(&rename.from), images_dir.join(&rename.to));
+            // Never replaces a file already at the new name
+            if let Err(e) = trash::move_file(&from, &to) {
+                log::warn!("Failed to rename {} to {}: {}", rename.from, rename.to, e);
+                plan.skipped.push(rename::Skipped { filename: rename.from, reason: e.to_string() });
+                continue;
+            }
+            images.invalidate(&rename.from);
+            variants.invalidate(&rename.from);
+            if let Err(e) = thumbnails::remove(&images_dir, &rename.from) {
+                log::warn!("Failed to remove thumbnails of {}: {}", rename.from, e);
+            }
+            stats.rename(&rename.from, &rename.to);
+            for filename in [&rename.from, &rename.to] {
+                index.refresh(&images_dir, &config.policy.referenced_dirs, filename);
+            }
+            renamed.push(rename);
+        }
+        plan.renames = renamed;
+        Ok(plan)
+    })
+    .await??;
+
+    let mut purge = Purge::default();
+    if !dry_run && !plan.renames.is_empty() {
+        purge.0.push(cdn::GALLERY_KEY.to_string());
+        for rename in &plan.renames {
+            purge.0.extend([cdn::image_key(&rename.from), cdn::image_key(&rename.to)]);
+        }
+    }
+    let mut response = HttpResponse::Ok().json(RenameRulesResponse { dry_run, plan });
+    response.extensions_mut().insert(purge);
+    Ok(response)
+}
+
+#[post("/admin/reindex")]
+pub async fn start_reindex(
+    index: web::Data<LibraryIndex>,
+    images_dir: web::Data<PathBuf>,
+    config: web::Data<Config>,
+) -> impl Responder {
+    if !indexer::spawn(
+        index.clone().into_inner(),
+        images_dir.to_path_buf(),
+        config.policy.referenced_dirs.clone(),
+    ) {
+        log::info!("Reindex requested while one is already running");
+    }
+    HttpResponse::Accepted().json(index.progress())
+}
+
+#[get("/admin/reindex")]
+pub async fn reindex_status(index: web::Data<LibraryIndex>) -> impl Responder {
+    HttpResponse::Ok().json(index.progress())
+}
+
+#[get("/gallery/images")]
+pub async fn list_images(
+    query: web::Query<ListImagesQuery>,
+    images_dir: web::Data<PathBuf>,
+    config: web::Data<Config>,
+    prefetcher: web::Data<ThumbnailPrefetcher>,
+) -> Result<HttpResponse, ApiError> {
+    let (page, page_size) = page_bounds(query.page, query.limit, DEFAULT_PAGE_SIZE, MAX_PAGE_SIZE)?;
+
+    let stacks = gallery_stacks(&config, &images_dir, query.sort, query.order, query.tag.as_deref(), query.media_type, query.field.as_deref()).await?;
+
+    let total = stacks.len();
+    let mut rest = stacks.into_iter().skip((page - 1).saturating_mul(page_size));
+    let stacks: Vec<library::ImageStack> = rest.by_ref().take(page_size).collect();
+    // Get the next page's thumbnails ready while this one is being viewed
+    if config.prefetch_thumbnails {
+        let next: Vec<PathBuf> = rest.take(page_size).map(|stack| stack.primary().path.clone()).collect();
+        prefetcher.enqueue(&next, thumbnails::DEFAULT_SIZE);
+        if query.include_thumbnail {
+            prefetcher.enqueue(&next, thumbnails::INLINE_SIZE);
+        }
+    }
+    let mut images: Vec<GalleryImage> = stacks.iter().map(|stack| gallery_image(stack, &config)).collect();
+
+    if query.include_thumbnail {
+        let dir = images_dir.get_ref().clone();
+        let sources: Vec<PathBuf> = stacks.iter().map(|stack| stack.primary().path.clone()).collect();
+        let thumbnails = web::block(move || {
+            sources
+                .iter()
+                .map(|source| inline_thumbnail(&dir, source))
+                .collect::<Vec<_>>()
+        })
+        .await?;
+        for (image, thumbnail) in images.iter_mut().zip(thumbnails) {
+            image.thumbnail = thumbnail;
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(PaginatedImageResponse {
+        images,
+        page,
+        page_size,
+        total,
+        total_pages: total.div_ceil(page_size),
+    }))
+}
+
+/// Free-text and fielded search, answered in the `/gallery/images` envelope.
+#[get("/gallery/search")]
+pub async fn search_images(
+    query: web::Query<SearchQuery>,
+    images_dir: web::Data<PathBuf>,
+    config: web::Data<Config>,
+) -> Result<HttpResponse, ApiError> {
+    let (page, page_size) = page_bounds(query.page, query.limit, DEFAULT_PAGE_SIZE, MAX_PAGE_SIZE)?;
+    let bound = |raw: &Option<String>, name: &str| {
+        raw.as_deref()
+            .map(|raw| {
+                search::parse_bound(raw)
+                    .ok_or_else(|| ApiError::validation(format!("{} must be RFC 3339 or YYYY-MM-DD", name)))
+            })
+            .transpose()
+    };
+    let (after, before) = (bound(&query.after, "after")?, bound(&query.before, "before")?);
+    let terms = search::Terms::parse(query.q.as_deref().unwrap_or_default());
+
+    let mut stacks = gallery_stacks(&config, &images_dir, query.sort, query.order, query.tag.as_deref(), query.media_type, None).await?;
+    stacks.retain(|stack| {
+        let primary = stack.primary();
+        let in_range = |bound: Option<DateTime<Utc>>, keep: fn(&DateTime<Utc>, &DateTime<Utc>) -> bool| {
+            bound.is_none_or(|bound| primary.modified.is_some_and(|modified| keep(&modified, &bound)))
+        };
+        query.min_size.is_none_or(|min| primary.size_bytes >= min)
+            && query.max_size.is_none_or(|max| primary.size_bytes <= max)
+            && in_range(after, |modified, bound| modified >= bound)
+            && in_range(before, |modified, bound| modified < bound)
+            && terms.matches_stack(stack)
+    });
+
+    let total = stacks.len();
+    let images = stacks
+        .iter()
+        .skip((page - 1).saturating_mul(page_size))
+        .take(page_size)
+        .map(|stack| gallery_image(stack, &config))
+        .collect();
+    Ok(HttpResponse::Ok().json(PaginatedImageResponse {
+        images,
+        page,
+        page_size,
+        total,
+        total_pages: total.div_ceil(page_size),
+    }))
+}
+
+/// The whole filtered, sorted gallery as newline-delimited JSON, one
+/// [`GalleryImage`] per line, serialized as the client reads rather than
+/// buffered into one array.
+#[get("/gallery/images/stream")]
+pub async fn stream_images(
+    query: web::Query<StreamImagesQuery>,
+    images_dir: web::Data<PathBuf>,
+    config: web::Data<Config>,
+) -> Result<HttpResponse, ApiError> {
+    let stacks = gallery_stacks(&config, &images_dir, query.sort, query.order, query.tag.as_deref(), query.media_type, query.field.as_deref()).await?;
+
+    let lines = futures_util::stream::iter(stacks).map(move |stack| {
+        let mut line = serde_json::to_vec(&gallery_image(&stack, &config))
+            .context("Failed to serialize gallery image")
+            .map_err(ApiError::Processing)?;
+        line.push(b'\n');
+        Ok::<_, actix_web::Error>(Bytes::from(line))
+    });
+
+    Ok(HttpResponse::Ok()
+        .content_type("application/x-ndjson")
+        .streaming(lines))
+}
+
+/// Primary files on one gallery page, as the sprite endpoints see it.
+async fn sprite_page(
+    query: &SpriteQuery,
+    config: &web::Data<Config>,
+    images_dir: &web::Data<PathBuf>,
+) -> Result<(Vec<PathBuf>, u32), ApiError> {
+    let size = query.size.unwrap_or(sprite::DEFAULT_SIZE);
+    if !sprite::SIZES.contains(&size) {
+        return Err(ApiError::validation(format!("size must be one of {:?}", sprite::SIZES)));
+    }
+    let (page, page_size) = page_bounds(query.page, query.limit, DEFAULT_PAGE_SIZE, sprite::MAX_TILES)?;
+
+    let sources = gallery_stacks(config, images_dir, query.sort, query.order, query.tag.as_deref(), query.media_type, query.field.as_deref())
+        .await?
+        .into_iter()
+        .skip((page - 1).saturating_mul(page_size))
+        .take(page_size)
+        .map(|stack| stack.primary().path.clone())
+        .collect();
+    Ok((sources, size))
+}
+
+/// Tile coordinates for one gallery page's thumbnails, plus the URL of the
+/// sprite they refer to. Takes the same parameters as `/gallery/images`, with
+/// `limit` capped at 100; images that cannot be thumbnailed get no tile.
+#[get("/gallery/images/sprite")]
+pub async fn gallery_sprite_layout(
+    req: HttpRequest,
+    query: web::Query<SpriteQuery>,
+    images_dir: web::Data<PathBuf>,
+    config: web::Data<Config>,
+) -> Result<HttpResponse, ApiError> {
+    let (sources, size) = sprite_page(&query, &config, &images_dir).await?;
+    let dir = images_dir.get_ref().clone();
+    let layout = web::block(move || sprite::layout(&dir, &sources, size)).await?;
+
+    Ok(HttpResponse::Ok().json(SpriteResponse {
+        url: config.url(&format!("/gallery/images/sprite.jpg?{}", req.query_string())),
+        layout,
+    }))
+}
+
+#[get("/gallery/images/sprite.jpg")]
+pub async fn gallery_sprite(
+    query: web::Query<SpriteQuery>,
+    images_dir: web::Data<PathBuf>,
+    config: web::Data<Config>,
+) -> Result<HttpResponse, ApiError> {
+    let (sources, size) = sprite_page(&query, &config, &images_dir).await?;
+    let dir = images_dir.get_ref().clone();
+    let (_, sprite) = web::block(move || sprite::render(&dir, &sources, size))
+        .await?
+        .map_err(ApiError::Processing)?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(sprite.content_type())
+        .body(sprite.bytes))
+}
+
+#[derive(Deserialize)]
+pub struct UsageQuery {
+    pub period: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct UsageResponse {
+    pub period: String,
+    #[serde(flatten)]
+    pub report: UsageReport,
+}
+
+/// Requests, bytes served and transform time per API key over `period`
+/// (`1h` to `7d`, default `24h`), busiest first.
+#[get("/admin/usage")]
+pub async fn usage_report(
+    query: web::Query<UsageQuery>,
+    tracker: web::Data<UsageTracker>,
+) -> Result<HttpResponse, ApiError> {
+    let period = query.period.clone().unwrap_or_else(|| "24h".to_string());
+    let hours = usage::parse_period(&period)
+        .ok_or_else(|| ApiError::validation("period must be a number of hours or days, such as 6h or 7d, up to 7d"))?;
+    Ok(HttpResponse::Ok().json(UsageResponse {
+        period,
+        report: tracker.report(hours, Utc::now()),
+    }))
+}
+
+#[derive(Deserialize)]
+pub struct ManifestQuery {
+    #[serde(default)]
+    pub format: ManifestFormat,
+}
+
+/// Filename, size, modification time and SHA-256 of every library file, for
+/// audits and verifying backups. Files are hashed as the client reads, so a
+/// large library starts streaming at once; a file deleted meanwhile is left out.
+#[get("/admin/manifest")]
+pub async fn library_manifest(
+    query: web::Query<ManifestQuery>,
+    images_dir: web::Data<PathBuf>,
+    config: web::Data<Config>,
+) -> Result<HttpResponse, ApiError> {
+    let images = web::block(move || config.policy.scan(&images_dir))
+        .await?
+        .context("Failed to read images directory")
+        .map_err(ApiError::Storage)?;
+    let format = query.format;
+
+    let header = futures_util::stream::iter((format == ManifestFormat::Csv).then(|| manifest::CSV_HEADER.into()));
+    let lines = futures_util::stream::iter(images)
+        .then(|image| web::block(move || ManifestEntry::read(&image).map_err(|e| (image.filename, e))))
+        .filter_map(move |entry| async move {
+            let entry = match entry {
+                Ok(Ok(entry)) => entry,
+                Ok(Err((filename, e))) => {
+                    log::warn!("Left {} out of the manifest: {}", filename, e);
+                    return None;
+                }
+                Err(e) => return Some(Err(ApiError::from(e).into())),
+            };
+            Some(match format {
+                ManifestFormat::Csv => Ok(Bytes::from(entry.csv_line())),
+                ManifestFormat::Ndjson => serde_json::to_vec(&entry)
+                    .map(|mut line| {
+                        line.push(b'\n');
+                        Bytes::from(line)
+                    })
+                    .context("Failed to serialize manifest entry")
+                    .map_err(|e| ApiError::Processing(e).into()),
+            })
+        });
+
+    Ok(HttpResponse::Ok()
+        .content_type(match format {
+            ManifestFormat::Csv => "text/csv; charset=utf-8",
+            ManifestFormat::Ndjson => "application/x-ndjson",
+        })
+        .streaming(header.map(Ok::<_, actix_web::Error>).chain(lines)))
+}
+
+#[derive(Deserialize)]
+pub struct BackupChangesQuery {
+    /// RFC 3339 time, normally the `next_since` of the previous call.
+    pub since: Option<DateTime<Utc>>,
+}
+
+/// A file as the backup last saw it; other fields, such as a manifest's
+/// `sha256` or a listing's `download_url`, are ignored.
+#[derive(Deserialize)]
+pub struct BackedUpFile {
+    pub filename: String,
+    pub size_bytes: u64,
+    pub modified: Option<DateTime<Utc>>,
+}
+
+#[derive(Deserialize)]
+pub struct BackedUpFiles {
+    pub files: Vec<BackedUpFile>,
+}
+
+#[derive(Serialize)]
+pub struct ChangedFile {
+    pub filename: String,
+    pub size_bytes: u64,
+    pub modified: Option<DateTime<Utc>>,
+    pub download_url: String,
+}
+
+#[derive(Serialize)]
+pub struct BackupChangesResponse {
+    pub since: Option<DateTime<Utc>>,
+    /// Pass as `since` next time. Taken before the scan, so a file changed
+    /// while the listing is built shows up again rather than being missed.
+    pub next_since: DateTime<Utc>,
+    /// Oldest change first.
+    pub files: Vec<ChangedFile>,
+    /// Files the backup has that are gone; only when it sent its file list.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub removed: Option<Vec<String>>,
+}
+
+/// When the file's contents or its directory entry last changed. A file
+/// copied or moved in with its modification time preserved still gets a
+/// fresh status-change time.
+#[cfg(unix)]
+fn changed_at(image: &library::LibraryImage) -> Option<DateTime<Utc>> {
+    use std::os::unix::fs::MetadataExt;
+    let metadata = image.path.symlink_metadata().ok()?;
+    let changed = DateTime::from_timestamp(metadata.ctime(), metadata.ctime_nsec() as u32);
+    image.modified.max(changed)
+}
+
+#[cfg(not(unix))]
+fn changed_at(image: &library::LibraryImage) -> Option<DateTime<Utc>> {
+    image.modified
+}
+
+fn changed_files(mut images: Vec<library::LibraryImage>, config: &Config) -> Vec<ChangedFile> {
+    images.sort_by(|a, b| a.modified.cmp(&b.modified).then_with(|| a.filename.cmp(&b.filename)));
+    images
+        .into_iter()
+        .map(|image| ChangedFile {
+            download_url: format!("{}/download", config.image_url(&image.filename)),
+            filename: image.filename,
+            size_bytes: image.size_bytes,
+            modified: image.modified,
+        })
+        .collect()
+}
+
+/// Files modified, or copied or moved in, at or after `since` (every file
+/// without it), for incremental backups that then fetch each through its
+/// download URL. Deletions are not reported; POST the backup's file list to
+/// learn about those.
+#[get("/admin/backup/changed")]
+pub async fn backup_changes(
+    query: web::Query<BackupChangesQuery>,
+    images_dir: web::Data<PathBuf>,
+    config: web::Data<Config>,
+) -> Result<HttpResponse, ApiError> {
+    let next_since = Utc::now();
+    // The scan and each file's status-change time are file I/O
+    let (since, scan_config) = (query.since, config.clone());
+    let images = web::block(move || -> Result<_, ApiError> {
+        let mut images = scan_config
+            .policy
+            .scan(&images_dir)
+            .context("Failed to read images directory")
+            .map_err(ApiError::Storage)?;
+        if let Some(since) = since {
+            images.retain(|image| changed_at(image).is_none_or(|changed| changed >= since));
+        }
+        Ok(images)
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(BackupChangesResponse {
+        since: query.since,
+        next_since,
+        files: changed_files(images, &config),
+        removed: None,
+    }))
+}
+
+/// The difference between the files a backup holds and the library: files
+/// it lacks or holds with another size or modification time, and names it
+/// holds that are gone from the library.
+#[post("/admin/backup/changed")]
+pub async fn backup_diff(
+    body: web::Json<BackedUpFiles>,
+    images_dir: web::Data<PathBuf>,
+    config: web::Data<Config>,
+) -> Result<HttpResponse, ApiError> {
+    let next_since = Utc::now();
+    let scan_config = config.clone();
+    let images = web::block(move || scan_config.policy.scan(&images_dir))
+        .await?
+        .context("Failed to read images directory")
+        .map_err(ApiError::Storage)?;
+
+    let mut backed_up: BTreeMap<String, (u64, Option<DateTime<Utc>>)> = body
+        .into_inner()
+        .files
+        .into_iter()
+        .map(|file| (file.filename, (file.size_bytes, file.modified)))
+        .collect();
+    let changed = images
+        .into_iter()
+        .filter(|image| backed_up.remove(&image.filename) != Some((image.size_bytes, image.modified)))
+        .collect();
+
+    Ok(HttpResponse::Ok().json(BackupChangesResponse {
+        since: None,
+        next_since,
+        files: changed_files(changed, &config),
+        removed: Some(backed_up.into_keys().collect()),
+    }))
+}
+
+#[get("/admin/cache")]
+pub async fn cache_stats(
+    images: web::Data<ImageCache>,
+    variants: web::Data<VariantCache>,
+) -> impl Responder {
+    HttpResponse::Ok().json(CacheStatsResponse {
+        images: images.stats(),
+        variants: variants.stats(),
+    })
+}
+
+/// One document for the ops page: index freshness, recent server errors,
+/// cache hit rates and disk headroom, each scored and rolled into one score.
+#[get("/admin/dashboard")]
+pub async fn admin_dashboard(
+    images_dir: web::Data<PathBuf>,
+    config: web::Data<Config>,
+    images: web::Data<ImageCache>,
+    variants: web::Data<VariantCache>,
+    index: web::Data<LibraryIndex>,
+    usage: web::Data<UsageTracker>,
+) -> Result<HttpResponse, ApiError> {
+    let dir = images_dir.get_ref().clone();
+    let min_free_bytes = config.min_free_disk_bytes;
+    let disk = web::block(move || health::check_disk(&dir, min_free_bytes)).await?;
+    let now = Utc::now();
+    Ok(HttpResponse::Ok().json(Dashboard::new(
+        index.progress(),
+        index.images().len(),
+        config.watch_images_dir,
+        usage.total(dashboard::ERROR_PERIOD_HOURS, now),
+        (images.stats(), variants.stats()),
+        disk,
+        now,
+    )))
+}
+
+/// Dumps what the server knows about one file, for debugging: its stat,
+/// parsed and raw metadata, index entry and cached renditions.
+#[get("/admin/inspect/{filename}")]
+#[allow(clippy::too_many_arguments)]
+pub async fn inspect_file(
+    filename: web::Path<String>,
+    images_dir: web::Data<PathBuf>,
+    config: web::Data<Config>,
+    stats: web::Data<ViewStats>,
+    images: web::Data<ImageCache>,
+    variants: web::Data<VariantCache>,
+    index: web::Data<LibraryIndex>,
+    transcodes: web::Data<TranscodeCache>,
+) -> Result<HttpResponse, ApiError> {
+    let path = config.policy.resolve(&images_dir, &filename)?;
+    if !path.is_file() {
+        return Err(ApiError::not_found("File not found"));
+    }
+    let filename = paths::file_name(&path);
+    let stat = std::fs::metadata(&path)
+        .context("Failed to read file metadata")
+        .map_err(ApiError::Storage)?;
+    let time = |time: std::io::Result<SystemTime>| time.ok().map(DateTime::<Utc>::from);
+
+    let source = path.clone();
+    let (metadata, xattrs) = web::block(move || {
+        let decodable = media_types::for_path(&source).is_some_and(|media| media.decodable);
+        let metadata = InspectedMetadata {
+            dimensions: decodable.then(|| image::image_dimensions(&source).ok()).flatten(),
+            orientation: metadata::orientation(&source),
+            capture_date: metadata::capture_date(&source),
+            camera: metadata::camera(&source),
+            tags: tags::read(&source),
+            fields: fields::read(&source),
+            published: publish::is_published(&source),
+        };
+        let xattrs = xattr::list(&source)
+            .map(|names| {
+                names
+                    .filter_map(|name| {
+                        let value = xattr::get(&source, &name).ok()??;
+                        let value = String::from_utf8(value).unwrap_or_else(|e| {
+                            format!("base64:{}", base64::Engine::encode(&base64::engine::general_purpose::STANDARD, e.as_bytes()))
+                        });
+                        Some((name.to_string_lossy().into_owned(), value))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        (metadata, xattrs)
+    })
+    .await?;
+
+    let mut cached_variants: Vec<CachedVariant> = variants
+        .cached(&filename)
+        .into_iter()
+        .map(|(key, bytes)| CachedVariant {
+            width: key.width,
+            height: key.height,
+            fit: key.fit,
+            respect_orientation: key.respect_orientation,
+            format: key.convert.map(|(format, _)| format),
+            quality: key.convert.map(|(_, quality)| quality),
+            bytes,
+        })
+        .collect();
+    cached_variants.sort_by_key(|variant| (variant.width, variant.height, variant.bytes));
+
+    Ok(HttpResponse::Ok().json(InspectResponse {
+        filename: filename.clone(),
+        referenced: config.policy.is_referenced(&path),
+        stat: FileStat {
+            size_bytes: stat.len(),
+            modified: time(stat.modified()),
+            created: time(stat.created()),
+            accessed: time(stat.accessed()),
+            readonly: stat.permissions().readonly(),
+        },
+        mime: media_types::for_path(&path).map(|media| media.mime),
+        metadata,
+        xattrs,
+        index: index.get(&filename),
+        stats: stats.get(&filename),
+        cache: InspectedCaches {
+            original_bytes: images.cached_bytes(&filename),
+            variants: cached_variants,
+            thumbnails: thumbnails::SIZES
+                .iter()
+                .copied()
+                .filter(|size| thumbnails::thumbnail_path(&images_dir, &filename, *size).is_file())
+                .collect(),
+            transcoded: transcodes.path_for(&filename).is_file(),
+            hls: transcodes.hls_dir_for(&filename).join(HLS_PLAYLIST).is_file(),
+        },
+        path,
+    }))
+}
+
+/// Publishes an item under `/public` or withdraws it.
+#[put("/admin/published/{filename}")]
+pub async fn set_published(
+    filename: web::Path<String>,
+    body: web::Json<PublishRequest>,
+    images_dir: web::Data<PathBuf>,
+    config: web::Data<Config>,
+) -> Result<HttpResponse, ApiError> {
+    let path = config.policy.resolve(&images_dir, &filename)?;
+    if !path.is_file() {
+        return Err(ApiError::not_found("Image not found"));
+    }
+    publish::set_published(&path, body.published)
+        .context("Failed to write published flag")
+        .map_err(ApiError::Storage)?;
+
+    let filename = paths::file_name(&path);
+    let purge = Purge::image(&filename);
+    let mut response = HttpResponse::Ok().json(PublishResponse {
+        public_url: body.published.then(|| config.public_image_url(&filename)),
+        filename,
+        published: body.published,
+    });
+    response.extensions_mut().insert(purge);
+    Ok(response)
+}
+
+/// A published file in the images directory. Anything else is a 404, so the
+/// public routes do not reveal what else exists.
+fn resolve_published(policy: &ContentPolicy, images_dir: &Path, filename: &str) -> Result<PathBuf, ApiError> {
+    let path = policy.resolve(images_dir, filename)?;
+    if !path.is_file() || !publish::is_published(&path) {
+        return Err(ApiError::not_found("Image not found"));
+    }
+    Ok(path)
+}
+
+/// Published items, newest first. Needs no token even when authentication
+/// is required.
+#[get("/public/images")]
+pub async fn public_images(
+    query: web::Query<PublicImagesQuery>,
+    images_dir: web::Data<PathBuf>,
+    config: web::Data<Config>,
+) -> Result<HttpResponse, ApiError> {
+    let (page, page_size) = page_bounds(query.page, query.limit, DEFAULT_PAGE_SIZE, MAX_PAGE_SIZE)?;
+
+    // The published flag is an extended attribute on every file
+    let scan_config = config.clone();
+    let published = web::block(move || -> Result<_, ApiError> {
+        let mut published: Vec<library::LibraryImage> = scan_config
+            .policy
+            .scan(&images_dir)
+            .context("Failed to read images directory")
+            .map_err(ApiError::Storage)?
+            .into_iter()
+            .filter(|image| publish::is_published(&image.path))
+            .collect();
+        published.sort_by(|a, b| b.modified.cmp(&a.modified).then_with(|| a.filename.cmp(&b.filename)));
+        Ok(published)
+    })
+    .await??;
+
+    let total = published.len();
+    let images = published
+        .iter()
+        .skip((page - 1).saturating_mul(page_size))
+        .take(page_size)
+        .map(|image| PublicImage {
+            filename: image.filename.clone(),
+            media_type: media_types::for_path(&image.path).map_or(MediaClass::Image, |media| media.kind.class()),
+            url: config.public_image_url(&image.filename),
+            thumbnail_url: format!("{}/thumbnail?size={}", config.public_image_url(&image.filename), thumbnails::DEFAULT_SIZE),
+            modified: image.modified,
+        })
+        .collect();
+
+    Ok(HttpResponse::Ok().json(PublicImagesResponse {
+        images,
+        page,
+        page_size,
+        total,
+        total_pages: total.div_ceil(page_size),
+    }))
+}
+
+/// A published file, streamed with `Range` support and conditional requests.
+#[get("/public/images/{filename}")]
+pub async fn public_image(
+    req: HttpRequest,
+    filename: web::Path<String>,
+    images_dir: web::Data<PathBuf>,
+    config: web::Data<Config>,
+) -> Result<HttpResponse, ApiError> {
+    let path = resolve_published(&config.policy, &images_dir, &filename)?;
+    config.policy.ensure_originals()?;
+    let metadata = std::fs::metadata(&path)
+        .context("Failed to read image metadata")
+        .map_err(ApiError::Storage)?;
+
+    let validators = Validators::for_file(&metadata, None);
+    let mut response = if validators.is_not_modified(&req) {
+        HttpResponse::NotModified().finish()
+    } else {
+        serve_file(&req, &path, sniff_content_type(&path), &config).await?
+    };
+    validators.apply(response.headers_mut(), &config.cache_control());
+    Ok(response)
+}
+
+/// Thumbnail of a published image, or a still from a published video.
+#[get("/public/images/{filename}/thumbnail")]
+pub async fn public_thumbnail(
+    filename: web::Path<String>,
+    query: web::Query<ThumbnailQuery>,
+    images_dir: web::Data<PathBuf>,
+    config: web::Data<Config>,
+    transcodes: web::Data<TranscodeCache>,
+) -> Result<HttpResponse, ApiError> {
+    let size = thumbnail_size(query.size)?;
+    let path = resolve_published(&config.policy, &images_dir, &filename)?;
+
+    let thumbnail = if library::is_video_file(&path) {
+        let at_millis = poster_millis(&path, None).await?;
+        let name = paths::file_name(&path);
+        video_poster(&images_dir, path, &name, size, at_millis, ConvertFormat::Jpeg, transcodes).await?
+    } else {
+        ensure_decodable(&path)?;
+        let dir = images_dir.get_ref().clone();
+        web::block(move || thumbnails::get_or_create(&dir, &path, size))
+            .await?
+            .context("Failed to create thumbnail")
+            .map_err(ApiError::Processing)?
+    };
+    Ok(HttpResponse::Ok()
+        .content_type(thumbnail.content_type())
+        .body(thumbnail.bytes))
+}
+
+/// Published items, newest first. Like the `/public` routes it needs no
+/// token; thumbnails are linked through signed URLs, except a video's own
+/// frame when its poster is not published too.
+#[get("/feed.json")]
+pub async fn json_feed(
+    query: web::Query<FeedQuery>,
+    images_dir: web::Data<PathBuf>,
+    config: web::Data<Config>,
+    signer: web::Data<UrlSigner>,
+) -> Result<HttpResponse, ApiError> {
+    let limit = query.limit.unwrap_or(DEFAULT_FEED_ITEMS).clamp(1, MAX_FEED_ITEMS);
+
+    // Published flags are extended attributes, read off the executor. Each
+    // item comes with whether its video poster is published too.
+    let scan_config = config.clone();
+    let items = web::block(move || -> Result<_, ApiError> {
+        let mut stacks: Vec<library::ImageStack> = library::stack(
+            scan_config
+                .policy
+                .scan(&images_dir)
+                .context("Failed to read images directory")
+                .map_err(ApiError::Storage)?,
+        )
+        .into_iter()
+        .filter(|stack| publish::is_published(&stack.primary().path))
+        .collect();
+        library::sort(&mut stacks, library::SortField::Date, library::SortOrder::Desc);
+        stacks.truncate(limit);
+        Ok(stacks
+            .into_iter()
+            .map(|stack| {
+                let poster_published = stack.poster.as_deref().is_some_and(|poster| {
+                    scan_config.policy
+                        .resolve(&images_dir, poster)
+                        .is_ok_and(|path| publish::is_published(&path))
+                });
+                (stack, poster_published)
+            })
+            .collect::<Vec<_>>())
+    })
+    .await??;
+
+    // Feed readers poll rarely, so thumbnails stay valid for as long as allowed
+    let expires_at = Utc::now() + chrono::Duration::seconds(signing::MAX_EXPIRES_SECS as i64);
+    let thumbnail_url = |filename: &str| {
+        let path = format!("{}/thumbnail?size={}", config.image_path(filename), FEED_THUMBNAIL_SIZE);
+        config.url(&signer.sign(&path, expires_at))
+    };
+    let items = items
+        .iter()
+        .map(|(stack, poster_published)| {
+            let primary = stack.primary();
+            FeedItem {
+                id: primary.filename.clone(),
+                url: config.public_image_url(&primary.filename),
+                title: primary.filename.clone(),
+                content_text: primary.filename.clone(),
+                image: match stack.media_class() {
+                    MediaClass::Image => Some(thumbnail_url(&primary.filename)),
+                    MediaClass::Video => Some(
+                        stack
+                            .poster
+                            .as_deref()
+                            .filter(|_| *poster_published)
+                            .map(thumbnail_url)
+                            .unwrap_or_else(|| {
+                                format!(
+                                    "{}/thumbnail?size={}",
+                                    config.public_image_url(&primary.filename),
+                                    FEED_THUMBNAIL_SIZE
+                                )
+                            }),
+                    ),
+                },
+                date_published: primary.modified,
+            }
+        })
+        .collect();
+
+    let feed = JsonFeed {
+        version: "https://jsonfeed.org/version/1.1",
+        title: config.feed_title.clone(),
+        home_page_url: config.url("/public/images"),
+        feed_url: config.url("/feed.json"),
+        items,
     };
 
-    HttpResponse::Ok().json(info)
+    Ok(HttpResponse::Ok()
+        .content_type("application/feed+json")
+        .json(feed))
 }
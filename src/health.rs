@@ -0,0 +1,145 @@
+use serde::Serialize;
+use std::path::Path;
+
+use crate::cache::CacheStats;
+use crate::indexer::{IndexProgress, IndexState};
+
+/// Ordered from best to worst, so the overall status is the worst of the checks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HealthStatus {
+    Healthy,
+    Degraded,
+    Unhealthy,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DirectoryCheck {
+    pub status: HealthStatus,
+    pub readable: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DiskCheck {
+    pub status: HealthStatus,
+    pub free_bytes: Option<u64>,
+    pub total_bytes: Option<u64>,
+    pub min_free_bytes: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CachesCheck {
+    pub images: CacheStats,
+    pub variants: CacheStats,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct IndexCheck {
+    pub status: HealthStatus,
+    #[serde(flatten)]
+    pub progress: IndexProgress,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HealthChecks {
+    pub images_dir: DirectoryCheck,
+    pub disk: DiskCheck,
+    pub caches: CachesCheck,
+    pub index: IndexCheck,
+}
+
+impl HealthChecks {
+    pub fn status(&self) -> HealthStatus {
+        [self.images_dir.status, self.disk.status, self.index.status]
+            .into_iter()
+            .max()
+            .unwrap_or(HealthStatus::Healthy)
+    }
+}
+
+/// Nothing can be served when the images directory cannot be listed.
+pub fn check_images_dir(images_dir: &Path) -> DirectoryCheck {
+    match std::fs::read_dir(images_dir) {
+        Ok(_) => DirectoryCheck {
+            status: HealthStatus::Healthy,
+            readable: true,
+            error: None,
+        },
+        Err(e) => DirectoryCheck {
+            status: HealthStatus::Unhealthy,
+            readable: false,
+            error: Some(e.to_string()),
+        },
+    }
+}
+
+/// Free space on the images filesystem, which also holds the thumbnail and
+/// transcode caches. Running low degrades rather than fails: reads still work.
+pub fn check_disk(images_dir: &Path, min_free_bytes: u64) -> DiskCheck {
+    match rustix::fs::statvfs(images_dir) {
+        Ok(stats) => {
+            let free_bytes = stats.f_bavail.saturating_mul(stats.f_frsize);
+            DiskCheck {
+                status: if free_bytes < min_free_bytes {
+                    HealthStatus::Degraded
+                } else {
+                    HealthStatus::Healthy
+                },
+                free_bytes: Some(free_bytes),
+                total_bytes: Some(stats.f_blocks.saturating_mul(stats.f_frsize)),
+                min_free_bytes,
+            }
+        }
+        Err(e) => {
+            log::warn!("Failed to read free space of {:?}: {}", images_dir, e);
+            DiskCheck {
+                status: HealthStatus::Degraded,
+                free_bytes: None,
+                total_bytes: None,
+                min_free_bytes,
+            }
+        }
+    }
+}
+
+/// A failed reindex leaves the previous catalog in place, so it only degrades.
+pub fn check_index(progress: IndexProgress) -> IndexCheck {
+    IndexCheck {
+        status: if progress.state == IndexState::Failed {
+            HealthStatus::Degraded
+        } else {
+            HealthStatus::Healthy
+        },
+        progress,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overall_status_is_the_worst_check() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        let mut checks = HealthChecks {
+            images_dir: check_images_dir(temp.path()),
+            disk: check_disk(temp.path(), 0),
+            caches: CachesCheck {
+                images: CacheStats::default(),
+                variants: CacheStats::default(),
+            },
+            index: check_index(IndexProgress::default()),
+        };
+        assert_eq!(checks.status(), HealthStatus::Healthy);
+        assert!(checks.disk.free_bytes.is_some());
+
+        checks.disk = check_disk(temp.path(), u64::MAX);
+        assert_eq!(checks.status(), HealthStatus::Degraded);
+
+        checks.images_dir = check_images_dir(&temp.path().join("missing"));
+        assert!(checks.images_dir.error.is_some());
+        assert_eq!(checks.status(), HealthStatus::Unhealthy);
+    }
+}
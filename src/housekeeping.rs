@@ -0,0 +1,70 @@
+use serde::Deserialize;
+
+use crate::library::{self, LibraryImage};
+use crate::{metadata, tags};
+
+/// Which files a housekeeping report lists, and in what order.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Report {
+    /// Every file, largest first.
+    #[default]
+    Largest,
+    /// Every file, least recently modified first.
+    Oldest,
+    /// Files never viewed since the server started, largest first.
+    Unviewed,
+    /// Files without Finder tags, largest first.
+    Untagged,
+    /// Images without an EXIF capture date, largest first.
+    Undated,
+}
+
+impl Report {
+    /// The files of `images` this report lists, in order. `views` gives a
+    /// file's view count.
+    pub fn select(self, images: Vec<LibraryImage>, views: impl Fn(&str) -> u64) -> Vec<LibraryImage> {
+        let mut selected: Vec<LibraryImage> = images
+            .into_iter()
+            .filter(|image| match self {
+                Report::Largest | Report::Oldest => true,
+                Report::Unviewed => views(&image.filename) == 0,
+                Report::Untagged => tags::read(&image.path).is_empty(),
+                Report::Undated => {
+                    !library::is_video_file(&image.path) && metadata::capture_date(&image.path).is_none()
+                }
+            })
+            .collect();
+        match self {
+            Report::Oldest => selected.sort_by(|a, b| a.modified.cmp(&b.modified).then_with(|| a.filename.cmp(&b.filename))),
+            _ => selected.sort_by(|a, b| b.size_bytes.cmp(&a.size_bytes).then_with(|| a.filename.cmp(&b.filename))),
+        }
+        selected
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+
+    fn image(filename: &str, size_bytes: u64, year: i32) -> LibraryImage {
+        LibraryImage {
+            filename: filename.to_string(),
+            path: filename.into(),
+            size_bytes,
+            modified: Some(Utc.with_ymd_and_hms(year, 1, 1, 0, 0, 0).unwrap()),
+        }
+    }
+
+    #[test]
+    fn orders_and_filters_by_report() {
+        let images = || vec![image("a.jpg", 10, 2020), image("b.jpg", 30, 2022), image("c.jpg", 20, 2018)];
+        let names = |selected: Vec<LibraryImage>| selected.into_iter().map(|image| image.filename).collect::<Vec<_>>();
+
+        assert_eq!(names(Report::Largest.select(images(), |_| 0)), ["b.jpg", "c.jpg", "a.jpg"]);
+        assert_eq!(names(Report::Oldest.select(images(), |_| 0)), ["c.jpg", "a.jpg", "b.jpg"]);
+        let viewed = |filename: &str| u64::from(filename == "b.jpg");
+        assert_eq!(names(Report::Unviewed.select(images(), viewed)), ["c.jpg", "a.jpg"]);
+    }
+}
@@ -0,0 +1,324 @@
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+use crate::ingest::IngestRules;
+use crate::library;
+use crate::media_types;
+use crate::suggest::SuggestIndex;
+use crate::tags::{self, Tag};
+
+/// What the indexer knows about one file, gathered without decoding pixels.
+#[derive(Debug, Clone, Serialize)]
+pub struct IndexedImage {
+    pub filename: String,
+    pub size_bytes: u64,
+    pub modified: Option<DateTime<Utc>>,
+    /// Width and height read from the file header, when the format is decodable.
+    pub dimensions: Option<(u32, u32)>,
+    pub tags: Vec<Tag>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IndexState {
+    #[default]
+    Idle,
+    Running,
+    Completed,
+    Failed,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct IndexProgress {
+    pub state: IndexState,
+    pub processed: usize,
+    pub total: usize,
+    pub started_at: Option<DateTime<Utc>>,
+    pub finished_at: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Files in referenced folders left out because an earlier folder has
+    /// one of the same name.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub hidden: Vec<PathBuf>,
+}
+
+/// In-memory catalog of the images directory, rebuilt by [`LibraryIndex::run`].
+///
+/// Readers keep seeing the previous catalog until a run completes and swaps
+/// the new one in.
+///
+/// Listings, search, the feed and suggestions do not read it: they scan the
+/// folders, so they never lag a rebuild or a missed watcher event. The
+/// catalog backs the health check, `/admin/dashboard` and `/admin/inspect`,
+/// and running it applies the ingest rules.
+#[derive(Default)]
+pub struct LibraryIndex {
+    images: RwLock<Arc<BTreeMap<String, IndexedImage>>>,
+    progress: RwLock<IndexProgress>,
+    rules: IngestRules,
+    suggestions: Option<Arc<SuggestIndex>>,
+}
+
+/// Reads size, mtime, header dimensions and tags for one scanned file.
+pub fn index_image(image: &library::LibraryImage) -> IndexedImage {
+    let decodable = media_types::for_path(&image.path).is_some_and(|media| media.decodable);
+    IndexedImage {
+        filename: image.filename.clone(),
+        size_bytes: image.size_bytes,
+        modified: image.modified,
+        dimensions: decodable
+            .then(|| image::image_dimensions(&image.path).ok())
+            .flatten(),
+        tags: tags::read(&image.path),
+    }
+}
+
+impl LibraryIndex {
+    /// An empty index that applies `rules` to each file it catalogs.
+    pub fn with_rules(rules: IngestRules) -> Self {
+        LibraryIndex {
+            rules,
+            ..LibraryIndex::default()
+        }
+    }
+
+    /// Has completed runs drop `suggestions` when they find a file's tags
+    /// changed, such as by an ingest rule: tags live in extended attributes,
+    /// which leave the directory times the suggestions are keyed on alone.
+    pub fn invalidating(self, suggestions: Arc<SuggestIndex>) -> Self {
+        LibraryIndex {
+            suggestions: Some(suggestions),
+            ..self
+        }
+    }
+
+    /// Reads `image` for the catalog, first running the ingest rules on it if
+    /// it is new to the catalog or its size or modification time changed
+    /// since `previous`. Unchanged files are left alone, so a tag a rule added
+    /// and someone removed stays removed.
+    fn catalog(&self, image: &library::LibraryImage, previous: Option<&IndexedImage>) -> IndexedImage {
+        let changed = previous
+            .is_none_or(|previous| previous.size_bytes != image.size_bytes || previous.modified != image.modified);
+        if changed {
+            match self.rules.apply(&image.path) {
+                Ok(added) if !added.is_empty() => log::info!("Tagged {} with {:?}", image.filename, added),
+                Ok(_) => {}
+                Err(e) => log::warn!("Could not apply ingest rules to {:?}: {}", image.path, e),
+            }
+        }
+        index_image(image)
+    }
+
+    pub fn progress(&self) -> IndexProgress {
+        self.progress.read().unwrap().clone()
+    }
+
+    pub fn images(&self) -> Arc<BTreeMap<String, IndexedImage>> {
+        self.images.read().unwrap().clone()
+    }
+
+    pub fn get(&self, filename: &str) -> Option<IndexedImage> {
+        self.images.read().unwrap().get(filename).cloned()
+    }
+
+    /// Brings the entry for `filename` in line with the disk: re-reads it, or
+    /// drops it when the file is gone from every folder. Blocks on the file,
+    /// so call it off the async executor.
+    pub fn refresh(&self, images_dir: &Path, referenced_dirs: &[PathBuf], filename: &str) {
+        let previous = self.get(filename);
+        let indexed = library::open_in(images_dir, referenced_dirs, filename)
+            .map(|image| self.catalog(&image, previous.as_ref()));
+        self.store(filename, indexed);
+    }
+
+    /// [`refresh`](Self::refresh) after the API changed the file's tags or
+    /// fields, which never runs the ingest rules: they would put back a tag
+    /// that was just removed.
+    pub fn refresh_edited(&self, images_dir: &Path, referenced_dirs: &[PathBuf], filename: &str) {
+        let indexed = library::open_in(images_dir, referenced_dirs, filename).map(|image| index_image(&image));
+        self.store(filename, indexed);
+    }
+
+    fn store(&self, filename: &str, indexed: Option<IndexedImage>) {
+        let mut images = self.images.write().unwrap();
+        let images = Arc::make_mut(&mut images);
+        match indexed {
+            Some(indexed) => {
+                images.insert(filename.to_string(), indexed);
+            }
+            None => {
+                images.remove(filename);
+            }
+        }
+    }
+
+    /// Marks a run as started, or returns false when one is already running.
+    pub fn begin(&self) -> bool {
+        let mut progress = self.progress.write().unwrap();
+        if progress.state == IndexState::Running {
+            return false;
+        }
+        *progress = IndexProgress {
+            state: IndexState::Running,
+            started_at: Some(Utc::now()),
+            ..IndexProgress::default()
+        };
+        true
+    }
+
+    /// Walks `images_dir` and the referenced folders and replaces the catalog.
+    /// Call after [`begin`](Self::begin); blocks for the whole walk, so run it
+    /// off the async executor.
+    pub fn run(&self, images_dir: &Path, referenced_dirs: &[PathBuf]) {
+        let result = self.walk(images_dir, referenced_dirs);
+        let mut progress = self.progress.write().unwrap();
+        progress.finished_at = Some(Utc::now());
+        match result {
+            Ok(()) => {
+                progress.state = IndexState::Completed;
+                log::info!("Indexed {} images", progress.processed);
+            }
+            Err(e) => {
+                log::error!("Reindex failed: {:#}", e);
+                progress.state = IndexState::Failed;
+                progress.error = Some(e.to_string());
+            }
+        }
+    }
+
+    fn walk(&self, images_dir: &Path, referenced_dirs: &[PathBuf]) -> anyhow::Result<()> {
+        let (scanned, hidden) =
+            library::scan_all_with_hidden(images_dir, referenced_dirs).context("Failed to read images directory")?;
+        for path in &hidden {
+            log::warn!("{:?} is hidden by an earlier file of the same name", path);
+        }
+        {
+            let mut progress = self.progress.write().unwrap();
+            progress.total = scanned.len();
+            progress.hidden = hidden;
+        }
+
+        let previous = self.images();
+        let mut images = BTreeMap::new();
+        for image in &scanned {
+            images.insert(image.filename.clone(), self.catalog(image, previous.get(&image.filename)));
+            self.progress.write().unwrap().processed += 1;
+        }
+
+        let tags_changed = images
+            .iter()
+            .any(|(filename, image)| previous.get(filename).is_none_or(|previous| previous.tags != image.tags));
+        *self.images.write().unwrap() = Arc::new(images);
+        if let Some(suggestions) = self.suggestions.as_ref().filter(|_| tags_changed) {
+            suggestions.invalidate();
+        }
+        Ok(())
+    }
+}
+
+/// Starts a background reindex of `images_dir` unless one is already running.
+pub fn spawn(index: Arc<LibraryIndex>, images_dir: PathBuf, referenced_dirs: Vec<PathBuf>) -> bool {
+    if !index.begin() {
+        return false;
+    }
+    tokio::task::spawn_blocking(move || index.run(&images_dir, &referenced_dirs));
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_fs::prelude::*;
+
+    #[test]
+    fn run_catalogs_images_and_reports_progress() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        let png = image::DynamicImage::new_rgb8(3, 2);
+        png.save(temp.child("a.png").path()).unwrap();
+        temp.child("b.heic").write_binary(b"not decodable here").unwrap();
+        temp.child("notes.txt").write_str("ignored").unwrap();
+
+        let index = LibraryIndex::default();
+        assert!(index.begin());
+        assert!(!index.begin());
+        index.run(temp.path(), &[]);
+
+        let progress = index.progress();
+        assert_eq!(progress.state, IndexState::Completed);
+        assert_eq!((progress.processed, progress.total), (2, 2));
+        assert_eq!(index.get("a.png").unwrap().dimensions, Some((3, 2)));
+        assert_eq!(index.get("b.heic").unwrap().dimensions, None);
+        assert!(index.get("notes.txt").is_none());
+
+        assert!(index.begin());
+        index.run(&temp.path().join("missing"), &[]);
+        assert_eq!(index.progress().state, IndexState::Failed);
+        assert_eq!(index.images().len(), 2);
+    }
+
+    #[test]
+    fn run_applies_ingest_rules() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        temp.child("Screenshot 2024-05-01.png").write_binary(b"png").unwrap();
+        temp.child("beach.jpg").write_binary(b"jpg").unwrap();
+        let rules = IngestRules::compile(&[crate::ingest::IngestRule {
+            glob: Some("**/Screenshot*".to_string()),
+            tags: vec!["screenshot".to_string()],
+            ..Default::default()
+        }])
+        .unwrap();
+
+        let suggestions = Arc::new(SuggestIndex::default());
+        let policy = crate::policy::ContentPolicy::default();
+        assert_eq!(suggestions.get(temp.path(), &policy).unwrap().lookup("screen", 10).len(), 1);
+
+        let index = LibraryIndex::with_rules(rules).invalidating(suggestions.clone());
+        index.begin();
+        index.run(temp.path(), &[]);
+        index.begin();
+        index.run(temp.path(), &[]);
+        // The rule's tag is suggested although no directory time changed
+        assert_eq!(suggestions.get(temp.path(), &policy).unwrap().lookup("screen", 10).len(), 2);
+
+        let names = |filename: &str| -> Vec<String> {
+            index.get(filename).unwrap().tags.into_iter().map(|tag| tag.name).collect()
+        };
+        assert_eq!(names("Screenshot 2024-05-01.png"), ["screenshot"]);
+        assert!(names("beach.jpg").is_empty());
+
+        // A removed rule tag stays removed until the file itself changes
+        let screenshot = temp.child("Screenshot 2024-05-01.png");
+        tags::write(screenshot.path(), &[]).unwrap();
+        index.refresh_edited(temp.path(), &[], "Screenshot 2024-05-01.png");
+        index.refresh(temp.path(), &[], "Screenshot 2024-05-01.png");
+        index.begin();
+        index.run(temp.path(), &[]);
+        assert!(names("Screenshot 2024-05-01.png").is_empty());
+
+        screenshot.write_binary(b"new png").unwrap();
+        index.refresh(temp.path(), &[], "Screenshot 2024-05-01.png");
+        assert_eq!(names("Screenshot 2024-05-01.png"), ["screenshot"]);
+    }
+
+    #[test]
+    fn run_reports_referenced_files_hidden_by_name() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        let archive = assert_fs::TempDir::new().unwrap();
+        temp.child("a.jpg").write_binary(b"local").unwrap();
+        archive.child("a.jpg").write_binary(b"archived").unwrap();
+        archive.child("b.jpg").write_binary(b"archived").unwrap();
+
+        let index = LibraryIndex::default();
+        index.begin();
+        index.run(temp.path(), &[archive.path().to_path_buf()]);
+
+        assert_eq!(index.images().len(), 2);
+        assert_eq!(index.get("a.jpg").unwrap().size_bytes, 5);
+        assert_eq!(index.progress().hidden, [archive.path().join("a.jpg")]);
+    }
+}
@@ -0,0 +1,98 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::hash::Hash;
+use std::sync::{Arc, Mutex};
+use tokio::sync::OnceCell;
+
+/// Coalesces identical concurrent work: while a call for a key is running,
+/// later callers with the same key wait for it and share its result instead
+/// of repeating it.
+pub struct Inflight<K, V> {
+    calls: Mutex<HashMap<K, Arc<OnceCell<V>>>>,
+}
+
+impl<K: Eq + Hash + Clone, V: Clone> Inflight<K, V> {
+    pub fn new() -> Self {
+        Inflight {
+            calls: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Runs `work` for `key`, or joins the call already running for it. If the
+    /// caller doing the work goes away (e.g. its client disconnects), one of
+    /// the waiters takes over.
+    pub async fn run<F, Fut>(&self, key: K, work: F) -> V
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = V>,
+    {
+        let call = self.calls.lock().unwrap().entry(key.clone()).or_default().clone();
+        let value = call.get_or_init(work).await.clone();
+
+        // The first caller to finish forgets the call so later work starts afresh
+        let mut calls = self.calls.lock().unwrap();
+        if calls.get(&key).is_some_and(|current| Arc::ptr_eq(current, &call)) {
+            calls.remove(&key);
+        }
+        value
+    }
+
+    /// Number of distinct calls currently running.
+    pub fn len(&self) -> usize {
+        self.calls.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<K: Eq + Hash + Clone, V: Clone> Default for Inflight<K, V> {
+    fn default() -> Self {
+        Inflight::new()
+    }
+}
+
+/// A copy of a shared error, keeping its message and causes.
+pub fn unshare(error: &anyhow::Error) -> anyhow::Error {
+    let causes: Vec<String> = error.chain().skip(1).map(ToString::to_string).collect();
+    if causes.is_empty() {
+        anyhow::anyhow!("{}", error)
+    } else {
+        anyhow::anyhow!("{}", causes.join(": ")).context(error.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[actix_rt::test]
+    async fn concurrent_calls_share_one_run() {
+        let inflight: Inflight<&str, u32> = Inflight::new();
+        let runs = AtomicUsize::new(0);
+        let work = || async {
+            runs.fetch_add(1, Ordering::SeqCst);
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+            7
+        };
+
+        let results = futures_util::future::join_all((0..20).map(|_| inflight.run("a.jpg", work))).await;
+        assert!(results.iter().all(|value| *value == 7));
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+        assert!(inflight.is_empty());
+
+        // Once finished, the next call does the work again
+        inflight.run("a.jpg", work).await;
+        assert_eq!(runs.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn unshared_errors_keep_their_causes() {
+        let error = anyhow::anyhow!("decode failed").context("Failed to resize a.jpg");
+        let copy = unshare(&error);
+        assert_eq!(copy.to_string(), "Failed to resize a.jpg");
+        assert_eq!(format!("{:#}", copy), "Failed to resize a.jpg: decode failed");
+    }
+}
@@ -0,0 +1,164 @@
+use globset::{Glob, GlobBuilder, GlobMatcher};
+use regex::Regex;
+use serde::Deserialize;
+use std::path::Path;
+
+use crate::{metadata, tags};
+
+/// One `[[ingest_rules]]` entry: conditions on a file, all of which must
+/// hold, and the tags it then gets.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct IngestRule {
+    /// Glob on the file's full path, e.g. `**/screenshots/*` or `*Screenshot*`.
+    pub glob: Option<String>,
+    /// Regular expression searched for in the file's full path.
+    pub regex: Option<String>,
+    /// Glob on the EXIF camera make and model, ignoring case, e.g. `*iphone*`.
+    pub camera: Option<String>,
+    /// Whether the file must (or must not) have an EXIF capture date.
+    pub has_capture_date: Option<bool>,
+    /// Finder tags added to matching files; tags already present are kept.
+    pub tags: Vec<String>,
+}
+
+struct CompiledRule {
+    glob: Option<GlobMatcher>,
+    regex: Option<Regex>,
+    camera: Option<GlobMatcher>,
+    has_capture_date: Option<bool>,
+    tags: Vec<String>,
+}
+
+impl CompiledRule {
+    fn matches(&self, path: &Path) -> bool {
+        if self.glob.as_ref().is_some_and(|glob| !glob.is_match(path)) {
+            return false;
+        }
+        if self.regex.as_ref().is_some_and(|regex| !regex.is_match(&path.to_string_lossy())) {
+            return false;
+        }
+        if let Some(camera) = &self.camera {
+            if !metadata::camera(path).is_some_and(|model| camera.is_match(model)) {
+                return false;
+            }
+        }
+        if let Some(wanted) = self.has_capture_date {
+            if metadata::capture_date(path).is_some() != wanted {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// Ingest rules ready to apply to files as the index comes across them.
+#[derive(Default)]
+pub struct IngestRules(Vec<CompiledRule>);
+
+impl IngestRules {
+    /// Compiles `rules`, failing on a bad pattern or a rule that adds nothing.
+    pub fn compile(rules: &[IngestRule]) -> Result<Self, String> {
+        let mut compiled = Vec::new();
+        for (i, rule) in rules.iter().enumerate() {
+            let invalid = |e: String| format!("ingest_rules[{}]: {}", i, e);
+            if rule.tags.iter().all(|tag| tag.trim().is_empty()) {
+                return Err(invalid("tags must not be empty".to_string()));
+            }
+            compiled.push(CompiledRule {
+                glob: rule
+                    .glob
+                    .as_deref()
+                    .map(|glob| Glob::new(glob).map(|glob| glob.compile_matcher()))
+                    .transpose()
+                    .map_err(|e| invalid(e.to_string()))?,
+                regex: rule
+                    .regex
+                    .as_deref()
+                    .map(Regex::new)
+                    .transpose()
+                    .map_err(|e| invalid(e.to_string()))?,
+                camera: rule
+                    .camera
+                    .as_deref()
+                    .map(|camera| {
+                        GlobBuilder::new(camera)
+                            .case_insensitive(true)
+                            .build()
+                            .map(|glob| glob.compile_matcher())
+                    })
+                    .transpose()
+                    .map_err(|e| invalid(e.to_string()))?,
+                has_capture_date: rule.has_capture_date,
+                tags: rule.tags.iter().map(|tag| tag.trim().to_string()).filter(|tag| !tag.is_empty()).collect(),
+            });
+        }
+        Ok(IngestRules(compiled))
+    }
+
+    /// Tags every rule matching `path` adds, without duplicates.
+    pub fn tags_for(&self, path: &Path) -> Vec<String> {
+        let mut wanted: Vec<String> = Vec::new();
+        for rule in self.0.iter().filter(|rule| rule.matches(path)) {
+            for tag in &rule.tags {
+                if !wanted.iter().any(|existing| existing.eq_ignore_ascii_case(tag)) {
+                    wanted.push(tag.clone());
+                }
+            }
+        }
+        wanted
+    }
+
+    /// Adds the tags of every matching rule that `path` does not carry yet.
+    /// Returns the tags added.
+    pub fn apply(&self, path: &Path) -> std::io::Result<Vec<String>> {
+        if self.0.is_empty() {
+            return Ok(Vec::new());
+        }
+        let wanted = self.tags_for(path);
+        let mut names: Vec<String> = tags::read(path).into_iter().map(|tag| tag.name).collect();
+        let added: Vec<String> = wanted
+            .into_iter()
+            .filter(|tag| !names.iter().any(|name| name.eq_ignore_ascii_case(tag)))
+            .collect();
+        if !added.is_empty() {
+            names.extend(added.iter().cloned());
+            tags::write(path, &names)?;
+        }
+        Ok(added)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_paths_and_collects_tags() {
+        let rules = IngestRules::compile(&[
+            IngestRule {
+                glob: Some("**/screenshots/*".to_string()),
+                tags: vec!["screenshot".to_string()],
+                ..IngestRule::default()
+            },
+            IngestRule {
+                regex: Some(r"(?i)screen ?shot".to_string()),
+                tags: vec!["Screenshot".to_string(), "inbox".to_string()],
+                ..IngestRule::default()
+            },
+            IngestRule {
+                has_capture_date: Some(true),
+                tags: vec!["dated".to_string()],
+                ..IngestRule::default()
+            },
+        ])
+        .unwrap();
+
+        assert_eq!(rules.tags_for(Path::new("/photos/screenshots/a.png")), ["screenshot", "inbox"]);
+        assert_eq!(rules.tags_for(Path::new("/photos/Screen Shot 2024.png")), ["Screenshot", "inbox"]);
+        assert!(rules.tags_for(Path::new("/photos/beach.jpg")).is_empty());
+
+        assert!(IngestRules::compile(&[IngestRule { regex: Some("(".to_string()), tags: vec!["x".to_string()], ..IngestRule::default() }]).is_err());
+        assert!(IngestRules::compile(&[IngestRule { glob: Some("*".to_string()), ..IngestRule::default() }]).is_err());
+    }
+}
@@ -1,5 +1,52 @@
+pub mod auth;
+pub mod budget;
+pub mod cache;
+pub mod cdn;
+pub mod cli;
+pub mod conditional;
+pub mod config;
+pub mod dashboard;
+pub mod error;
+pub mod fields;
+pub mod galleryignore;
 pub mod handlers;
+pub mod health;
+pub mod housekeeping;
+pub mod indexer;
+pub mod inflight;
+pub mod ingest;
+pub mod library;
+pub mod maintenance;
+pub mod manifest;
+pub mod media_types;
+pub mod metadata;
+pub mod paths;
+pub mod placeholder;
+pub mod policy;
+pub mod prefetch;
+pub mod presets;
+pub mod preview;
+pub mod processor;
+pub mod publish;
+pub mod range;
+pub mod rename;
+pub mod search;
+pub mod signing;
+pub mod sprite;
 pub mod startup;
+pub mod stats;
+pub mod suggest;
+pub mod tags;
+pub mod throttle;
+pub mod thumbnails;
+pub mod tls;
+pub mod transcode;
+pub mod trash;
+pub mod usage;
+pub mod video;
+pub mod video_roots;
+pub mod volumes;
+pub mod watcher;
 
 pub use handlers::*;
 pub use startup::*;
@@ -12,8 +59,14 @@ mod tests {
 
     #[actix_rt::test]
     async fn test_health_check() {
+        let temp = assert_fs::TempDir::new().unwrap();
         let app = test::init_service(
             App::new()
+                .app_data(web::Data::new(temp.path().to_path_buf()))
+                .app_data(web::Data::new(config::Config { min_free_disk_bytes: 0, ..config::Config::default() }))
+                .app_data(web::Data::new(cache::ImageCache::default()))
+                .app_data(web::Data::new(cache::VariantCache::default()))
+                .app_data(web::Data::new(indexer::LibraryIndex::default()))
                 .service(health_check)
         ).await;
 
@@ -23,6 +76,33 @@ mod tests {
 
         let resp = test::call_service(&app, req).await;
         assert!(resp.status().is_success());
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(body["status"], "healthy");
+        assert_eq!(body["checks"]["images_dir"]["readable"], true);
+        assert_eq!(body["checks"]["index"]["state"], "idle");
+        assert!(body["checks"]["disk"]["free_bytes"].as_u64().is_some());
+        assert_eq!(body["checks"]["caches"]["images"]["entries"], 0);
+    }
+
+    #[actix_rt::test]
+    async fn test_health_check_reports_unreadable_images_dir() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(temp.path().join("missing")))
+                .app_data(web::Data::new(config::Config::default()))
+                .app_data(web::Data::new(cache::ImageCache::default()))
+                .app_data(web::Data::new(cache::VariantCache::default()))
+                .app_data(web::Data::new(indexer::LibraryIndex::default()))
+                .service(health_check)
+        ).await;
+
+        let req = test::TestRequest::get().uri("/health").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 503);
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(body["status"], "unhealthy");
+        assert_eq!(body["checks"]["images_dir"]["readable"], false);
     }
 
     #[actix_rt::test]
@@ -34,6 +114,11 @@ mod tests {
         let app = test::init_service(
             App::new()
                 .app_data(web::Data::new(temp.path().to_path_buf()))
+                .app_data(web::Data::new(config::Config::default()))
+                .app_data(web::Data::new(stats::ViewStats::default()))
+                .app_data(web::Data::new(cache::ImageCache::default()))
+                .app_data(web::Data::new(cache::VariantCache::default()))
+                .app_data(web::Data::new(transcode::TranscodeCache::new(temp.path(), &config::Config::default())))
                 .service(serve_image)
         ).await;
 
@@ -44,4 +129,2857 @@ mod tests {
         let resp = test::call_service(&app, req).await;
         assert!(resp.status().is_success());
     }
+
+    #[actix_rt::test]
+    async fn test_serve_image_uses_media_type_registry() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        temp.child("photo.png").write_binary(b"fake image content").unwrap();
+        temp.child("phone.HEIC").write_binary(b"fake image content").unwrap();
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(temp.path().to_path_buf()))
+                .app_data(web::Data::new(config::Config::default()))
+                .app_data(web::Data::new(stats::ViewStats::default()))
+                .app_data(web::Data::new(cache::ImageCache::default()))
+                .app_data(web::Data::new(cache::VariantCache::default()))
+                .app_data(web::Data::new(transcode::TranscodeCache::new(temp.path(), &config::Config::default())))
+                .service(serve_image)
+                .service(image_thumbnail)
+        ).await;
+
+        for (uri, content_type) in [("/images/photo.png", "image/png"), ("/images/phone.HEIC", "image/heic")] {
+            let req = test::TestRequest::get().uri(uri).to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.headers().get("Content-Type").unwrap(), content_type);
+        }
+
+        for uri in ["/images/phone.HEIC?w=100", "/images/phone.HEIC/thumbnail"] {
+            let req = test::TestRequest::get().uri(uri).to_request();
+            assert_eq!(test::call_service(&app, req).await.status(), 415, "{}", uri);
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_serve_image_with_download_rate_limit() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        temp.child("test.jpg").write_binary(b"fake image content").unwrap();
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(temp.path().to_path_buf()))
+                .app_data(web::Data::new(config::Config {
+                    download_rate_limit: Some(1024),
+                    ..config::Config::default()
+                }))
+                .app_data(web::Data::new(stats::ViewStats::default()))
+                .app_data(web::Data::new(cache::ImageCache::default()))
+                .app_data(web::Data::new(cache::VariantCache::default()))
+                .app_data(web::Data::new(transcode::TranscodeCache::new(temp.path(), &config::Config::default())))
+                .service(serve_image)
+        ).await;
+
+        let req = test::TestRequest::get()
+            .uri("/images/test.jpg")
+            .to_request();
+
+        let body = test::call_and_read_body(&app, req).await;
+        assert_eq!(body, "fake image content");
+    }
+
+    #[actix_rt::test]
+    async fn test_serve_image_uses_memory_cache() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        temp.child("test.jpg").write_binary(b"fake image content").unwrap();
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(temp.path().to_path_buf()))
+                .app_data(web::Data::new(config::Config::default()))
+                .app_data(web::Data::new(stats::ViewStats::default()))
+                .app_data(web::Data::new(cache::ImageCache::default()))
+                .app_data(web::Data::new(cache::VariantCache::default()))
+                .app_data(web::Data::new(transcode::TranscodeCache::new(temp.path(), &config::Config::default())))
+                .service(serve_image)
+                .service(cache_stats)
+        ).await;
+
+        for _ in 0..2 {
+            let req = test::TestRequest::get().uri("/images/test.jpg").to_request();
+            assert_eq!(test::call_and_read_body(&app, req).await, "fake image content");
+        }
+
+        let req = test::TestRequest::get().uri("/admin/cache").to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["images"]["hits"], 1);
+        assert_eq!(body["images"]["misses"], 1);
+        assert_eq!(body["images"]["bytes"], 18);
+    }
+
+    #[actix_rt::test]
+    async fn test_serve_image_supports_ranges() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        temp.child("test.jpg").write_binary(b"fake image content").unwrap();
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(temp.path().to_path_buf()))
+                .app_data(web::Data::new(config::Config::default()))
+                .app_data(web::Data::new(stats::ViewStats::default()))
+                .app_data(web::Data::new(cache::ImageCache::default()))
+                .app_data(web::Data::new(cache::VariantCache::default()))
+                .app_data(web::Data::new(transcode::TranscodeCache::new(temp.path(), &config::Config::default())))
+                .service(serve_image)
+        ).await;
+
+        let req = test::TestRequest::get()
+            .uri("/images/test.jpg")
+            .insert_header(("Range", "bytes=-7"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 206);
+        assert_eq!(resp.headers().get("Accept-Ranges").unwrap(), "bytes");
+        assert_eq!(resp.headers().get("Content-Range").unwrap(), "bytes 11-17/18");
+        assert!(resp.headers().contains_key("ETag"));
+        assert_eq!(test::read_body(resp).await, "content");
+
+        let req = test::TestRequest::get()
+            .uri("/images/test.jpg")
+            .insert_header(("Range", "bytes=18-"))
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 416);
+    }
+
+    #[actix_rt::test]
+    async fn test_serve_image_streams_files_too_large_to_cache() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        temp.child("clip.mp4").write_binary(b"fake video content").unwrap();
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(temp.path().to_path_buf()))
+                .app_data(web::Data::new(config::Config::default()))
+                .app_data(web::Data::new(stats::ViewStats::default()))
+                .app_data(web::Data::new(cache::ImageCache::new(8)))
+                .app_data(web::Data::new(cache::VariantCache::default()))
+                .app_data(web::Data::new(transcode::TranscodeCache::new(temp.path(), &config::Config::default())))
+                .service(serve_image)
+        ).await;
+
+        let req = test::TestRequest::get()
+            .uri("/images/clip.mp4")
+            .insert_header(("Range", "bytes=5-9"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 206);
+        assert_eq!(resp.headers().get("Content-Type").unwrap(), "video/mp4");
+        assert_eq!(resp.headers().get("Content-Range").unwrap(), "bytes 5-9/18");
+        assert_eq!(test::read_body(resp).await, "video");
+
+        let req = test::TestRequest::get().uri("/images/clip.mp4").to_request();
+        assert_eq!(test::call_and_read_body(&app, req).await, "fake video content");
+    }
+
+    #[actix_rt::test]
+    async fn test_serve_image_resizes_files_too_large_to_cache() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        image::DynamicImage::new_rgb8(40, 20)
+            .save(temp.child("photo.png").path())
+            .unwrap();
+        let config = config::Config {
+            image_cache_max_bytes: 8,
+            policy: policy::ContentPolicy {
+                download_originals: false,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(temp.path().to_path_buf()))
+                .app_data(web::Data::new(cache::ImageCache::new(config.image_cache_max_bytes)))
+                .app_data(web::Data::new(config))
+                .app_data(web::Data::new(stats::ViewStats::default()))
+                .app_data(web::Data::new(cache::VariantCache::default()))
+                .app_data(web::Data::new(transcode::TranscodeCache::new(temp.path(), &config::Config::default())))
+                .service(serve_image)
+        ).await;
+
+        let req = test::TestRequest::get().uri("/images/photo.png?w=10").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+        let body = test::read_body(resp).await;
+        let resized = image::load_from_memory(&body).unwrap();
+        assert_eq!(image::GenericImageView::dimensions(&resized), (10, 5));
+
+        // The original is still refused by the policy, however large
+        let req = test::TestRequest::get().uri("/images/photo.png").to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 403);
+    }
+
+    #[actix_rt::test]
+    async fn test_serve_image_transcodes_unsupported_codecs() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        // moov/trak declaring an HEVC video track
+        let boxed = |kind: &[u8; 4], payload: &[u8]| {
+            let mut bytes = ((payload.len() + 8) as u32).to_be_bytes().to_vec();
+            bytes.extend_from_slice(kind);
+            bytes.extend_from_slice(payload);
+            bytes
+        };
+        let mut hdlr = vec![0u8; 8];
+        hdlr.extend_from_slice(b"vide");
+        let mut stsd = vec![0, 0, 0, 0, 0, 0, 0, 1];
+        stsd.extend(boxed(b"hvc1", &[0u8; 8]));
+        let mut mdia = boxed(b"hdlr", &hdlr);
+        mdia.extend(boxed(b"minf", &boxed(b"stbl", &boxed(b"stsd", &stsd))));
+        let movie = boxed(b"moov", &boxed(b"trak", &boxed(b"mdia", &mdia)));
+        temp.child("phone.mov").write_binary(&movie).unwrap();
+
+        let config = config::Config {
+            ffmpeg_path: temp.path().join("no-ffmpeg-here").to_string_lossy().into_owned(),
+            ..config::Config::default()
+        };
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(temp.path().to_path_buf()))
+                .app_data(web::Data::new(config.clone()))
+                .app_data(web::Data::new(stats::ViewStats::default()))
+                .app_data(web::Data::new(cache::ImageCache::default()))
+                .app_data(web::Data::new(cache::VariantCache::default()))
+                .app_data(web::Data::new(transcode::TranscodeCache::new(temp.path(), &config)))
+                .service(serve_image)
+        ).await;
+
+        // An explicit request fails loudly without ffmpeg...
+        let req = test::TestRequest::get().uri("/images/phone.mov?unsupported_codecs=prores,hevc").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 500);
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(body["message"], "Failed to transcode video");
+
+        // ...while a guess from the User-Agent falls back to the original
+        let req = test::TestRequest::get()
+            .uri("/images/phone.mov")
+            .insert_header(("User-Agent", "Mozilla/5.0 (X11; Linux x86_64; rv:128.0) Gecko/20100101 Firefox/128.0"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+        assert_eq!(resp.headers().get("Content-Type").unwrap(), "video/quicktime");
+
+        // A cached copy is served as H.264 MP4 with its own ETag
+        temp.child(".transcodes/phone.mov.mp4").write_binary(b"h264 copy").unwrap();
+        let req = test::TestRequest::get().uri("/images/phone.mov?unsupported_codecs=hevc").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.headers().get("Content-Type").unwrap(), "video/mp4");
+        assert!(resp.headers().get("ETag").unwrap().to_str().unwrap().ends_with("-h264\""));
+        assert_eq!(test::read_body(resp).await, "h264 copy");
+
+        let req = test::TestRequest::get().uri("/images/phone.mov?unsupported_codecs=av1").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.headers().get("Content-Type").unwrap(), "video/quicktime");
+    }
+
+    #[actix_rt::test]
+    async fn test_video_roots_serve_ranges_and_list_folders() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        temp.child("2024/clip 1.mp4").write_binary(b"0123456789").unwrap();
+        temp.child("2024/extras/b.mov").write_binary(b"movie").unwrap();
+        temp.child("2024/info/c.mp4").write_binary(b"movie").unwrap();
+        let config = config::Config {
+            video_roots: std::collections::HashMap::from([("haley-reed".to_string(), temp.path().to_path_buf())]),
+            ..config::Config::default()
+        };
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(temp.path().to_path_buf()))
+                .app_data(web::Data::new(config))
+                .app_data(web::Data::new(video::ProbeCache::default()))
+                .service(video_info)
+                .service(video_root)
+        ).await;
+
+        let req = test::TestRequest::get()
+            .uri("/videos/haley-reed/2024/clip%201.mp4")
+            .insert_header(("Range", "bytes=2-5"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 206);
+        assert_eq!(resp.headers().get("content-range").unwrap(), "bytes 2-5/10");
+        assert!(resp.headers().contains_key("etag"));
+        assert_eq!(test::read_body(resp).await, "2345");
+
+        let req = test::TestRequest::get().uri("/videos/haley-reed").to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["path"], "");
+        assert_eq!(body["entries"][0]["kind"], "folder");
+        assert_eq!(body["entries"][0]["url"], "/videos/haley-reed/2024");
+
+        let req = test::TestRequest::get().uri("/videos/haley-reed/2024/").to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["path"], "2024");
+        let entries: Vec<_> = body["entries"].as_array().unwrap().iter().map(|e| e["url"].as_str().unwrap()).collect();
+        assert_eq!(entries, [
+            "/videos/haley-reed/2024/extras",
+            "/videos/haley-reed/2024/info",
+            "/videos/haley-reed/2024/clip%201.mp4",
+        ]);
+        assert_eq!(body["entries"][2]["size_bytes"], 10);
+
+        // Video endpoints sit below files, so folders named after them stay reachable
+        let req = test::TestRequest::get().uri("/videos/haley-reed/2024/clip%201.mp4/info").to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["filename"], "clip 1.mp4");
+        assert_eq!(body["size_bytes"], 10);
+        let req = test::TestRequest::get().uri("/videos/haley-reed/2024/info").to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["path"], "2024/info");
+        assert_eq!(body["entries"][0]["url"], "/videos/haley-reed/2024/info/c.mp4");
+
+        for uri in ["/videos/other/clip.mp4", "/videos/haley-reed/2024/missing.mp4"] {
+            let req = test::TestRequest::get().uri(uri).to_request();
+            assert_eq!(test::call_service(&app, req).await.status(), 404, "{}", uri);
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_video_keyframes_lists_sync_samples() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        let boxed = |kind: &[u8; 4], payload: &[u8]| {
+            let mut bytes = ((payload.len() + 8) as u32).to_be_bytes().to_vec();
+            bytes.extend_from_slice(kind);
+            bytes.extend_from_slice(payload);
+            bytes
+        };
+        // 8 samples of half a second at a 1000 timescale, keyframes 1, 2 and 5
+        let mut mdhd = vec![0u8; 12];
+        mdhd.extend_from_slice(&1000u32.to_be_bytes());
+        mdhd.extend_from_slice(&[0u8; 8]);
+        let mut hdlr = vec![0u8; 8];
+        hdlr.extend_from_slice(b"vide");
+        let stts = [0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 8, 0, 0, 1, 0xf4];
+        let stss = [0, 0, 0, 0, 0, 0, 0, 3, 0, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0, 5];
+        let mut stbl = boxed(b"stts", &stts);
+        stbl.extend(boxed(b"stss", &stss));
+        let mut mdia = boxed(b"mdhd", &mdhd);
+        mdia.extend(boxed(b"hdlr", &hdlr));
+        mdia.extend(boxed(b"minf", &boxed(b"stbl", &stbl)));
+        temp.child("clip #1.mp4")
+            .write_binary(&boxed(b"moov", &boxed(b"trak", &boxed(b"mdia", &mdia))))
+            .unwrap();
+        temp.child("photo.jpg").write_binary(b"jpeg").unwrap();
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(temp.path().to_path_buf()))
+                .app_data(web::Data::new(config::Config::default()))
+                .app_data(web::Data::new(transcode::TranscodeCache::new(temp.path(), &config::Config::default())))
+                .app_data(web::Data::new(video::ProbeCache::default()))
+                .service(video_keyframes)
+                .service(video_frame)
+                .service(video_thumbnail)
+                .service(video_preview)
+                .service(video_info)
+                .service(video_playlist)
+                .service(video_segment)
+        ).await;
+
+        let req = test::TestRequest::get().uri("/videos/library/clip%20%231.mp4/keyframes?thumbnails=true&size=64").to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["filename"], "clip #1.mp4");
+        // The keyframe at 0.5s is within a second of the first
+        assert_eq!(body["keyframes"], serde_json::json!([
+            {"time_secs": 0.0, "thumbnail_url": "/videos/library/clip%20%231.mp4/frame?t=0&size=64"},
+            {"time_secs": 2.0, "thumbnail_url": "/videos/library/clip%20%231.mp4/frame?t=2&size=64"},
+        ]));
+
+        let req = test::TestRequest::get().uri("/videos/library/clip%20%231.mp4/keyframes?min_interval=0").to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["keyframes"], serde_json::json!([{"time_secs": 0.0}, {"time_secs": 0.5}, {"time_secs": 2.0}]));
+
+        let req = test::TestRequest::get().uri("/videos/library/photo.jpg/keyframes").to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 404);
+        let req = test::TestRequest::get().uri("/videos/library/clip%20%231.mp4/frame?t=-1").to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 400);
+        let req = test::TestRequest::get().uri("/videos/library/clip%20%231.mp4/keyframes?size=100").to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 400);
+
+        // Thumbnails come from the disk cache when present, without running ffmpeg.
+        // Cached files carry the modification time of the video they were made from.
+        let video_modified = std::fs::metadata(temp.child("clip #1.mp4").path()).unwrap().modified().unwrap();
+        let stamp = |path: &std::path::Path| {
+            std::fs::File::options().write(true).open(path).unwrap().set_modified(video_modified).unwrap();
+        };
+        let poster = thumbnails::poster_path(temp.path(), "clip #1.mp4", 256, 1500, processor::ConvertFormat::Webp);
+        std::fs::create_dir_all(poster.parent().unwrap()).unwrap();
+        std::fs::write(&poster, processor::ImageProcessor::convert(&image::DynamicImage::new_rgb8(4, 4), processor::ConvertFormat::Webp, 80).unwrap().bytes).unwrap();
+        stamp(&poster);
+        let req = test::TestRequest::get().uri("/videos/library/clip%20%231.mp4/thumbnail?at=1.5s&format=webp").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+        assert_eq!(resp.headers().get("content-type").unwrap(), "image/webp");
+        assert!(resp.headers().contains_key("etag"));
+        for uri in ["/videos/library/clip%20%231.mp4/thumbnail?at=1:75", "/videos/library/clip%20%231.mp4/thumbnail?format=gif"] {
+            let req = test::TestRequest::get().uri(uri).to_request();
+            assert_eq!(test::call_service(&app, req).await.status(), 400, "{}", uri);
+        }
+        let req = test::TestRequest::get().uri("/videos/library/photo.jpg/thumbnail").to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 404);
+
+        let req = test::TestRequest::get().uri("/videos/library/clip%20%231.mp4/info").to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["filename"], "clip #1.mp4");
+        assert_eq!(body["container"], "mp4");
+        assert_eq!(body["duration_secs"], serde_json::Value::Null);
+        let req = test::TestRequest::get().uri("/videos/library/photo.jpg/info").to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 404);
+
+        // Previews are served from the disk cache too
+        let animation = preview::encode(&[image::DynamicImage::new_rgb8(4, 4)], preview::PreviewFormat::Gif).unwrap();
+        let cached = thumbnails::preview_path(temp.path(), "clip #1.mp4", 128, preview::PreviewFormat::Gif);
+        std::fs::create_dir_all(cached.parent().unwrap()).unwrap();
+        std::fs::write(&cached, &animation.bytes).unwrap();
+        stamp(&cached);
+        let req = test::TestRequest::get().uri("/videos/library/clip%20%231.mp4/preview?size=128&format=gif").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.headers().get("content-type").unwrap(), "image/gif");
+        let req = test::TestRequest::get().uri("/videos/library/clip%20%231.mp4/preview?format=png").to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 400);
+
+        // HLS files come from the segment cache when it is fresh
+        let hls = transcode::TranscodeCache::new(temp.path(), &config::Config::default()).hls_dir_for("clip #1.mp4");
+        temp.child(hls.join(transcode::HLS_PLAYLIST)).write_str("#EXTM3U\nsegments/seg00000.ts\n").unwrap();
+        temp.child(hls.join("seg00000.ts")).write_binary(b"ts").unwrap();
+        let req = test::TestRequest::get().uri("/videos/library/clip%20%231.mp4/playlist.m3u8").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.headers().get("content-type").unwrap(), "application/vnd.apple.mpegurl");
+        assert!(test::read_body(resp).await.starts_with(b"#EXTM3U"));
+        let req = test::TestRequest::get().uri("/videos/library/clip%20%231.mp4/segments/seg00000.ts").to_request();
+        assert_eq!(test::call_and_read_body(&app, req).await, "ts");
+        for uri in ["/videos/library/clip%20%231.mp4/segments/seg00001.ts", "/videos/library/clip%20%231.mp4/segments/index.m3u8"] {
+            let req = test::TestRequest::get().uri(uri).to_request();
+            assert_eq!(test::call_service(&app, req).await.status(), 404, "{}", uri);
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_content_policy_is_enforced() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        image::DynamicImage::new_rgb8(4, 4).save(temp.child("a.jpg").path()).unwrap();
+        image::DynamicImage::new_rgb8(4, 4).save(temp.child("b.png").path()).unwrap();
+
+        let config = config::Config {
+            policy: policy::ContentPolicy {
+                allowed_extensions: vec!["jpg".to_string()],
+                download_originals: false,
+                cache: policy::CacheVisibility::NoStore,
+                ..policy::ContentPolicy::default()
+            },
+            ..config::Config::default()
+        };
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(temp.path().to_path_buf()))
+                .app_data(web::Data::new(config))
+                .app_data(web::Data::new(prefetch::ThumbnailPrefetcher::disabled()))
+                .app_data(web::Data::new(stats::ViewStats::default()))
+                .app_data(web::Data::new(cache::ImageCache::default()))
+                .app_data(web::Data::new(cache::VariantCache::default()))
+                .app_data(web::Data::new(transcode::TranscodeCache::new(temp.path(), &config::Config::default())))
+                .service(serve_image)
+                .service(download_image)
+                .service(image_info)
+                .service(list_images)
+        ).await;
+
+        let req = test::TestRequest::get().uri("/images/b.png?w=2").to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 404);
+        let req = test::TestRequest::get().uri("/images/b.png/info").to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 404);
+
+        let req = test::TestRequest::get().uri("/images/a.jpg").to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 403);
+        let req = test::TestRequest::get().uri("/images/a.jpg/download").to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 403);
+
+        let req = test::TestRequest::get().uri("/images/a.jpg?w=2").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+        assert_eq!(resp.headers().get("Cache-Control").unwrap(), "no-store");
+
+        let req = test::TestRequest::get().uri("/gallery/images").to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["total"], 1);
+        assert_eq!(body["images"][0]["filename"], "a.jpg");
+    }
+
+    #[actix_rt::test]
+    async fn test_usage_is_tracked_per_api_key() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        temp.child("a.jpg").write_binary(b"fake image content").unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(temp.path().to_path_buf()))
+                .app_data(web::Data::new(config::Config::default()))
+                .app_data(web::Data::new(stats::ViewStats::default()))
+                .app_data(web::Data::new(cache::ImageCache::default()))
+                .app_data(web::Data::new(cache::VariantCache::default()))
+                .app_data(web::Data::new(transcode::TranscodeCache::new(temp.path(), &config::Config::default())))
+                .app_data(web::Data::new(usage::UsageTracker::default()))
+                .wrap(actix_web::middleware::from_fn(usage::track))
+                .service(download_image)
+                .service(serve_image)
+                .service(usage_report)
+        ).await;
+
+        for _ in 0..2 {
+            let req = test::TestRequest::get()
+                .uri("/images/a.jpg")
+                .insert_header(("X-API-Key", "integration-secret"))
+                .to_request();
+            assert_eq!(test::call_and_read_body(&app, req).await.len(), 18);
+        }
+        // Streamed ranges count the bytes actually sent
+        let req = test::TestRequest::get()
+            .uri("/images/a.jpg/download")
+            .insert_header(("X-API-Key", "integration-secret"))
+            .insert_header(("Range", "bytes=0-3"))
+            .to_request();
+        assert_eq!(test::call_and_read_body(&app, req).await.len(), 4);
+        let req = test::TestRequest::get().uri("/images/missing.jpg").to_request();
+        test::call_service(&app, req).await;
+
+        let req = test::TestRequest::get().uri("/admin/usage?period=1h").to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["period"], "1h");
+        assert_eq!(body["keys"][0]["key"], "integr…");
+        assert_eq!(body["keys"][0]["requests"], 3);
+        assert_eq!(body["keys"][0]["bytes_served"], 40);
+        assert_eq!(body["keys"][1]["key"], "anonymous");
+
+        let req = test::TestRequest::get().uri("/admin/usage?period=1y").to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 400);
+    }
+
+    #[actix_rt::test]
+    async fn test_transform_budget_per_api_key() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        image::DynamicImage::new_rgb8(1200, 600)
+            .save(temp.child("wide.png").path())
+            .unwrap();
+
+        for over_budget in [budget::OverBudget::Reject, budget::OverBudget::Degrade] {
+            let config = config::Config {
+                transform_budgets: [("batch".to_string(), 1e-12)].into(),
+                over_budget,
+                ..config::Config::default()
+            };
+            let app = test::init_service(
+                App::new()
+                    .app_data(web::Data::new(temp.path().to_path_buf()))
+                    .app_data(web::Data::new(stats::ViewStats::default()))
+                    .app_data(web::Data::new(cache::ImageCache::default()))
+                    .app_data(web::Data::new(cache::VariantCache::default()))
+                    .app_data(web::Data::new(transcode::TranscodeCache::new(temp.path(), &config)))
+                    .app_data(web::Data::new(config))
+                    .app_data(web::Data::new(budget::TransformBudget::default()))
+                    .wrap(actix_web::middleware::from_fn(budget::enforce))
+                    .service(serve_image)
+            ).await;
+
+            let resize = |uri: &str, key: &str| {
+                test::TestRequest::get().uri(uri).insert_header(("X-API-Key", key)).to_request()
+            };
+            let resp = test::call_service(&app, resize("/images/wide.png?w=10", "batch")).await;
+            assert_eq!(resp.status(), 200);
+
+            let resp = test::call_service(&app, resize("/images/wide.png?w=2000", "batch")).await;
+            match over_budget {
+                budget::OverBudget::Reject => {
+                    assert_eq!(resp.status(), 429);
+                    assert!(resp.headers().contains_key("Retry-After"));
+                    let body: serde_json::Value = test::read_body_json(resp).await;
+                    assert_eq!(body["error"], "rate_limited");
+                }
+                budget::OverBudget::Degrade => {
+                    assert_eq!(resp.status(), 200);
+                    let resized = image::load_from_memory(&test::read_body(resp).await).unwrap();
+                    assert_eq!(image::GenericImageView::width(&resized), budget::DEGRADED_MAX_DIMENSION);
+                }
+            }
+
+            // Originals and other keys are not metered
+            let resp = test::call_service(&app, resize("/images/wide.png", "batch")).await;
+            assert_eq!(resp.status(), 200);
+            let resp = test::call_service(&app, resize("/images/wide.png?w=20", "viewer")).await;
+            assert_eq!(resp.status(), 200);
+        }
+
+        // Keys without a budget of their own share the default one
+        let config = config::Config {
+            transform_budget: Some(1e-12),
+            ..config::Config::default()
+        };
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(temp.path().to_path_buf()))
+                .app_data(web::Data::new(stats::ViewStats::default()))
+                .app_data(web::Data::new(cache::ImageCache::default()))
+                .app_data(web::Data::new(cache::VariantCache::default()))
+                .app_data(web::Data::new(transcode::TranscodeCache::new(temp.path(), &config)))
+                .app_data(web::Data::new(config))
+                .app_data(web::Data::new(budget::TransformBudget::default()))
+                .wrap(actix_web::middleware::from_fn(budget::enforce))
+                .service(serve_image)
+        ).await;
+        let resize = |key: &str| {
+            test::TestRequest::get().uri("/images/wide.png?w=10").insert_header(("X-API-Key", key)).to_request()
+        };
+        assert_eq!(test::call_service(&app, resize("made-up-1")).await.status(), 200);
+        assert_eq!(test::call_service(&app, resize("made-up-2")).await.status(), 429);
+    }
+
+    #[actix_rt::test]
+    async fn test_manifest_lists_checksums() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        temp.child("b.jpg").write_binary(b"abc").unwrap();
+        temp.child("a.png").write_binary(b"").unwrap();
+        temp.child("notes.txt").write_binary(b"not media").unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(temp.path().to_path_buf()))
+                .app_data(web::Data::new(config::Config::default()))
+                .service(library_manifest)
+        ).await;
+
+        let req = test::TestRequest::get().uri("/admin/manifest").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.headers().get("Content-Type").unwrap(), "application/x-ndjson");
+        let body = test::read_body(resp).await;
+        let entries: Vec<serde_json::Value> = body
+            .split(|b| *b == b'\n')
+            .filter(|line| !line.is_empty())
+            .map(|line| serde_json::from_slice(line).unwrap())
+            .collect();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0]["filename"], "a.png");
+        assert_eq!(entries[0]["sha256"], "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855");
+        assert_eq!(entries[1]["size_bytes"], 3);
+
+        let req = test::TestRequest::get().uri("/admin/manifest?format=csv").to_request();
+        let body = String::from_utf8(test::call_and_read_body(&app, req).await.to_vec()).unwrap();
+        let lines: Vec<&str> = body.lines().collect();
+        assert_eq!(lines[0], "filename,size_bytes,modified,sha256");
+        assert!(lines[2].starts_with("b.jpg,3,"));
+        assert!(lines[2].ends_with(",ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"));
+
+        let req = test::TestRequest::get().uri("/admin/manifest?format=xml").to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 400);
+    }
+
+    #[actix_rt::test]
+    async fn test_backup_changes_since() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        let day = std::time::Duration::from_secs(24 * 3600);
+        for (name, age_days) in [("old.jpg", 10), ("new.jpg", 1), ("newer.png", 0)] {
+            temp.child(name).write_binary(b"image").unwrap();
+            std::fs::File::options()
+                .write(true)
+                .open(temp.child(name).path())
+                .unwrap()
+                .set_modified(std::time::SystemTime::now() - day * age_days)
+                .unwrap();
+        }
+        let app = test::init_service(
+            App::new()
+                .app_data(error::query_config())
+                .app_data(web::Data::new(temp.path().to_path_buf()))
+                .app_data(web::Data::new(config::Config::default()))
+                .service(backup_changes)
+                .service(backup_diff)
+        ).await;
+
+        let req = test::TestRequest::get().uri("/admin/backup/changed").to_request();
+        let full: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        let names: Vec<_> = full["files"].as_array().unwrap().iter().map(|f| f["filename"].clone()).collect();
+        assert_eq!(names, vec!["old.jpg", "new.jpg", "newer.png"]);
+        assert_eq!(full["files"][0]["download_url"], "/images/old.jpg/download");
+        assert!(full.get("removed").is_none());
+
+        // Back-dated files were still just copied in, so they count as changed
+        let since = (chrono::Utc::now() - chrono::Duration::days(2)).to_rfc3339_opts(chrono::SecondsFormat::Secs, true);
+        let req = test::TestRequest::get().uri(&format!("/admin/backup/changed?since={}", since)).to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["files"].as_array().unwrap().len(), 3);
+
+        let req = test::TestRequest::get()
+            .uri(&format!("/admin/backup/changed?since={}", body["next_since"].as_str().unwrap()))
+            .to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["files"].as_array().unwrap().len(), 0);
+
+        // Against the backup's own file list: edits, additions and removals
+        let mut backed_up = full["files"].as_array().unwrap().clone();
+        backed_up.push(serde_json::json!({"filename": "gone.jpg", "size_bytes": 1, "modified": null}));
+        temp.child("new.jpg").write_binary(b"edited image").unwrap();
+        std::fs::File::options()
+            .write(true)
+            .open(temp.child("new.jpg").path())
+            .unwrap()
+            .set_modified(std::time::SystemTime::now() - day)
+            .unwrap();
+        temp.child("added.jpg").write_binary(b"image").unwrap();
+        let req = test::TestRequest::post()
+            .uri("/admin/backup/changed")
+            .set_json(serde_json::json!({"files": backed_up}))
+            .to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        let names: Vec<_> = body["files"].as_array().unwrap().iter().map(|f| f["filename"].clone()).collect();
+        assert_eq!(names, vec!["new.jpg", "added.jpg"]);
+        assert_eq!(body["removed"], serde_json::json!(["gone.jpg"]));
+
+        let req = test::TestRequest::get().uri("/admin/backup/changed?since=yesterday").to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 400);
+    }
+
+    #[actix_rt::test]
+    async fn test_bearer_authentication() {
+        let (pair, jwk) = auth::tests::signing_key("k1");
+        let exp = chrono::Utc::now().timestamp() + 300;
+        let token = auth::tests::sign(&pair, "k1", serde_json::json!({"sub": "user-1", "aud": "images-api", "exp": exp}));
+        let oidc = |required| auth::OidcConfig {
+            audience: "images-api".to_string(),
+            jwks_url: "https://idp.example/jwks".to_string(),
+            required,
+            ..auth::OidcConfig::default()
+        };
+
+        for required in [false, true] {
+            let app = test::init_service(
+                App::new()
+                    .app_data(web::Data::new(auth::Authenticator::with_keys(oidc(required), vec![jwk.clone()])))
+                    .wrap(actix_web::middleware::from_fn(auth::authenticate))
+                    .service(current_user)
+                    .route("/health", web::get().to(actix_web::HttpResponse::Ok))
+                    .route("/public", web::get().to(actix_web::HttpResponse::Ok))
+            ).await;
+
+            let req = test::TestRequest::get()
+                .uri("/me")
+                .insert_header(("Authorization", format!("Bearer {}", token)))
+                .to_request();
+            let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+            assert_eq!(body["subject"], "user-1");
+            assert_eq!(body["claims"]["aud"], "images-api");
+
+            let req = test::TestRequest::get()
+                .uri("/public")
+                .insert_header(("Authorization", "Bearer forged.token.here"))
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), 401);
+            assert_eq!(resp.headers().get("WWW-Authenticate").unwrap(), "Bearer");
+
+            let req = test::TestRequest::get().uri("/public").to_request();
+            assert_eq!(test::call_service(&app, req).await.status(), if required { 401 } else { 200 });
+            let req = test::TestRequest::get().uri("/me").to_request();
+            assert_eq!(test::call_service(&app, req).await.status(), 401);
+            let req = test::TestRequest::get().uri("/health").to_request();
+            assert_eq!(test::call_service(&app, req).await.status(), 200);
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_referenced_folders_are_served_in_place() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        let archive = assert_fs::TempDir::new().unwrap();
+        image::DynamicImage::new_rgb8(40, 20).save(temp.child("local.png").path()).unwrap();
+        image::DynamicImage::new_rgb8(40, 20).save(archive.child("old.png").path()).unwrap();
+        image::DynamicImage::new_rgb8(10, 10).save(archive.child("local.png").path()).unwrap();
+        let config = config::Config {
+            policy: policy::ContentPolicy {
+                referenced_dirs: vec![archive.path().to_path_buf()],
+                ..policy::ContentPolicy::default()
+            },
+            ..config::Config::default()
+        };
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(temp.path().to_path_buf()))
+                .app_data(web::Data::new(stats::ViewStats::default()))
+                .app_data(web::Data::new(cache::ImageCache::default()))
+                .app_data(web::Data::new(cache::VariantCache::default()))
+                .app_data(web::Data::new(transcode::TranscodeCache::new(temp.path(), &config)))
+                .app_data(web::Data::new(config))
+                .app_data(web::Data::new(prefetch::ThumbnailPrefetcher::disabled()))
+                .service(list_images)
+                .service(image_thumbnail)
+                .service(serve_image)
+        ).await;
+
+        let req = test::TestRequest::get().uri("/gallery/images?sort=name").to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["total"], 2);
+        assert_eq!(body["images"][0]["filename"], "local.png");
+        assert!(body["images"][0].get("referenced_path").is_none());
+        assert_eq!(body["images"][1]["filename"], "old.png");
+        assert_eq!(body["images"][1]["referenced_path"], archive.path().join("old.png").to_str().unwrap());
+        assert_eq!(
+            body["images"][1]["renditions"][0]["referenced_path"],
+            archive.path().join("old.png").to_str().unwrap()
+        );
+
+        for uri in ["/images/old.png", "/images/old.png?w=10", "/images/old.png/thumbnail?size=64"] {
+            let req = test::TestRequest::get().uri(uri).to_request();
+            assert_eq!(test::call_service(&app, req).await.status(), 200, "{}", uri);
+        }
+        // Thumbnails are cached in the images directory, not the referenced folder
+        assert!(thumbnails::thumbnail_path(temp.path(), "old.png", 64).exists());
+        assert!(!archive.path().join(thumbnails::THUMBNAIL_DIR).exists());
+    }
+
+    #[actix_rt::test]
+    async fn test_ignored_files_are_not_served() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        let archive = assert_fs::TempDir::new().unwrap();
+        temp.child(".galleryignore").write_str("private-*\nshared.png\n").unwrap();
+        image::DynamicImage::new_rgb8(40, 20).save(temp.child("private-1.png").path()).unwrap();
+        image::DynamicImage::new_rgb8(40, 20).save(temp.child("shared.png").path()).unwrap();
+        image::DynamicImage::new_rgb8(10, 10).save(archive.child("shared.png").path()).unwrap();
+        let config = config::Config {
+            policy: policy::ContentPolicy {
+                referenced_dirs: vec![archive.path().to_path_buf()],
+                ..policy::ContentPolicy::default()
+            },
+            ..config::Config::default()
+        };
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(temp.path().to_path_buf()))
+                .app_data(web::Data::new(stats::ViewStats::default()))
+                .app_data(web::Data::new(cache::ImageCache::default()))
+                .app_data(web::Data::new(cache::VariantCache::default()))
+                .app_data(web::Data::new(transcode::TranscodeCache::new(temp.path(), &config)))
+                .app_data(web::Data::new(config))
+                .service(image_thumbnail)
+                .service(download_image)
+                .service(image_info)
+                .service(serve_image)
+        ).await;
+
+        for uri in [
+            "/images/private-1.png",
+            "/images/PRIVATE-1.png",
+            "/images/private-1.png/info",
+            "/images/private-1.png/download",
+            "/images/private-1.png/thumbnail?size=64",
+        ] {
+            let req = test::TestRequest::get().uri(uri).to_request();
+            assert_eq!(test::call_service(&app, req).await.status(), 404, "{}", uri);
+        }
+
+        // An ignored name falls through to the referenced folder's file
+        let req = test::TestRequest::get().uri("/images/shared.png/info").to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["referenced_path"], archive.path().join("shared.png").to_str().unwrap());
+        assert_eq!(body["dimensions"], serde_json::json!([10, 10]));
+    }
+
+    #[actix_rt::test]
+    async fn test_offline_volume_answers_503() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        temp.child("local.jpg").write_binary(b"fake image content").unwrap();
+        let drive = temp.path().join("VideosNew");
+        let config = config::Config {
+            policy: policy::ContentPolicy {
+                referenced_dirs: vec![drive.clone()],
+                ..policy::ContentPolicy::default()
+            },
+            ..config::Config::default()
+        };
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(temp.path().to_path_buf()))
+                .app_data(web::Data::new(stats::ViewStats::default()))
+                .app_data(web::Data::new(cache::ImageCache::default()))
+                .app_data(web::Data::new(cache::VariantCache::default()))
+                .app_data(web::Data::new(transcode::TranscodeCache::new(temp.path(), &config)))
+                .app_data(web::Data::new(config))
+                .service(volume_health)
+                .service(serve_image)
+        ).await;
+
+        let req = test::TestRequest::get().uri("/images/clip.mp4").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 503);
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(body["error"], "volume_offline");
+        assert_eq!(body["message"], "Volume VideosNew is offline");
+        let req = test::TestRequest::get().uri("/images/local.jpg").to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 200);
+
+        let req = test::TestRequest::get().uri("/health/volumes").to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["status"], "degraded");
+        assert_eq!(body["volumes"][1]["name"], "VideosNew");
+        assert_eq!(body["volumes"][1]["online"], false);
+
+        std::fs::create_dir(&drive).unwrap();
+        let req = test::TestRequest::get().uri("/images/clip.mp4").to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 404);
+        let req = test::TestRequest::get().uri("/health/volumes").to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["status"], "healthy");
+    }
+
+    #[actix_rt::test]
+    async fn test_signed_urls_bypass_required_auth() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        image::DynamicImage::new_rgb8(40, 20).save(temp.child("a b.png").path()).unwrap();
+        let (pair, jwk) = auth::tests::signing_key("k1");
+        let token = auth::tests::sign(&pair, "k1", serde_json::json!({"sub": "user-1", "exp": chrono::Utc::now().timestamp() + 300}));
+        let oidc = auth::OidcConfig {
+            jwks_url: "https://idp.example/jwks".to_string(),
+            required: true,
+            ..auth::OidcConfig::default()
+        };
+        let config = config::Config::default();
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(temp.path().to_path_buf()))
+                .app_data(web::Data::new(stats::ViewStats::default()))
+                .app_data(web::Data::new(cache::ImageCache::default()))
+                .app_data(web::Data::new(cache::VariantCache::default()))
+                .app_data(web::Data::new(transcode::TranscodeCache::new(temp.path(), &config)))
+                .app_data(web::Data::new(config))
+                .app_data(web::Data::new(indexer::LibraryIndex::default()))
+                .app_data(web::Data::new(auth::Authenticator::with_keys(oidc, vec![jwk])))
+                .app_data(web::Data::new(signing::UrlSigner::new(b"secret")))
+                .wrap(actix_web::middleware::from_fn(auth::authenticate))
+                .service(signed_url)
+                .service(image_thumbnail)
+                .service(serve_image)
+                .service(delete_image)
+        ).await;
+
+        let req = test::TestRequest::get().uri("/images/a%20b.png/signed-url?thumbnail_size=64").to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 401);
+        let req = test::TestRequest::get()
+            .uri("/images/a%20b.png/signed-url?thumbnail_size=64&expires_in=60")
+            .insert_header(("Authorization", format!("Bearer {}", token)))
+            .to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        let url = body["url"].as_str().unwrap();
+        assert!(url.starts_with("/images/a%20b.png/thumbnail?size=64&expires="));
+
+        let req = test::TestRequest::get().uri(url).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+        assert_eq!(resp.headers().get("Content-Type").unwrap(), "image/png");
+
+        let req = test::TestRequest::get().uri(&url.replace("size=64", "size=1024")).to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 403);
+        let req = test::TestRequest::get().uri("/images/a%20b.png").to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 401);
+
+        // A link to read the image is no licence to delete it
+        let req = test::TestRequest::get()
+            .uri("/images/a%20b.png/signed-url")
+            .insert_header(("Authorization", format!("Bearer {}", token)))
+            .to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        let url = body["url"].as_str().unwrap();
+        let req = test::TestRequest::get().uri(url).to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 200);
+        let req = test::TestRequest::delete().uri(url).to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 401);
+        assert!(temp.child("a b.png").path().exists());
+
+        let req = test::TestRequest::get()
+            .uri("/images/a%20b.png/signed-url?expires_in=0")
+            .insert_header(("Authorization", format!("Bearer {}", token)))
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 400);
+    }
+
+    #[actix_rt::test]
+    async fn test_public_routes_expose_only_published_items() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        image::DynamicImage::new_rgb8(40, 20).save(temp.child("shared.png").path()).unwrap();
+        image::DynamicImage::new_rgb8(40, 20).save(temp.child("private.png").path()).unwrap();
+        let (_, jwk) = auth::tests::signing_key("k1");
+        let oidc = auth::OidcConfig {
+            jwks_url: "https://idp.example/jwks".to_string(),
+            required: true,
+            ..auth::OidcConfig::default()
+        };
+        let config = config::Config::default();
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(temp.path().to_path_buf()))
+                .app_data(web::Data::new(transcode::TranscodeCache::new(temp.path(), &config)))
+                .app_data(web::Data::new(config))
+                .app_data(web::Data::new(auth::Authenticator::with_keys(oidc, vec![jwk])))
+                .app_data(web::Data::new(signing::UrlSigner::new(b"secret")))
+                .wrap(actix_web::middleware::from_fn(auth::authenticate))
+                .service(public_images)
+                .service(public_thumbnail)
+                .service(public_image)
+                .service(json_feed)
+        ).await;
+        let admin = test::init_service(
+            App::new()
+                .app_data(web::Data::new(temp.path().to_path_buf()))
+                .app_data(web::Data::new(config::Config::default()))
+                .service(set_published)
+        ).await;
+
+        let req = test::TestRequest::put().uri("/admin/published/shared.png").set_json(serde_json::json!({"published": true})).to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&admin, req).await;
+        assert_eq!(body["public_url"], "/public/images/shared.png");
+
+        let req = test::TestRequest::get().uri("/public/images").to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["total"], 1);
+        assert_eq!(body["images"][0]["filename"], "shared.png");
+        assert_eq!(body["images"][0]["thumbnail_url"], "/public/images/shared.png/thumbnail?size=256");
+        assert!(body["images"][0].get("size_bytes").is_none());
+
+        let req = test::TestRequest::get().uri("/feed.json").to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["items"].as_array().unwrap().len(), 1);
+        assert_eq!(body["items"][0]["id"], "shared.png");
+
+        let req = test::TestRequest::get().uri("/public/images/shared.png").to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 200);
+        let req = test::TestRequest::get().uri("/public/images/shared.png/thumbnail?size=64").to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 200);
+        for uri in ["/public/images/private.png", "/public/images/private.png/thumbnail", "/public/images/missing.png"] {
+            let req = test::TestRequest::get().uri(uri).to_request();
+            assert_eq!(test::call_service(&app, req).await.status(), 404, "{}", uri);
+        }
+
+        let req = test::TestRequest::put().uri("/admin/published/shared.png").set_json(serde_json::json!({"published": false})).to_request();
+        assert_eq!(test::call_service(&admin, req).await.status(), 200);
+        let req = test::TestRequest::get().uri("/public/images/shared.png").to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 404);
+    }
+
+    #[actix_rt::test]
+    async fn test_dashboard_scores_library_health() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        temp.child("a.jpg").write_binary(b"a").unwrap();
+        let index = web::Data::new(indexer::LibraryIndex::default());
+        index.begin();
+        index.run(temp.path(), &[]);
+        let usage = web::Data::new(usage::UsageTracker::default());
+        usage.record("k", usage::Usage { requests: 10, errors: 1, ..Default::default() }, chrono::Utc::now());
+        let config = config::Config {
+            min_free_disk_bytes: 0,
+            ..Default::default()
+        };
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(temp.path().to_path_buf()))
+                .app_data(web::Data::new(config))
+                .app_data(web::Data::new(cache::ImageCache::default()))
+                .app_data(web::Data::new(cache::VariantCache::default()))
+                .app_data(index)
+                .app_data(usage)
+                .service(admin_dashboard)
+        ).await;
+
+        let req = test::TestRequest::get().uri("/admin/dashboard").to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["index"]["state"], "completed");
+        assert_eq!(body["index"]["images"], 1);
+        assert_eq!(body["index"]["score"], 100);
+        assert_eq!(body["errors"]["server_errors"], 1);
+        assert_eq!(body["errors"]["score"], 0);
+        assert!(body["caches"]["hit_rate"].is_null());
+        assert_eq!(body["storage"]["score"], 100);
+        assert!(body["storage"]["free_bytes"].is_u64());
+        assert_eq!(body["score"], 75);
+        assert_eq!(body["status"], "degraded");
+    }
+
+    #[actix_rt::test]
+    async fn test_inspect_dumps_metadata_and_caches() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        image::DynamicImage::new_rgb8(40, 20).save(temp.child("wide.png").path()).unwrap();
+        let path = temp.child("wide.png").path().to_path_buf();
+        tags::write(&path, &["Red\n6".to_string()]).unwrap();
+        publish::set_published(&path, true).unwrap();
+        let config = config::Config::default();
+        let index = web::Data::new(indexer::LibraryIndex::default());
+        index.begin();
+        index.run(temp.path(), &[]);
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(temp.path().to_path_buf()))
+                .app_data(web::Data::new(transcode::TranscodeCache::new(temp.path(), &config)))
+                .app_data(web::Data::new(config))
+                .app_data(web::Data::new(stats::ViewStats::default()))
+                .app_data(web::Data::new(cache::ImageCache::default()))
+                .app_data(web::Data::new(cache::VariantCache::default()))
+                .app_data(index)
+                .service(image_thumbnail)
+                .service(inspect_file)
+                .service(serve_image)
+        ).await;
+
+        for uri in ["/images/wide.png", "/images/wide.png?w=10", "/images/wide.png/thumbnail?size=64"] {
+            assert_eq!(test::call_service(&app, test::TestRequest::get().uri(uri).to_request()).await.status(), 200);
+        }
+
+        let req = test::TestRequest::get().uri("/admin/inspect/wide.png").to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["filename"], "wide.png");
+        assert_eq!(body["mime"], "image/png");
+        assert_eq!(body["stat"]["size_bytes"], std::fs::metadata(&path).unwrap().len());
+        assert_eq!(body["metadata"]["dimensions"], serde_json::json!([40, 20]));
+        assert_eq!(body["metadata"]["tags"][0]["name"], "Red");
+        assert_eq!(body["metadata"]["published"], true);
+        assert_eq!(body["xattrs"][publish::PUBLISHED_XATTR], "1");
+        assert!(body["xattrs"][tags::USER_TAGS_XATTR].as_str().unwrap().starts_with("base64:"));
+        assert_eq!(body["index"]["filename"], "wide.png");
+        assert_eq!(body["stats"]["views"], 2);
+        assert!(body["cache"]["original_bytes"].as_u64().unwrap() > 0);
+        assert_eq!(body["cache"]["variants"].as_array().unwrap().len(), 1);
+        assert_eq!(body["cache"]["variants"][0]["width"], 10);
+        assert_eq!(body["cache"]["thumbnails"], serde_json::json!([64]));
+        assert_eq!(body["cache"]["transcoded"], false);
+
+        let req = test::TestRequest::get().uri("/admin/inspect/missing.png").to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 404);
+    }
+
+    #[actix_rt::test]
+    async fn test_rename_rules_preview_then_apply() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        let march = chrono::NaiveDate::from_ymd_opt(2023, 3, 5).unwrap().and_hms_opt(12, 0, 0).unwrap().and_utc();
+        for name in ["IMG_1.jpg", "IMG_1.cr2", "2023-03-05_done.jpg"] {
+            temp.child(name).write_binary(b"fake image content").unwrap();
+            std::fs::File::options()
+                .write(true)
+                .open(temp.child(name).path())
+                .unwrap()
+                .set_modified(march.into())
+                .unwrap();
+        }
+        let stats = stats::ViewStats::default();
+        stats.record_view("IMG_1.jpg");
+        let app = test::init_service(
+            App::new()
+                .app_data(error::json_config())
+                .app_data(web::Data::new(temp.path().to_path_buf()))
+                .app_data(web::Data::new(config::Config::default()))
+                .app_data(web::Data::new(cache::ImageCache::default()))
+                .app_data(web::Data::new(cache::VariantCache::default()))
+                .app_data(web::Data::new(indexer::LibraryIndex::default()))
+                .app_data(web::Data::new(stats))
+                .service(apply_rename_rules)
+                .service(image_info)
+        ).await;
+
+        let template = "{yyyy}-{mm}-{dd}_{original}";
+        let req = test::TestRequest::post()
+            .uri("/admin/rename-rules/apply")
+            .set_json(serde_json::json!({"template": template}))
+            .to_request();
+        let preview: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(preview["dry_run"], true);
+        assert_eq!(preview["renames"].as_array().unwrap().len(), 2);
+        temp.child("IMG_1.jpg").assert(predicates::path::exists());
+
+        // A file changed since the preview refuses the whole batch
+        let req = test::TestRequest::post()
+            .uri("/admin/rename-rules/apply")
+            .insert_header(("If-Unmodified-Since", "Mon, 01 Jan 2001 00:00:00 GMT"))
+            .set_json(serde_json::json!({"template": template, "dry_run": false}))
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 412);
+        temp.child("IMG_1.jpg").assert(predicates::path::exists());
+
+        let previewed = actix_web::http::header::HttpDate::from(std::time::SystemTime::from(march));
+        let req = test::TestRequest::post()
+            .uri("/admin/rename-rules/apply")
+            .insert_header(("If-Unmodified-Since", previewed.to_string()))
+            .set_json(serde_json::json!({"template": template, "dry_run": false}))
+            .to_request();
+        let applied: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(applied["renames"], preview["renames"]);
+        assert_eq!(applied["skipped"], serde_json::json!([]));
+        temp.child("IMG_1.jpg").assert(predicates::path::missing());
+        temp.child("2023-03-05_IMG_1.cr2").assert(predicates::path::exists());
+
+        let req = test::TestRequest::get().uri("/images/2023-03-05_IMG_1.jpg/info").to_request();
+        let info: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(info["stats"]["views"], 1);
+
+        let req = test::TestRequest::post()
+            .uri("/admin/rename-rules/apply")
+            .set_json(serde_json::json!({"template": "{yyyy}/{mm}/{original}"}))
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 400);
+
+        // Renaming takes a bearer token whenever OIDC is configured
+        let (_, jwk) = auth::tests::signing_key("k1");
+        let oidc = auth::OidcConfig {
+            jwks_url: "https://idp.example/jwks".to_string(),
+            ..auth::OidcConfig::default()
+        };
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(temp.path().to_path_buf()))
+                .app_data(web::Data::new(config::Config::default()))
+                .app_data(web::Data::new(cache::ImageCache::default()))
+                .app_data(web::Data::new(cache::VariantCache::default()))
+                .app_data(web::Data::new(indexer::LibraryIndex::default()))
+                .app_data(web::Data::new(stats::ViewStats::default()))
+                .app_data(web::Data::new(auth::Authenticator::with_keys(oidc, vec![jwk])))
+                .wrap(actix_web::middleware::from_fn(auth::authenticate))
+                .service(apply_rename_rules)
+        ).await;
+        let req = test::TestRequest::post()
+            .uri("/admin/rename-rules/apply")
+            .set_json(serde_json::json!({"template": "{original}_copy", "dry_run": false}))
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 401);
+        temp.child("2023-03-05_done.jpg").assert(predicates::path::exists());
+    }
+
+    #[actix_rt::test]
+    async fn test_errors_render_as_json() {
+        let temp = assert_fs::TempDir::new().unwrap();
+
+        let app = test::init_service(
+            App::new()
+                .app_data(error::query_config())
+                .app_data(web::Data::new(temp.path().to_path_buf()))
+                .app_data(web::Data::new(config::Config::default()))
+                .app_data(web::Data::new(stats::ViewStats::default()))
+                .app_data(web::Data::new(cache::ImageCache::default()))
+                .app_data(web::Data::new(cache::VariantCache::default()))
+                .app_data(web::Data::new(transcode::TranscodeCache::new(temp.path(), &config::Config::default())))
+                .service(serve_image)
+        ).await;
+
+        let req = test::TestRequest::get().uri("/images/missing.jpg").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 404);
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(body, serde_json::json!({"error": "not_found", "message": "Image not found"}));
+
+        let req = test::TestRequest::get().uri("/images/missing.jpg?w=abc").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 400);
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(body["error"], "validation");
+    }
+
+    #[actix_rt::test]
+    async fn test_file_handlers_reject_path_traversal() {
+        let root = assert_fs::TempDir::new().unwrap();
+        root.child("secret.jpg").write_binary(b"secret").unwrap();
+        let images = root.child("images");
+        images.create_dir_all().unwrap();
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(images.path().to_path_buf()))
+                .app_data(web::Data::new(config::Config::default()))
+                .app_data(web::Data::new(stats::ViewStats::default()))
+                .app_data(web::Data::new(cache::ImageCache::default()))
+                .app_data(web::Data::new(cache::VariantCache::default()))
+                .app_data(web::Data::new(transcode::TranscodeCache::new(images.path(), &config::Config::default())))
+                .service(serve_image)
+                .service(download_image)
+                .service(image_info)
+                .service(image_thumbnail)
+        ).await;
+
+        for uri in [
+            "/images/..%2Fsecret.jpg",
+            "/images/%2e%2e%2fsecret.jpg",
+            "/images/%252e%252e%252fsecret.jpg",
+            "/images/..%2Fsecret.jpg/download",
+            "/images/..%2Fsecret.jpg/info",
+            "/images/..%2Fsecret.jpg/thumbnail",
+        ] {
+            let req = test::TestRequest::get().uri(uri).to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), 400, "{}", uri);
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_lookups_ignore_case_consistently() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        temp.child("Photo.JPG").write_binary(b"fake image content").unwrap();
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(temp.path().to_path_buf()))
+                .app_data(web::Data::new(config::Config::default()))
+                .app_data(web::Data::new(stats::ViewStats::default()))
+                .app_data(web::Data::new(cache::ImageCache::default()))
+                .app_data(web::Data::new(cache::VariantCache::default()))
+                .app_data(web::Data::new(transcode::TranscodeCache::new(temp.path(), &config::Config::default())))
+                .service(serve_image)
+                .service(image_info)
+        ).await;
+
+        for uri in ["/images/photo.jpg", "/images/PHOTO.jpg", "/images/Photo.JPG"] {
+            let req = test::TestRequest::get().uri(uri).to_request();
+            assert_eq!(test::call_and_read_body(&app, req).await, "fake image content");
+        }
+
+        let req = test::TestRequest::get().uri("/images/photo.jpg/info").to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["filename"], "Photo.JPG");
+        assert_eq!(body["stats"]["views"], 3);
+    }
+
+    #[actix_rt::test]
+    async fn test_serve_image_honors_conditional_requests() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        temp.child("test.jpg").write_binary(b"fake image content").unwrap();
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(temp.path().to_path_buf()))
+                .app_data(web::Data::new(config::Config {
+                    cache_max_age_secs: 600,
+                    ..config::Config::default()
+                }))
+                .app_data(web::Data::new(stats::ViewStats::default()))
+                .app_data(web::Data::new(cache::ImageCache::default()))
+                .app_data(web::Data::new(cache::VariantCache::default()))
+                .app_data(web::Data::new(transcode::TranscodeCache::new(temp.path(), &config::Config::default())))
+                .service(serve_image)
+        ).await;
+
+        let req = test::TestRequest::get().uri("/images/test.jpg").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.headers().get("Cache-Control").unwrap(), "public, max-age=600");
+        let etag = resp.headers().get("ETag").unwrap().clone();
+        let last_modified = resp.headers().get("Last-Modified").unwrap().clone();
+
+        let req = test::TestRequest::get()
+            .uri("/images/test.jpg")
+            .insert_header(("If-None-Match", etag.clone()))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 304);
+        assert_eq!(resp.headers().get("ETag").unwrap(), &etag);
+
+        let req = test::TestRequest::get()
+            .uri("/images/test.jpg")
+            .insert_header(("If-Modified-Since", last_modified))
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 304);
+
+        let req = test::TestRequest::get()
+            .uri("/images/test.jpg")
+            .insert_header(("If-None-Match", "W/\"stale\""))
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 200);
+
+        // Resized variants get their own ETag
+        let req = test::TestRequest::get()
+            .uri("/images/test.jpg?w=10")
+            .insert_header(("If-None-Match", etag))
+            .to_request();
+        assert_ne!(test::call_service(&app, req).await.status(), 304);
+    }
+
+    #[actix_rt::test]
+    async fn test_serve_image_resizes_on_the_fly() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        image::DynamicImage::new_rgb8(40, 20)
+            .save(temp.child("wide.png").path())
+            .unwrap();
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(temp.path().to_path_buf()))
+                .app_data(web::Data::new(config::Config::default()))
+                .app_data(web::Data::new(stats::ViewStats::default()))
+                .app_data(web::Data::new(cache::ImageCache::default()))
+                .app_data(web::Data::new(cache::VariantCache::default()))
+                .app_data(web::Data::new(transcode::TranscodeCache::new(temp.path(), &config::Config::default())))
+                .service(serve_image)
+        ).await;
+
+        for (query, expected) in [("w=10", (10, 5)), ("w=10&h=10&fit=cover", (10, 10)), ("w=10", (10, 5))] {
+            let req = test::TestRequest::get()
+                .uri(&format!("/images/wide.png?{}", query))
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.headers().get("Content-Type").unwrap(), "image/png");
+            let body = test::read_body(resp).await;
+            let resized = image::load_from_memory(&body).unwrap();
+            assert_eq!(image::GenericImageView::dimensions(&resized), expected);
+        }
+
+        let req = test::TestRequest::get()
+            .uri("/images/wide.png?w=0")
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 400);
+    }
+
+    #[actix_rt::test]
+    async fn test_image_thumbnail() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        image::DynamicImage::new_rgb8(600, 300)
+            .save(temp.child("wide.png").path())
+            .unwrap();
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(temp.path().to_path_buf()))
+                .app_data(web::Data::new(config::Config::default()))
+                .app_data(web::Data::new(cache::VariantCache::default()))
+                .service(image_thumbnail)
+        ).await;
+
+        let req = test::TestRequest::get()
+            .uri("/images/wide.png/thumbnail?size=128")
+            .to_request();
+        let body = test::call_and_read_body(&app, req).await;
+        let thumbnail = image::load_from_memory(&body).unwrap();
+        assert_eq!(image::GenericImageView::dimensions(&thumbnail), (128, 64));
+        temp.child(".thumbnails/128/wide.png").assert(predicates::path::exists());
+
+        let req = test::TestRequest::get()
+            .uri("/images/wide.png/thumbnail?size=100")
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 400);
+
+        let req = test::TestRequest::get()
+            .uri("/images/missing.png/thumbnail")
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 404);
+    }
+
+    #[actix_rt::test]
+    async fn test_missing_and_corrupt_images_get_placeholders() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        temp.child("broken.jpg").write_binary(b"not a jpeg").unwrap();
+        let config = config::Config {
+            placeholder: placeholder::PlaceholderConfig {
+                enabled: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(temp.path().to_path_buf()))
+                .app_data(web::Data::new(config))
+                .app_data(web::Data::new(stats::ViewStats::default()))
+                .app_data(web::Data::new(cache::ImageCache::default()))
+                .app_data(web::Data::new(cache::VariantCache::default()))
+                .app_data(web::Data::new(transcode::TranscodeCache::new(temp.path(), &config::Config::default())))
+                .service(image_thumbnail)
+                .service(serve_image)
+        ).await;
+
+        for (uri, size) in [
+            ("/images/missing.jpg", r#"width="400" height="300""#),
+            ("/images/missing.jpg?w=200", r#"width="200" height="150""#),
+            ("/images/broken.jpg?w=50&h=40", r#"width="50" height="40""#),
+            ("/images/missing.jpg/thumbnail?size=64", r#"width="64" height="64""#),
+            ("/images/broken.jpg/thumbnail?size=128", r#"width="128" height="128""#),
+        ] {
+            let resp = test::call_service(&app, test::TestRequest::get().uri(uri).to_request()).await;
+            assert_eq!(resp.status(), 200, "{}", uri);
+            assert_eq!(resp.headers().get("X-Placeholder").unwrap(), "true");
+            assert_eq!(resp.headers().get("Content-Type").unwrap(), "image/svg+xml");
+            assert_eq!(resp.headers().get("Cache-Control").unwrap(), "no-store");
+            let body = test::read_body(resp).await;
+            assert!(std::str::from_utf8(&body).unwrap().contains(size), "{}", uri);
+        }
+
+        // Bad requests are still refused
+        let req = test::TestRequest::get()
+            .uri("/images/missing.jpg/thumbnail?size=100")
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 400);
+    }
+
+    #[actix_rt::test]
+    async fn test_convert_image() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        image::DynamicImage::new_rgb8(64, 32)
+            .save(temp.child("photo.png").path())
+            .unwrap();
+
+        let app = test::init_service(
+            App::new()
+                .app_data(error::query_config())
+                .app_data(web::Data::new(temp.path().to_path_buf()))
+                .app_data(web::Data::new(config::Config::default()))
+                .app_data(web::Data::new(cache::ImageCache::default()))
+                .app_data(web::Data::new(cache::VariantCache::default()))
+                .app_data(web::Data::new(transcode::TranscodeCache::new(temp.path(), &config::Config::default())))
+                .service(convert_image)
+                .service(cache_stats)
+        ).await;
+
+        for _ in 0..2 {
+            let req = test::TestRequest::get()
+                .uri("/images/photo.png/convert?format=webp&quality=70")
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), 200);
+            assert_eq!(resp.headers().get("Content-Type").unwrap(), "image/webp");
+            let body = test::read_body(resp).await;
+            assert_eq!(image::guess_format(&body).unwrap(), image::ImageFormat::WebP);
+        }
+
+        let req = test::TestRequest::get().uri("/admin/cache").to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["variants"]["hits"], 1);
+
+        for uri in [
+            "/images/photo.png/convert?format=avif",
+            "/images/photo.png/convert?format=webp&quality=0",
+        ] {
+            let req = test::TestRequest::get().uri(uri).to_request();
+            assert_eq!(test::call_service(&app, req).await.status(), 400, "{}", uri);
+        }
+
+        let req = test::TestRequest::get()
+            .uri("/images/missing.png/convert?format=png")
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 404);
+    }
+
+    #[actix_rt::test]
+    async fn test_transform_processes_uploads_without_storing() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        let mut png = std::io::Cursor::new(Vec::new());
+        image::DynamicImage::new_rgb8(40, 20)
+            .write_to(&mut png, image::ImageOutputFormat::Png)
+            .unwrap();
+        let png = png.into_inner();
+        let config = config::Config {
+            images_dir: temp.path().to_path_buf(),
+            transform_max_upload_bytes: png.len(),
+            ..Default::default()
+        };
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(config))
+                .service(transform_upload)
+        ).await;
+
+        for (query, content_type, expected) in [
+            ("w=10", "image/png", (10, 5)),
+            ("w=10&h=10&fit=cover&format=webp", "image/webp", (10, 10)),
+            ("format=jpeg&quality=60", "image/jpeg", (40, 20)),
+        ] {
+            let req = test::TestRequest::post()
+                .uri(&format!("/transform?{}", query))
+                .set_payload(png.clone())
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), 200, "{}", query);
+            assert_eq!(resp.headers().get("Content-Type").unwrap(), content_type);
+            assert_eq!(resp.headers().get("Cache-Control").unwrap(), "no-store");
+            let body = test::read_body(resp).await;
+            let transformed = image::load_from_memory(&body).unwrap();
+            assert_eq!(image::GenericImageView::dimensions(&transformed), expected);
+        }
+        assert_eq!(std::fs::read_dir(temp.path()).unwrap().count(), 0);
+
+        let mut too_large = png.clone();
+        too_large.push(0);
+        for (query, payload, status) in [
+            ("w=10", Vec::new(), 400),
+            ("w=0", png.clone(), 400),
+            ("format=jpeg&quality=101", png.clone(), 400),
+            ("w=10", b"not an image".to_vec(), 415),
+            ("w=10", too_large, 413),
+        ] {
+            let req = test::TestRequest::post()
+                .uri(&format!("/transform?{}", query))
+                .set_payload(payload)
+                .to_request();
+            assert_eq!(test::call_service(&app, req).await.status(), status, "{}", query);
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_presets_apply_on_serve_and_transform() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        image::DynamicImage::new_rgb8(40, 20)
+            .save(temp.child("photo.png").path())
+            .unwrap();
+        let png = std::fs::read(temp.child("photo.png").path()).unwrap();
+        let web_preset = presets::Preset {
+            width: Some(10),
+            format: Some(processor::ConvertFormat::Webp),
+            ..Default::default()
+        };
+        let config = config::Config {
+            presets: std::collections::HashMap::from([("web".to_string(), web_preset)]),
+            ..Default::default()
+        };
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(temp.path().to_path_buf()))
+                .app_data(web::Data::new(config))
+                .app_data(web::Data::new(stats::ViewStats::default()))
+                .app_data(web::Data::new(cache::ImageCache::default()))
+                .app_data(web::Data::new(cache::VariantCache::default()))
+                .app_data(web::Data::new(transcode::TranscodeCache::new(temp.path(), &config::Config::default())))
+                .service(serve_image)
+                .service(transform_upload)
+        ).await;
+
+        let mut etags = Vec::new();
+        for (uri, content_type, expected) in [
+            ("/images/photo.png?preset=web", "image/webp", (10, 5)),
+            ("/images/photo.png?preset=web&w=20&quality=60", "image/webp", (20, 10)),
+            ("/images/photo.png?w=10", "image/png", (10, 5)),
+        ] {
+            let resp = test::call_service(&app, test::TestRequest::get().uri(uri).to_request()).await;
+            assert_eq!(resp.status(), 200, "{}", uri);
+            assert_eq!(resp.headers().get("Content-Type").unwrap(), content_type);
+            etags.push(resp.headers().get("ETag").unwrap().clone());
+            let body = test::read_body(resp).await;
+            let served = image::load_from_memory(&body).unwrap();
+            assert_eq!(image::GenericImageView::dimensions(&served), expected, "{}", uri);
+        }
+        assert_ne!(etags[0], etags[2]);
+
+        let req = test::TestRequest::post()
+            .uri("/transform?preset=web&h=10")
+            .set_payload(png)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+        assert_eq!(resp.headers().get("Content-Type").unwrap(), "image/webp");
+
+        for uri in ["/images/photo.png?preset=print", "/images/photo.png?preset=web&quality=0"] {
+            let resp = test::call_service(&app, test::TestRequest::get().uri(uri).to_request()).await;
+            assert_eq!(resp.status(), 400, "{}", uri);
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_download_image_supports_ranges_and_counts_downloads() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        temp.child("test.jpg").write_binary(b"fake image content").unwrap();
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(temp.path().to_path_buf()))
+                .app_data(web::Data::new(config::Config::default()))
+                .app_data(web::Data::new(stats::ViewStats::default()))
+                .service(download_image)
+                .service(image_info)
+        ).await;
+
+        let req = test::TestRequest::get()
+            .uri("/images/test.jpg/download")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+        assert_eq!(
+            resp.headers().get("Content-Disposition").unwrap(),
+            "attachment; filename=\"test.jpg\""
+        );
+        assert_eq!(resp.headers().get("Content-Length").unwrap(), "18");
+        assert_eq!(test::read_body(resp).await, "fake image content");
+
+        let req = test::TestRequest::get()
+            .uri("/images/test.jpg/download")
+            .insert_header(("Range", "bytes=5-9"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 206);
+        assert_eq!(resp.headers().get("Content-Range").unwrap(), "bytes 5-9/18");
+        assert_eq!(resp.headers().get("Content-Length").unwrap(), "5");
+        assert_eq!(test::read_body(resp).await, "image");
+
+        let req = test::TestRequest::get()
+            .uri("/images/test.jpg/download")
+            .insert_header(("Range", "bytes=100-"))
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 416);
+
+        let req = test::TestRequest::get()
+            .uri("/images/test.jpg/info")
+            .to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["stats"]["downloads"], 1);
+        assert_eq!(body["stats"]["views"], 0);
+    }
+
+    async fn list_images_page(temp: &assert_fs::TempDir, query: &str) -> actix_web::dev::ServiceResponse {
+        let app = test::init_service(
+            App::new()
+                .app_data(error::query_config())
+                .app_data(web::Data::new(temp.path().to_path_buf()))
+                .app_data(web::Data::new(config::Config::default()))
+                .app_data(web::Data::new(prefetch::ThumbnailPrefetcher::disabled()))
+                .service(list_images)
+        ).await;
+
+        let req = test::TestRequest::get()
+            .uri(&format!("/gallery/images{}", query))
+            .to_request();
+        test::call_service(&app, req).await
+    }
+
+    #[actix_rt::test]
+    async fn test_list_images_paginates() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        for name in ["a.jpg", "b.jpg", "c.jpg", "d.jpg", "e.jpg"] {
+            temp.child(name).write_binary(b"fake image content").unwrap();
+        }
+
+        let resp = list_images_page(&temp, "?page=2&limit=2").await;
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(body["total"], 5);
+        assert_eq!(body["totalPages"], 3);
+        assert_eq!(body["pageSize"], 2);
+        let names: Vec<_> = body["images"].as_array().unwrap().iter().map(|i| i["filename"].as_str().unwrap()).collect();
+        assert_eq!(names, vec!["c.jpg", "d.jpg"]);
+
+        let resp = list_images_page(&temp, "?page=3&limit=2").await;
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(body["images"].as_array().unwrap().len(), 1);
+    }
+
+    #[actix_rt::test]
+    async fn test_list_images_sorts() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        temp.child("a.jpg").write_binary(b"medium!").unwrap();
+        temp.child("b.jpg").write_binary(b"largest image").unwrap();
+        temp.child("c.jpg").write_binary(b"tiny").unwrap();
+        let base = std::time::SystemTime::now();
+        for (name, age) in [("a.jpg", 30), ("b.jpg", 10), ("c.jpg", 20)] {
+            std::fs::File::options()
+                .write(true)
+                .open(temp.child(name).path())
+                .unwrap()
+                .set_modified(base - std::time::Duration::from_secs(age))
+                .unwrap();
+        }
+
+        for (query, expected) in [
+            ("", ["a.jpg", "b.jpg", "c.jpg"]),
+            ("?sort=name&order=desc", ["c.jpg", "b.jpg", "a.jpg"]),
+            ("?sort=size", ["c.jpg", "a.jpg", "b.jpg"]),
+            ("?sort=date&order=desc", ["b.jpg", "c.jpg", "a.jpg"]),
+        ] {
+            let body: serde_json::Value = test::read_body_json(list_images_page(&temp, query).await).await;
+            let names: Vec<_> = body["images"].as_array().unwrap().iter().map(|i| i["filename"].as_str().unwrap()).collect();
+            assert_eq!(names, expected, "{}", query);
+        }
+
+        for query in ["?sort=color", "?order=sideways"] {
+            let resp = list_images_page(&temp, query).await;
+            assert_eq!(resp.status(), 400, "{}", query);
+            let body: serde_json::Value = test::read_body_json(resp).await;
+            assert_eq!(body["error"], "validation");
+        }
+    }
+
+    fn set_tags(path: &std::path::Path, names: &[&str]) -> std::io::Result<()> {
+        let value = plist::Value::Array(names.iter().map(|n| plist::Value::String(n.to_string())).collect());
+        let mut bytes = Vec::new();
+        value.to_writer_binary(&mut bytes).unwrap();
+        xattr::set(path, tags::USER_TAGS_XATTR, &bytes)
+    }
+
+    #[actix_rt::test]
+    async fn test_list_images_filters_by_tag() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        for name in ["a.jpg", "b.jpg", "c.jpg", "c.cr2"] {
+            temp.child(name).write_binary(b"fake image content").unwrap();
+        }
+        if set_tags(temp.child("a.jpg").path(), &["Red\n6"]).is_err() {
+            eprintln!("skipping: filesystem does not support extended attributes");
+            return;
+        }
+        set_tags(temp.child("c.cr2").path(), &["Green\n2", "red"]).unwrap();
+
+        let body: serde_json::Value = test::read_body_json(list_images_page(&temp, "?tag=RED").await).await;
+        assert_eq!(body["total"], 2);
+        let names: Vec<_> = body["images"].as_array().unwrap().iter().map(|i| i["filename"].as_str().unwrap()).collect();
+        assert_eq!(names, vec!["a.jpg", "c.jpg"]);
+
+        let body: serde_json::Value = test::read_body_json(list_images_page(&temp, "?tag=blue").await).await;
+        assert_eq!(body["total"], 0);
+    }
+
+    #[actix_rt::test]
+    async fn test_search_combines_text_and_filters() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        let day = |d| chrono::NaiveDate::from_ymd_opt(2024, 6, d).unwrap().and_hms_opt(12, 0, 0).unwrap().and_utc();
+        for (name, contents, modified) in [
+            ("beach_sunset.jpg", "small", day(1)),
+            ("beach_day.jpg", "a larger file", day(10)),
+            ("IMG_7.jpg", "a larger file", day(20)),
+            ("clip.mp4", "a larger file", day(20)),
+        ] {
+            temp.child(name).write_binary(contents.as_bytes()).unwrap();
+            std::fs::File::options()
+                .write(true)
+                .open(temp.child(name).path())
+                .unwrap()
+                .set_modified(modified.into())
+                .unwrap();
+        }
+        set_tags(temp.child("IMG_7.jpg").path(), &["Beach trip", "Red\n6"]).unwrap();
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(temp.path().to_path_buf()))
+                .app_data(web::Data::new(config::Config::default()))
+                .service(search_images)
+        ).await;
+        let search = |query: &'static str| {
+            let req = test::TestRequest::get().uri(&format!("/gallery/search?{}", query)).to_request();
+            test::call_and_read_body_json::<_, _, serde_json::Value>(&app, req)
+        };
+        let names = |body: &serde_json::Value| -> Vec<String> {
+            body["images"].as_array().unwrap().iter().map(|i| i["filename"].as_str().unwrap().to_string()).collect()
+        };
+
+        assert_eq!(names(&search("q=BEACH").await), ["IMG_7.jpg", "beach_day.jpg", "beach_sunset.jpg"]);
+        assert_eq!(names(&search("q=beach%20trip").await), ["IMG_7.jpg"]);
+        assert_eq!(names(&search("q=beach&min_size=10").await), ["IMG_7.jpg", "beach_day.jpg"]);
+        assert_eq!(names(&search("q=beach&max_size=5").await), ["beach_sunset.jpg"]);
+        assert_eq!(names(&search("after=2024-06-05&before=2024-06-15").await), ["beach_day.jpg"]);
+        assert_eq!(names(&search("tag=red").await), ["IMG_7.jpg"]);
+        assert_eq!(names(&search("type=video&after=2024-06-15T00:00:00Z").await), ["clip.mp4"]);
+
+        let body = search("q=beach&sort=date&order=desc&limit=2&page=2").await;
+        assert_eq!((body["total"].as_u64(), body["totalPages"].as_u64()), (Some(3), Some(2)));
+        assert_eq!(names(&body), ["beach_sunset.jpg"]);
+
+        let req = test::TestRequest::get().uri("/gallery/search?after=yesterday").to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 400);
+    }
+
+    #[actix_rt::test]
+    async fn test_custom_fields_are_set_listed_and_filtered() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        temp.child("a.jpg").write_binary(b"fake image content").unwrap();
+        temp.child("b.jpg").write_binary(b"fake image content").unwrap();
+        if xattr::set(temp.child("b.jpg").path(), fields::FIELDS_XATTR, b"{}").is_err() {
+            eprintln!("skipping: filesystem does not support extended attributes");
+            return;
+        }
+        let app = test::init_service(
+            App::new()
+                .app_data(error::query_config())
+                .app_data(error::json_config())
+                .app_data(web::Data::new(temp.path().to_path_buf()))
+                .app_data(web::Data::new(config::Config::default()))
+                .app_data(web::Data::new(prefetch::ThumbnailPrefetcher::disabled()))
+                .app_data(web::Data::new(stats::ViewStats::default()))
+                .app_data(web::Data::new(fields::FieldRegistry::default()))
+                .app_data(web::Data::new(indexer::LibraryIndex::default()))
+                .service(field_schema)
+                .service(set_field_schema)
+                .service(set_image_fields)
+                .service(image_info)
+                .service(list_images)
+        ).await;
+
+        let schema = serde_json::json!({
+            "model_release": {"type": "enum", "values": ["signed", "pending"]},
+            "rating": {"type": "number"},
+        });
+        let req = test::TestRequest::put().uri("/admin/schema").set_json(&schema).to_request();
+        assert_eq!(test::call_and_read_body_json::<_, _, serde_json::Value>(&app, req).await, schema);
+        let req = test::TestRequest::get().uri("/admin/schema").to_request();
+        assert_eq!(test::call_and_read_body_json::<_, _, serde_json::Value>(&app, req).await, schema);
+
+        let req = test::TestRequest::put()
+            .uri("/images/a.jpg/fields")
+            .set_json(serde_json::json!({"model_release": "signed", "rating": 4}))
+            .to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["fields"], serde_json::json!({"model_release": "signed", "rating": 4}));
+
+        // null clears one field and leaves the rest
+        let req = test::TestRequest::put()
+            .uri("/images/a.jpg/fields")
+            .set_json(serde_json::json!({"rating": null}))
+            .to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["fields"], serde_json::json!({"model_release": "signed"}));
+
+        let req = test::TestRequest::put()
+            .uri("/images/a.jpg/fields")
+            .insert_header(("If-Unmodified-Since", "Mon, 01 Jan 2001 00:00:00 GMT"))
+            .set_json(serde_json::json!({"model_release": null}))
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 412);
+
+        let req = test::TestRequest::get().uri("/images/a.jpg/info").to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["fields"]["model_release"], "signed");
+
+        let req = test::TestRequest::get().uri("/gallery/images?field=model_release:signed").to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["total"], 1);
+        assert_eq!(body["images"][0]["fields"]["model_release"], "signed");
+
+        for (uri, body, status) in [
+            ("/images/b.jpg/fields", serde_json::json!({"model_release": "lost"}), 400),
+            ("/images/b.jpg/fields", serde_json::json!({"notes": "x"}), 400),
+            ("/images/missing.jpg/fields", serde_json::json!({"rating": 1}), 404),
+            ("/admin/schema", serde_json::json!({"Bad Name": {"type": "text"}}), 400),
+            ("/admin/schema", serde_json::json!({"x": {"type": "color"}}), 400),
+        ] {
+            let req = test::TestRequest::put().uri(uri).set_json(&body).to_request();
+            assert_eq!(test::call_service(&app, req).await.status(), status, "{} {}", uri, body);
+        }
+        let req = test::TestRequest::get().uri("/gallery/images?field=model_release").to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 400);
+    }
+
+    #[actix_rt::test]
+    async fn test_set_image_tags() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        temp.child("a.jpg").write_binary(b"fake image content").unwrap();
+        temp.child("b.jpg").write_binary(b"fake image content").unwrap();
+        temp.child("folder.jpg").create_dir_all().unwrap();
+        if set_tags(temp.child("a.jpg").path(), &["Red\n6"]).is_err() {
+            eprintln!("skipping: filesystem does not support extended attributes");
+            return;
+        }
+        let index = web::Data::new(indexer::LibraryIndex::default());
+        index.begin();
+        index.run(temp.path(), &[]);
+        let app = test::init_service(
+            App::new()
+                .app_data(error::query_config())
+                .app_data(error::json_config())
+                .app_data(web::Data::new(temp.path().to_path_buf()))
+                .app_data(web::Data::new(config::Config::default()))
+                .app_data(web::Data::new(prefetch::ThumbnailPrefetcher::disabled()))
+                .app_data(web::Data::new(suggest::SuggestIndex::default()))
+                .app_data(index.clone())
+                .service(set_image_tags)
+                .service(list_images)
+                .service(handlers::suggest)
+        ).await;
+
+        let req = test::TestRequest::get().uri("/suggest?q=port").to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["suggestions"], serde_json::json!([]));
+
+        let req = test::TestRequest::put()
+            .uri("/images/B.JPG/tags")
+            .set_json(["portfolio", " Red ", "RED"])
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(body["filename"], "b.jpg");
+        assert_eq!(
+            body["tags"],
+            serde_json::json!([{"name": "portfolio", "color": null}, {"name": "Red", "color": null}])
+        );
+        let indexed: Vec<_> = index.get("b.jpg").unwrap().tags.into_iter().map(|tag| tag.name).collect();
+        assert_eq!(indexed, ["portfolio", "Red"]);
+
+        let req = test::TestRequest::get().uri("/gallery/images?tag=red").to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["total"], 2);
+        assert_eq!(body["images"][0]["tags"], serde_json::json!([{"name": "Red", "color": "red"}]));
+
+        let req = test::TestRequest::get().uri("/suggest?q=port").to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(
+            body["suggestions"],
+            serde_json::json!([{"kind": "tag", "value": "portfolio", "url": "/gallery/images?tag=portfolio"}])
+        );
+
+        for (uri, tags, status) in [
+            ("/images/b.jpg/tags", serde_json::json!(["", "x"]), 400),
+            ("/images/b.jpg/tags", serde_json::json!("red"), 400),
+            ("/images/missing.jpg/tags", serde_json::json!(["red"]), 404),
+            ("/images/folder.jpg/tags", serde_json::json!(["red"]), 404),
+        ] {
+            let req = test::TestRequest::put().uri(uri).set_json(&tags).to_request();
+            assert_eq!(test::call_service(&app, req).await.status(), status, "{} {}", uri, tags);
+        }
+
+        // A client that read the image before it changed gets 412 and no write
+        let req = test::TestRequest::put()
+            .uri("/images/a.jpg/tags")
+            .insert_header(("If-Unmodified-Since", "Mon, 01 Jan 2001 00:00:00 GMT"))
+            .set_json(Vec::<String>::new())
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 412);
+        let req = test::TestRequest::put()
+            .uri("/images/a.jpg/tags")
+            .insert_header(("If-Match", "W/\"0-0\""))
+            .set_json(Vec::<String>::new())
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 412);
+        assert_eq!(tags::read(temp.child("a.jpg").path())[0].name, "Red");
+
+        let etag = conditional::etag_for(&temp.child("a.jpg").path().metadata().unwrap(), None);
+        let req = test::TestRequest::put()
+            .uri("/images/a.jpg/tags")
+            .insert_header(("If-Match", etag))
+            .set_json(Vec::<String>::new())
+            .to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["tags"], serde_json::json!([]));
+    }
+
+    #[actix_rt::test]
+    async fn test_surrogate_keys_and_purges() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        temp.child("a b.jpg").write_binary(b"fake image content").unwrap();
+        if set_tags(temp.child("a b.jpg").path(), &["Red\n6"]).is_err() {
+            eprintln!("skipping: filesystem does not support extended attributes");
+            return;
+        }
+        let server = wiremock::MockServer::start().await;
+        wiremock::Mock::given(wiremock::matchers::path("/service/svc/purge"))
+            .respond_with(wiremock::ResponseTemplate::new(200))
+            .mount(&server)
+            .await;
+        let config = config::Config {
+            cdn: cdn::CdnConfig {
+                surrogate_keys: true,
+                provider: Some(cdn::CdnProvider::Fastly),
+                service_id: "svc".to_string(),
+                api_token: "secret".to_string(),
+                api_url: Some(server.uri()),
+            },
+            ..Default::default()
+        };
+        let purger = cdn::CdnPurger::new(config.cdn.clone());
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(temp.path().to_path_buf()))
+                .app_data(web::Data::new(config))
+                .app_data(web::Data::new(purger))
+                .app_data(web::Data::new(stats::ViewStats::default()))
+                .app_data(web::Data::new(prefetch::ThumbnailPrefetcher::disabled()))
+                .app_data(web::Data::new(suggest::SuggestIndex::default()))
+                .app_data(web::Data::new(indexer::LibraryIndex::default()))
+                .service(download_image)
+                .service(set_image_tags)
+                .service(list_images)
+                .wrap(actix_web::middleware::from_fn(cdn::surrogate))
+        ).await;
+
+        for (uri, surrogate_key, cache_tag) in [
+            ("/images/a%20b.jpg/download", "image:a%20b.jpg", "image:a%20b.jpg"),
+            ("/gallery/images?tag=Red", "gallery tag:red", "gallery,tag:red"),
+        ] {
+            let resp = test::call_service(&app, test::TestRequest::get().uri(uri).to_request()).await;
+            assert_eq!(resp.status(), 200, "{}", uri);
+            assert_eq!(resp.headers().get("Surrogate-Key").unwrap(), surrogate_key);
+            assert_eq!(resp.headers().get("Cache-Tag").unwrap(), cache_tag);
+        }
+        let resp = test::call_service(&app, test::TestRequest::get().uri("/images/missing.jpg/download").to_request()).await;
+        assert!(resp.headers().get("Surrogate-Key").is_none());
+
+        let req = test::TestRequest::put()
+            .uri("/images/a%20b.jpg/tags")
+            .set_json(["Blue"])
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 200);
+        let mut purged = Vec::new();
+        for _ in 0..50 {
+            purged = server.received_requests().await.unwrap();
+            if !purged.is_empty() {
+                break;
+            }
+            actix_rt::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+        assert_eq!(purged.len(), 1);
+        assert_eq!(
+            purged[0].headers.get(&"Surrogate-Key".into()).unwrap().last().as_str(),
+            "image:a%20b.jpg gallery tag:blue tag:red"
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_delete_image_moves_it_to_the_trash() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        for name in ["a.jpg", "b.jpg"] {
+            temp.child(name).write_binary(b"fake image content").unwrap();
+        }
+        temp.child(".thumbnails/256/a.jpg").write_binary(b"thumb").unwrap();
+
+        let app = test::init_service(
+            App::new()
+                .app_data(error::query_config())
+                .app_data(web::Data::new(temp.path().to_path_buf()))
+                .app_data(web::Data::new(config::Config::default()))
+                .app_data(web::Data::new(cache::ImageCache::default()))
+                .app_data(web::Data::new(cache::VariantCache::default()))
+                .app_data(web::Data::new(indexer::LibraryIndex::default()))
+                .service(delete_image)
+                .service(trashed_images)
+                .service(restore_image)
+        ).await;
+
+        let req = test::TestRequest::delete().uri("/images/A.JPG").to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!((&body["filename"], &body["status"]), (&"a.jpg".into(), &"trashed".into()));
+        let first = body["trashed_name"].as_str().unwrap().to_string();
+        assert!(first.ends_with("_a.jpg"));
+        assert!(!temp.child("a.jpg").path().exists());
+        assert!(!temp.child(".thumbnails/256/a.jpg").path().exists());
+        assert!(temp.child(".Trash").child(&first).path().exists());
+
+        // Deleting the name again keeps the first copy
+        temp.child("a.jpg").write_binary(b"second copy").unwrap();
+        let req = test::TestRequest::delete().uri("/images/a.jpg").to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_ne!(body["trashed_name"], first.as_str());
+
+        let req = test::TestRequest::get().uri("/trash").to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["images"].as_array().unwrap().len(), 2);
+        assert_eq!(body["images"][0]["filename"], "a.jpg");
+        assert_eq!(body["images"][0]["size_bytes"], 11);
+        assert_eq!(body["images"][1]["trashed_name"], first.as_str());
+
+        // A new file under the old name blocks the restore until it is gone
+        temp.child("a.jpg").write_binary(b"replacement").unwrap();
+        let req = test::TestRequest::post().uri(&format!("/trash/{}/restore", first)).to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 409);
+        let req = test::TestRequest::delete().uri("/images/a.jpg?permanent=true").to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!((&body["status"], &body["dry_run"]), (&"deleted".into(), &true.into()));
+        assert!(temp.child("a.jpg").path().exists());
+        let req = test::TestRequest::delete().uri("/images/a.jpg?permanent=true&dry_run=false").to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["status"], "deleted");
+        assert!(body.get("dry_run").is_none());
+        assert!(!temp.child("a.jpg").path().exists());
+
+        let req = test::TestRequest::post().uri(&format!("/trash/{}/restore", first)).to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!((&body["filename"], &body["status"]), (&"a.jpg".into(), &"restored".into()));
+        assert_eq!(std::fs::read(temp.child("a.jpg").path()).unwrap(), b"fake image content");
+        std::fs::remove_file(temp.child("a.jpg").path()).unwrap();
+        // An original name restores its latest deletion
+        let req = test::TestRequest::post().uri("/trash/a.jpg/restore").to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 200);
+        assert_eq!(std::fs::read(temp.child("a.jpg").path()).unwrap(), b"second copy");
+
+        for (req, status) in [
+            (test::TestRequest::delete().uri("/images/missing.jpg"), 404),
+            (test::TestRequest::delete().uri("/images/b.jpg?permanent=maybe"), 400),
+            (test::TestRequest::post().uri("/trash/a.jpg/restore"), 404),
+        ] {
+            assert_eq!(test::call_service(&app, req.to_request()).await.status(), status);
+        }
+        assert!(temp.child("b.jpg").path().exists());
+
+        // Optional tokens elsewhere still leave deletes and restores to callers with one
+        let (pair, jwk) = auth::tests::signing_key("k1");
+        let token = auth::tests::sign(&pair, "k1", serde_json::json!({"sub": "user-1", "exp": chrono::Utc::now().timestamp() + 300}));
+        let oidc = auth::OidcConfig {
+            jwks_url: "https://idp.example/jwks".to_string(),
+            ..auth::OidcConfig::default()
+        };
+        let app = test::init_service(
+            App::new()
+                .app_data(error::query_config())
+                .app_data(web::Data::new(temp.path().to_path_buf()))
+                .app_data(web::Data::new(config::Config::default()))
+                .app_data(web::Data::new(cache::ImageCache::default()))
+                .app_data(web::Data::new(cache::VariantCache::default()))
+                .app_data(web::Data::new(indexer::LibraryIndex::default()))
+                .app_data(web::Data::new(auth::Authenticator::with_keys(oidc, vec![jwk])))
+                .wrap(actix_web::middleware::from_fn(auth::authenticate))
+                .service(delete_image)
+                .service(restore_image)
+        ).await;
+        let req = test::TestRequest::delete().uri("/images/b.jpg").to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 401);
+        let req = test::TestRequest::delete()
+            .uri("/images/b.jpg")
+            .insert_header(("Authorization", format!("Bearer {}", token)))
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 200);
+        let req = test::TestRequest::post().uri("/trash/b.jpg/restore").to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 401);
+        assert!(!temp.child("b.jpg").path().exists());
+    }
+
+    #[actix_rt::test]
+    async fn test_admin_server_requires_authentication() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        let free_address = || {
+            let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            listener.local_addr().unwrap().to_string()
+        };
+        let (public, admin) = (free_address(), free_address());
+        let config = config::Config {
+            images_dir: temp.path().to_path_buf(),
+            bind_addresses: vec![public],
+            admin_bind_addresses: vec![admin.clone()],
+            watch_images_dir: false,
+            oidc: auth::OidcConfig {
+                jwks_url: "http://127.0.0.1:9/jwks".to_string(),
+                required: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let server = startup::run(config).await.unwrap();
+        let handle = server.handle();
+        actix_rt::spawn(server);
+
+        let resp = reqwest::get(format!("http://{}/admin/cache", admin)).await.unwrap();
+        assert_eq!(resp.status(), 401);
+        handle.stop(false).await;
+    }
+
+    #[actix_rt::test]
+    async fn test_validate_etags() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        for name in ["fresh.jpg", "thumb.jpg", "changed.jpg"] {
+            temp.child(name).write_binary(b"fake image content").unwrap();
+        }
+        let etag = |name: &str, variant: Option<&str>| {
+            conditional::etag_for(&temp.child(name).path().metadata().unwrap(), variant)
+        };
+        let body = serde_json::json!({
+            "fresh.jpg": etag("fresh.jpg", None),
+            "thumb.jpg": etag("thumb.jpg", Some("thumb-300")),
+            "changed.jpg": etag("changed.jpg", None),
+            "gone.jpg": "W/\"1-2\"",
+            "../etc/passwd": "W/\"1-2\"",
+        });
+        temp.child("changed.jpg").write_binary(b"edited image content").unwrap();
+
+        let app = test::init_service(
+            App::new()
+                .app_data(error::json_config())
+                .app_data(web::Data::new(config::Config::default()))
+                .app_data(web::Data::new(temp.path().to_path_buf()))
+                .service(validate_etags)
+        ).await;
+
+        let req = test::TestRequest::post().uri("/validate").set_json(&body).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(body["stale"], serde_json::json!(["changed.jpg"]));
+        assert_eq!(body["missing"], serde_json::json!(["../etc/passwd", "gone.jpg"]));
+        assert_eq!(body["unavailable"], serde_json::json!([]));
+
+        let req = test::TestRequest::post().uri("/validate").set_json(["not", "a", "map"]).to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 400);
+
+        // Names that may be on an unmounted drive are neither current nor gone
+        let config = config::Config {
+            policy: policy::ContentPolicy {
+                referenced_dirs: vec![temp.path().join("VideosNew")],
+                ..policy::ContentPolicy::default()
+            },
+            ..config::Config::default()
+        };
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(config))
+                .app_data(web::Data::new(temp.path().to_path_buf()))
+                .service(validate_etags)
+        ).await;
+        let body = serde_json::json!({"fresh.jpg": etag("fresh.jpg", None), "clip.mp4": "W/\"1-2\""});
+        let req = test::TestRequest::post().uri("/validate").set_json(&body).to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body, serde_json::json!({"stale": [], "missing": [], "unavailable": ["clip.mp4"]}));
+    }
+
+    #[actix_rt::test]
+    async fn test_reindex_reports_progress() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        for name in ["a.jpg", "b.jpg"] {
+            temp.child(name).write_binary(b"fake image content").unwrap();
+        }
+        let index = web::Data::new(indexer::LibraryIndex::default());
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(temp.path().to_path_buf()))
+                .app_data(web::Data::new(config::Config::default()))
+                .app_data(index.clone())
+                .service(start_reindex)
+                .service(reindex_status)
+        ).await;
+
+        let req = test::TestRequest::get().uri("/admin/reindex").to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["state"], "idle");
+
+        let req = test::TestRequest::post().uri("/admin/reindex").to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 202);
+
+        let mut body = serde_json::Value::Null;
+        for _ in 0..100 {
+            let req = test::TestRequest::get().uri("/admin/reindex").to_request();
+            body = test::call_and_read_body_json(&app, req).await;
+            if body["state"] != "running" {
+                break;
+            }
+            actix_rt::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+        assert_eq!(body["state"], "completed");
+        assert_eq!(body["processed"], 2);
+        assert_eq!(body["total"], 2);
+        assert_eq!(index.get("b.jpg").unwrap().size_bytes, 18);
+    }
+
+    #[actix_rt::test]
+    async fn test_gallery_sprite() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        for name in ["a.png", "b.png", "c.png"] {
+            image::DynamicImage::new_rgb8(100, 50).save(temp.child(name).path()).unwrap();
+        }
+        let app = test::init_service(
+            App::new()
+                .app_data(error::query_config())
+                .app_data(web::Data::new(temp.path().to_path_buf()))
+                .app_data(web::Data::new(config::Config::default()))
+                .service(gallery_sprite_layout)
+                .service(gallery_sprite)
+        ).await;
+
+        let req = test::TestRequest::get().uri("/gallery/images/sprite?page=2&limit=2&size=64").to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["url"], "/gallery/images/sprite.jpg?page=2&limit=2&size=64");
+        assert_eq!(
+            body["tiles"],
+            serde_json::json!([{"filename": "c.png", "x": 0, "y": 0, "width": 64, "height": 32}])
+        );
+
+        let req = test::TestRequest::get().uri("/gallery/images/sprite.jpg?limit=2&size=64").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.headers().get("Content-Type").unwrap(), "image/jpeg");
+        let sprite = image::load_from_memory(&test::read_body(resp).await).unwrap();
+        assert_eq!((sprite.width(), sprite.height()), (128, 64));
+
+        let req = test::TestRequest::get().uri("/gallery/images/sprite?size=1024").to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 400);
+    }
+
+    #[actix_rt::test]
+    async fn test_list_images_includes_inline_thumbnails() {
+        use base64::Engine;
+        let temp = assert_fs::TempDir::new().unwrap();
+        image::DynamicImage::new_rgb8(200, 100).save(temp.child("a.png").path()).unwrap();
+        temp.child("b.nef").write_binary(b"raw").unwrap();
+
+        let body: serde_json::Value = test::read_body_json(list_images_page(&temp, "").await).await;
+        assert!(body["images"][0].get("thumbnail").is_none());
+
+        let body: serde_json::Value =
+            test::read_body_json(list_images_page(&temp, "?include_thumbnail=true").await).await;
+        let uri = body["images"][0]["thumbnail"].as_str().unwrap();
+        let encoded = uri.strip_prefix("data:image/png;base64,").unwrap();
+        let bytes = base64::engine::general_purpose::STANDARD.decode(encoded).unwrap();
+        assert_eq!(image::load_from_memory(&bytes).unwrap().width(), 64);
+        assert!(body["images"][1].get("thumbnail").is_none());
+    }
+
+    #[actix_rt::test]
+    async fn test_list_images_mixes_videos() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        temp.child("clip.jpg").write_binary(b"fake image content").unwrap();
+        // moov/mvhd (version 0) declaring 3 seconds at a 1000 timescale
+        let mut mvhd = vec![0u8; 12];
+        mvhd.extend_from_slice(&1000u32.to_be_bytes());
+        mvhd.extend_from_slice(&3000u32.to_be_bytes());
+        let mut movie = ((mvhd.len() + 16) as u32).to_be_bytes().to_vec();
+        movie.extend_from_slice(b"moov");
+        movie.extend_from_slice(&((mvhd.len() + 8) as u32).to_be_bytes());
+        movie.extend_from_slice(b"mvhd");
+        movie.extend_from_slice(&mvhd);
+        temp.child("clip.mp4").write_binary(&movie).unwrap();
+
+        let body: serde_json::Value = test::read_body_json(list_images_page(&temp, "").await).await;
+        let images = body["images"].as_array().unwrap();
+        assert_eq!(images.len(), 2);
+        assert_eq!(images[0]["filename"], "clip.jpg");
+        assert_eq!(images[0]["media_type"], "image");
+        assert!(images[0].get("duration_secs").is_none());
+        assert_eq!(images[1]["filename"], "clip.mp4");
+        assert_eq!(images[1]["media_type"], "video");
+        assert_eq!(images[1]["duration_secs"], 3.0);
+        assert_eq!(images[1]["poster_url"], "/images/clip.jpg/thumbnail?size=256");
+        assert_eq!(images[1]["preview_url"], "/videos/library/clip.mp4/preview?size=256");
+        assert!(images[0].get("preview_url").is_none());
+
+        // Filtered before paging, so totals count only videos
+        let body: serde_json::Value = test::read_body_json(list_images_page(&temp, "?type=video").await).await;
+        assert_eq!(body["total"], 1);
+        assert_eq!(body["images"][0]["filename"], "clip.mp4");
+        assert_eq!(list_images_page(&temp, "?type=archive").await.status(), 400);
+    }
+
+    #[actix_rt::test]
+    async fn test_list_images_prefetches_next_page_thumbnails() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        for name in ["a.png", "b.png", "c.png"] {
+            image::DynamicImage::new_rgb8(40, 20).save(temp.child(name).path()).unwrap();
+        }
+        let config = config::Config {
+            prefetch_thumbnails: true,
+            ..config::Config::default()
+        };
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(temp.path().to_path_buf()))
+                .app_data(web::Data::new(config))
+                .app_data(web::Data::new(prefetch::ThumbnailPrefetcher::spawn(temp.path().to_path_buf()).unwrap()))
+                .service(list_images)
+        ).await;
+
+        let req = test::TestRequest::get().uri("/gallery/images?page=1&limit=1").to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 200);
+
+        let next = temp.path().join("b.png");
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+        while !thumbnails::is_cached(temp.path(), &next, thumbnails::DEFAULT_SIZE) {
+            assert!(std::time::Instant::now() < deadline, "next page was not prefetched");
+            actix_rt::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        assert!(!thumbnails::is_cached(temp.path(), &temp.path().join("c.png"), thumbnails::DEFAULT_SIZE));
+    }
+
+    #[actix_rt::test]
+    async fn test_stream_images_emits_ndjson() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        for name in ["b.jpg", "a.jpg", "a.cr2", "notes.txt"] {
+            temp.child(name).write_binary(b"fake image content").unwrap();
+        }
+        let app = test::init_service(
+            App::new()
+                .app_data(error::query_config())
+                .app_data(web::Data::new(temp.path().to_path_buf()))
+                .app_data(web::Data::new(config::Config::default()))
+                .app_data(web::Data::new(prefetch::ThumbnailPrefetcher::disabled()))
+                .service(stream_images)
+                .service(list_images)
+        ).await;
+
+        let req = test::TestRequest::get().uri("/gallery/images/stream?order=desc").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+        assert_eq!(resp.headers().get("content-type").unwrap(), "application/x-ndjson");
+
+        let body = test::read_body(resp).await;
+        let lines: Vec<serde_json::Value> = std::str::from_utf8(&body)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        let names: Vec<_> = lines.iter().map(|i| i["filename"].as_str().unwrap()).collect();
+        assert_eq!(names, vec!["b.jpg", "a.jpg"]);
+        assert_eq!(lines[1]["renditions"].as_array().unwrap().len(), 2);
+    }
+
+    #[actix_rt::test]
+    async fn test_list_images_pagination_edge_cases() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        temp.child("a.jpg").write_binary(b"fake image content").unwrap();
+
+        // Past the last page: empty page, totals intact
+        let resp = list_images_page(&temp, "?page=5&limit=10").await;
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(body["images"].as_array().unwrap().len(), 0);
+        assert_eq!(body["total"], 1);
+        assert_eq!(body["totalPages"], 1);
+
+        // Oversized limits are clamped
+        let resp = list_images_page(&temp, "?limit=100000").await;
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(body["pageSize"], 500);
+
+        assert_eq!(list_images_page(&temp, "?page=0").await.status(), 400);
+        assert_eq!(list_images_page(&temp, "?limit=0").await.status(), 400);
+        assert_eq!(list_images_page(&temp, "?page=abc").await.status(), 400);
+
+        let empty = assert_fs::TempDir::new().unwrap();
+        let body: serde_json::Value = test::read_body_json(list_images_page(&empty, "").await).await;
+        assert_eq!(body["total"], 0);
+        assert_eq!(body["totalPages"], 0);
+    }
+
+    #[actix_rt::test]
+    async fn test_json_feed_lists_newest_published_items_first() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        for name in ["old.jpg", "new.jpg", "private.jpg", "clip.mp4", "clip.jpg", "shown.mov", "shown.jpg"] {
+            temp.child(name).write_binary(b"fake image content").unwrap();
+        }
+        for name in ["old.jpg", "new.jpg", "clip.mp4", "shown.mov", "shown.jpg"] {
+            if publish::set_published(temp.child(name).path(), true).is_err() {
+                eprintln!("skipping: filesystem does not support extended attributes");
+                return;
+            }
+        }
+        let old = std::time::SystemTime::now() - std::time::Duration::from_secs(3600);
+        for (name, minutes) in [("old.jpg", 0), ("clip.mp4", 1), ("shown.mov", 2), ("shown.jpg", 3)] {
+            std::fs::File::options()
+                .write(true)
+                .open(temp.child(name).path())
+                .unwrap()
+                .set_modified(old - std::time::Duration::from_secs(60 * minutes))
+                .unwrap();
+        }
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(temp.path().to_path_buf()))
+                .app_data(web::Data::new(config::Config {
+                    public_base_url: "https://example.com/photos".to_string(),
+                    ..config::Config::default()
+                }))
+                .app_data(web::Data::new(signing::UrlSigner::new(b"secret")))
+                .service(json_feed)
+        ).await;
+
+        let req = test::TestRequest::get().uri("/feed.json").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.headers().get("Content-Type").unwrap(), "application/feed+json");
+
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(body["version"], "https://jsonfeed.org/version/1.1");
+        assert_eq!(body["feed_url"], "https://example.com/photos/feed.json");
+        assert_eq!(body["home_page_url"], "https://example.com/photos/public/images");
+        let items = body["items"].as_array().unwrap();
+        let ids: Vec<_> = items.iter().map(|item| item["id"].as_str().unwrap()).collect();
+        assert_eq!(ids, ["new.jpg", "old.jpg", "clip.mp4", "shown.mov", "shown.jpg"]);
+        // An unpublished poster stays private; the video's own frame stands in
+        assert_eq!(items[2]["image"], "https://example.com/photos/public/images/clip.mp4/thumbnail?size=512");
+        assert!(items[3]["image"].as_str().unwrap().starts_with("https://example.com/photos/images/shown.jpg/thumbnail?size=512&expires="));
+        assert_eq!(items[0]["url"], "https://example.com/photos/public/images/new.jpg");
+
+        let thumbnail = items[0]["image"].as_str().unwrap();
+        let (path, query) = thumbnail
+            .strip_prefix("https://example.com/photos")
+            .and_then(|rest| rest.split_once('?'))
+            .unwrap();
+        assert_eq!(path, "/images/new.jpg/thumbnail");
+        assert!(query.starts_with("size=512&expires="));
+        signing::UrlSigner::new(b"secret").verify(path, query, chrono::Utc::now()).unwrap();
+        assert!(items[0].get("size_bytes").is_none());
+    }
+
+    /// Inserts an EXIF APP1 segment holding `fields` right after the JPEG's SOI marker.
+    fn with_exif(jpeg: &[u8], fields: &[exif::Field]) -> Vec<u8> {
+        let mut writer = exif::experimental::Writer::new();
+        for field in fields {
+            writer.push_field(field);
+        }
+        let mut tiff = std::io::Cursor::new(Vec::new());
+        writer.write(&mut tiff, false).unwrap();
+        let tiff = tiff.into_inner();
+
+        let mut out = vec![0xFF, 0xD8, 0xFF, 0xE1];
+        out.extend_from_slice(&((tiff.len() + 8) as u16).to_be_bytes());
+        out.extend_from_slice(b"Exif\0\0");
+        out.extend_from_slice(&tiff);
+        out.extend_from_slice(&jpeg[2..]);
+        out
+    }
+
+    fn jpeg_with_capture_date(datetime: &str) -> Vec<u8> {
+        let field = exif::Field {
+            tag: exif::Tag::DateTimeOriginal,
+            ifd_num: exif::In::PRIMARY,
+            value: exif::Value::Ascii(vec![datetime.as_bytes().to_vec()]),
+        };
+        with_exif(&[0xFF, 0xD8, 0xFF, 0xD9], &[field])
+    }
+
+    #[actix_rt::test]
+    async fn test_resizing_applies_exif_orientation() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        let mut jpeg = std::io::Cursor::new(Vec::new());
+        image::DynamicImage::new_rgb8(200, 100)
+            .write_to(&mut jpeg, image::ImageOutputFormat::Jpeg(90))
+            .unwrap();
+        let orientation = exif::Field {
+            tag: exif::Tag::Orientation,
+            ifd_num: exif::In::PRIMARY,
+            value: exif::Value::Short(vec![6]),
+        };
+        temp.child("portrait.jpg").write_binary(&with_exif(jpeg.get_ref(), &[orientation])).unwrap();
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(temp.path().to_path_buf()))
+                .app_data(web::Data::new(config::Config::default()))
+                .app_data(web::Data::new(stats::ViewStats::default()))
+                .app_data(web::Data::new(cache::ImageCache::default()))
+                .app_data(web::Data::new(cache::VariantCache::default()))
+                .app_data(web::Data::new(transcode::TranscodeCache::new(temp.path(), &config::Config::default())))
+                .service(serve_image)
+                .service(image_thumbnail)
+        ).await;
+
+        for (uri, expected) in [
+            ("/images/portrait.jpg?w=50", (50, 100)),
+            ("/images/portrait.jpg?w=50&respect_orientation=false", (50, 25)),
+            ("/images/portrait.jpg/thumbnail?size=64", (32, 64)),
+            ("/images/portrait.jpg/thumbnail?size=64&respect_orientation=false", (64, 32)),
+        ] {
+            let req = test::TestRequest::get().uri(uri).to_request();
+            let body = test::call_and_read_body(&app, req).await;
+            let img = image::load_from_memory(&body).unwrap();
+            assert_eq!(image::GenericImageView::dimensions(&img), expected, "{}", uri);
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_memories_groups_previous_years() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        temp.child("2019.jpg").write_binary(&jpeg_with_capture_date("2019:10:16 08:00:00")).unwrap();
+        temp.child("2022.jpg").write_binary(&jpeg_with_capture_date("2022:10:16 12:30:00")).unwrap();
+        temp.child("other-day.jpg").write_binary(&jpeg_with_capture_date("2022:10:17 12:30:00")).unwrap();
+        temp.child("this-year.jpg").write_binary(&jpeg_with_capture_date("2024:10:16 09:00:00")).unwrap();
+        temp.child("no-exif.jpg").write_binary(b"fake image content").unwrap();
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(temp.path().to_path_buf()))
+                .app_data(web::Data::new(config::Config::default()))
+                .service(memories)
+        ).await;
+
+        let req = test::TestRequest::get()
+            .uri("/memories?date=2024-10-16")
+            .to_request();
+
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        let years: Vec<_> = body["years"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|year| (year["year"].as_i64().unwrap(), year["images"][0]["filename"].as_str().unwrap().to_string()))
+            .collect();
+        assert_eq!(years, vec![(2022, "2022.jpg".to_string()), (2019, "2019.jpg".to_string())]);
+    }
+
+    #[actix_rt::test]
+    async fn test_memories_rejects_invalid_date() {
+        let temp = assert_fs::TempDir::new().unwrap();
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(temp.path().to_path_buf()))
+                .app_data(web::Data::new(config::Config::default()))
+                .service(memories)
+        ).await;
+
+        let req = test::TestRequest::get()
+            .uri("/memories?date=16-10-2024")
+            .to_request();
+
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 400);
+    }
+
+    #[actix_rt::test]
+    async fn test_library_activity_counts_additions_and_views_per_day() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        let march = chrono::NaiveDate::from_ymd_opt(2023, 3, 5).unwrap().and_hms_opt(12, 0, 0).unwrap().and_utc();
+        for (name, modified) in [("a.jpg", march), ("b.jpg", march), ("old.jpg", march - chrono::Duration::days(365))] {
+            temp.child(name).write_binary(b"fake image content").unwrap();
+            std::fs::File::options()
+                .write(true)
+                .open(temp.child(name).path())
+                .unwrap()
+                .set_modified(modified.into())
+                .unwrap();
+        }
+        let stats = stats::ViewStats::default();
+        stats.record_view("a.jpg");
+        stats.record_view("b.jpg");
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(temp.path().to_path_buf()))
+                .app_data(web::Data::new(config::Config::default()))
+                .app_data(web::Data::new(stats))
+                .service(library_activity)
+        ).await;
+
+        let req = test::TestRequest::get().uri("/stats/activity?year=2023").to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body, serde_json::json!({
+            "year": 2023,
+            "total_additions": 2,
+            "total_views": 0,
+            "days": [{"date": "2023-03-05", "additions": 2, "views": 0}],
+        }));
+
+        // Views are recorded today, which is the default year
+        let req = test::TestRequest::get().uri("/stats/activity").to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["year"], chrono::Datelike::year(&chrono::Utc::now()));
+        assert_eq!(body["total_views"], 2);
+
+        let req = test::TestRequest::get().uri("/stats/activity?year=300000").to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 400);
+    }
+
+    #[actix_rt::test]
+    async fn test_housekeeping_report_pages_through_selected_files() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        temp.child("small.jpg").write_binary(&[0; 10]).unwrap();
+        temp.child("big.jpg").write_binary(&[0; 30]).unwrap();
+        temp.child("medium.jpg").write_binary(&[0; 20]).unwrap();
+        let stats = stats::ViewStats::default();
+        stats.record_view("big.jpg");
+
+        let app = test::init_service(
+            App::new()
+                .app_data(error::query_config())
+                .app_data(web::Data::new(temp.path().to_path_buf()))
+                .app_data(web::Data::new(config::Config::default()))
+                .app_data(web::Data::new(stats))
+                .service(housekeeping_report)
+        ).await;
+
+        let req = test::TestRequest::get().uri("/reports/housekeeping?page=2&limit=1").to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!((body["total"].as_u64(), body["totalPages"].as_u64()), (Some(3), Some(3)));
+        assert_eq!(body["items"][0]["filename"], "medium.jpg");
+        assert_eq!(body["items"][0]["url"], "/images/medium.jpg");
+        assert_eq!(body["items"][0]["size_bytes"], 20);
+
+        let req = test::TestRequest::get().uri("/reports/housekeeping?report=unviewed").to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        let names: Vec<_> = body["items"].as_array().unwrap().iter().map(|i| i["filename"].as_str().unwrap()).collect();
+        assert_eq!(names, ["medium.jpg", "small.jpg"]);
+
+        let req = test::TestRequest::get().uri("/reports/housekeeping?report=duplicates").to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 400);
+    }
+
+    #[actix_rt::test]
+    async fn test_suggest_returns_matching_filenames() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        temp.child("sunset.jpg").write_binary(b"fake image content").unwrap();
+        temp.child("beach_sunrise.png").write_binary(b"fake image content").unwrap();
+        temp.child("forest.jpg").write_binary(b"fake image content").unwrap();
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(temp.path().to_path_buf()))
+                .app_data(web::Data::new(suggest::SuggestIndex::default()))
+                .app_data(web::Data::new(config::Config {
+                    public_base_url: "/photos".to_string(),
+                    ..config::Config::default()
+                }))
+                .service(handlers::suggest)
+        ).await;
+
+        let req = test::TestRequest::get()
+            .uri("/suggest?q=sun")
+            .to_request();
+
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        let values: Vec<_> = body["suggestions"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|s| s["value"].as_str().unwrap())
+            .collect();
+        assert_eq!(values, vec!["sunset.jpg", "beach_sunrise.png"]);
+        assert_eq!(body["suggestions"][0]["url"], "/photos/images/sunset.jpg");
+
+        // Hidden files do not use up the limit
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(temp.path().to_path_buf()))
+                .app_data(web::Data::new(suggest::SuggestIndex::default()))
+                .app_data(web::Data::new(config::Config {
+                    policy: policy::ContentPolicy {
+                        allowed_extensions: vec!["png".to_string()],
+                        ..policy::ContentPolicy::default()
+                    },
+                    ..config::Config::default()
+                }))
+                .service(handlers::suggest)
+        ).await;
+        let req = test::TestRequest::get().uri("/suggest?q=sun&limit=1").to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["suggestions"][0]["value"], "beach_sunrise.png");
+    }
+
+    #[actix_rt::test]
+    async fn test_maintenance_mode_rejects_non_admin_requests() {
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(maintenance::MaintenanceState::default()))
+                .app_data(web::Data::new(std::env::temp_dir()))
+                .app_data(web::Data::new(config::Config::default()))
+                .app_data(web::Data::new(cache::ImageCache::default()))
+                .app_data(web::Data::new(cache::VariantCache::default()))
+                .app_data(web::Data::new(indexer::LibraryIndex::default()))
+                .wrap(actix_web::middleware::from_fn(maintenance::reject_during_maintenance))
+                .service(health_check)
+                .service(maintenance_status)
+                .service(set_maintenance)
+                .service(delete_image)
+                .service(trashed_images)
+        ).await;
+
+        let req = test::TestRequest::put()
+            .uri("/admin/maintenance")
+            .set_json(serde_json::json!({ "enabled": true, "retry_after_secs": 120 }))
+            .to_request();
+        assert!(test::call_service(&app, req).await.status().is_success());
+
+        let req = test::TestRequest::get().uri("/health").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 503);
+        assert_eq!(resp.headers().get("Retry-After").unwrap(), "120");
+        // Only the /admin segment is exempt, not every path sharing its letters
+        let req = test::TestRequest::get().uri("/administrator").to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 503);
+        // Admin endpoints outside /admin keep working too
+        let req = test::TestRequest::delete().uri("/images/missing.jpg").to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 404);
+        let req = test::TestRequest::get().uri("/trash").to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 200);
+        let req = test::TestRequest::get().uri("/images/missing.jpg").to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 503);
+
+        let req = test::TestRequest::get().uri("/admin/maintenance").to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["enabled"], true);
+
+        let req = test::TestRequest::put()
+            .uri("/admin/maintenance")
+            .set_json(serde_json::json!({ "enabled": false }))
+            .to_request();
+        test::call_service(&app, req).await;
+
+        let req = test::TestRequest::get().uri("/health").to_request();
+        assert!(test::call_service(&app, req).await.status().is_success());
+    }
 }
@@ -0,0 +1,327 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use crate::galleryignore::IgnoreRules;
+use crate::media_types::{self, MediaClass, MediaKind};
+
+// Suffixes editors append to exported copies, e.g. IMG_0001-edited.jpg
+const EDIT_SUFFIXES: &[&str] = &["-edited", "_edited", "-edit", "_edit", " edited", " copy"];
+
+#[derive(Debug, Clone)]
+pub struct LibraryImage {
+    pub filename: String,
+    pub path: PathBuf,
+    pub size_bytes: u64,
+    pub modified: Option<DateTime<Utc>>,
+}
+
+impl LibraryImage {
+    /// The file at `path`, or `None` when it is gone, not a regular file or not a supported format.
+    pub fn open(path: &Path) -> Option<Self> {
+        let metadata = path.symlink_metadata().ok()?;
+        if !metadata.is_file() || !is_media_file(path) {
+            return None;
+        }
+        Some(LibraryImage {
+            filename: path.file_name()?.to_str()?.to_string(),
+            size_bytes: metadata.len(),
+            modified: metadata.modified().ok().map(DateTime::<Utc>::from),
+            path: path.to_path_buf(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RenditionKind {
+    Original,
+    Edit,
+    Raw,
+    Video,
+}
+
+#[derive(Debug, Clone)]
+pub struct Rendition {
+    pub kind: RenditionKind,
+    pub image: LibraryImage,
+}
+
+/// One logical photo: the preferred rendition plus every file sharing its basename.
+/// Videos stand alone, each in a stack of its own.
+#[derive(Debug, Clone)]
+pub struct ImageStack {
+    pub key: String,
+    pub renditions: Vec<Rendition>,
+    /// For a video, a decodable still sharing its basename (a Live Photo's
+    /// image or an exported poster frame).
+    pub poster: Option<String>,
+}
+
+impl ImageStack {
+    /// The rendition shown by default: an original, then an edit, then the RAW.
+    pub fn primary(&self) -> &LibraryImage {
+        &self.renditions[0].image
+    }
+
+    pub fn media_class(&self) -> MediaClass {
+        match self.renditions[0].kind {
+            RenditionKind::Video => MediaClass::Video,
+            _ => MediaClass::Image,
+        }
+    }
+}
+
+/// Attribute of an image stack's primary rendition to order listings by.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortField {
+    #[default]
+    Name,
+    Size,
+    Date,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    #[default]
+    Asc,
+    Desc,
+}
+
+pub fn is_raw_file(path: &Path) -> bool {
+    media_types::for_path(path).is_some_and(|media| media.kind == MediaKind::Raw)
+}
+
+pub fn is_video_file(path: &Path) -> bool {
+    media_types::for_path(path).is_some_and(|media| media.kind == MediaKind::Video)
+}
+
+pub fn is_media_file(path: &Path) -> bool {
+    media_types::for_path(path).is_some()
+}
+
+fn stack_key(path: &Path) -> (String, RenditionKind) {
+    let stem = path
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or_default()
+        .to_lowercase();
+
+    if is_raw_file(path) {
+        return (stem, RenditionKind::Raw);
+    }
+    // Kept apart from stills of the same name, which become its poster instead
+    if is_video_file(path) {
+        let name = path.file_name().and_then(|n| n.to_str()).unwrap_or_default();
+        return (format!("{}\0{}", stem, name.to_lowercase()), RenditionKind::Video);
+    }
+
+    match EDIT_SUFFIXES.iter().find_map(|suffix| stem.strip_suffix(suffix)) {
+        Some(base) => (base.to_string(), RenditionKind::Edit),
+        None => (stem, RenditionKind::Original),
+    }
+}
+
+/// Groups RAW files, originals and exported edits that share a basename.
+pub fn stack(images: Vec<LibraryImage>) -> Vec<ImageStack> {
+    let mut stacks: BTreeMap<String, Vec<Rendition>> = BTreeMap::new();
+    for image in images {
+        let (key, kind) = stack_key(&image.path);
+        stacks.entry(key).or_default().push(Rendition { kind, image });
+    }
+
+    let mut stacks: Vec<ImageStack> = stacks
+        .into_iter()
+        .map(|(key, mut renditions)| {
+            renditions.sort_by(|a, b| a.kind.cmp(&b.kind).then(a.image.filename.cmp(&b.image.filename)));
+            ImageStack { key, renditions, poster: None }
+        })
+        .collect();
+
+    let stills: BTreeMap<String, String> = stacks
+        .iter()
+        .filter(|stack| media_types::for_path(&stack.primary().path).is_some_and(|media| media.decodable))
+        .map(|stack| (stack.key.clone(), stack.primary().filename.clone()))
+        .collect();
+    for stack in &mut stacks {
+        if stack.media_class() == MediaClass::Video {
+            let stem = stack.key.split('\0').next().unwrap_or_default();
+            stack.poster = stills.get(stem).cloned();
+        }
+    }
+
+    stacks.sort_by(|a, b| a.primary().filename.cmp(&b.primary().filename));
+    stacks
+}
+
+/// Orders stacks by their primary rendition; ties fall back to filename ascending.
+/// Images without a modification time sort before dated ones in ascending order.
+pub fn sort(stacks: &mut [ImageStack], field: SortField, order: SortOrder) {
+    stacks.sort_by(|a, b| {
+        let (a, b) = (a.primary(), b.primary());
+        let ordering = match field {
+            SortField::Name => a.filename.cmp(&b.filename),
+            SortField::Size => a.size_bytes.cmp(&b.size_bytes),
+            SortField::Date => a.modified.cmp(&b.modified),
+        };
+        let ordering = match order {
+            SortOrder::Asc => ordering,
+            SortOrder::Desc => ordering.reverse(),
+        };
+        ordering.then_with(|| a.filename.cmp(&b.filename))
+    });
+}
+
+/// Lists the image files directly inside `dir`, sorted by filename, leaving
+/// out those excluded by its `.galleryignore`.
+pub fn scan(dir: &Path) -> std::io::Result<Vec<LibraryImage>> {
+    let mut images = Vec::new();
+    let ignored = IgnoreRules::for_dir(dir, dir);
+
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        let metadata = entry.metadata()?;
+        if !metadata.is_file() || !is_media_file(&path) || ignored.is_ignored(&path, false) {
+            continue;
+        }
+
+        let filename = match path.file_name().and_then(|n| n.to_str()) {
+            Some(name) => name.to_string(),
+            None => continue,
+        };
+
+        images.push(LibraryImage {
+            filename,
+            size_bytes: metadata.len(),
+            modified: metadata.modified().ok().map(DateTime::<Utc>::from),
+            path,
+        });
+    }
+
+    images.sort_by(|a, b| a.filename.cmp(&b.filename));
+    Ok(images)
+}
+
+/// [`scan`] of the images directory `dir` plus referenced folders, which are
+/// served in place without being copied in. A name in `dir` hides the same
+/// name in a referenced folder, and earlier referenced folders hide later
+/// ones. An unreadable referenced folder is skipped with a warning.
+pub fn scan_all(dir: &Path, referenced_dirs: &[PathBuf]) -> std::io::Result<Vec<LibraryImage>> {
+    scan_all_with_hidden(dir, referenced_dirs).map(|(images, _)| images)
+}
+
+/// [`scan_all`], plus the paths of referenced files it left out because an
+/// earlier folder has a file of the same name.
+pub fn scan_all_with_hidden(
+    dir: &Path,
+    referenced_dirs: &[PathBuf],
+) -> std::io::Result<(Vec<LibraryImage>, Vec<PathBuf>)> {
+    let mut images = scan(dir)?;
+    let mut hidden = Vec::new();
+    if referenced_dirs.is_empty() {
+        return Ok((images, hidden));
+    }
+
+    let mut names: HashSet<String> = images.iter().map(|image| image.filename.clone()).collect();
+    for referenced in referenced_dirs {
+        match scan(referenced) {
+            Ok(found) => {
+                for image in found {
+                    if names.insert(image.filename.clone()) {
+                        images.push(image);
+                    } else {
+                        hidden.push(image.path);
+                    }
+                }
+            }
+            Err(e) => log::warn!("Skipping referenced folder {:?}: {}", referenced, e),
+        }
+    }
+    images.sort_by(|a, b| a.filename.cmp(&b.filename));
+    Ok((images, hidden))
+}
+
+/// The file [`scan_all`] lists as `filename`, if any.
+pub fn open_in(dir: &Path, referenced_dirs: &[PathBuf], filename: &str) -> Option<LibraryImage> {
+    std::iter::once(dir)
+        .chain(referenced_dirs.iter().map(PathBuf::as_path))
+        .find_map(|dir| {
+            let path = dir.join(filename);
+            if IgnoreRules::for_dir(dir, dir).is_ignored(&path, false) {
+                return None;
+            }
+            LibraryImage::open(&path)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_fs::prelude::*;
+
+    #[test]
+    fn scan_skips_non_images_and_directories() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        temp.child("b.JPG").write_binary(b"b").unwrap();
+        temp.child("a.png").write_binary(b"a").unwrap();
+        temp.child("notes.txt").write_str("not an image").unwrap();
+        temp.child("nested.jpg").create_dir_all().unwrap();
+
+        let names: Vec<_> = scan(temp.path())
+            .unwrap()
+            .into_iter()
+            .map(|image| image.filename)
+            .collect();
+
+        assert_eq!(names, vec!["a.png", "b.JPG"]);
+    }
+
+    #[test]
+    fn scan_and_open_honor_galleryignore() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        temp.child("a.jpg").write_binary(b"a").unwrap();
+        temp.child("private-1.jpg").write_binary(b"p").unwrap();
+        temp.child(".galleryignore").write_str("# drafts\nprivate-*\n").unwrap();
+
+        let names: Vec<_> = scan(temp.path()).unwrap().into_iter().map(|image| image.filename).collect();
+        assert_eq!(names, vec!["a.jpg"]);
+        assert!(open_in(temp.path(), &[], "private-1.jpg").is_none());
+        assert!(open_in(temp.path(), &[], "a.jpg").is_some());
+    }
+
+    #[test]
+    fn stack_pairs_raw_and_edits_with_original() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        for name in ["IMG_0001.CR2", "IMG_0001.jpg", "IMG_0001-edited.jpg", "IMG_0002.nef", "IMG_0003.png", "IMG_0003.MOV", "IMG_0004.mp4"] {
+            temp.child(name).write_binary(b"x").unwrap();
+        }
+
+        let stacks = stack(scan(temp.path()).unwrap());
+        let summary: Vec<(String, Vec<RenditionKind>)> = stacks
+            .iter()
+            .map(|s| (s.primary().filename.clone(), s.renditions.iter().map(|r| r.kind).collect()))
+            .collect();
+
+        assert_eq!(
+            summary,
+            vec![
+                ("IMG_0001.jpg".to_string(), vec![RenditionKind::Original, RenditionKind::Edit, RenditionKind::Raw]),
+                ("IMG_0002.nef".to_string(), vec![RenditionKind::Raw]),
+                ("IMG_0003.MOV".to_string(), vec![RenditionKind::Video]),
+                ("IMG_0003.png".to_string(), vec![RenditionKind::Original]),
+                ("IMG_0004.mp4".to_string(), vec![RenditionKind::Video]),
+            ]
+        );
+
+        let posters: Vec<_> = stacks
+            .iter()
+            .filter(|s| s.media_class() == MediaClass::Video)
+            .map(|s| s.poster.as_deref())
+            .collect();
+        assert_eq!(posters, vec![Some("IMG_0003.png"), None]);
+    }
+}
@@ -2,9 +2,12 @@ use actix_web::{get, web, HttpResponse, Result};
 use std::path::{Path, PathBuf};
 use anyhow::Context;
 use log::info;
-use images_api::startup;
+use clap::Parser;
+use images_api::cli::{Cli, Command};
+use images_api::indexer::{IndexState, LibraryIndex};
+use images_api::ingest::IngestRules;
+use images_api::{library, startup, thumbnails};
 use serde::Serialize;
-use chrono;
 use image::{io, GenericImageView};
 
 #[derive(Serialize)]
@@ -31,8 +34,7 @@ async fn get_image_info(path: &Path) -> anyhow::Result<ImageInfo> {
         last_modified: metadata.modified()
             .ok()
             .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
-            .map(|d| chrono::DateTime::from_timestamp(d.as_secs() as i64, 0))
-            .flatten()
+            .and_then(|d| chrono::DateTime::from_timestamp(d.as_secs() as i64, 0))
             .map(|dt| dt.to_rfc3339())
             .unwrap_or_else(|| "unknown".to_string()),
     })
@@ -97,13 +99,48 @@ async fn health_check() -> Result<HttpResponse> {
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     env_logger::init_from_env(env_logger::Env::new().default_filter_or("info"));
-    
+
+    let cli = Cli::parse();
+    let mut config = cli.load_config().map_err(|e| std::io::Error::other(format!("{:#}", e)))?;
+
     // Create images directory if it doesn't exist
-    std::fs::create_dir_all("images")?;
-    
-    let images_dir = PathBuf::from("images");
-    info!("Starting server with images directory: {:?}", images_dir);
-    let server = startup::run(images_dir).await?;
-    
-    server.await
+    std::fs::create_dir_all(&config.images_dir)?;
+    config.images_dir = config.images_dir.canonicalize()?;
+    // Video roots on unmounted volumes are left as configured for the volume checks
+    for dir in config.video_roots.values_mut() {
+        if let Ok(canonical) = dir.canonicalize() {
+            *dir = canonical;
+        }
+    }
+
+    match cli.command() {
+        Command::Serve => {
+            info!("Starting server with images directory: {:?}", config.images_dir);
+            let server = startup::run(config).await?;
+            server.await
+        }
+        Command::Index => {
+            let rules = IngestRules::compile(&config.ingest_rules).map_err(std::io::Error::other)?;
+            let index = LibraryIndex::with_rules(rules);
+            index.begin();
+            index.run(&config.images_dir, &config.policy.referenced_dirs);
+            let progress = index.progress();
+            if progress.state == IndexState::Failed {
+                println!("{}", serde_json::to_string_pretty(&serde_json::json!({ "index": progress }))?);
+                return Err(std::io::Error::other("Indexing failed"));
+            }
+
+            // The catalog dies with the process; the thumbnails stay for `serve`
+            let sources: Vec<PathBuf> = library::scan_all(&config.images_dir, &config.policy.referenced_dirs)?
+                .into_iter()
+                .map(|image| image.path)
+                .collect();
+            let warmed = thumbnails::warm(&config.images_dir, &sources, thumbnails::DEFAULT_SIZE);
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&serde_json::json!({ "index": progress, "thumbnails": warmed }))?
+            );
+            Ok(())
+        }
+    }
 }
@@ -0,0 +1,92 @@
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::Method;
+use actix_web::middleware::Next;
+use actix_web::{web, Error, HttpResponse};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::sync::RwLock;
+
+const DEFAULT_RETRY_AFTER_SECS: u64 = 300;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MaintenanceWindow {
+    pub message: String,
+    pub retry_after_secs: u64,
+    pub since: DateTime<Utc>,
+}
+
+#[derive(Serialize)]
+pub struct MaintenanceStatus {
+    pub enabled: bool,
+    #[serde(flatten)]
+    pub window: Option<MaintenanceWindow>,
+}
+
+/// Shared switch consulted by [`reject_during_maintenance`] on every request.
+#[derive(Default)]
+pub struct MaintenanceState {
+    window: RwLock<Option<MaintenanceWindow>>,
+}
+
+impl MaintenanceState {
+    pub fn current(&self) -> Option<MaintenanceWindow> {
+        self.window.read().unwrap().clone()
+    }
+
+    pub fn enable(&self, message: Option<String>, retry_after_secs: Option<u64>) {
+        *self.window.write().unwrap() = Some(MaintenanceWindow {
+            message: message.unwrap_or_else(|| "Scheduled maintenance in progress".to_string()),
+            retry_after_secs: retry_after_secs.unwrap_or(DEFAULT_RETRY_AFTER_SECS),
+            since: Utc::now(),
+        });
+    }
+
+    pub fn disable(&self) {
+        *self.window.write().unwrap() = None;
+    }
+
+    pub fn status(&self) -> MaintenanceStatus {
+        let window = self.current();
+        MaintenanceStatus {
+            enabled: window.is_some(),
+            window,
+        }
+    }
+}
+
+/// Whether `req` is for an admin endpoint: everything under `/admin`, plus
+/// deleting images and the trash, which are served with them.
+fn is_admin(req: &ServiceRequest) -> bool {
+    let path = req.path();
+    path == "/admin"
+        || path.starts_with("/admin/")
+        || path == "/trash"
+        || path.starts_with("/trash/")
+        || (req.method() == Method::DELETE && path.starts_with("/images/"))
+}
+
+/// Answers every non-admin request with 503 and `Retry-After` while maintenance is on.
+pub async fn reject_during_maintenance(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let window = req
+        .app_data::<web::Data<MaintenanceState>>()
+        .and_then(|state| state.current());
+
+    match window {
+        Some(window) if !is_admin(&req) => {
+            let response = HttpResponse::ServiceUnavailable()
+                .insert_header(("Retry-After", window.retry_after_secs.to_string()))
+                .json(serde_json::json!({
+                    "status": "maintenance",
+                    "message": window.message,
+                    "retry_after_secs": window.retry_after_secs,
+                    "since": window.since,
+                }));
+            Ok(req.into_response(response))
+        }
+        _ => next.call(req).await.map(ServiceResponse::map_into_boxed_body),
+    }
+}
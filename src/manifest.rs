@@ -0,0 +1,97 @@
+use chrono::{DateTime, Utc};
+use ring::digest::{Context, SHA256};
+use serde::{Deserialize, Serialize};
+use std::fmt::Write as _;
+use std::io::Read;
+
+use crate::library::LibraryImage;
+
+/// Column names of the CSV manifest, in the order [`ManifestEntry::csv_line`] writes them.
+pub const CSV_HEADER: &str = "filename,size_bytes,modified,sha256\n";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ManifestFormat {
+    Csv,
+    #[default]
+    Ndjson,
+}
+
+/// One library file as recorded in an audit manifest.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ManifestEntry {
+    pub filename: String,
+    pub size_bytes: u64,
+    pub modified: Option<DateTime<Utc>>,
+    /// Lowercase hex SHA-256 of the file contents.
+    pub sha256: String,
+}
+
+impl ManifestEntry {
+    /// Hashes `image`, taking size and modification time from the file as read.
+    pub fn read(image: &LibraryImage) -> std::io::Result<Self> {
+        let mut file = std::fs::File::open(&image.path)?;
+        let metadata = file.metadata()?;
+        Ok(ManifestEntry {
+            filename: image.filename.clone(),
+            size_bytes: metadata.len(),
+            modified: metadata.modified().ok().map(DateTime::<Utc>::from),
+            sha256: sha256(&mut file)?,
+        })
+    }
+
+    pub fn csv_line(&self) -> String {
+        format!(
+            "{},{},{},{}\n",
+            csv_field(&self.filename),
+            self.size_bytes,
+            self.modified.map(|modified| modified.to_rfc3339()).unwrap_or_default(),
+            self.sha256
+        )
+    }
+}
+
+fn sha256(reader: &mut impl Read) -> std::io::Result<String> {
+    let mut context = Context::new(&SHA256);
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        let read = reader.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        context.update(&buffer[..read]);
+    }
+    let mut hex = String::with_capacity(64);
+    for byte in context.finish().as_ref() {
+        let _ = write!(hex, "{:02x}", byte);
+    }
+    Ok(hex)
+}
+
+/// Quotes a field containing a separator, quote or line break, per RFC 4180.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_fs::prelude::*;
+
+    #[test]
+    fn hashes_and_formats_entries() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        temp.child("a, \"b\".jpg").write_binary(b"abc").unwrap();
+
+        let image = LibraryImage::open(temp.child("a, \"b\".jpg").path()).unwrap();
+        let entry = ManifestEntry::read(&image).unwrap();
+        assert_eq!(entry.size_bytes, 3);
+        assert_eq!(entry.sha256, "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+        assert!(entry.csv_line().starts_with("\"a, \"\"b\"\".jpg\",3,"));
+        assert!(entry.csv_line().ends_with(",ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad\n"));
+    }
+}
@@ -0,0 +1,137 @@
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Broad family of a media type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MediaKind {
+    /// Ordinary raster images: JPEG, PNG, HEIC and friends.
+    Image,
+    /// Camera RAW files, stacked behind their JPEG/edit renditions.
+    Raw,
+    /// Movies, served as-is (with range support) but never decoded.
+    Video,
+}
+
+impl MediaKind {
+    pub fn class(self) -> MediaClass {
+        match self {
+            MediaKind::Image | MediaKind::Raw => MediaClass::Image,
+            MediaKind::Video => MediaClass::Video,
+        }
+    }
+}
+
+/// What a listing entry is from a client's point of view; RAW files are images too.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MediaClass {
+    Image,
+    Video,
+}
+
+/// What the server can do with files of one format.
+#[derive(Debug, PartialEq, Eq)]
+pub struct MediaType {
+    pub extensions: &'static [&'static str],
+    pub mime: &'static str,
+    pub kind: MediaKind,
+    /// Can be decoded here, so it can be resized, converted and thumbnailed.
+    pub decodable: bool,
+    /// Carries EXIF in a container the metadata reader understands.
+    pub exif: bool,
+}
+
+const fn image(extensions: &'static [&'static str], mime: &'static str, decodable: bool, exif: bool) -> MediaType {
+    MediaType {
+        extensions,
+        mime,
+        kind: MediaKind::Image,
+        decodable,
+        exif,
+    }
+}
+
+const fn raw(extensions: &'static [&'static str], mime: &'static str, exif: bool) -> MediaType {
+    MediaType {
+        extensions,
+        mime,
+        kind: MediaKind::Raw,
+        decodable: false,
+        exif,
+    }
+}
+
+const fn video(extensions: &'static [&'static str], mime: &'static str) -> MediaType {
+    MediaType {
+        extensions,
+        mime,
+        kind: MediaKind::Video,
+        decodable: false,
+        exif: false,
+    }
+}
+
+/// Every format the library recognizes. Supporting a new one is a single entry here.
+pub const MEDIA_TYPES: &[MediaType] = &[
+    image(&["jpg", "jpeg"], "image/jpeg", true, true),
+    image(&["png"], "image/png", true, true),
+    image(&["gif"], "image/gif", true, false),
+    image(&["webp"], "image/webp", true, true),
+    image(&["tif", "tiff"], "image/tiff", true, true),
+    image(&["bmp"], "image/bmp", true, false),
+    image(&["heic", "heif"], "image/heic", false, true),
+    // TIFF-based RAW containers expose EXIF; the rest need vendor parsers
+    raw(&["cr2"], "image/x-canon-cr2", true),
+    raw(&["cr3"], "image/x-canon-cr3", false),
+    raw(&["nef"], "image/x-nikon-nef", true),
+    raw(&["arw"], "image/x-sony-arw", true),
+    raw(&["dng"], "image/x-adobe-dng", true),
+    raw(&["raf"], "image/x-fuji-raf", false),
+    raw(&["orf"], "image/x-olympus-orf", false),
+    raw(&["rw2"], "image/x-panasonic-rw2", false),
+    video(&["mp4"], "video/mp4"),
+    video(&["mov"], "video/quicktime"),
+    video(&["m4v"], "video/x-m4v"),
+    video(&["webm"], "video/webm"),
+];
+
+/// Looks up the media type for `path` by extension, ignoring case.
+pub fn for_path(path: &Path) -> Option<&'static MediaType> {
+    let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+    MEDIA_TYPES
+        .iter()
+        .find(|media| media.extensions.contains(&extension.as_str()))
+}
+
+/// Looks up the media type by sniffing magic bytes, for files with a missing or wrong extension.
+pub fn for_bytes(bytes: &[u8]) -> Option<&'static MediaType> {
+    let mime = image::guess_format(bytes).ok()?.to_mime_type();
+    MEDIA_TYPES.iter().find(|media| media.mime == mime)
+}
+
+/// `Content-Type` for serving `path`: by extension, then by content, then generic.
+pub fn content_type(path: &Path, bytes: &[u8]) -> &'static str {
+    for_path(path)
+        .or_else(|| for_bytes(bytes))
+        .map(|media| media.mime)
+        .unwrap_or("application/octet-stream")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn looks_up_by_extension_then_magic() {
+        assert_eq!(for_path(Path::new("a.JPEG")).unwrap().mime, "image/jpeg");
+        assert_eq!(for_path(Path::new("a.nef")).unwrap().kind, MediaKind::Raw);
+        assert!(!for_path(Path::new("a.heic")).unwrap().decodable);
+        assert!(for_path(Path::new("notes.txt")).is_none());
+        assert_eq!(for_path(Path::new("clip.MOV")).unwrap().kind.class(), MediaClass::Video);
+
+        let png = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR";
+        assert_eq!(content_type(Path::new("upload.bin"), png), "image/png");
+        assert_eq!(content_type(Path::new("a.png"), b"not really"), "image/png");
+        assert_eq!(content_type(Path::new("upload.bin"), b"???"), "application/octet-stream");
+    }
+}
@@ -0,0 +1,69 @@
+use chrono::{NaiveDate, NaiveDateTime};
+use std::fs::File;
+use std::io::{BufReader, Cursor};
+use std::path::Path;
+
+use crate::media_types;
+
+fn read_exif(path: &Path) -> Option<exif::Exif> {
+    // Skip formats whose container the EXIF reader cannot parse
+    if !media_types::for_path(path).is_none_or(|media| media.exif) {
+        return None;
+    }
+    let file = File::open(path).ok()?;
+    exif::Reader::new()
+        .read_from_container(&mut BufReader::new(file))
+        .ok()
+}
+
+fn orientation_of(exif: &exif::Exif) -> Option<u32> {
+    exif.get_field(exif::Tag::Orientation, exif::In::PRIMARY)?
+        .value
+        .get_uint(0)
+        .filter(|orientation| (1..=8).contains(orientation))
+}
+
+/// Reads the EXIF `Orientation` tag (1-8); `None` when absent or unreadable.
+pub fn orientation(path: &Path) -> Option<u32> {
+    orientation_of(&read_exif(path)?)
+}
+
+/// Like [`orientation`], for an image held in memory.
+pub fn orientation_in(bytes: &[u8]) -> Option<u32> {
+    let exif = exif::Reader::new().read_from_container(&mut Cursor::new(bytes)).ok()?;
+    orientation_of(&exif)
+}
+
+/// Reads the EXIF capture date (`DateTimeOriginal`, falling back to `DateTime`).
+pub fn capture_date(path: &Path) -> Option<NaiveDateTime> {
+    let exif = read_exif(path)?;
+
+    [exif::Tag::DateTimeOriginal, exif::Tag::DateTime]
+        .iter()
+        .filter_map(|tag| exif.get_field(*tag, exif::In::PRIMARY))
+        .find_map(|field| match &field.value {
+            exif::Value::Ascii(values) => values.first().and_then(|raw| parse_exif_datetime(raw)),
+            _ => None,
+        })
+}
+
+/// The EXIF camera `Make` and `Model`, e.g. `Apple iPhone 15 Pro`.
+pub fn camera(path: &Path) -> Option<String> {
+    let exif = read_exif(path)?;
+    let parts: Vec<String> = [exif::Tag::Make, exif::Tag::Model]
+        .iter()
+        .filter_map(|tag| exif.get_field(*tag, exif::In::PRIMARY))
+        .filter_map(|field| match &field.value {
+            exif::Value::Ascii(values) => values.first().map(|raw| String::from_utf8_lossy(raw).trim().to_string()),
+            _ => None,
+        })
+        .filter(|part| !part.is_empty())
+        .collect();
+    (!parts.is_empty()).then(|| parts.join(" "))
+}
+
+fn parse_exif_datetime(raw: &[u8]) -> Option<NaiveDateTime> {
+    let dt = exif::DateTime::from_ascii(raw).ok()?;
+    NaiveDate::from_ymd_opt(dt.year.into(), dt.month.into(), dt.day.into())?
+        .and_hms_opt(dt.hour.into(), dt.minute.into(), dt.second.into())
+}
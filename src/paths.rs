@@ -0,0 +1,249 @@
+use percent_encoding::percent_decode_str;
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, SystemTime};
+
+use crate::error::ApiError;
+
+// Enough to unwrap any realistic multiple encoding without looping forever
+const MAX_DECODE_ROUNDS: usize = 4;
+
+/// True when `name` is exactly one ordinary path component: no separators,
+/// no `.`/`..`, no root or drive prefix.
+fn is_plain_file_name(name: &str) -> bool {
+    let mut components = Path::new(name).components();
+    matches!(components.next(), Some(Component::Normal(_)))
+        && components.next().is_none()
+        && !name.contains(['/', '\\', '\0'])
+}
+
+/// True when `name` stays a plain file name however many times it is percent-decoded.
+fn decodes_to_plain_file_name(name: &str) -> bool {
+    let mut candidate = name.to_string();
+    for _ in 0..MAX_DECODE_ROUNDS {
+        if !is_plain_file_name(&candidate) {
+            return false;
+        }
+        match percent_decode_str(&candidate).decode_utf8() {
+            Ok(decoded) if decoded == candidate => return true,
+            Ok(decoded) => candidate = decoded.into_owned(),
+            Err(_) => return false,
+        }
+    }
+    false
+}
+
+/// Swaps the case of every letter, or `None` when the name has no cased letters.
+fn swap_case(name: &str) -> Option<String> {
+    let swapped: String = name
+        .chars()
+        .map(|c| match c {
+            c if c.is_lowercase() => c.to_uppercase().next().unwrap_or(c),
+            c if c.is_uppercase() => c.to_lowercase().next().unwrap_or(c),
+            c => c,
+        })
+        .collect();
+    (swapped != name).then_some(swapped)
+}
+
+// Distinguishes "A.JPG is another name for a.jpg" from two files differing in case
+#[cfg(unix)]
+fn is_same_file(a: &Path, b: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    match (a.symlink_metadata(), b.symlink_metadata()) {
+        (Ok(a), Ok(b)) => (a.dev(), a.ino()) == (b.dev(), b.ino()),
+        _ => false,
+    }
+}
+
+#[cfg(not(unix))]
+fn is_same_file(_: &Path, b: &Path) -> bool {
+    b.symlink_metadata().is_ok()
+}
+
+/// Whether `root` lives on a case-insensitive volume (e.g. APFS by default).
+///
+/// Probed once per root by looking up an existing entry under swapped case;
+/// the answer is only remembered once a probe was possible.
+fn is_case_insensitive(root: &Path) -> bool {
+    static VOLUMES: OnceLock<Mutex<HashMap<PathBuf, bool>>> = OnceLock::new();
+    let volumes = VOLUMES.get_or_init(Default::default);
+    if let Some(insensitive) = volumes.lock().unwrap().get(root) {
+        return *insensitive;
+    }
+
+    let probe = std::fs::read_dir(root).ok().and_then(|entries| {
+        entries.flatten().find_map(|entry| {
+            let swapped = swap_case(entry.file_name().to_str()?)?;
+            Some(is_same_file(&entry.path(), &root.join(swapped)))
+        })
+    });
+    match probe {
+        Some(insensitive) => {
+            volumes.lock().unwrap().insert(root.to_path_buf(), insensitive);
+            insensitive
+        }
+        None => false,
+    }
+}
+
+// Lowercased entry names, each mapped to its on-disk spelling, or to None
+// when several entries differ only in case
+type CaseMap = HashMap<String, Option<String>>;
+
+// A directory's case map and the modification time it was read at
+type Listing = (SystemTime, Arc<CaseMap>);
+
+// A directory changed this recently may change again within the same
+// modification time tick, so its listing is not reused
+const RACY_MTIME: Duration = Duration::from_secs(2);
+
+fn read_case_map(root: &Path) -> Option<CaseMap> {
+    let mut names = CaseMap::new();
+    for name in std::fs::read_dir(root).ok()?.flatten().filter_map(|entry| entry.file_name().into_string().ok()) {
+        names
+            .entry(name.to_lowercase())
+            .and_modify(|found| *found = None)
+            .or_insert(Some(name));
+    }
+    Some(names)
+}
+
+/// The on-disk spelling of `name` in `root` when exactly one entry matches it
+/// case-insensitively.
+///
+/// Listings are cached per directory until its modification time changes,
+/// so repeated lookups do not read the directory each time.
+fn find_case_insensitive(root: &Path, name: &str) -> Option<String> {
+    static LISTINGS: OnceLock<Mutex<HashMap<PathBuf, Listing>>> = OnceLock::new();
+    let listings = LISTINGS.get_or_init(Default::default);
+    let wanted = name.to_lowercase();
+
+    let modified = root.metadata().and_then(|metadata| metadata.modified()).ok();
+    let cached = listings
+        .lock()
+        .unwrap()
+        .get(root)
+        .filter(|(at, _)| Some(*at) == modified)
+        .map(|(_, names)| names.clone());
+    let names = match cached {
+        Some(names) => names,
+        None => {
+            let names = Arc::new(read_case_map(root)?);
+            let mut listings = listings.lock().unwrap();
+            match modified.filter(|at| at.elapsed().is_ok_and(|age| age > RACY_MTIME)) {
+                Some(at) => {
+                    listings.insert(root.to_path_buf(), (at, names.clone()));
+                }
+                None => {
+                    listings.remove(root);
+                }
+            }
+            names
+        }
+    };
+    names.get(&wanted).cloned().flatten()
+}
+
+/// Resolves a client-supplied file name to a path inside `root`.
+///
+/// Lookups ignore case when that is unambiguous: on case-sensitive volumes a
+/// miss falls back to the single entry matching case-insensitively, and on
+/// case-insensitive volumes the returned path always carries the on-disk
+/// spelling. Either way `test.JPG` and `test.jpg` resolve to the same file
+/// name, so caches and stats keyed by it stay consistent.
+///
+/// The name is checked as received and after each round of percent-decoding,
+/// so encoded and double-encoded traversal such as `%252e%252e%252f` is
+/// rejected (400). Existing paths are canonicalized and must stay inside the
+/// canonical root, which catches symlinks pointing elsewhere (403). Paths that
+/// do not exist are returned as-is for the caller to 404.
+pub fn resolve(root: &Path, name: &str) -> Result<PathBuf, ApiError> {
+    if !decodes_to_plain_file_name(name) {
+        return Err(ApiError::validation("Invalid file name"));
+    }
+
+    let mut path = root.join(name);
+    let exists = path.symlink_metadata().is_ok();
+    if !exists || is_case_insensitive(root) {
+        if let Some(on_disk) = find_case_insensitive(root, name) {
+            path = root.join(on_disk);
+        }
+    }
+
+    let Ok(resolved) = path.canonicalize() else {
+        return Ok(path);
+    };
+    let root = root.canonicalize().unwrap_or_else(|_| root.to_path_buf());
+    if !resolved.starts_with(&root) {
+        log::warn!("Refusing {:?}: resolves outside {:?}", name, root);
+        return Err(ApiError::Forbidden("Access denied".to_string()));
+    }
+    Ok(path)
+}
+
+/// The file name component of a path returned by [`resolve`].
+pub fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_fs::prelude::*;
+
+    #[test]
+    fn rejects_traversal_and_separators() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        temp.child("a.jpg").write_binary(b"a").unwrap();
+
+        assert_eq!(resolve(temp.path(), "a.jpg").unwrap(), temp.path().join("a.jpg"));
+        assert_eq!(resolve(temp.path(), "missing.jpg").unwrap(), temp.path().join("missing.jpg"));
+
+        for name in ["..", ".", "", "../etc/passwd", "/etc/passwd", "a/b.jpg", "..\\a.jpg", "%2e%2e", "..%2Fa.jpg", "%252e%252e%252f"] {
+            assert!(
+                matches!(resolve(temp.path(), name), Err(ApiError::Validation(_))),
+                "{:?} should be rejected",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn resolves_case_mismatches_to_the_on_disk_name() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        temp.child("Photo.JPG").write_binary(b"p").unwrap();
+
+        assert_eq!(resolve(temp.path(), "photo.jpg").unwrap(), temp.path().join("Photo.JPG"));
+        assert_eq!(resolve(temp.path(), "Photo.JPG").unwrap(), temp.path().join("Photo.JPG"));
+    }
+
+    #[test]
+    fn cached_listings_follow_renames() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        temp.child("Photo.JPG").write_binary(b"p").unwrap();
+        let settled = SystemTime::now() - Duration::from_secs(3600);
+        if std::fs::File::open(temp.path()).and_then(|dir| dir.set_modified(settled)).is_err() {
+            eprintln!("skipping: cannot set directory modification times");
+            return;
+        }
+        assert_eq!(find_case_insensitive(temp.path(), "photo.jpg").as_deref(), Some("Photo.JPG"));
+
+        std::fs::rename(temp.path().join("Photo.JPG"), temp.path().join("PHOTO.jpg")).unwrap();
+        assert_eq!(find_case_insensitive(temp.path(), "photo.jpg").as_deref(), Some("PHOTO.jpg"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn rejects_symlinks_escaping_the_root() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        let outside = assert_fs::TempDir::new().unwrap();
+        outside.child("secret.jpg").write_binary(b"s").unwrap();
+        std::os::unix::fs::symlink(outside.path().join("secret.jpg"), temp.path().join("link.jpg")).unwrap();
+
+        assert!(matches!(resolve(temp.path(), "link.jpg"), Err(ApiError::Forbidden(_))));
+    }
+}
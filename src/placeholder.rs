@@ -0,0 +1,137 @@
+use actix_web::HttpResponse;
+use serde::Deserialize;
+
+/// Response header marking a generated stand-in for a missing or broken image.
+pub const HEADER: &str = "X-Placeholder";
+
+/// Stand-in images answered instead of 404s and decode failures, so gallery
+/// grids show a tile rather than a broken image; the `[placeholder]` table.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PlaceholderConfig {
+    pub enabled: bool,
+    /// Size used when the request names none, as for full-size originals.
+    pub width: u32,
+    pub height: u32,
+    /// Background as `#rgb` or `#rrggbb`.
+    pub color: String,
+    /// Caption in the middle; empty leaves the tile blank.
+    pub text: String,
+}
+
+impl Default for PlaceholderConfig {
+    fn default() -> Self {
+        PlaceholderConfig {
+            enabled: false,
+            width: 400,
+            height: 300,
+            color: "#e0e0e0".to_string(),
+            text: "Image unavailable".to_string(),
+        }
+    }
+}
+
+/// Red, green and blue of a `#rgb` or `#rrggbb` color.
+fn parse_color(color: &str) -> Option<[u8; 3]> {
+    let hex = color.strip_prefix('#')?;
+    if !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    let channel = |digits: &str| u8::from_str_radix(digits, 16).ok();
+    match hex.len() {
+        3 => Some([
+            channel(&hex[0..1])? * 17,
+            channel(&hex[1..2])? * 17,
+            channel(&hex[2..3])? * 17,
+        ]),
+        6 => Some([channel(&hex[0..2])?, channel(&hex[2..4])?, channel(&hex[4..6])?]),
+        _ => None,
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+impl PlaceholderConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if parse_color(&self.color).is_none() {
+            return Err(format!("placeholder color {:?} must be #rgb or #rrggbb", self.color));
+        }
+        if self.width == 0 || self.height == 0 {
+            return Err("placeholder width and height must be positive".to_string());
+        }
+        Ok(())
+    }
+
+    /// An SVG of `width` by `height`, defaulting to the configured size, with
+    /// the caption in dark or light gray, whichever reads on the background.
+    pub fn render(&self, width: Option<u32>, height: Option<u32>) -> String {
+        let (width, height) = match (width, height) {
+            (Some(width), Some(height)) => (width, height),
+            // Keep the configured shape when only one side is asked for
+            (Some(width), None) => (width, (width as u64 * self.height as u64 / self.width as u64).max(1) as u32),
+            (None, Some(height)) => ((height as u64 * self.width as u64 / self.height as u64).max(1) as u32, height),
+            (None, None) => (self.width, self.height),
+        };
+        let [r, g, b] = parse_color(&self.color).unwrap_or([0xe0; 3]);
+        let luminance = 0.299 * r as f64 + 0.587 * g as f64 + 0.114 * b as f64;
+        let ink = if luminance > 128.0 { "#666666" } else { "#cccccc" };
+        let font_size = (width.min(height) / 10).clamp(8, 48);
+        format!(
+            concat!(
+                r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" viewBox="0 0 {w} {h}">"#,
+                r##"<rect width="100%" height="100%" fill="#{r:02x}{g:02x}{b:02x}"/>"##,
+                r#"<text x="50%" y="50%" dominant-baseline="middle" text-anchor="middle" "#,
+                r#"font-family="sans-serif" font-size="{size}" fill="{ink}">{text}</text></svg>"#
+            ),
+            w = width,
+            h = height,
+            r = r,
+            g = g,
+            b = b,
+            size = font_size,
+            ink = ink,
+            text = escape(&self.text),
+        )
+    }
+
+    /// A 200 carrying the placeholder, never cached, so the real image shows
+    /// once it turns up.
+    pub fn response(&self, width: Option<u32>, height: Option<u32>) -> HttpResponse {
+        HttpResponse::Ok()
+            .content_type("image/svg+xml")
+            .insert_header((HEADER, "true"))
+            .insert_header(("Cache-Control", "no-store"))
+            .body(self.render(width, height))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_sized_and_escaped_svg() {
+        let config = PlaceholderConfig {
+            color: "#222".to_string(),
+            text: "<missing> & gone".to_string(),
+            ..PlaceholderConfig::default()
+        };
+        assert!(config.validate().is_ok());
+
+        let svg = config.render(None, None);
+        assert!(svg.contains(r#"width="400" height="300""#));
+        assert!(svg.contains("#222222"));
+        assert!(svg.contains(r##"fill="#cccccc""##));
+        assert!(svg.contains("&lt;missing&gt; &amp; gone"));
+        assert!(config.render(Some(200), None).contains(r#"width="200" height="150""#));
+        assert!(config.render(Some(64), Some(64)).contains(r#"width="64" height="64""#));
+
+        let bad = PlaceholderConfig {
+            color: "grey".to_string(),
+            ..PlaceholderConfig::default()
+        };
+        assert!(bad.validate().is_err());
+    }
+}
@@ -0,0 +1,195 @@
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+use crate::error::ApiError;
+use crate::galleryignore::IgnoreRules;
+use crate::library::{self, LibraryImage};
+use crate::{paths, volumes};
+
+/// Who may store served files, sent as the `Cache-Control` directive.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CacheVisibility {
+    #[default]
+    Public,
+    Private,
+    NoStore,
+}
+
+/// What the images directory exposes. Handlers look files up and list them
+/// through this, so a file the policy hides is missing everywhere at once.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ContentPolicy {
+    /// Lowercase extensions that may be served or listed; empty allows every
+    /// supported media type.
+    pub allowed_extensions: Vec<String>,
+    /// Whether full-size originals may be fetched; when false only resized,
+    /// converted and thumbnail variants are served.
+    pub download_originals: bool,
+    pub cache: CacheVisibility,
+    /// Folders outside the images directory whose files are listed and served
+    /// in place ("referenced"), e.g. an archive drive. Use absolute paths.
+    pub referenced_dirs: Vec<PathBuf>,
+}
+
+impl Default for ContentPolicy {
+    fn default() -> Self {
+        ContentPolicy {
+            allowed_extensions: Vec::new(),
+            download_originals: true,
+            cache: CacheVisibility::Public,
+            referenced_dirs: Vec::new(),
+        }
+    }
+}
+
+impl ContentPolicy {
+    pub fn permits(&self, path: &Path) -> bool {
+        if self.allowed_extensions.is_empty() {
+            return true;
+        }
+        path.extension()
+            .and_then(|extension| extension.to_str())
+            .is_some_and(|extension| {
+                self.allowed_extensions
+                    .iter()
+                    .any(|allowed| allowed.eq_ignore_ascii_case(extension))
+            })
+    }
+
+    /// [`paths::resolve`] in `root`, then in each referenced folder when the
+    /// name is not in `root`, reporting files the policy hides as not found.
+    /// Like [`library::open_in`], a file excluded by a `.galleryignore` counts
+    /// as absent from its folder, so a later folder's file of the same name
+    /// is served instead. A name found nowhere while one of the folders is
+    /// unmounted is reported as [`ApiError::VolumeOffline`], since the file
+    /// may be on it.
+    pub fn resolve(&self, root: &Path, name: &str) -> Result<PathBuf, ApiError> {
+        let mut missing = None;
+        let mut ignored = false;
+        for dir in self.roots(root) {
+            let path = paths::resolve(dir, name)?;
+            if path.symlink_metadata().is_err() {
+                missing.get_or_insert(path);
+                continue;
+            }
+            if IgnoreRules::for_dir(dir, dir).is_ignored(&path, path.is_dir()) {
+                ignored = true;
+                continue;
+            }
+            if !self.permits(&path) {
+                return Err(ApiError::not_found("Not found"));
+            }
+            return Ok(path);
+        }
+
+        if ignored {
+            return Err(ApiError::not_found("Not found"));
+        }
+        if let Some(offline) = volumes::first_offline(self.roots(root)) {
+            return Err(ApiError::VolumeOffline(volumes::name(offline)));
+        }
+        // Missing files go back to the caller, which answers 404 as it sees fit
+        let path = missing.unwrap_or_else(|| root.join(name));
+        if !self.permits(&path) {
+            return Err(ApiError::not_found("Not found"));
+        }
+        Ok(path)
+    }
+
+    /// [`library::scan_all`] of `dir` and the referenced folders, without the
+    /// files the policy hides.
+    pub fn scan(&self, dir: &Path) -> std::io::Result<Vec<LibraryImage>> {
+        let mut images = library::scan_all(dir, &self.referenced_dirs)?;
+        images.retain(|image| self.permits(&image.path));
+        Ok(images)
+    }
+
+    /// The images directory `root` followed by the referenced folders.
+    pub fn roots<'a>(&'a self, root: &'a Path) -> impl Iterator<Item = &'a Path> {
+        std::iter::once(root).chain(self.referenced_dirs.iter().map(PathBuf::as_path))
+    }
+
+    /// Whether `path` lies in a referenced folder rather than the images directory.
+    pub fn is_referenced(&self, path: &Path) -> bool {
+        self.referenced_dirs.iter().any(|dir| path.starts_with(dir))
+    }
+
+    pub fn ensure_originals(&self) -> Result<(), ApiError> {
+        if self.download_originals {
+            Ok(())
+        } else {
+            Err(ApiError::Forbidden("Originals are not downloadable".to_string()))
+        }
+    }
+
+    /// `Cache-Control` value for served files.
+    pub fn cache_control(&self, max_age_secs: u64) -> String {
+        match self.cache {
+            CacheVisibility::Public => format!("public, max-age={}", max_age_secs),
+            CacheVisibility::Private => format!("private, max-age={}", max_age_secs),
+            CacheVisibility::NoStore => "no-store".to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_fs::prelude::*;
+
+    #[test]
+    fn hides_files_outside_allowed_extensions() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        temp.child("a.JPG").write_binary(b"jpeg").unwrap();
+        temp.child("b.png").write_binary(b"png").unwrap();
+        let policy = ContentPolicy {
+            allowed_extensions: vec!["jpg".to_string()],
+            ..ContentPolicy::default()
+        };
+
+        let names: Vec<_> = policy.scan(temp.path()).unwrap().into_iter().map(|i| i.filename).collect();
+        assert_eq!(names, vec!["a.JPG"]);
+        assert!(policy.resolve(temp.path(), "a.JPG").is_ok());
+        assert!(matches!(policy.resolve(temp.path(), "b.png"), Err(ApiError::NotFound(_))));
+        assert!(ContentPolicy::default().resolve(temp.path(), "b.png").is_ok());
+    }
+
+    #[test]
+    fn serves_referenced_folders_after_the_images_directory() {
+        let images = assert_fs::TempDir::new().unwrap();
+        let archive = assert_fs::TempDir::new().unwrap();
+        images.child("a.jpg").write_binary(b"library").unwrap();
+        archive.child("a.jpg").write_binary(b"archive").unwrap();
+        archive.child("b.jpg").write_binary(b"archive").unwrap();
+        let policy = ContentPolicy {
+            referenced_dirs: vec![archive.path().to_path_buf(), images.path().join("unmounted")],
+            ..ContentPolicy::default()
+        };
+
+        let scanned: Vec<_> = policy.scan(images.path()).unwrap().into_iter().map(|i| i.path).collect();
+        assert_eq!(scanned, vec![images.path().join("a.jpg"), archive.path().join("b.jpg")]);
+        assert_eq!(policy.resolve(images.path(), "a.jpg").unwrap(), images.path().join("a.jpg"));
+        let b = policy.resolve(images.path(), "b.jpg").unwrap();
+        assert_eq!(b, archive.path().join("b.jpg"));
+        assert!(policy.is_referenced(&b));
+        assert!(!policy.is_referenced(&images.path().join("a.jpg")));
+        assert!(matches!(
+            policy.resolve(images.path(), "c.jpg"),
+            Err(ApiError::VolumeOffline(volume)) if volume == "unmounted"
+        ));
+        std::fs::create_dir(images.path().join("unmounted")).unwrap();
+        assert!(!policy.resolve(images.path(), "c.jpg").unwrap().exists());
+    }
+
+    #[test]
+    fn cache_control_follows_visibility() {
+        let mut policy = ContentPolicy::default();
+        assert_eq!(policy.cache_control(60), "public, max-age=60");
+        policy.cache = CacheVisibility::Private;
+        assert_eq!(policy.cache_control(60), "private, max-age=60");
+        policy.cache = CacheVisibility::NoStore;
+        assert_eq!(policy.cache_control(60), "no-store");
+    }
+}
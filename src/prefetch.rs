@@ -0,0 +1,98 @@
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, SyncSender, TrySendError};
+
+use crate::media_types;
+use crate::thumbnails;
+
+/// Thumbnails waiting to be rendered; beyond this, new requests are dropped.
+pub const QUEUE_CAPACITY: usize = 1000;
+
+/// Renders thumbnails ahead of time on one background thread, so paging
+/// forward through the gallery finds them on disk. Work is best effort: it
+/// runs one thumbnail at a time and is dropped when the queue is full.
+#[derive(Default)]
+pub struct ThumbnailPrefetcher {
+    queue: Option<SyncSender<(PathBuf, u32)>>,
+}
+
+impl ThumbnailPrefetcher {
+    /// A prefetcher that ignores everything it is given.
+    pub fn disabled() -> Self {
+        ThumbnailPrefetcher::default()
+    }
+
+    /// Starts the background thread, caching thumbnails under `images_dir`.
+    pub fn spawn(images_dir: PathBuf) -> std::io::Result<Self> {
+        let (tx, rx) = mpsc::sync_channel::<(PathBuf, u32)>(QUEUE_CAPACITY);
+        std::thread::Builder::new()
+            .name("thumbnail-prefetch".to_string())
+            .spawn(move || {
+                for (source, size) in rx {
+                    prefetch(&images_dir, &source, size);
+                }
+            })?;
+        Ok(ThumbnailPrefetcher { queue: Some(tx) })
+    }
+
+    /// Queues `size` thumbnails of `sources` that can be decoded. Returns how
+    /// many were queued. Never touches the thumbnail cache, since callers are
+    /// on the async executor; the background thread skips those already there.
+    pub fn enqueue(&self, sources: &[PathBuf], size: u32) -> usize {
+        let Some(queue) = &self.queue else {
+            return 0;
+        };
+        let mut queued = 0;
+        for source in sources {
+            if !media_types::for_path(source).is_some_and(|media| media.decodable) {
+                continue;
+            }
+            match queue.try_send((source.clone(), size)) {
+                Ok(()) => queued += 1,
+                Err(TrySendError::Full(_)) => {
+                    log::debug!("Thumbnail prefetch queue is full; skipping the rest");
+                    break;
+                }
+                Err(TrySendError::Disconnected(_)) => break,
+            }
+        }
+        queued
+    }
+}
+
+fn prefetch(images_dir: &Path, source: &Path, size: u32) {
+    if thumbnails::is_cached(images_dir, source, size) {
+        return;
+    }
+    if let Err(e) = thumbnails::get_or_create(images_dir, source, size) {
+        log::debug!("Failed to prefetch thumbnail for {:?}: {:#}", source, e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn renders_queued_thumbnails_in_the_background() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        let source = temp.path().join("next.png");
+        image::DynamicImage::new_rgb8(40, 20).save(&source).unwrap();
+        let raw = temp.path().join("phone.HEIC");
+        std::fs::write(&raw, b"not decodable").unwrap();
+        let sources = [source.clone(), raw];
+
+        assert_eq!(ThumbnailPrefetcher::disabled().enqueue(&sources, 64), 0);
+
+        let prefetcher = ThumbnailPrefetcher::spawn(temp.path().to_path_buf()).unwrap();
+        assert_eq!(prefetcher.enqueue(&sources, 64), 1);
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !thumbnails::is_cached(temp.path(), &source, 64) {
+            assert!(Instant::now() < deadline, "thumbnail was not prefetched");
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        // Whether it is cached is for the background thread to find out
+        assert_eq!(prefetcher.enqueue(&sources, 64), 1);
+    }
+}
@@ -0,0 +1,94 @@
+use serde::Deserialize;
+use std::collections::HashMap;
+
+use crate::processor::{ConvertFormat, FitMode, DEFAULT_CONVERT_QUALITY, MAX_DIMENSION};
+
+/// A named set of processing parameters, requested with `?preset=name`;
+/// a `[presets.name]` table in a config file.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Preset {
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub fit: Option<FitMode>,
+    /// Re-encode as this format; otherwise the source format is kept.
+    pub format: Option<ConvertFormat>,
+    /// Quality for `format`, 1-100.
+    pub quality: Option<u8>,
+}
+
+/// What to do to an image: the request's own parameters, falling back to
+/// those of its preset.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Operations {
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub fit: FitMode,
+    pub convert: Option<(ConvertFormat, u8)>,
+}
+
+impl Operations {
+    pub fn resolve(
+        preset: Option<&Preset>,
+        width: Option<u32>,
+        height: Option<u32>,
+        fit: Option<FitMode>,
+        format: Option<ConvertFormat>,
+        quality: Option<u8>,
+    ) -> Self {
+        let preset = preset.cloned().unwrap_or_default();
+        let quality = quality.or(preset.quality).unwrap_or(DEFAULT_CONVERT_QUALITY);
+        Operations {
+            width: width.or(preset.width),
+            height: height.or(preset.height),
+            fit: fit.or(preset.fit).unwrap_or_default(),
+            convert: format.or(preset.format).map(|format| (format, quality)),
+        }
+    }
+
+    /// Whether the source has to be decoded and encoded again.
+    pub fn transforms(&self) -> bool {
+        self.width.is_some() || self.height.is_some() || self.convert.is_some()
+    }
+}
+
+pub fn validate(presets: &HashMap<String, Preset>) -> Result<(), String> {
+    for (name, preset) in presets {
+        let side_ok = |side: Option<u32>| side.is_none_or(|side| (1..=MAX_DIMENSION).contains(&side));
+        if !side_ok(preset.width) || !side_ok(preset.height) {
+            return Err(format!("Preset {}: width and height must be between 1 and {}", name, MAX_DIMENSION));
+        }
+        if preset.quality.is_some_and(|quality| !(1..=100).contains(&quality)) {
+            return Err(format!("Preset {}: quality must be between 1 and 100", name));
+        }
+        if preset.width.is_none() && preset.height.is_none() && preset.format.is_none() {
+            return Err(format!("Preset {}: set width, height or format", name));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn request_parameters_override_the_preset() {
+        let web = Preset {
+            width: Some(1600),
+            format: Some(ConvertFormat::Webp),
+            quality: Some(70),
+            ..Preset::default()
+        };
+        let ops = Operations::resolve(Some(&web), None, None, None, None, None);
+        assert_eq!(ops, Operations { width: Some(1600), height: None, fit: FitMode::Contain, convert: Some((ConvertFormat::Webp, 70)) });
+        let ops = Operations::resolve(Some(&web), Some(800), None, Some(FitMode::Cover), None, Some(50));
+        assert_eq!((ops.width, ops.fit, ops.convert), (Some(800), FitMode::Cover, Some((ConvertFormat::Webp, 50))));
+        assert!(!Operations::resolve(None, None, None, None, None, Some(50)).transforms());
+
+        let mut presets = HashMap::from([("web".to_string(), web)]);
+        assert!(validate(&presets).is_ok());
+        presets.insert("empty".to_string(), Preset { fit: Some(FitMode::Cover), ..Preset::default() });
+        assert!(validate(&presets).unwrap_err().contains("empty"));
+    }
+}
@@ -0,0 +1,110 @@
+use actix_web::web::Bytes;
+use anyhow::Context;
+use image::codecs::gif::{GifEncoder, Repeat};
+use image::{DynamicImage, Frame, GenericImageView, ImageFormat};
+use serde::Deserialize;
+
+use crate::processor::EncodedImage;
+
+/// Frames in a hover preview.
+pub const FRAME_COUNT: usize = 10;
+/// How long each frame shows.
+const FRAME_MILLIS: u32 = 400;
+const WEBP_QUALITY: f32 = 70.0;
+
+/// Animation formats offered for hover previews.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PreviewFormat {
+    #[default]
+    Webp,
+    Gif,
+}
+
+impl PreviewFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            PreviewFormat::Webp => "webp",
+            PreviewFormat::Gif => "gif",
+        }
+    }
+}
+
+/// Times, in seconds, of `count` frames spread evenly over `duration_secs`,
+/// each in the middle of its stretch so black lead-ins and fade-outs are missed.
+pub fn frame_times(duration_secs: f64, count: usize) -> Vec<f64> {
+    let step = duration_secs / count as f64;
+    (0..count).map(|i| (i as f64 + 0.5) * step).collect()
+}
+
+/// Encodes `frames` as an endlessly looping animation. Frames that differ in
+/// size from the first are scaled to match.
+pub fn encode(frames: &[DynamicImage], format: PreviewFormat) -> anyhow::Result<EncodedImage> {
+    let first = frames.first().context("No frames to animate")?;
+    let (width, height) = first.dimensions();
+    let frames: Vec<image::RgbaImage> = frames
+        .iter()
+        .map(|frame| {
+            if frame.dimensions() == (width, height) {
+                frame.to_rgba8()
+            } else {
+                frame.resize_exact(width, height, image::imageops::FilterType::Triangle).to_rgba8()
+            }
+        })
+        .collect();
+
+    match format {
+        PreviewFormat::Gif => {
+            let mut bytes = Vec::new();
+            {
+                let mut encoder = GifEncoder::new_with_speed(&mut bytes, 10);
+                encoder.set_repeat(Repeat::Infinite).context("Failed to encode GIF")?;
+                let delay = image::Delay::from_numer_denom_ms(FRAME_MILLIS, 1);
+                encoder
+                    .encode_frames(frames.into_iter().map(|frame| Frame::from_parts(frame, 0, 0, delay)))
+                    .context("Failed to encode GIF")?;
+            }
+            Ok(EncodedImage {
+                bytes: bytes.into(),
+                format: ImageFormat::Gif,
+            })
+        }
+        PreviewFormat::Webp => {
+            let mut config = webp::WebPConfig::new().map_err(|()| anyhow::anyhow!("Failed to set up WebP encoder"))?;
+            config.quality = WEBP_QUALITY;
+            let mut encoder = webp::AnimEncoder::new(width, height, &config);
+            encoder.set_loop_count(0);
+            for (i, frame) in frames.iter().enumerate() {
+                let timestamp = (i as u32 * FRAME_MILLIS) as i32;
+                encoder.add_frame(webp::AnimFrame::from_rgba(frame.as_raw(), width, height, timestamp));
+            }
+            let encoded = encoder
+                .try_encode()
+                .map_err(|e| anyhow::anyhow!("Failed to encode WebP animation: {:?}", e))?;
+            Ok(EncodedImage {
+                bytes: Bytes::copy_from_slice(&encoded),
+                format: ImageFormat::WebP,
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spreads_frames_and_encodes_animations() {
+        assert_eq!(frame_times(10.0, 5), [1.0, 3.0, 5.0, 7.0, 9.0]);
+
+        let frames = vec![DynamicImage::new_rgb8(8, 6), DynamicImage::new_rgb8(8, 6), DynamicImage::new_rgb8(4, 3)];
+        let gif = encode(&frames, PreviewFormat::Gif).unwrap();
+        assert_eq!(image::guess_format(&gif.bytes).unwrap(), ImageFormat::Gif);
+        let decoded = image::codecs::gif::GifDecoder::new(std::io::Cursor::new(gif.bytes.to_vec())).unwrap();
+        assert_eq!(image::AnimationDecoder::into_frames(decoded).count(), 3);
+
+        let webp = encode(&frames, PreviewFormat::Webp).unwrap();
+        assert_eq!(image::guess_format(&webp.bytes).unwrap(), ImageFormat::WebP);
+        assert!(encode(&[], PreviewFormat::Gif).is_err());
+    }
+}
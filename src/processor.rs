@@ -0,0 +1,227 @@
+use actix_web::web::Bytes;
+use anyhow::Context;
+use image::imageops::FilterType;
+use image::{DynamicImage, GenericImageView, ImageFormat, ImageOutputFormat};
+use serde::{Deserialize, Serialize};
+use std::io::Cursor;
+use std::path::Path;
+
+use crate::metadata;
+
+const JPEG_QUALITY: u8 = 85;
+pub const MAX_DIMENSION: u32 = 8192;
+pub const DEFAULT_CONVERT_QUALITY: u8 = 80;
+
+/// How a resize fits the source into the requested box.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FitMode {
+    /// Scale to fit inside the box, keeping aspect ratio; never upscales.
+    #[default]
+    Contain,
+    /// Scale and center-crop to fill the box exactly.
+    Cover,
+    /// Stretch to the exact box, ignoring aspect ratio.
+    Fill,
+}
+
+/// Output formats offered by [`ImageProcessor::convert`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConvertFormat {
+    Jpeg,
+    Png,
+    Webp,
+}
+
+/// Encoded image bytes plus the format they were encoded in.
+#[derive(Debug, Clone)]
+pub struct EncodedImage {
+    pub bytes: Bytes,
+    pub format: ImageFormat,
+}
+
+impl EncodedImage {
+    pub fn content_type(&self) -> &'static str {
+        self.format.to_mime_type()
+    }
+}
+
+pub struct ImageProcessor;
+
+impl ImageProcessor {
+    /// Decodes the image at `path`, rotating/flipping it upright according to its
+    /// EXIF orientation unless `respect_orientation` is false.
+    pub fn open(path: &Path, respect_orientation: bool) -> anyhow::Result<(DynamicImage, ImageFormat)> {
+        let reader = image::io::Reader::open(path)
+            .context("Failed to open image")?
+            .with_guessed_format()
+            .context("Failed to detect image format")?;
+        let format = reader.format().context("Unknown image format")?;
+        let img = reader.decode().context("Failed to decode image")?;
+
+        let img = match metadata::orientation(path) {
+            Some(orientation) if respect_orientation => Self::apply_orientation(img, orientation),
+            _ => img,
+        };
+        Ok((img, format))
+    }
+
+    /// Like [`open`](Self::open), for an image held in memory.
+    pub fn decode(bytes: &[u8], respect_orientation: bool) -> anyhow::Result<(DynamicImage, ImageFormat)> {
+        let reader = image::io::Reader::new(Cursor::new(bytes))
+            .with_guessed_format()
+            .context("Failed to detect image format")?;
+        let format = reader.format().context("Unknown image format")?;
+        let img = reader.decode().context("Failed to decode image")?;
+
+        let img = match metadata::orientation_in(bytes) {
+            Some(orientation) if respect_orientation => Self::apply_orientation(img, orientation),
+            _ => img,
+        };
+        Ok((img, format))
+    }
+
+    /// Applies an EXIF orientation value (1-8) so the result displays upright.
+    pub fn apply_orientation(img: DynamicImage, orientation: u32) -> DynamicImage {
+        match orientation {
+            2 => img.fliph(),
+            3 => img.rotate180(),
+            4 => img.flipv(),
+            5 => img.rotate90().fliph(),
+            6 => img.rotate90(),
+            7 => img.rotate270().fliph(),
+            8 => img.rotate270(),
+            _ => img,
+        }
+    }
+
+    /// Resizes to `width` x `height`; a missing side follows the source aspect ratio.
+    pub fn resize_image(img: &DynamicImage, width: Option<u32>, height: Option<u32>, fit: FitMode) -> DynamicImage {
+        let (src_w, src_h) = img.dimensions();
+        let (width, height) = match (width, height) {
+            (Some(w), Some(h)) => (w, h),
+            (Some(w), None) => (w, scale(src_h, w, src_w)),
+            (None, Some(h)) => (scale(src_w, h, src_h), h),
+            (None, None) => return img.clone(),
+        };
+
+        match fit {
+            FitMode::Contain if width >= src_w && height >= src_h => img.clone(),
+            FitMode::Contain => img.resize(width, height, FilterType::Lanczos3),
+            FitMode::Cover => img.resize_to_fill(width, height, FilterType::Lanczos3),
+            FitMode::Fill => img.resize_exact(width, height, FilterType::Lanczos3),
+        }
+    }
+
+    /// Encodes in `format`, falling back to JPEG for formats we cannot write.
+    pub fn encode(img: &DynamicImage, format: ImageFormat) -> anyhow::Result<EncodedImage> {
+        let (output, format) = match format {
+            ImageFormat::Png => (ImageOutputFormat::Png, ImageFormat::Png),
+            ImageFormat::Gif => (ImageOutputFormat::Gif, ImageFormat::Gif),
+            ImageFormat::Bmp => (ImageOutputFormat::Bmp, ImageFormat::Bmp),
+            ImageFormat::Tiff => (ImageOutputFormat::Tiff, ImageFormat::Tiff),
+            _ => (ImageOutputFormat::Jpeg(JPEG_QUALITY), ImageFormat::Jpeg),
+        };
+
+        let mut bytes = Cursor::new(Vec::new());
+        if format == ImageFormat::Jpeg {
+            // JPEG has no alpha channel
+            DynamicImage::ImageRgb8(img.to_rgb8()).write_to(&mut bytes, output)
+        } else {
+            img.write_to(&mut bytes, output)
+        }
+        .context("Failed to encode image")?;
+
+        Ok(EncodedImage {
+            bytes: bytes.into_inner().into(),
+            format,
+        })
+    }
+
+    /// Re-encodes in `format`; `quality` (1-100) applies to the lossy formats.
+    pub fn convert(img: &DynamicImage, format: ConvertFormat, quality: u8) -> anyhow::Result<EncodedImage> {
+        let quality = quality.clamp(1, 100);
+        match format {
+            ConvertFormat::Jpeg => {
+                let mut bytes = Cursor::new(Vec::new());
+                DynamicImage::ImageRgb8(img.to_rgb8())
+                    .write_to(&mut bytes, ImageOutputFormat::Jpeg(quality))
+                    .context("Failed to encode JPEG")?;
+                Ok(EncodedImage {
+                    bytes: bytes.into_inner().into(),
+                    format: ImageFormat::Jpeg,
+                })
+            }
+            ConvertFormat::Png => Self::encode(img, ImageFormat::Png),
+            ConvertFormat::Webp => {
+                let (width, height) = img.dimensions();
+                let encoded = if img.color().has_alpha() {
+                    webp::Encoder::from_rgba(img.to_rgba8().as_raw(), width, height).encode(quality as f32)
+                } else {
+                    webp::Encoder::from_rgb(img.to_rgb8().as_raw(), width, height).encode(quality as f32)
+                };
+                Ok(EncodedImage {
+                    bytes: Bytes::copy_from_slice(&encoded),
+                    format: ImageFormat::WebP,
+                })
+            }
+        }
+    }
+}
+
+fn scale(side: u32, target: u32, reference: u32) -> u32 {
+    ((side as u64 * target as u64) / reference.max(1) as u64).max(1) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(width: u32, height: u32) -> DynamicImage {
+        DynamicImage::new_rgb8(width, height)
+    }
+
+    #[test]
+    fn resize_image_respects_fit_modes() {
+        let img = sample(400, 200);
+
+        assert_eq!(ImageProcessor::resize_image(&img, Some(100), None, FitMode::Contain).dimensions(), (100, 50));
+        assert_eq!(ImageProcessor::resize_image(&img, None, Some(50), FitMode::Contain).dimensions(), (100, 50));
+        assert_eq!(ImageProcessor::resize_image(&img, Some(100), Some(100), FitMode::Contain).dimensions(), (100, 50));
+        assert_eq!(ImageProcessor::resize_image(&img, Some(100), Some(100), FitMode::Cover).dimensions(), (100, 100));
+        assert_eq!(ImageProcessor::resize_image(&img, Some(100), Some(100), FitMode::Fill).dimensions(), (100, 100));
+        // Contain never upscales
+        assert_eq!(ImageProcessor::resize_image(&img, Some(800), None, FitMode::Contain).dimensions(), (400, 200));
+    }
+
+    #[test]
+    fn apply_orientation_swaps_sides_for_rotations() {
+        assert_eq!(ImageProcessor::apply_orientation(sample(40, 20), 1).dimensions(), (40, 20));
+        assert_eq!(ImageProcessor::apply_orientation(sample(40, 20), 3).dimensions(), (40, 20));
+        for orientation in 5..=8 {
+            assert_eq!(ImageProcessor::apply_orientation(sample(40, 20), orientation).dimensions(), (20, 40));
+        }
+    }
+
+    #[test]
+    fn convert_sets_output_format() {
+        let img = sample(8, 8);
+        for (format, expected) in [
+            (ConvertFormat::Webp, ImageFormat::WebP),
+            (ConvertFormat::Png, ImageFormat::Png),
+            (ConvertFormat::Jpeg, ImageFormat::Jpeg),
+        ] {
+            let converted = ImageProcessor::convert(&img, format, 80).unwrap();
+            assert_eq!(converted.format, expected);
+            assert_eq!(image::guess_format(&converted.bytes).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn encode_falls_back_to_jpeg() {
+        let encoded = ImageProcessor::encode(&sample(4, 4), ImageFormat::WebP).unwrap();
+        assert_eq!(encoded.content_type(), "image/jpeg");
+        assert_eq!(image::guess_format(&encoded.bytes).unwrap(), ImageFormat::Jpeg);
+    }
+}
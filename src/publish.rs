@@ -0,0 +1,52 @@
+use std::io;
+use std::path::Path;
+
+/// Extended attribute marking a file as published under `/public`.
+#[cfg(target_os = "macos")]
+pub const PUBLISHED_XATTR: &str = "org.images-api.published";
+
+// Unprivileged attributes must live in the user namespace on Linux.
+#[cfg(not(target_os = "macos"))]
+pub const PUBLISHED_XATTR: &str = "user.images-api.published";
+
+/// Whether `path` has been published; unreadable attributes count as not.
+pub fn is_published(path: &Path) -> bool {
+    match xattr::get(path, PUBLISHED_XATTR) {
+        Ok(value) => value.is_some(),
+        Err(e) => {
+            log::debug!("Could not read published flag for {:?}: {}", path, e);
+            false
+        }
+    }
+}
+
+/// Publishes or withdraws `path`.
+pub fn set_published(path: &Path, published: bool) -> io::Result<()> {
+    if published {
+        return xattr::set(path, PUBLISHED_XATTR, b"1");
+    }
+    if xattr::get(path, PUBLISHED_XATTR)?.is_none() {
+        return Ok(());
+    }
+    xattr::remove(path, PUBLISHED_XATTR)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_fs::prelude::*;
+
+    #[test]
+    fn publishes_and_withdraws() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        temp.child("a.jpg").write_binary(b"a").unwrap();
+        let path = temp.child("a.jpg").path().to_path_buf();
+
+        assert!(!is_published(&path));
+        set_published(&path, true).unwrap();
+        assert!(is_published(&path));
+        set_published(&path, false).unwrap();
+        set_published(&path, false).unwrap();
+        assert!(!is_published(&path));
+    }
+}
@@ -0,0 +1,123 @@
+use actix_web::http::header;
+use actix_web::{HttpRequest, HttpResponse, HttpResponseBuilder};
+
+/// An inclusive byte range within a resource of known length.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ByteRange {
+    pub start: u64,
+    pub end: u64,
+}
+
+impl ByteRange {
+    pub fn length(&self) -> u64 {
+        self.end - self.start + 1
+    }
+
+    pub fn content_range(&self, total: u64) -> String {
+        format!("bytes {}-{}/{}", self.start, self.end, total)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum RangeRequest {
+    Full,
+    Partial(ByteRange),
+    Unsatisfiable,
+}
+
+/// Interprets a `Range` header against a resource of `total` bytes.
+///
+/// Only single `bytes=` ranges are honored; malformed or multi-range headers
+/// fall back to the full body, which RFC 9110 allows servers to do.
+pub fn parse(header: Option<&str>, total: u64) -> RangeRequest {
+    let Some(spec) = header.and_then(|h| h.trim().strip_prefix("bytes=")) else {
+        return RangeRequest::Full;
+    };
+    if spec.contains(',') {
+        return RangeRequest::Full;
+    }
+    let Some((start, end)) = spec.trim().split_once('-') else {
+        return RangeRequest::Full;
+    };
+
+    let range = match (start.trim(), end.trim()) {
+        ("", "") => return RangeRequest::Full,
+        // bytes=-N: the last N bytes
+        ("", suffix) => match suffix.parse::<u64>() {
+            Ok(0) => return RangeRequest::Unsatisfiable,
+            Ok(n) if total > 0 => ByteRange {
+                start: total.saturating_sub(n),
+                end: total - 1,
+            },
+            Ok(_) => return RangeRequest::Unsatisfiable,
+            Err(_) => return RangeRequest::Full,
+        },
+        (start, end) => {
+            let Ok(start) = start.parse::<u64>() else {
+                return RangeRequest::Full;
+            };
+            let end = match end {
+                "" => total.saturating_sub(1),
+                end => match end.parse::<u64>() {
+                    Ok(end) if end >= start => end.min(total.saturating_sub(1)),
+                    _ => return RangeRequest::Full,
+                },
+            };
+            if start >= total {
+                return RangeRequest::Unsatisfiable;
+            }
+            ByteRange { start, end }
+        }
+    };
+
+    RangeRequest::Partial(range)
+}
+
+/// Interprets the request's `Range` header against a resource of `total` bytes.
+pub fn from_request(req: &HttpRequest, total: u64) -> RangeRequest {
+    parse(req.headers().get(header::RANGE).and_then(|v| v.to_str().ok()), total)
+}
+
+/// The 416 response for a range that lies outside a resource of `total` bytes.
+pub fn unsatisfiable(total: u64) -> HttpResponse {
+    HttpResponse::RangeNotSatisfiable()
+        .insert_header((header::CONTENT_RANGE, format!("bytes */{}", total)))
+        .finish()
+}
+
+/// Starts a 200 (whole body) or 206 (`range`) response that advertises range support.
+pub fn response(range: Option<ByteRange>, total: u64) -> HttpResponseBuilder {
+    let mut response = match range {
+        Some(range) => {
+            let mut response = HttpResponse::PartialContent();
+            response.insert_header((header::CONTENT_RANGE, range.content_range(total)));
+            response
+        }
+        None => HttpResponse::Ok(),
+    };
+    response.insert_header((header::ACCEPT_RANGES, "bytes"));
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_single_ranges() {
+        assert_eq!(parse(Some("bytes=0-9"), 100), RangeRequest::Partial(ByteRange { start: 0, end: 9 }));
+        assert_eq!(parse(Some("bytes=90-"), 100), RangeRequest::Partial(ByteRange { start: 90, end: 99 }));
+        assert_eq!(parse(Some("bytes=-10"), 100), RangeRequest::Partial(ByteRange { start: 90, end: 99 }));
+        assert_eq!(parse(Some("bytes=50-500"), 100), RangeRequest::Partial(ByteRange { start: 50, end: 99 }));
+    }
+
+    #[test]
+    fn falls_back_or_rejects_invalid_ranges() {
+        assert_eq!(parse(None, 100), RangeRequest::Full);
+        assert_eq!(parse(Some("bytes=0-1,5-6"), 100), RangeRequest::Full);
+        assert_eq!(parse(Some("items=0-1"), 100), RangeRequest::Full);
+        assert_eq!(parse(Some("bytes=9-1"), 100), RangeRequest::Full);
+        assert_eq!(parse(Some("bytes=100-"), 100), RangeRequest::Unsatisfiable);
+        assert_eq!(parse(Some("bytes=-0"), 100), RangeRequest::Unsatisfiable);
+    }
+}
@@ -0,0 +1,186 @@
+use chrono::{Datelike, NaiveDateTime, Timelike};
+use serde::Serialize;
+use std::collections::HashSet;
+use std::path::Path;
+
+use crate::library::ImageStack;
+use crate::metadata;
+
+const PLACEHOLDERS: &[&str] = &["yyyy", "mm", "dd", "hh", "min", "ss", "original", "name", "ext"];
+
+/// A file name pattern such as `{yyyy}-{mm}-{dd}_{original}`. Dates come from
+/// the EXIF capture date, or the modification time when there is none;
+/// `{original}` is the current file name, `{name}` and `{ext}` its parts.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Template(String);
+
+impl Template {
+    /// Checks that every `{...}` is a known placeholder and that the result is
+    /// a file name: the library is a single folder, so no separators.
+    pub fn parse(template: &str) -> Result<Self, String> {
+        if template.contains(['/', '\\']) {
+            return Err(format!(
+                "Templates cannot create folders: the library is one flat folder addressed by file name, \
+                 so files moved into subfolders would vanish from it. Use a flat name such as {:?}",
+                template.replace(['/', '\\'], "-")
+            ));
+        }
+        if template.starts_with('.') {
+            return Err("Templates must produce a visible file name".to_string());
+        }
+        let mut rest = template;
+        while let Some(start) = rest.find('{') {
+            let end = rest[start..]
+                .find('}')
+                .ok_or_else(|| "Unclosed { in template".to_string())?;
+            let placeholder = &rest[start + 1..start + end];
+            if !PLACEHOLDERS.contains(&placeholder) {
+                return Err(format!("Unknown placeholder {{{}}}; use one of {:?}", placeholder, PLACEHOLDERS));
+            }
+            rest = &rest[start + end + 1..];
+        }
+        if !template.contains("{original}") && !template.contains("{name}") {
+            return Err("Templates must include {original} or {name} to keep names apart".to_string());
+        }
+        Ok(Template(template.to_string()))
+    }
+
+    pub fn render(&self, original: &str, date: NaiveDateTime) -> String {
+        let path = Path::new(original);
+        let name = path.file_stem().map(|stem| stem.to_string_lossy()).unwrap_or_default();
+        self.fill(original, &name, &extension(original), date)
+    }
+
+    /// Whether `filename` already has the form this template gives for
+    /// `date`, so applying a template twice does not stack prefixes.
+    pub fn is_applied(&self, filename: &str, date: NaiveDateTime) -> bool {
+        const MARK: &str = "\u{0}";
+        let pattern = self.fill(MARK, MARK, &extension(filename), date);
+        match pattern.split_once(MARK) {
+            Some((prefix, suffix)) if !suffix.contains(MARK) => {
+                filename.len() > prefix.len() + suffix.len()
+                    && filename.starts_with(prefix)
+                    && filename.ends_with(suffix)
+            }
+            _ => false,
+        }
+    }
+
+    fn fill(&self, original: &str, name: &str, ext: &str, date: NaiveDateTime) -> String {
+        self.0
+            .replace("{yyyy}", &format!("{:04}", date.year()))
+            .replace("{mm}", &format!("{:02}", date.month()))
+            .replace("{dd}", &format!("{:02}", date.day()))
+            .replace("{hh}", &format!("{:02}", date.hour()))
+            .replace("{min}", &format!("{:02}", date.minute()))
+            .replace("{ss}", &format!("{:02}", date.second()))
+            .replace("{original}", original)
+            .replace("{name}", name)
+            .replace("{ext}", ext)
+    }
+}
+
+fn extension(filename: &str) -> String {
+    Path::new(filename)
+        .extension()
+        .map(|ext| ext.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Rename {
+    pub from: String,
+    pub to: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Skipped {
+    pub filename: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Serialize)]
+pub struct RenamePlan {
+    pub renames: Vec<Rename>,
+    pub skipped: Vec<Skipped>,
+}
+
+/// The renames `template` calls for across `stacks`. Every rendition of a
+/// stack is dated the same, so RAW files and exports stay together. Names
+/// already taken, or claimed twice, are skipped rather than overwritten;
+/// names are compared ignoring case, as on macOS volumes.
+pub fn plan(stacks: &[ImageStack], template: &Template) -> RenamePlan {
+    let mut taken: HashSet<String> = stacks
+        .iter()
+        .flat_map(|stack| &stack.renditions)
+        .map(|rendition| rendition.image.filename.to_lowercase())
+        .collect();
+    let mut plan = RenamePlan::default();
+    for stack in stacks {
+        let date = stack
+            .renditions
+            .iter()
+            .find_map(|rendition| metadata::capture_date(&rendition.image.path))
+            .or_else(|| stack.primary().modified.map(|modified| modified.naive_utc()));
+        for rendition in &stack.renditions {
+            let from = &rendition.image.filename;
+            let Some(date) = date else {
+                plan.skipped.push(Skipped { filename: from.clone(), reason: "No date".to_string() });
+                continue;
+            };
+            if template.is_applied(from, date) {
+                continue;
+            }
+            let to = template.render(from, date);
+            if !taken.insert(to.to_lowercase()) {
+                plan.skipped.push(Skipped { filename: from.clone(), reason: format!("{} is taken", to) });
+                continue;
+            }
+            taken.remove(&from.to_lowercase());
+            plan.renames.push(Rename { from: from.clone(), to });
+        }
+    }
+    plan
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::library::{self, LibraryImage};
+    use chrono::{TimeZone, Utc};
+
+    fn image(filename: &str) -> LibraryImage {
+        LibraryImage {
+            filename: filename.to_string(),
+            path: Path::new("/nonexistent").join(filename),
+            size_bytes: 1,
+            modified: Some(Utc.with_ymd_and_hms(2024, 3, 5, 9, 30, 0).unwrap()),
+        }
+    }
+
+    #[test]
+    fn plans_dated_names_and_skips_collisions() {
+        let template = Template::parse("{yyyy}-{mm}-{dd}_{original}").unwrap();
+        let stacks = library::stack(vec![
+            image("IMG_1.jpg"),
+            image("IMG_1.CR2"),
+            image("2024-03-05_IMG_2.jpg"),
+            image("IMG_2.jpg"),
+        ]);
+        let plan = plan(&stacks, &template);
+
+        let renames: Vec<(&str, &str)> = plan.renames.iter().map(|r| (r.from.as_str(), r.to.as_str())).collect();
+        assert_eq!(renames, [("IMG_1.jpg", "2024-03-05_IMG_1.jpg"), ("IMG_1.CR2", "2024-03-05_IMG_1.CR2")]);
+        assert_eq!(plan.skipped, [Skipped { filename: "IMG_2.jpg".to_string(), reason: "2024-03-05_IMG_2.jpg is taken".to_string() }]);
+
+        let nested = Template::parse("{yyyy}/{mm}/{dd}_{original}").unwrap_err();
+        assert!(nested.ends_with("Use a flat name such as \"{yyyy}-{mm}-{dd}_{original}\""), "{}", nested);
+        assert!(Template::parse(".{original}").is_err());
+        assert!(Template::parse("{yyyy}-{month}_{original}").is_err());
+        assert!(Template::parse("{yyyy}").is_err());
+        let date = image("a.jpg").modified.unwrap().naive_utc();
+        let template = Template::parse("{name}_{hh}{min}.{ext}").unwrap();
+        assert_eq!(template.render("a.jpg", date), "a_0930.jpg");
+        assert!(template.is_applied("a_0930.jpg", date) && !template.is_applied("a.jpg", date));
+    }
+}
@@ -0,0 +1,77 @@
+use chrono::{DateTime, NaiveDate, Utc};
+
+use crate::library::ImageStack;
+use crate::tags;
+
+/// The words of a `q` search, each of which must appear, ignoring case, in
+/// the name or a tag of some rendition of a stack.
+#[derive(Debug, Default)]
+pub struct Terms(Vec<String>);
+
+impl Terms {
+    pub fn parse(q: &str) -> Self {
+        Terms(q.split_whitespace().map(str::to_lowercase).collect())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Whether every term is found in `names` or `tags`, all lowercase.
+    pub fn matches(&self, names: &[String], tags: &[String]) -> bool {
+        self.0
+            .iter()
+            .all(|term| names.iter().chain(tags).any(|text| text.contains(term.as_str())))
+    }
+
+    /// [`matches`](Self::matches) against every rendition of `stack`; tags are
+    /// only read when the names alone do not match.
+    pub fn matches_stack(&self, stack: &ImageStack) -> bool {
+        if self.is_empty() {
+            return true;
+        }
+        let names: Vec<String> = stack
+            .renditions
+            .iter()
+            .map(|rendition| rendition.image.filename.to_lowercase())
+            .collect();
+        if self.matches(&names, &[]) {
+            return true;
+        }
+        let tags: Vec<String> = stack
+            .renditions
+            .iter()
+            .flat_map(|rendition| tags::read(&rendition.image.path))
+            .map(|tag| tag.name.to_lowercase())
+            .collect();
+        self.matches(&names, &tags)
+    }
+}
+
+/// An `after`/`before` bound: RFC 3339, or `YYYY-MM-DD` for midnight UTC.
+pub fn parse_bound(raw: &str) -> Option<DateTime<Utc>> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(raw) {
+        return Some(time.with_timezone(&Utc));
+    }
+    let day = NaiveDate::parse_from_str(raw, "%Y-%m-%d").ok()?;
+    Some(day.and_hms_opt(0, 0, 0)?.and_utc())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_term_must_match_a_name_or_tag() {
+        let names = ["beach_sunset.jpg".to_string()];
+        let tags = ["portfolio".to_string()];
+        assert!(Terms::parse("Sunset").matches(&names, &tags));
+        assert!(Terms::parse("beach PORTFOLIO").matches(&names, &tags));
+        assert!(!Terms::parse("beach mountain").matches(&names, &tags));
+        assert!(Terms::parse("  ").matches(&names, &[]));
+
+        assert_eq!(parse_bound("2024-06-01").unwrap().to_rfc3339(), "2024-06-01T00:00:00+00:00");
+        assert_eq!(parse_bound("2024-06-01T12:00:00+02:00").unwrap().to_rfc3339(), "2024-06-01T10:00:00+00:00");
+        assert!(parse_bound("June 1").is_none());
+    }
+}
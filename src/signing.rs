@@ -0,0 +1,112 @@
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use ring::hmac;
+use ring::rand::{SecureRandom, SystemRandom};
+
+use crate::error::ApiError;
+
+/// Query parameter carrying the URL's expiry, in Unix seconds.
+pub const EXPIRES_PARAM: &str = "expires";
+/// Query parameter carrying the signature; always last.
+pub const SIGNATURE_PARAM: &str = "signature";
+pub const DEFAULT_EXPIRES_SECS: u64 = 3600;
+pub const MAX_EXPIRES_SECS: u64 = 7 * 24 * 3600;
+
+/// Signs and checks expiring URLs with HMAC-SHA256, so a link to one image
+/// can be handed out without handing out credentials.
+pub struct UrlSigner {
+    key: hmac::Key,
+}
+
+impl UrlSigner {
+    pub fn new(secret: &[u8]) -> Self {
+        UrlSigner {
+            key: hmac::Key::new(hmac::HMAC_SHA256, secret),
+        }
+    }
+
+    /// A signer for `secret`, or for a random key when it is empty, in which
+    /// case URLs signed before a restart stop working.
+    pub fn from_secret(secret: &str) -> Self {
+        if !secret.is_empty() {
+            return UrlSigner::new(secret.as_bytes());
+        }
+        let mut random = [0u8; 32];
+        if SystemRandom::new().fill(&mut random).is_err() {
+            log::error!("No system randomness for the URL signing key");
+        }
+        UrlSigner::new(&random)
+    }
+
+    /// `path_and_query` (e.g. `/images/a.jpg/thumbnail?size=256`) with the
+    /// expiry and signature appended.
+    pub fn sign(&self, path_and_query: &str, expires: DateTime<Utc>) -> String {
+        let separator = if path_and_query.contains('?') { '&' } else { '?' };
+        let unsigned = format!("{}{}{}={}", path_and_query, separator, EXPIRES_PARAM, expires.timestamp());
+        let signature = hmac::sign(&self.key, unsigned.as_bytes());
+        format!("{}&{}={}", unsigned, SIGNATURE_PARAM, URL_SAFE_NO_PAD.encode(signature.as_ref()))
+    }
+
+    /// Checks a request's path and raw query against its trailing signature
+    /// and expiry. Fails with 403 when the signature is wrong or expired.
+    pub fn verify(&self, path: &str, query: &str, now: DateTime<Utc>) -> Result<(), ApiError> {
+        let invalid = || ApiError::Forbidden("Invalid URL signature".to_string());
+        let (unsigned_query, signature) = match query.rsplit_once('&') {
+            Some((rest, last)) => (rest, last),
+            None => ("", query),
+        };
+        let signature = signature
+            .strip_prefix(SIGNATURE_PARAM)
+            .and_then(|rest| rest.strip_prefix('='))
+            .and_then(|signature| URL_SAFE_NO_PAD.decode(signature).ok())
+            .ok_or_else(invalid)?;
+        let unsigned = format!("{}?{}", path, unsigned_query);
+        hmac::verify(&self.key, unsigned.as_bytes(), &signature).map_err(|_| invalid())?;
+
+        let expires = unsigned_query
+            .split('&')
+            .find_map(|pair| pair.strip_prefix(EXPIRES_PARAM)?.strip_prefix('='))
+            .and_then(|expires| expires.parse::<i64>().ok())
+            .ok_or_else(invalid)?;
+        if expires < now.timestamp() {
+            return Err(ApiError::Forbidden("Signed URL has expired".to_string()));
+        }
+        Ok(())
+    }
+}
+
+/// Whether a raw query string carries a signature to check.
+pub fn is_signed(query: &str) -> bool {
+    query
+        .split('&')
+        .any(|pair| pair.strip_prefix(SIGNATURE_PARAM).is_some_and(|rest| rest.starts_with('=')))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    #[test]
+    fn signed_urls_verify_until_they_expire() {
+        let signer = UrlSigner::new(b"secret");
+        let now = Utc::now();
+        let url = signer.sign("/images/a.jpg/thumbnail?size=256", now + Duration::hours(1));
+        let (path, query) = url.split_once('?').unwrap();
+        assert!(is_signed(query));
+        assert!(signer.verify(path, query, now).is_ok());
+
+        let expired = signer.verify(path, query, now + Duration::hours(2)).unwrap_err();
+        assert_eq!(expired.to_string(), "Signed URL has expired");
+        assert!(signer.verify("/images/b.jpg/thumbnail", query, now).is_err());
+        assert!(signer.verify(path, &query.replace("size=256", "size=1024"), now).is_err());
+        assert!(UrlSigner::new(b"other").verify(path, query, now).is_err());
+
+        let url = signer.sign("/images/a.jpg", now + Duration::hours(1));
+        assert!(url.starts_with("/images/a.jpg?expires="));
+        let (path, query) = url.split_once('?').unwrap();
+        assert!(signer.verify(path, query, now).is_ok());
+        assert!(!is_signed("size=256&signatures=1"));
+    }
+}
@@ -0,0 +1,143 @@
+use anyhow::Context;
+use image::{DynamicImage, ImageFormat, Rgba, RgbaImage};
+use serde::Serialize;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+
+use crate::processor::{EncodedImage, ImageProcessor};
+use crate::thumbnails;
+
+/// Thumbnail sizes allowed in a sprite; larger ones would make the sheet huge.
+pub const SIZES: &[u32] = &[64, 128, 256];
+pub const DEFAULT_SIZE: u32 = 128;
+pub const MAX_TILES: usize = 100;
+
+// Shown through transparent thumbnails, since the sheet is a JPEG
+const BACKGROUND: Rgba<u8> = Rgba([255, 255, 255, 255]);
+
+/// Where one thumbnail sits in the sprite, in pixels.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Tile {
+    pub filename: String,
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Thumbnails laid out on a grid of `size` x `size` cells, left to right, top to bottom.
+#[derive(Debug, Clone, Serialize)]
+pub struct SpriteLayout {
+    pub size: u32,
+    pub columns: u32,
+    pub width: u32,
+    pub height: u32,
+    pub tiles: Vec<Tile>,
+}
+
+/// Cached thumbnails for `sources`, skipping (and logging) any that cannot be made.
+fn load_thumbnails(images_dir: &Path, sources: &[PathBuf], size: u32) -> Vec<(String, EncodedImage)> {
+    sources
+        .iter()
+        .filter_map(|source| {
+            let filename = source.file_name()?.to_string_lossy().into_owned();
+            match thumbnails::get_or_create(images_dir, source, size) {
+                Ok(thumbnail) => Some((filename, thumbnail)),
+            Err(e) => {
+                    log::warn!("Leaving {} out of sprite: {:#}", filename, e);
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+fn place(dimensions: Vec<(String, (u32, u32))>, size: u32) -> SpriteLayout {
+    let count = dimensions.len() as u32;
+    let columns = (count as f64).sqrt().ceil().max(1.0) as u32;
+    let rows = count.div_ceil(columns);
+    let tiles = dimensions
+        .into_iter()
+        .zip(0u32..)
+        .map(|((filename, (width, height)), i)| Tile {
+            filename,
+            x: (i % columns) * size,
+            y: (i / columns) * size,
+            width,
+            height,
+        })
+        .collect();
+    SpriteLayout {
+        size,
+        columns,
+        width: columns * size,
+        height: rows.max(1) * size,
+        tiles,
+    }
+}
+
+/// Computes the layout from thumbnail headers, without compositing.
+pub fn layout(images_dir: &Path, sources: &[PathBuf], size: u32) -> SpriteLayout {
+    let dimensions = load_thumbnails(images_dir, sources, size)
+        .into_iter()
+        .filter_map(|(filename, thumbnail)| {
+            let dimensions = image::io::Reader::new(Cursor::new(&thumbnail.bytes))
+                .with_guessed_format()
+                .ok()?
+                .into_dimensions()
+                .ok()?;
+            Some((filename, dimensions))
+        })
+        .collect();
+    place(dimensions, size)
+}
+
+/// Composites the thumbnails into one JPEG matching [`layout`].
+pub fn render(images_dir: &Path, sources: &[PathBuf], size: u32) -> anyhow::Result<(SpriteLayout, EncodedImage)> {
+    let decoded: Vec<(String, DynamicImage)> = load_thumbnails(images_dir, sources, size)
+        .into_iter()
+        .filter_map(|(filename, thumbnail)| Some((filename, image::load_from_memory(&thumbnail.bytes).ok()?)))
+        .collect();
+    let layout = place(
+        decoded
+            .iter()
+            .map(|(filename, img)| (filename.clone(), (img.width(), img.height())))
+            .collect(),
+        size,
+    );
+
+    let mut sheet = RgbaImage::from_pixel(layout.width, layout.height, BACKGROUND);
+    for (tile, (_, img)) in layout.tiles.iter().zip(&decoded) {
+        image::imageops::overlay(&mut sheet, img, tile.x.into(), tile.y.into());
+    }
+    let sheet = DynamicImage::ImageRgb8(DynamicImage::ImageRgba8(sheet).to_rgb8());
+    let encoded = ImageProcessor::encode(&sheet, ImageFormat::Jpeg).context("Failed to encode sprite")?;
+    Ok((layout, encoded))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_fs::prelude::*;
+    use image::GenericImageView;
+
+    #[test]
+    fn lays_out_thumbnails_on_a_square_grid() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        for (name, (w, h)) in [("a.png", (200, 100)), ("b.png", (50, 50)), ("c.png", (100, 200))] {
+            image::DynamicImage::new_rgb8(w, h).save(temp.child(name).path()).unwrap();
+        }
+        temp.child("broken.jpg").write_binary(b"not an image").unwrap();
+        let sources: Vec<PathBuf> = ["a.png", "broken.jpg", "b.png", "c.png"].iter().map(|n| temp.path().join(n)).collect();
+
+        let layout = layout(temp.path(), &sources, 64);
+        assert_eq!((layout.columns, layout.width, layout.height), (2, 128, 128));
+        let tiles: Vec<_> = layout.tiles.iter().map(|t| (t.filename.as_str(), t.x, t.y, t.width, t.height)).collect();
+        assert_eq!(tiles, vec![("a.png", 0, 0, 64, 32), ("b.png", 64, 0, 50, 50), ("c.png", 0, 64, 32, 64)]);
+
+        let (rendered, sprite) = render(temp.path(), &sources, 64).unwrap();
+        assert_eq!(rendered.tiles, layout.tiles);
+        assert_eq!(sprite.content_type(), "image/jpeg");
+        assert_eq!(image::load_from_memory(&sprite.bytes).unwrap().dimensions(), (128, 128));
+    }
+}
@@ -1,19 +1,328 @@
-use actix_web::{web, App, HttpServer};
+use actix_cors::Cors;
+use actix_web::{middleware, web, App, HttpServer};
+use socket2::{Domain, Protocol, Socket, Type};
+use std::net::{TcpListener, ToSocketAddrs};
 use std::path::PathBuf;
+use std::sync::Arc;
+use crate::auth::{self, Authenticator};
+use crate::budget::{self, TransformBudget};
+use crate::cache::{ImageCache, VariantCache};
+use crate::cdn::{self, CdnPurger};
+use crate::config::Config;
+use crate::error;
+use crate::fields::FieldRegistry;
+use crate::tls;
 use crate::handlers::*;
+use crate::indexer::{self, LibraryIndex};
+use crate::ingest::IngestRules;
+use crate::maintenance::{self, MaintenanceState};
+use crate::prefetch::ThumbnailPrefetcher;
+use crate::signing::UrlSigner;
+use crate::stats::ViewStats;
+use crate::suggest::SuggestIndex;
+use crate::transcode::TranscodeCache;
+use crate::usage::{self, UsageTracker};
+use crate::video::ProbeCache;
+use crate::volumes::{self, VolumeMonitor};
+use crate::watcher::LibraryWatcher;
 
-pub async fn run(images_dir: PathBuf) -> std::io::Result<actix_web::dev::Server> {
-    let images_dir = web::Data::new(images_dir);
-    
-    let server = HttpServer::new(move || {
+/// CORS for the configured origins; `*` allows any origin.
+fn cors(origins: &[String]) -> Cors {
+    let cors = Cors::default()
+        .allowed_methods(["GET", "HEAD", "POST", "PUT", "DELETE"])
+        .allow_any_header()
+        .expose_headers(["Content-Range", "ETag", "Retry-After"])
+        .max_age(3600);
+    if origins.iter().any(|origin| origin == "*") {
+        return cors.allow_any_origin();
+    }
+    origins.iter().fold(cors, |cors, origin| cors.allowed_origin(origin))
+}
+
+/// Binds `address`. IPv6 sockets are made IPv6-only, so `[::]` and `0.0.0.0`
+/// can listen on the same port side by side.
+fn listener(address: &str) -> std::io::Result<TcpListener> {
+    let addr = address.to_socket_addrs()?.next().ok_or_else(|| {
+        std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("No address for {}", address))
+    })?;
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+    if addr.is_ipv6() {
+        socket.set_only_v6(true)?;
+    }
+    socket.set_reuse_address(true)?;
+    socket.bind(&addr.into())?;
+    socket.listen(1024)?;
+    log::info!("Listening on {}", addr);
+    Ok(socket.into())
+}
+
+/// Shared state handed to every worker of both servers.
+#[derive(Clone)]
+struct AppState {
+    images_dir: web::Data<PathBuf>,
+    config: web::Data<Config>,
+    suggest_index: web::Data<SuggestIndex>,
+    maintenance_state: web::Data<MaintenanceState>,
+    view_stats: web::Data<ViewStats>,
+    image_cache: web::Data<ImageCache>,
+    variant_cache: web::Data<VariantCache>,
+    library_index: web::Data<LibraryIndex>,
+    transcodes: web::Data<TranscodeCache>,
+    usage: web::Data<UsageTracker>,
+    budget: web::Data<TransformBudget>,
+    authenticator: web::Data<Authenticator>,
+    signer: web::Data<UrlSigner>,
+    prefetcher: web::Data<ThumbnailPrefetcher>,
+    fields: web::Data<FieldRegistry>,
+    probes: web::Data<ProbeCache>,
+    purger: web::Data<CdnPurger>,
+}
+
+impl AppState {
+    fn register(&self, cfg: &mut web::ServiceConfig) {
+        cfg.app_data(error::query_config())
+            .app_data(error::json_config())
+            .app_data(self.images_dir.clone())
+            .app_data(self.config.clone())
+            .app_data(self.suggest_index.clone())
+            .app_data(self.maintenance_state.clone())
+            .app_data(self.view_stats.clone())
+            .app_data(self.image_cache.clone())
+            .app_data(self.variant_cache.clone())
+            .app_data(self.library_index.clone())
+            .app_data(self.transcodes.clone())
+            .app_data(self.usage.clone())
+            .app_data(self.budget.clone())
+            .app_data(self.authenticator.clone())
+            .app_data(self.signer.clone())
+            .app_data(self.prefetcher.clone())
+            .app_data(self.fields.clone())
+            .app_data(self.probes.clone())
+            .app_data(self.purger.clone());
+    }
+}
+
+fn public_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(health_check)
+        .service(volume_health)
+        .service(current_user)
+        .service(serve_image)
+        .service(download_image)
+        .service(image_thumbnail)
+        .service(convert_image)
+        .service(transform_upload)
+        .service(image_info)
+        .service(signed_url)
+        .service(set_image_tags)
+        .service(set_image_fields)
+        .service(video_keyframes)
+        .service(video_frame)
+        .service(video_thumbnail)
+        .service(video_preview)
+        .service(video_info)
+        .service(video_playlist)
+        .service(video_segment)
+        .service(video_root)
+        .service(validate_etags)
+        .service(stream_images)
+        .service(gallery_sprite_layout)
+        .service(gallery_sprite)
+        .service(list_images)
+        .service(search_images)
+        .service(memories)
+        .service(library_activity)
+        .service(housekeeping_report)
+        .service(json_feed)
+        .service(public_images)
+        .service(public_thumbnail)
+        .service(public_image)
+        .service(suggest);
+}
+
+fn admin_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(maintenance_status)
+        .service(field_schema)
+        .service(set_field_schema)
+        .service(set_maintenance)
+        .service(cache_stats)
+        .service(admin_dashboard)
+        .service(start_reindex)
+        .service(reindex_status)
+        .service(apply_rename_rules)
+        .service(inspect_file)
+        .service(set_published)
+        .service(delete_image)
+        .service(trashed_images)
+        .service(restore_image)
+        .service(usage_report)
+        .service(library_manifest)
+        .service(backup_changes)
+        .service(backup_diff);
+}
+
+/// Checks the library's volumes every [`volumes::CHECK_INTERVAL`] for as long
+/// as the server runs, reindexing when one comes back so its files reappear.
+fn monitor_volumes(index: Arc<LibraryIndex>, images_dir: PathBuf, referenced_dirs: Vec<PathBuf>) {
+    let roots: Vec<PathBuf> = std::iter::once(images_dir.clone()).chain(referenced_dirs.iter().cloned()).collect();
+    let monitor = Arc::new(VolumeMonitor::default());
+    actix_web::rt::spawn(async move {
+        let mut interval = actix_web::rt::time::interval(volumes::CHECK_INTERVAL);
+        loop {
+            interval.tick().await;
+            let (checker, roots) = (monitor.clone(), roots.clone());
+            // A hung network mount blocks here, so keep it off the executor
+            let recovered = web::block(move || checker.check(&roots).1).await.unwrap_or(false);
+            if recovered {
+                indexer::spawn(index.clone(), images_dir.clone(), referenced_dirs.clone());
+            }
+        }
+    });
+}
+
+/// Builds and binds the server. `config.images_dir` must already exist.
+///
+/// With `admin_bind_addresses` set, the `/admin` endpoints move to a second
+/// server on those addresses, which runs in the background alongside the
+/// returned one; otherwise they are served with everything else.
+pub async fn run(config: Config) -> std::io::Result<actix_web::dev::Server> {
+    let images_dir = web::Data::new(config.images_dir.clone());
+    let image_cache = web::Data::new(ImageCache::new(config.image_cache_max_bytes));
+    let variant_cache = web::Data::new(VariantCache::new(config.variant_cache_max_bytes));
+    let transcodes = web::Data::new(TranscodeCache::new(&images_dir, &config));
+    let rules = IngestRules::compile(&config.ingest_rules).map_err(std::io::Error::other)?;
+    let suggest_index = web::Data::new(SuggestIndex::default());
+    let library_index = web::Data::new(LibraryIndex::with_rules(rules).invalidating(suggest_index.clone().into_inner()));
+    if config.index_on_startup {
+        indexer::spawn(
+            library_index.clone().into_inner(),
+            images_dir.to_path_buf(),
+            config.policy.referenced_dirs.clone(),
+        );
+    }
+    if config.watch_images_dir {
+        let watcher = LibraryWatcher {
+            images_dir: images_dir.to_path_buf(),
+            referenced_dirs: config.policy.referenced_dirs.clone(),
+            index: library_index.clone().into_inner(),
+            images: image_cache.clone().into_inner(),
+            variants: variant_cache.clone().into_inner(),
+            suggestions: suggest_index.clone().into_inner(),
+        };
+        if let Err(e) = watcher.spawn() {
+            log::warn!("Not watching {:?} for changes: {}", images_dir.as_ref(), e);
+        }
+    }
+    let prefetcher = if config.prefetch_thumbnails {
+        ThumbnailPrefetcher::spawn(images_dir.to_path_buf()).unwrap_or_else(|e| {
+            log::warn!("Not prefetching thumbnails: {}", e);
+            ThumbnailPrefetcher::disabled()
+        })
+    } else {
+        ThumbnailPrefetcher::disabled()
+    };
+    monitor_volumes(
+        library_index.clone().into_inner(),
+        images_dir.to_path_buf(),
+        config.policy.referenced_dirs.clone(),
+    );
+    let bind_addresses = config.bind_addresses.clone();
+    let admin_bind_addresses = config.admin_bind_addresses.clone();
+    let separate_admin = !admin_bind_addresses.is_empty();
+    let cors_origins = config.cors_origins.clone();
+    let tls = config
+        .tls()
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("{:#}", e)))?;
+    let tls_bind_addresses = config.tls_bind_addresses.clone();
+    let redirect_http = config.redirect_http;
+    let authenticator = web::Data::new(Authenticator::new(config.oidc.clone()));
+    if config.url_signing_key.is_empty() {
+        log::info!("No url_signing_key set; signed URLs will stop working at restart");
+    }
+    let signer = web::Data::new(UrlSigner::from_secret(&config.url_signing_key));
+    let fields = web::Data::new(FieldRegistry::new(config.fields.clone()));
+    let purger = web::Data::new(CdnPurger::new(config.cdn.clone()));
+    let state = AppState {
+        images_dir,
+        config: web::Data::new(config),
+        suggest_index,
+        maintenance_state: web::Data::new(MaintenanceState::default()),
+        view_stats: web::Data::new(ViewStats::default()),
+        image_cache,
+        variant_cache,
+        library_index,
+        transcodes,
+        usage: web::Data::new(UsageTracker::default()),
+        budget: web::Data::new(TransformBudget::default()),
+        authenticator,
+        signer,
+        prefetcher: web::Data::new(prefetcher),
+        fields,
+        probes: web::Data::new(ProbeCache::default()),
+        purger,
+    };
+
+    if separate_admin {
+        let admin_state = state.clone();
+        let mut admin = HttpServer::new(move || {
+            App::new()
+                .configure(|cfg| admin_state.register(cfg))
+                .configure(admin_routes)
+                .wrap(middleware::from_fn(cdn::surrogate))
+                // Maintenance mode turns away the public API, never the admin one
+                .wrap(middleware::from_fn(usage::track))
+                // The admin addresses are no reason to skip authentication
+                .wrap(middleware::from_fn(auth::authenticate))
+        })
+        .workers(1);
+        for address in &admin_bind_addresses {
+            admin = admin.listen(listener(address)?)?;
+        }
+        actix_web::rt::spawn(admin.run());
+    }
+
+    let mut server = HttpServer::new(move || {
         App::new()
-            .app_data(images_dir.clone())
-            .service(health_check)
-            .service(serve_image)
-            .service(image_info)
-    })
-    .bind(("127.0.0.1", 8081))?
-    .run();
-    
-    Ok(server)
+            .configure(|cfg| state.register(cfg))
+            .configure(public_routes)
+            .configure(|cfg| {
+                if !separate_admin {
+                    admin_routes(cfg);
+                }
+            })
+            .wrap(middleware::from_fn(cdn::surrogate))
+            .wrap(middleware::from_fn(budget::enforce))
+            .wrap(middleware::from_fn(maintenance::reject_during_maintenance))
+            .wrap(middleware::from_fn(usage::track))
+            .wrap(middleware::from_fn(auth::authenticate))
+            // Outermost, so maintenance responses carry CORS headers too
+            .wrap(middleware::Condition::new(!cors_origins.is_empty(), cors(&cors_origins)))
+    });
+    if let Some(tls) = &tls {
+        for address in &tls_bind_addresses {
+            server = server.listen_rustls_0_23(listener(address)?, tls.clone())?;
+        }
+    }
+    if redirect_http {
+        let https_port = tls_bind_addresses
+            .first()
+            .and_then(|address| address.rsplit_once(':'))
+            .and_then(|(_, port)| port.parse().ok())
+            .unwrap_or(443);
+        let mut redirects = HttpServer::new(move || {
+            App::new()
+                .app_data(web::Data::new(tls::HttpsPort(https_port)))
+                .default_service(web::to(tls::redirect))
+        })
+        .workers(1);
+        for address in &bind_addresses {
+            redirects = redirects.listen(listener(address)?)?;
+        }
+        actix_web::rt::spawn(redirects.run());
+    } else {
+        for address in &bind_addresses {
+            server = server.listen(listener(address)?)?;
+        }
+    }
+
+    Ok(server.run())
 }
@@ -0,0 +1,52 @@
+use chrono::{Datelike, NaiveDate, Utc};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct ImageStats {
+    pub views: u64,
+    pub downloads: u64,
+}
+
+/// In-memory per-image counters, reset when the process restarts.
+#[derive(Default)]
+pub struct ViewStats {
+    counters: Mutex<HashMap<String, ImageStats>>,
+    /// Views across the library per UTC day.
+    daily_views: Mutex<BTreeMap<NaiveDate, u64>>,
+}
+
+impl ViewStats {
+    pub fn record_view(&self, filename: &str) {
+        self.counters.lock().unwrap().entry(filename.to_string()).or_default().views += 1;
+        *self.daily_views.lock().unwrap().entry(Utc::now().date_naive()).or_default() += 1;
+    }
+
+    pub fn record_download(&self, filename: &str) {
+        self.counters.lock().unwrap().entry(filename.to_string()).or_default().downloads += 1;
+    }
+
+    pub fn get(&self, filename: &str) -> ImageStats {
+        self.counters.lock().unwrap().get(filename).copied().unwrap_or_default()
+    }
+
+    /// Carries `from`'s counters over to its new name `to`.
+    pub fn rename(&self, from: &str, to: &str) {
+        let mut counters = self.counters.lock().unwrap();
+        if let Some(stats) = counters.remove(from) {
+            counters.insert(to.to_string(), stats);
+        }
+    }
+
+    /// Views per day of `year`, leaving out days without any.
+    pub fn views_by_day(&self, year: i32) -> BTreeMap<NaiveDate, u64> {
+        self.daily_views
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(day, _)| day.year() == year)
+            .map(|(day, views)| (*day, *views))
+            .collect()
+    }
+}
@@ -0,0 +1,229 @@
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::SystemTime;
+
+use crate::library::LibraryImage;
+use crate::policy::ContentPolicy;
+use crate::tags;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SuggestionKind {
+    Filename,
+    Tag,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Suggestion {
+    pub kind: SuggestionKind,
+    pub value: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+}
+
+/// Sorted (token, suggestion) pairs so a prefix lookup is a binary search plus a short scan.
+#[derive(Debug, Default)]
+pub struct PrefixIndex {
+    tokens: Vec<(String, usize)>,
+    suggestions: Vec<Suggestion>,
+}
+
+impl PrefixIndex {
+    /// Indexes each file name and each distinct tag name, `tags` holding
+    /// the tags of each image.
+    pub fn build(images: &[LibraryImage], tags: &[Vec<tags::Tag>]) -> Self {
+        let mut index = PrefixIndex::default();
+        for image in images {
+            index.insert(SuggestionKind::Filename, &image.filename);
+        }
+        // Tags match case-insensitively, so the first spelling stands for all
+        let mut seen = HashSet::new();
+        for tag in tags.iter().flatten() {
+            if seen.insert(tag.name.to_lowercase()) {
+                index.insert(SuggestionKind::Tag, &tag.name);
+            }
+        }
+        index.tokens.sort();
+        index
+    }
+
+    fn insert(&mut self, kind: SuggestionKind, value: &str) {
+        let id = self.suggestions.len();
+        self.suggestions.push(Suggestion {
+            kind,
+            value: value.to_string(),
+            url: None,
+        });
+
+        let lowered = value.to_lowercase();
+        self.tokens.push((lowered.clone(), id));
+        // Also index each word so "beach" finds "2019_summer_beach.jpg"
+        for word in lowered.split(|c: char| !c.is_alphanumeric()).skip(1) {
+            if !word.is_empty() {
+                self.tokens.push((word.to_string(), id));
+            }
+        }
+    }
+
+    /// Suggestions whose value or any word in it starts with `query`, best matches first.
+    pub fn lookup(&self, query: &str, limit: usize) -> Vec<Suggestion> {
+        let query = query.to_lowercase();
+        let start = self.tokens.partition_point(|(token, _)| token.as_str() < query.as_str());
+
+        let mut matched: HashMap<usize, bool> = HashMap::new();
+        for (token, id) in self.tokens[start..].iter().take_while(|(token, _)| token.starts_with(&query)) {
+            let whole_value = self.suggestions[*id].value.to_lowercase() == *token;
+            *matched.entry(*id).or_default() |= whole_value;
+        }
+        let mut matches: Vec<(bool, usize)> = matched.into_iter().map(|(id, whole)| (whole, id)).collect();
+
+        // Whole-value prefix matches first, then shorter values, then alphabetical
+        matches.sort_by(|(a_whole, a), (b_whole, b)| {
+            let (a, b) = (&self.suggestions[*a].value, &self.suggestions[*b].value);
+            b_whole.cmp(a_whole).then(a.len().cmp(&b.len())).then(a.cmp(b))
+        });
+
+        matches
+            .into_iter()
+            .take(limit)
+            .map(|(_, id)| self.suggestions[id].clone())
+            .collect()
+    }
+}
+
+// Modification times of the images directory and each referenced folder
+type DirTimes = Vec<Option<SystemTime>>;
+
+/// Caches the prefix index and rebuilds it only when the images directory or
+/// a referenced folder changes, or it is told tags changed: through the API,
+/// an ingest rule or the folder watcher.
+#[derive(Default)]
+pub struct SuggestIndex {
+    cached: RwLock<Option<(DirTimes, Arc<PrefixIndex>)>>,
+    // One rebuild at a time; callers queued behind it reuse its result
+    rebuilding: Mutex<()>,
+    // Bumped by `invalidate`, so a rebuild it overtook is not cached
+    generation: AtomicU64,
+}
+
+impl SuggestIndex {
+    /// The index over what `policy` lets through of `images_dir` and the
+    /// referenced folders. Rebuilding reads every file, so call it off the
+    /// async executor.
+    pub fn get(&self, images_dir: &Path, policy: &ContentPolicy) -> std::io::Result<Arc<PrefixIndex>> {
+        let mut dirs_modified = vec![std::fs::metadata(images_dir)?.modified().ok()];
+        dirs_modified.extend(policy.referenced_dirs.iter().map(|dir| dir.metadata().and_then(|m| m.modified()).ok()));
+
+        if let Some(index) = self.current(&dirs_modified) {
+            return Ok(index);
+        }
+        let _rebuilding = self.rebuilding.lock().unwrap();
+        if let Some(index) = self.current(&dirs_modified) {
+            return Ok(index);
+        }
+
+        let generation = self.generation.load(Ordering::SeqCst);
+        let images = policy.scan(images_dir)?;
+        let tags: Vec<_> = images.iter().map(|image| tags::read(&image.path)).collect();
+        let index = Arc::new(PrefixIndex::build(&images, &tags));
+        let mut cached = self.cached.write().unwrap();
+        if self.generation.load(Ordering::SeqCst) == generation {
+            *cached = Some((dirs_modified, index.clone()));
+        }
+        Ok(index)
+    }
+
+    fn current(&self, dirs_modified: &DirTimes) -> Option<Arc<PrefixIndex>> {
+        match self.cached.read().unwrap().as_ref() {
+            Some((modified, index)) if dirs_modified[0].is_some() && modified == dirs_modified => Some(index.clone()),
+            _ => None,
+        }
+    }
+
+    /// Drops the cached index, for changes that leave directory times alone.
+    pub fn invalidate(&self) {
+        let mut cached = self.cached.write().unwrap();
+        self.generation.fetch_add(1, Ordering::SeqCst);
+        *cached = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn image(filename: &str) -> LibraryImage {
+        LibraryImage {
+            filename: filename.to_string(),
+            path: PathBuf::from(filename),
+            size_bytes: 0,
+            modified: None,
+        }
+    }
+
+    #[test]
+    fn lookup_ranks_whole_value_prefix_before_word_matches() {
+        let index = PrefixIndex::build(&[
+            image("2019_summer_beach.jpg"),
+            image("beach.png"),
+            image("beachfront-sunset.jpg"),
+            image("mountains.jpg"),
+        ], &[]);
+
+        let values: Vec<_> = index.lookup("Beach", 10).into_iter().map(|s| s.value).collect();
+
+        assert_eq!(values, vec!["beach.png", "beachfront-sunset.jpg", "2019_summer_beach.jpg"]);
+        assert_eq!(index.lookup("beach", 1).len(), 1);
+        assert!(index.lookup("zebra", 10).is_empty());
+    }
+
+    #[test]
+    fn build_indexes_each_tag_once() {
+        let tag = |name: &str| tags::Tag { name: name.to_string(), color: None };
+        let index = PrefixIndex::build(
+            &[image("a.jpg"), image("b.jpg")],
+            &[vec![tag("Summer trip"), tag("Red")], vec![tag("summer trip")]],
+        );
+
+        let found = index.lookup("trip", 10);
+        assert_eq!(found.len(), 1);
+        assert_eq!((found[0].kind, found[0].value.as_str()), (SuggestionKind::Tag, "Summer trip"));
+    }
+
+    #[test]
+    fn index_covers_referenced_folders() {
+        use assert_fs::prelude::*;
+
+        let temp = assert_fs::TempDir::new().unwrap();
+        let archive = assert_fs::TempDir::new().unwrap();
+        temp.child("beach.jpg").write_binary(b"local").unwrap();
+        archive.child("beach_2009.jpg").write_binary(b"archived").unwrap();
+
+        let policy = ContentPolicy {
+            referenced_dirs: vec![archive.path().to_path_buf()],
+            ..ContentPolicy::default()
+        };
+        let index = SuggestIndex::default().get(temp.path(), &policy).unwrap();
+        let values: Vec<_> = index.lookup("beach", 10).into_iter().map(|s| s.value).collect();
+        assert_eq!(values, vec!["beach.jpg", "beach_2009.jpg"]);
+    }
+
+    #[test]
+    fn invalidate_drops_the_cached_index() {
+        use assert_fs::prelude::*;
+
+        let temp = assert_fs::TempDir::new().unwrap();
+        temp.child("beach.jpg").write_binary(b"local").unwrap();
+        let suggestions = SuggestIndex::default();
+        let policy = ContentPolicy::default();
+        let first = suggestions.get(temp.path(), &policy).unwrap();
+        assert!(Arc::ptr_eq(&first, &suggestions.get(temp.path(), &policy).unwrap()));
+
+        suggestions.invalidate();
+        assert!(!Arc::ptr_eq(&first, &suggestions.get(temp.path(), &policy).unwrap()));
+    }
+}
@@ -0,0 +1,184 @@
+use serde::Serialize;
+use std::io::{self, Cursor};
+use std::path::Path;
+
+/// Extended attribute where Finder stores user tags as a binary plist array of
+/// strings such as `"Red\n6"` (name, newline, color index).
+#[cfg(target_os = "macos")]
+pub const USER_TAGS_XATTR: &str = "com.apple.metadata:_kMDItemUserTags";
+
+// Linux only allows unprivileged attributes in the user namespace; this is the
+// name Samba and netatalk use when a Mac copies tagged files onto a share.
+#[cfg(not(target_os = "macos"))]
+pub const USER_TAGS_XATTR: &str = "user.com.apple.metadata:_kMDItemUserTags";
+
+/// Finder's label colors, by the index stored after the tag name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TagColor {
+    Gray,
+    Green,
+    Purple,
+    Blue,
+    Yellow,
+    Red,
+    Orange,
+}
+
+impl TagColor {
+    fn from_index(index: &str) -> Option<Self> {
+        match index.trim() {
+            "1" => Some(TagColor::Gray),
+            "2" => Some(TagColor::Green),
+            "3" => Some(TagColor::Purple),
+            "4" => Some(TagColor::Blue),
+            "5" => Some(TagColor::Yellow),
+            "6" => Some(TagColor::Red),
+            "7" => Some(TagColor::Orange),
+            _ => None,
+        }
+    }
+}
+
+/// One user tag: its full name as shown in Finder and, separately, its color.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Tag {
+    pub name: String,
+    pub color: Option<TagColor>,
+}
+
+impl Tag {
+    fn from_raw(raw: &str) -> Self {
+        let (name, color) = match raw.split_once('\n') {
+            Some((name, index)) => (name, TagColor::from_index(index)),
+            None => (raw, None),
+        };
+        Tag { name: name.to_string(), color }
+    }
+}
+
+/// Tag name without the `\n<color>` suffix Finder appends.
+fn tag_name(raw: &str) -> &str {
+    raw.split('\n').next().unwrap_or(raw)
+}
+
+/// Decodes the user-tags plist into its raw entries, color suffixes included.
+fn decode(plist_bytes: &[u8]) -> Vec<String> {
+    let Ok(plist::Value::Array(entries)) = plist::Value::from_reader(Cursor::new(plist_bytes)) else {
+        return Vec::new();
+    };
+    entries
+        .into_iter()
+        .filter_map(|entry| entry.into_string())
+        .filter(|raw| !tag_name(raw).is_empty())
+        .collect()
+}
+
+fn encode(entries: &[String]) -> Vec<u8> {
+    let value = plist::Value::Array(entries.iter().cloned().map(plist::Value::String).collect());
+    let mut bytes = Vec::new();
+    value
+        .to_writer_binary(&mut bytes)
+        .expect("writing a plist to memory cannot fail");
+    bytes
+}
+
+/// Decodes the user-tags plist in memory. Anything malformed yields no tags.
+pub fn parse(plist_bytes: &[u8]) -> Vec<Tag> {
+    decode(plist_bytes).iter().map(|raw| Tag::from_raw(raw)).collect()
+}
+
+fn read_raw(path: &Path) -> Vec<String> {
+    match xattr::get(path, USER_TAGS_XATTR) {
+        Ok(Some(bytes)) => decode(&bytes),
+        Ok(None) => Vec::new(),
+        Err(e) => {
+            log::debug!("Could not read tags for {:?}: {}", path, e);
+            Vec::new()
+        }
+    }
+}
+
+/// The user tags on `path`; files without tags, or on filesystems without
+/// extended attributes, have none.
+pub fn read(path: &Path) -> Vec<Tag> {
+    read_raw(path).iter().map(|raw| Tag::from_raw(raw)).collect()
+}
+
+/// Replaces the user tags on `path` with `names`. Tags already present keep
+/// their Finder color; an empty list removes the attribute.
+pub fn write(path: &Path, names: &[String]) -> io::Result<()> {
+    if names.is_empty() {
+        if xattr::get(path, USER_TAGS_XATTR)?.is_none() {
+            return Ok(());
+        }
+        return xattr::remove(path, USER_TAGS_XATTR);
+    }
+
+    let existing = read_raw(path);
+    let entries: Vec<String> = names
+        .iter()
+        .map(|name| {
+            existing
+                .iter()
+                .find(|raw| tag_name(raw).eq_ignore_ascii_case(name))
+                .cloned()
+                .unwrap_or_else(|| name.clone())
+        })
+        .collect();
+    xattr::set(path, USER_TAGS_XATTR, &encode(&entries))
+}
+
+/// Whether `path` carries `tag`, compared case-insensitively by name.
+pub fn has_tag(path: &Path, tag: &str) -> bool {
+    read_raw(path).iter().any(|raw| tag_name(raw).eq_ignore_ascii_case(tag))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_fs::prelude::*;
+
+    fn strings(tags: &[&str]) -> Vec<String> {
+        tags.iter().map(|t| t.to_string()).collect()
+    }
+
+    fn tag(name: &str, color: Option<TagColor>) -> Tag {
+        Tag { name: name.to_string(), color }
+    }
+
+    #[test]
+    fn parses_full_names_and_colors() {
+        assert_eq!(
+            parse(&encode(&strings(&["Red\n6", "portfolio", "Client: Acme\n4", "Odd\n9"]))),
+            vec![
+                tag("Red", Some(TagColor::Red)),
+                tag("portfolio", None),
+                tag("Client: Acme", Some(TagColor::Blue)),
+                tag("Odd", None),
+            ]
+        );
+        assert!(parse(b"not a plist").is_empty());
+        assert!(parse(&encode(&[])).is_empty());
+    }
+
+    #[test]
+    fn write_keeps_colors_of_existing_tags() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        let file = temp.child("a.jpg");
+        file.write_binary(b"a").unwrap();
+        if xattr::set(file.path(), USER_TAGS_XATTR, &encode(&strings(&["Red\n6", "Old"]))).is_err() {
+            eprintln!("skipping: filesystem does not support extended attributes");
+            return;
+        }
+
+        write(file.path(), &strings(&["red", "Portfolio"])).unwrap();
+        assert_eq!(read_raw(file.path()), vec!["Red\n6", "Portfolio"]);
+
+        assert_eq!(read(file.path()), vec![tag("Red", Some(TagColor::Red)), tag("Portfolio", None)]);
+
+        write(file.path(), &[]).unwrap();
+        assert!(read(file.path()).is_empty());
+        write(file.path(), &[]).unwrap();
+    }
+}
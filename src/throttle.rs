@@ -0,0 +1,81 @@
+use actix_web::web::Bytes;
+use futures_util::stream::{self, Stream};
+use std::time::Duration;
+use tokio::io::AsyncReadExt;
+
+const TICK: Duration = Duration::from_millis(100);
+const TICKS_PER_SEC: u64 = 10;
+const FILE_CHUNK: usize = 256 * 1024;
+
+/// Streams `body` at roughly `bytes_per_sec`, sending one chunk per 100ms tick.
+pub fn throttled(body: Bytes, bytes_per_sec: u64) -> impl Stream<Item = Result<Bytes, std::io::Error>> {
+    let chunk_size = (bytes_per_sec / TICKS_PER_SEC).max(1) as usize;
+
+    stream::unfold((body, true), move |(mut remaining, first)| async move {
+        if remaining.is_empty() {
+            return None;
+        }
+        if !first {
+            tokio::time::sleep(TICK).await;
+        }
+        let chunk = remaining.split_to(chunk_size.min(remaining.len()));
+        Some((Ok(chunk), (remaining, false)))
+    })
+}
+
+/// Streams the next `len` bytes of `file` without holding them all in memory,
+/// paced like [`throttled`] when `bytes_per_sec` is set.
+pub fn file(
+    file: tokio::fs::File,
+    len: u64,
+    bytes_per_sec: Option<u64>,
+) -> impl Stream<Item = Result<Bytes, std::io::Error>> {
+    let chunk_size = match bytes_per_sec {
+        Some(rate) => ((rate / TICKS_PER_SEC).max(1) as usize).min(FILE_CHUNK),
+        None => FILE_CHUNK,
+    };
+
+    stream::unfold((file, len, true), move |(mut file, remaining, first)| async move {
+        if remaining == 0 {
+            return None;
+        }
+        if !first && bytes_per_sec.is_some() {
+            tokio::time::sleep(TICK).await;
+        }
+        let mut chunk = vec![0u8; remaining.min(chunk_size as u64) as usize];
+        match file.read_exact(&mut chunk).await {
+            Ok(read) => Some((Ok(Bytes::from(chunk)), (file, remaining - read as u64, false))),
+            Err(e) => Some((Err(e), (file, 0, false))),
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::StreamExt;
+
+    #[actix_rt::test]
+    async fn throttled_splits_body_into_rate_sized_chunks() {
+        let body = Bytes::from(vec![7u8; 25]);
+        let chunks: Vec<Bytes> = throttled(body.clone(), 100)
+            .map(|chunk| chunk.unwrap())
+            .collect()
+            .await;
+
+        assert_eq!(chunks.iter().map(Bytes::len).collect::<Vec<_>>(), vec![10, 10, 5]);
+        assert_eq!(chunks.concat(), body.to_vec());
+    }
+
+    #[actix_rt::test]
+    async fn file_streams_the_requested_span() {
+        let temp = assert_fs::NamedTempFile::new("video.mp4").unwrap();
+        std::fs::write(temp.path(), b"0123456789").unwrap();
+        let mut handle = tokio::fs::File::open(temp.path()).await.unwrap();
+        tokio::io::AsyncSeekExt::seek(&mut handle, std::io::SeekFrom::Start(2)).await.unwrap();
+
+        let chunks: Vec<Bytes> = file(handle, 5, Some(30)).map(|chunk| chunk.unwrap()).collect().await;
+        assert_eq!(chunks.iter().map(Bytes::len).collect::<Vec<_>>(), vec![3, 2]);
+        assert_eq!(chunks.concat(), b"23456");
+    }
+}
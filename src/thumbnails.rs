@@ -0,0 +1,326 @@
+use anyhow::Context;
+use base64::Engine;
+use serde::Serialize;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::media_types;
+use crate::preview::PreviewFormat;
+use crate::processor::{ConvertFormat, EncodedImage, FitMode, ImageProcessor, DEFAULT_CONVERT_QUALITY};
+
+/// Cache directory inside the images directory; skipped by scans since it is not a file.
+pub const THUMBNAIL_DIR: &str = ".thumbnails";
+pub const DEFAULT_SIZE: u32 = 256;
+/// Fixed set of sizes so arbitrary query values cannot fill the disk.
+pub const SIZES: &[u32] = &[64, 128, 256, 512, 1024];
+/// Size of thumbnails embedded in listings as data URIs.
+pub const INLINE_SIZE: u32 = 64;
+/// Subdirectory of [`THUMBNAIL_DIR`] holding frames taken from videos.
+const POSTER_DIR: &str = "posters";
+/// Subdirectory of [`THUMBNAIL_DIR`] holding animated video previews.
+const PREVIEW_DIR: &str = "previews";
+
+pub fn thumbnail_path(images_dir: &Path, filename: &str, size: u32) -> PathBuf {
+    images_dir.join(THUMBNAIL_DIR).join(size.to_string()).join(filename)
+}
+
+/// Where the frame of video `filename` at `at_millis` is cached, per size and format.
+pub fn poster_path(images_dir: &Path, filename: &str, size: u32, at_millis: u64, format: ConvertFormat) -> PathBuf {
+    let extension = match format {
+        ConvertFormat::Jpeg => "jpg",
+        ConvertFormat::Png => "png",
+        ConvertFormat::Webp => "webp",
+    };
+    images_dir
+        .join(THUMBNAIL_DIR)
+        .join(POSTER_DIR)
+        .join(size.to_string())
+        .join(format!("{}@{}ms.{}", filename, at_millis, extension))
+}
+
+/// Where the animated preview of video `filename` is cached, per size and format.
+pub fn preview_path(images_dir: &Path, filename: &str, size: u32, format: PreviewFormat) -> PathBuf {
+    images_dir
+        .join(THUMBNAIL_DIR)
+        .join(PREVIEW_DIR)
+        .join(size.to_string())
+        .join(format!("{}.{}", filename, format.extension()))
+}
+
+/// Deletes every cached size of `filename`'s thumbnail, and its video frames
+/// and previews.
+pub fn remove(images_dir: &Path, filename: &str) -> std::io::Result<()> {
+    for size in SIZES {
+        let previews = [PreviewFormat::Webp, PreviewFormat::Gif].map(|format| preview_path(images_dir, filename, *size, format));
+        for path in std::iter::once(thumbnail_path(images_dir, filename, *size)).chain(previews) {
+            match std::fs::remove_file(path) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e),
+                _ => {}
+            }
+        }
+        let posters = images_dir.join(THUMBNAIL_DIR).join(POSTER_DIR).join(size.to_string());
+        let Ok(entries) = std::fs::read_dir(&posters) else {
+            continue;
+        };
+        let prefix = format!("{}@", filename);
+        for entry in entries.flatten() {
+            if entry.file_name().to_string_lossy().starts_with(&prefix) {
+                std::fs::remove_file(entry.path())?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Renders a thumbnail of `source` fitting a `size` x `size` box, without touching the disk cache.
+pub fn render(source: &Path, size: u32, respect_orientation: bool) -> anyhow::Result<EncodedImage> {
+    let (img, format) = ImageProcessor::open(source, respect_orientation)?;
+    let thumbnail = ImageProcessor::resize_image(&img, Some(size), Some(size), FitMode::Contain);
+    ImageProcessor::encode(&thumbnail, format)
+}
+
+/// Returns the cached (upright) thumbnail of `source`, regenerating it when the
+/// source's modification time has changed, either way. The cache lives under
+/// `images_dir` wherever the source is.
+pub fn get_or_create(images_dir: &Path, source: &Path, size: u32) -> anyhow::Result<EncodedImage> {
+    let filename = source.file_name().and_then(|name| name.to_str()).context("Invalid source file name")?;
+    let cached = thumbnail_path(images_dir, filename, size);
+    let modified = source_modified(source)?;
+
+    if is_fresh(&cached, modified) {
+        return read_cached(&cached);
+    }
+
+    let encoded = render(source, size, true)?;
+    store(&cached, &encoded, modified)?;
+    Ok(encoded)
+}
+
+/// Returns the cached frame of video `source` at `at_millis`, producing it
+/// with `extract` when missing or made from another version of the video. The cache entry is
+/// named after `name`, the file name for library videos.
+pub fn get_or_create_poster(
+    images_dir: &Path,
+    source: &Path,
+    name: &str,
+    size: u32,
+    at_millis: u64,
+    format: ConvertFormat,
+    extract: impl FnOnce() -> anyhow::Result<EncodedImage>,
+) -> anyhow::Result<EncodedImage> {
+    let cached = poster_path(images_dir, name, size, at_millis, format);
+    let modified = source_modified(source)?;
+
+    if is_fresh(&cached, modified) {
+        return read_cached(&cached);
+    }
+
+    let frame = extract()?;
+    let encoded = if frame.format == image::ImageFormat::Jpeg && format == ConvertFormat::Jpeg {
+        frame
+    } else {
+        let img = image::load_from_memory(&frame.bytes).context("Failed to decode video frame")?;
+        ImageProcessor::convert(&img, format, DEFAULT_CONVERT_QUALITY)?
+    };
+    store(&cached, &encoded, modified)?;
+    Ok(encoded)
+}
+
+/// Returns the cached animated preview of video `source`, cached as `name`,
+/// producing it with `render` when missing or made from another version of
+/// the video.
+pub fn get_or_create_preview(
+    images_dir: &Path,
+    source: &Path,
+    name: &str,
+    size: u32,
+    format: PreviewFormat,
+    render: impl FnOnce() -> anyhow::Result<EncodedImage>,
+) -> anyhow::Result<EncodedImage> {
+    let cached = preview_path(images_dir, name, size, format);
+    let modified = source_modified(source)?;
+    if is_fresh(&cached, modified) {
+        return read_cached(&cached);
+    }
+    let encoded = render()?;
+    store(&cached, &encoded, modified)?;
+    Ok(encoded)
+}
+
+fn read_cached(cached: &Path) -> anyhow::Result<EncodedImage> {
+    let bytes = std::fs::read(cached).context("Failed to read cached thumbnail")?;
+    let format = image::guess_format(&bytes).context("Unrecognized cached thumbnail")?;
+    Ok(EncodedImage {
+        bytes: bytes.into(),
+        format,
+    })
+}
+
+/// Writes `encoded` to a temporary file of its own, stamps it with the
+/// source's modification time and renames it into place, so neither readers
+/// nor other writers of the same thumbnail ever see a partial file.
+fn store(cached: &Path, encoded: &EncodedImage, source_modified: SystemTime) -> anyhow::Result<()> {
+    let parent = cached.parent().context("Invalid thumbnail path")?;
+    std::fs::create_dir_all(parent).context("Failed to create thumbnail directory")?;
+    let mut partial = tempfile::Builder::new()
+        .prefix(".")
+        .suffix(".partial")
+        .tempfile_in(parent)
+        .context("Failed to create thumbnail")?;
+    partial.write_all(&encoded.bytes).context("Failed to write thumbnail")?;
+    partial.as_file().set_modified(source_modified).context("Failed to write thumbnail")?;
+    partial.persist(cached).map_err(|e| e.error).context("Failed to store thumbnail")?;
+    Ok(())
+}
+
+/// Whether `source` already has an up-to-date cached thumbnail of `size`.
+pub fn is_cached(images_dir: &Path, source: &Path, size: u32) -> bool {
+    let Some(filename) = source.file_name().and_then(|name| name.to_str()) else {
+        return false;
+    };
+    source_modified(source).is_ok_and(|modified| is_fresh(&thumbnail_path(images_dir, filename, size), modified))
+}
+
+fn source_modified(source: &Path) -> anyhow::Result<SystemTime> {
+    std::fs::metadata(source)
+        .and_then(|m| m.modified())
+        .context("Failed to read source metadata")
+}
+
+/// Whether `cached` was made from the source as of `source_modified`: cached
+/// files carry their source's modification time, so any change to it, also
+/// back to an older copy, makes them stale.
+fn is_fresh(cached: &Path, source_modified: SystemTime) -> bool {
+    std::fs::metadata(cached)
+        .and_then(|m| m.modified())
+        .is_ok_and(|modified| modified == source_modified)
+}
+
+/// What a [`warm`] run did.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct WarmSummary {
+    pub generated: usize,
+    /// Already up to date on disk.
+    pub cached: usize,
+    pub failed: usize,
+}
+
+/// Writes the `size` thumbnail of every decodable file in `sources` that has
+/// no up-to-date one on disk, so the server finds them cached. Files that
+/// cannot be thumbnailed are logged and counted, not fatal.
+pub fn warm(images_dir: &Path, sources: &[PathBuf], size: u32) -> WarmSummary {
+    let mut summary = WarmSummary::default();
+    for source in sources {
+        if !media_types::for_path(source).is_some_and(|media| media.decodable) {
+            continue;
+        }
+        if is_cached(images_dir, source, size) {
+            summary.cached += 1;
+            continue;
+        }
+        match get_or_create(images_dir, source, size) {
+            Ok(_) => summary.generated += 1,
+            Err(e) => {
+                log::warn!("Failed to generate thumbnail for {:?}: {:#}", source, e);
+                summary.failed += 1;
+            }
+        }
+    }
+    summary
+}
+
+/// The cached thumbnail as a `data:` URI, for embedding in JSON.
+pub fn data_uri(images_dir: &Path, source: &Path, size: u32) -> anyhow::Result<String> {
+    let thumbnail = get_or_create(images_dir, source, size)?;
+    Ok(format!(
+        "data:{};base64,{}",
+        thumbnail.content_type(),
+        base64::engine::general_purpose::STANDARD.encode(&thumbnail.bytes)
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::GenericImageView;
+
+    #[test]
+    fn get_or_create_caches_on_disk_and_regenerates_when_source_changes() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        let source = temp.path().join("wide.png");
+        image::DynamicImage::new_rgb8(600, 300).save(&source).unwrap();
+
+        let first = get_or_create(temp.path(), &source, 256).unwrap();
+        assert_eq!(image::load_from_memory(&first.bytes).unwrap().dimensions(), (256, 128));
+        let cached = thumbnail_path(temp.path(), "wide.png", 256);
+        assert!(cached.exists());
+
+        // Replace the source with a newer, differently shaped image
+        let stale = std::fs::metadata(&cached).unwrap().modified().unwrap();
+        image::DynamicImage::new_rgb8(300, 600).save(&source).unwrap();
+        let newer = stale + std::time::Duration::from_secs(5);
+        std::fs::File::options()
+            .write(true)
+            .open(&source)
+            .unwrap()
+            .set_modified(newer)
+            .unwrap();
+
+        let second = get_or_create(temp.path(), &source, 256).unwrap();
+        assert_eq!(image::load_from_memory(&second.bytes).unwrap().dimensions(), (128, 256));
+
+        // An older copy put back in place counts as a change too
+        image::DynamicImage::new_rgb8(600, 300).save(&source).unwrap();
+        let older = stale - std::time::Duration::from_secs(3600);
+        std::fs::File::options()
+            .write(true)
+            .open(&source)
+            .unwrap()
+            .set_modified(older)
+            .unwrap();
+        assert!(!is_cached(temp.path(), &source, 256));
+        let third = get_or_create(temp.path(), &source, 256).unwrap();
+        assert_eq!(image::load_from_memory(&third.bytes).unwrap().dimensions(), (256, 128));
+        assert!(is_cached(temp.path(), &source, 256));
+        let leftovers = std::fs::read_dir(cached.parent().unwrap()).unwrap().count();
+        assert_eq!(leftovers, 1);
+    }
+
+    #[test]
+    fn warm_writes_missing_thumbnails_once() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        let sources: Vec<PathBuf> = ["a.png", "broken.png", "clip.mp4"].iter().map(|name| temp.path().join(name)).collect();
+        image::DynamicImage::new_rgb8(40, 20).save(&sources[0]).unwrap();
+        std::fs::write(&sources[1], b"not a png").unwrap();
+        std::fs::write(&sources[2], b"movie").unwrap();
+
+        let first = warm(temp.path(), &sources, 64);
+        assert_eq!(first, WarmSummary { generated: 1, cached: 0, failed: 1 });
+        assert!(thumbnail_path(temp.path(), "a.png", 64).exists());
+        let second = warm(temp.path(), &sources, 64);
+        assert_eq!(second, WarmSummary { generated: 0, cached: 1, failed: 1 });
+    }
+
+    #[test]
+    fn posters_are_cached_per_time_and_format_and_removed_with_the_video() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        let source = temp.path().join("clip.mp4");
+        std::fs::write(&source, b"movie").unwrap();
+        let frame = || ImageProcessor::encode(&image::DynamicImage::new_rgb8(16, 9), image::ImageFormat::Jpeg);
+
+        let webp = get_or_create_poster(temp.path(), &source, "clip.mp4", 256, 5000, ConvertFormat::Webp, frame).unwrap();
+        assert_eq!(webp.format, image::ImageFormat::WebP);
+        let cached = get_or_create_poster(temp.path(), &source, "clip.mp4", 256, 5000, ConvertFormat::Webp, || {
+            anyhow::bail!("should come from the cache")
+        })
+        .unwrap();
+        assert_eq!(cached.bytes, webp.bytes);
+        let jpeg = get_or_create_poster(temp.path(), &source, "clip.mp4", 256, 5000, ConvertFormat::Jpeg, frame).unwrap();
+        assert_eq!(jpeg.format, image::ImageFormat::Jpeg);
+
+        remove(temp.path(), "clip.mp4").unwrap();
+        assert!(!poster_path(temp.path(), "clip.mp4", 256, 5000, ConvertFormat::Webp).exists());
+        assert!(!poster_path(temp.path(), "clip.mp4", 256, 5000, ConvertFormat::Jpeg).exists());
+    }
+}
@@ -0,0 +1,113 @@
+use actix_web::http::header;
+use actix_web::{web, HttpRequest, HttpResponse};
+use anyhow::{bail, Context};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::ServerConfig;
+use std::io::BufReader;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Builds a rustls server config from PEM files: a certificate chain (leaf
+/// first) and its private key in PKCS#8, PKCS#1 or SEC1 form.
+pub fn server_config(cert_path: &Path, key_path: &Path) -> anyhow::Result<ServerConfig> {
+    let mut certs = BufReader::new(
+        std::fs::File::open(cert_path).with_context(|| format!("Failed to open certificate {:?}", cert_path))?,
+    );
+    let certs: Vec<CertificateDer<'static>> = rustls_pemfile::certs(&mut certs)
+        .collect::<Result<_, _>>()
+        .with_context(|| format!("Invalid certificate {:?}", cert_path))?;
+    if certs.is_empty() {
+        bail!("No certificates in {:?}", cert_path);
+    }
+
+    let mut key = BufReader::new(
+        std::fs::File::open(key_path).with_context(|| format!("Failed to open private key {:?}", key_path))?,
+    );
+    let key: PrivateKeyDer<'static> = rustls_pemfile::private_key(&mut key)
+        .with_context(|| format!("Invalid private key {:?}", key_path))?
+        .with_context(|| format!("No private key in {:?}", key_path))?;
+
+    let mut config = ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .context("Failed to set up TLS")?
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .context("Certificate and private key do not match")?;
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    Ok(config)
+}
+
+/// Port HTTP requests are redirected to.
+#[derive(Debug, Clone, Copy)]
+pub struct HttpsPort(pub u16);
+
+/// The HTTPS URL for a plain-HTTP request, on `https_port`.
+fn https_url(req: &HttpRequest, https_port: u16) -> String {
+    let info = req.connection_info();
+    let host = info.host();
+    // Strip the HTTP port, minding bracketed IPv6 hosts such as `[::1]:8081`
+    let host = match host.rsplit_once(':') {
+        Some((name, port)) if !port.contains(']') && port.chars().all(|c| c.is_ascii_digit()) => name,
+        _ => host,
+    };
+    let path = req.uri().path_and_query().map_or("/", |path| path.as_str());
+    match https_port {
+        443 => format!("https://{}{}", host, path),
+        port => format!("https://{}:{}{}", host, port, path),
+    }
+}
+
+/// Default service for plain-HTTP listeners when HTTPS is enforced: a 308 to
+/// the same URL over HTTPS.
+pub async fn redirect(req: HttpRequest, https_port: web::Data<HttpsPort>) -> HttpResponse {
+    HttpResponse::PermanentRedirect()
+        .insert_header((header::LOCATION, https_url(&req, https_port.0)))
+        .finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::{call_service, init_service, TestRequest};
+    use actix_web::App;
+    use assert_fs::prelude::*;
+
+    #[test]
+    fn loads_pem_certificate_and_key() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        temp.child("cert.pem").write_str(&cert.cert.pem()).unwrap();
+        temp.child("key.pem").write_str(&cert.key_pair.serialize_pem()).unwrap();
+        let (cert_path, key_path) = (temp.child("cert.pem"), temp.child("key.pem"));
+
+        let config = server_config(cert_path.path(), key_path.path()).unwrap();
+        assert_eq!(config.alpn_protocols[1], b"http/1.1");
+
+        let error = server_config(key_path.path(), key_path.path()).unwrap_err();
+        assert!(error.to_string().starts_with("No certificates"));
+        let error = server_config(cert_path.path(), cert_path.path()).unwrap_err();
+        assert!(error.to_string().starts_with("No private key"));
+    }
+
+    #[actix_rt::test]
+    async fn redirects_to_https() {
+        let app = init_service(
+            App::new()
+                .app_data(web::Data::new(HttpsPort(8443)))
+                .default_service(web::to(redirect)),
+        )
+        .await;
+
+        let req = TestRequest::get()
+            .uri("/images/a.jpg?w=100")
+            .insert_header(("Host", "photos.lan:8081"))
+            .to_request();
+        let resp = call_service(&app, req).await;
+        assert_eq!(resp.status(), 308);
+        assert_eq!(resp.headers().get("Location").unwrap(), "https://photos.lan:8443/images/a.jpg?w=100");
+
+        let req = TestRequest::get().uri("/").insert_header(("Host", "[::1]:8081")).to_request();
+        let resp = call_service(&app, req).await;
+        assert_eq!(resp.headers().get("Location").unwrap(), "https://[::1]:8443/");
+    }
+}
@@ -0,0 +1,299 @@
+use anyhow::{bail, Context};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::SystemTime;
+
+use crate::config::Config;
+use crate::processor::EncodedImage;
+use crate::video::{self, VideoCodec};
+
+/// Cache directory inside the images directory; hidden, so scans and the watcher skip it.
+pub const TRANSCODE_DIR: &str = ".transcodes";
+/// Subdirectory of the cache holding one HLS folder per video.
+const HLS_DIR: &str = "hls";
+/// Playlist file inside each HLS folder.
+pub const HLS_PLAYLIST: &str = "index.m3u8";
+/// Target length of HLS segments, in seconds.
+const HLS_SEGMENT_SECS: u32 = 6;
+
+/// H.264/AAC copies of videos some clients cannot play, and HLS segmentations
+/// of videos for slow links, made with ffmpeg on first request and kept on
+/// disk within a byte budget. Entries are evicted least recently used first,
+/// going by their mtime, which hits refresh.
+pub struct TranscodeCache {
+    dir: PathBuf,
+    max_bytes: u64,
+    ffmpeg: String,
+    partial_ids: AtomicU64,
+}
+
+impl TranscodeCache {
+    pub fn new(images_dir: &Path, config: &Config) -> Self {
+        TranscodeCache {
+            dir: images_dir.join(TRANSCODE_DIR),
+            max_bytes: config.transcode_cache_max_bytes,
+            ffmpeg: config.ffmpeg_path.clone(),
+            partial_ids: AtomicU64::new(0),
+        }
+    }
+
+    pub fn path_for(&self, filename: &str) -> PathBuf {
+        self.dir.join(format!("{}.mp4", filename))
+    }
+
+    pub fn hls_dir_for(&self, filename: &str) -> PathBuf {
+        self.dir.join(HLS_DIR).join(filename)
+    }
+
+    /// The H.264 copy of `source`, transcoding it when missing or older than the source.
+    /// Blocks for as long as ffmpeg runs.
+    pub fn get_or_create(&self, source: &Path, filename: &str) -> anyhow::Result<PathBuf> {
+        let cached = self.path_for(filename);
+        let source_modified = std::fs::metadata(source)
+            .and_then(|m| m.modified())
+            .context("Failed to read source metadata")?;
+        let cached_modified = std::fs::metadata(&cached).and_then(|m| m.modified()).ok();
+        if cached_modified.is_some_and(|modified| modified >= source_modified) {
+            touch(&cached);
+            return Ok(cached);
+        }
+
+        std::fs::create_dir_all(&self.dir).context("Failed to create transcode directory")?;
+        // Unique per attempt, so concurrent requests never write the same partial file
+        let id = self.partial_ids.fetch_add(1, Ordering::Relaxed);
+        let partial = self.dir.join(format!(".{}.{}.partial.mp4", filename, id));
+        let output = Command::new(&self.ffmpeg)
+            .args(["-nostdin", "-loglevel", "error", "-y", "-i"])
+            .arg(source)
+            .args(["-c:v", "libx264", "-preset", "veryfast", "-crf", "23", "-pix_fmt", "yuv420p"])
+            .args(["-c:a", "aac", "-movflags", "+faststart"])
+            .arg(&partial)
+            .output()
+            .with_context(|| format!("Failed to run {}", self.ffmpeg))?;
+        if !output.status.success() {
+            let _ = std::fs::remove_file(&partial);
+            bail!(
+                "ffmpeg exited with {}: {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        std::fs::rename(&partial, &cached).context("Failed to store transcoded video")?;
+
+        if let Err(e) = self.evict(&cached) {
+            log::warn!("Failed to trim transcode cache: {:#}", e);
+        }
+        Ok(cached)
+    }
+
+    /// The folder holding the HLS version of `source`: a VOD playlist named
+    /// [`HLS_PLAYLIST`] and MPEG-TS segments, which the playlist lists as
+    /// `segments/{name}`. H.264 video is remuxed as is; anything else is
+    /// transcoded. Segmented again when missing or older than the source.
+    /// Blocks for as long as ffmpeg runs.
+    pub fn hls(&self, source: &Path, filename: &str) -> anyhow::Result<PathBuf> {
+        let cached = self.hls_dir_for(filename);
+        let playlist = cached.join(HLS_PLAYLIST);
+        let source_modified = std::fs::metadata(source)
+            .and_then(|m| m.modified())
+            .context("Failed to read source metadata")?;
+        let cached_modified = std::fs::metadata(&playlist).and_then(|m| m.modified()).ok();
+        if cached_modified.is_some_and(|modified| modified >= source_modified) {
+            touch(&playlist);
+            return Ok(cached);
+        }
+
+        let parent = self.dir.join(HLS_DIR);
+        let id = self.partial_ids.fetch_add(1, Ordering::Relaxed);
+        let partial = parent.join(format!(".{}.{}.partial", filename, id));
+        std::fs::create_dir_all(&partial).context("Failed to create HLS directory")?;
+        let mut command = Command::new(&self.ffmpeg);
+        command.args(["-nostdin", "-loglevel", "error", "-y", "-i"]).arg(source);
+        match video::codec(source) {
+            Some(VideoCodec::H264) => command.args(["-c:v", "copy"]),
+            _ => command.args(["-c:v", "libx264", "-preset", "veryfast", "-crf", "23", "-pix_fmt", "yuv420p"]),
+        };
+        let output = command
+            .args(["-c:a", "aac", "-f", "hls", "-hls_time", &HLS_SEGMENT_SECS.to_string(), "-hls_playlist_type", "vod"])
+            .args(["-hls_base_url", "segments/", "-hls_segment_filename"])
+            .arg(partial.join("seg%05d.ts"))
+            .arg(partial.join(HLS_PLAYLIST))
+            .output()
+            .with_context(|| format!("Failed to run {}", self.ffmpeg))?;
+        if !output.status.success() {
+            let _ = std::fs::remove_dir_all(&partial);
+            bail!(
+                "ffmpeg exited with {}: {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+
+        // Drop the stale version; a concurrent request may also have just
+        // finished, in which case its copy is as good as this one
+        if cached_modified.is_some() {
+            let _ = std::fs::remove_dir_all(&cached);
+        }
+        if let Err(e) = std::fs::rename(&partial, &cached) {
+            let _ = std::fs::remove_dir_all(&partial);
+            if !playlist.is_file() {
+                return Err(e).context("Failed to store HLS segments");
+            }
+        }
+
+        if let Err(e) = self.evict(&cached) {
+            log::warn!("Failed to trim transcode cache: {:#}", e);
+        }
+        Ok(cached)
+    }
+
+    /// One JPEG frame of `source` at `at_secs`, scaled to fit a `size`×`size`
+    /// box. Seeks before decoding, so times that fall on a keyframe are cheap.
+    /// Blocks for as long as ffmpeg runs.
+    pub fn frame(&self, source: &Path, at_secs: f64, size: u32) -> anyhow::Result<EncodedImage> {
+        let scale = format!("scale=w={0}:h={0}:force_original_aspect_ratio=decrease", size);
+        let output = Command::new(&self.ffmpeg)
+            .args(["-nostdin", "-loglevel", "error", "-ss", &format!("{:.3}", at_secs), "-i"])
+            .arg(source)
+            .args(["-frames:v", "1", "-vf", &scale, "-f", "image2pipe", "-c:v", "mjpeg", "-q:v", "4", "pipe:1"])
+            .output()
+            .with_context(|| format!("Failed to run {}", self.ffmpeg))?;
+        if !output.status.success() || output.stdout.is_empty() {
+            bail!(
+                "ffmpeg exited with {}: {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        Ok(EncodedImage {
+            bytes: output.stdout.into(),
+            format: image::ImageFormat::Jpeg,
+        })
+    }
+
+    /// Deletes the least recently used copies and HLS folders until the cache
+    /// fits its budget, never removing `keep`.
+    fn evict(&self, keep: &Path) -> anyhow::Result<()> {
+        let mut entries: Vec<(SystemTime, u64, PathBuf)> = std::fs::read_dir(&self.dir)?
+            .flatten()
+            // Hidden entries are partial files still being written
+            .filter(|entry| !entry.file_name().to_string_lossy().starts_with('.'))
+            .filter_map(|entry| {
+                let metadata = entry.metadata().ok().filter(|metadata| metadata.is_file())?;
+                Some((metadata.modified().ok()?, metadata.len(), entry.path()))
+            })
+            .collect();
+        if let Ok(folders) = std::fs::read_dir(self.dir.join(HLS_DIR)) {
+            for folder in folders.flatten() {
+                if folder.file_name().to_string_lossy().starts_with('.') {
+                    continue;
+                }
+                let path = folder.path();
+                let Ok(used) = std::fs::metadata(path.join(HLS_PLAYLIST)).and_then(|m| m.modified()) else {
+                    continue;
+                };
+                let len = std::fs::read_dir(&path)?
+                    .flatten()
+                    .filter_map(|file| file.metadata().ok())
+                    .map(|metadata| metadata.len())
+                    .sum();
+                entries.push((used, len, path));
+            }
+        }
+        let mut total: u64 = entries.iter().map(|(_, len, _)| len).sum();
+        entries.sort();
+
+        for (_, len, path) in entries {
+            if total <= self.max_bytes {
+                break;
+            }
+            if path == keep {
+                continue;
+            }
+            if path.is_dir() {
+                std::fs::remove_dir_all(&path)
+            } else {
+                std::fs::remove_file(&path)
+            }
+            .with_context(|| format!("Failed to evict {:?}", path))?;
+            total -= len;
+        }
+        Ok(())
+    }
+}
+
+fn touch(path: &Path) {
+    let touched = std::fs::File::options()
+        .write(true)
+        .open(path)
+        .and_then(|file| file.set_modified(SystemTime::now()));
+    if let Err(e) = touched {
+        log::debug!("Could not refresh {:?}: {}", path, e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_fs::prelude::*;
+    use std::time::Duration;
+
+    #[test]
+    fn evicts_least_recently_used_copies_over_budget() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        let cache = TranscodeCache::new(
+            temp.path(),
+            &Config {
+                transcode_cache_max_bytes: 12,
+                ..Config::default()
+            },
+        );
+        let base = SystemTime::now() - Duration::from_secs(60);
+        for (name, age) in [("old.mov", 30), ("recent.mov", 10), ("new.mov", 0)] {
+            let child = temp.child(format!("{}/{}.mp4", TRANSCODE_DIR, name));
+            child.write_binary(b"123456").unwrap();
+            std::fs::File::options()
+                .write(true)
+                .open(child.path())
+                .unwrap()
+                .set_modified(base - Duration::from_secs(age))
+                .unwrap();
+        }
+
+        cache.evict(&cache.path_for("old.mov")).unwrap();
+        assert!(cache.path_for("old.mov").exists());
+        assert!(!cache.path_for("recent.mov").exists());
+        assert!(cache.path_for("new.mov").exists());
+
+        // HLS folders count with all their segments, aged by their playlist
+        let hls = cache.hls_dir_for("long.mov");
+        temp.child(hls.join("seg00000.ts")).write_binary(b"1234").unwrap();
+        temp.child(hls.join(HLS_PLAYLIST)).write_binary(b"#EXTM3U").unwrap();
+        std::fs::File::options()
+            .write(true)
+            .open(hls.join(HLS_PLAYLIST))
+            .unwrap()
+            .set_modified(base - Duration::from_secs(60))
+            .unwrap();
+        cache.evict(&cache.path_for("new.mov")).unwrap();
+        assert!(!hls.exists());
+        assert!(cache.path_for("old.mov").exists() && cache.path_for("new.mov").exists());
+    }
+
+    #[test]
+    fn reports_missing_ffmpeg() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        temp.child("clip.mov").write_binary(b"not really a movie").unwrap();
+        let cache = TranscodeCache::new(
+            temp.path(),
+            &Config {
+                ffmpeg_path: temp.path().join("no-such-ffmpeg").to_string_lossy().into_owned(),
+                ..Config::default()
+            },
+        );
+
+        let error = cache.get_or_create(&temp.path().join("clip.mov"), "clip.mov").unwrap_err();
+        assert!(error.to_string().starts_with("Failed to run"));
+    }
+}
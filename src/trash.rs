@@ -0,0 +1,223 @@
+use chrono::{DateTime, NaiveDateTime, Utc};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+/// Where deleted files go when `trash_dir` is not set, inside the images
+/// directory. Scans and the folder watcher skip it like any other folder.
+pub const DEFAULT_TRASH_DIR: &str = ".Trash";
+
+// Prefixed to trashed names, so deleting a name twice keeps both copies
+const TIMESTAMP_FORMAT: &str = "%Y%m%dT%H%M%S%.6fZ";
+
+/// A file in the trash.
+#[derive(Debug, Clone, Serialize)]
+pub struct Trashed {
+    /// The name it had in the library, and gets back on restore.
+    pub filename: String,
+    /// Its name in the trash, unique per deletion.
+    pub trashed_name: String,
+    pub trashed_at: DateTime<Utc>,
+    pub size_bytes: u64,
+    #[serde(skip)]
+    pub path: PathBuf,
+}
+
+/// The trash folder for `images_dir`.
+pub fn dir(images_dir: &Path, configured: Option<&Path>) -> PathBuf {
+    configured
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|| images_dir.join(DEFAULT_TRASH_DIR))
+}
+
+pub fn trashed_name(filename: &str, at: DateTime<Utc>) -> String {
+    format!("{}_{}", at.format(TIMESTAMP_FORMAT), filename)
+}
+
+/// The deletion time and original name encoded in a trashed name.
+pub fn parse(trashed_name: &str) -> Option<(DateTime<Utc>, &str)> {
+    let (timestamp, filename) = trashed_name.split_once('_')?;
+    let at = NaiveDateTime::parse_from_str(timestamp, TIMESTAMP_FORMAT).ok()?;
+    (!filename.is_empty()).then(|| (at.and_utc(), filename))
+}
+
+/// Moves `from` to `to`, copying and then removing when they are on
+/// different filesystems. Either way the file keeps its contents,
+/// modification time and extended attributes (tags, fields, published flag).
+/// Fails with `AlreadyExists` rather than replace a file already at `to`,
+/// even one that appeared a moment ago.
+pub fn move_file(from: &Path, to: &Path) -> std::io::Result<()> {
+    // Unlike a rename, a link never replaces its target
+    match std::fs::hard_link(from, to) {
+        Ok(()) => {
+            if let Err(e) = std::fs::remove_file(from) {
+                let _ = std::fs::remove_file(to);
+                return Err(e);
+            }
+            Ok(())
+        }
+        Err(e) if matches!(e.kind(), std::io::ErrorKind::CrossesDevices | std::io::ErrorKind::Unsupported) => {
+            copy_across(from, to)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+fn copy_across(from: &Path, to: &Path) -> std::io::Result<()> {
+    std::fs::File::options().write(true).create_new(true).open(to)?;
+    std::fs::copy(from, to)?;
+    let copied = copy_xattrs(from, to).and_then(|()| {
+        match from.metadata().and_then(|metadata| metadata.modified()) {
+            Ok(modified) => std::fs::File::options().write(true).open(to)?.set_modified(modified),
+            Err(_) => Ok(()),
+        }
+    });
+    // Half a copy is worse than none: the source stays where it was
+    if let Err(e) = copied {
+        let _ = std::fs::remove_file(to);
+        return Err(e);
+    }
+    std::fs::remove_file(from)
+}
+
+/// Copies every extended attribute of `from` onto `to`. A filesystem without
+/// extended attributes on either side has none to carry, so that is not an
+/// error unless `from` actually has some.
+pub fn copy_xattrs(from: &Path, to: &Path) -> std::io::Result<()> {
+    let names = match xattr::list(from) {
+        Ok(names) => names,
+        Err(e) if e.kind() == std::io::ErrorKind::Unsupported => return Ok(()),
+        Err(e) => return Err(e),
+    };
+    for name in names {
+        if let Some(value) = xattr::get(from, &name)? {
+            xattr::set(to, &name, &value)?;
+        }
+    }
+    Ok(())
+}
+
+/// Moves `path` into `trash_dir`, creating it as needed, under a name no
+/// earlier deletion has used, and returns where it went.
+pub fn put(trash_dir: &Path, path: &Path) -> std::io::Result<PathBuf> {
+    std::fs::create_dir_all(trash_dir)?;
+    let Some(filename) = path.file_name().and_then(|name| name.to_str()) else {
+        return Err(std::io::Error::from(std::io::ErrorKind::InvalidInput));
+    };
+    let mut trashed = trash_dir.join(trashed_name(filename, Utc::now()));
+    while trashed.symlink_metadata().is_ok() {
+        trashed = trash_dir.join(trashed_name(filename, Utc::now()));
+    }
+    move_file(path, &trashed)?;
+    Ok(trashed)
+}
+
+/// Everything in the trash, most recently deleted first. Files not put there
+/// by [`put`] are left out.
+pub fn list(trash_dir: &Path) -> std::io::Result<Vec<Trashed>> {
+    let entries = match std::fs::read_dir(trash_dir) {
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        entries => entries?,
+    };
+    let mut trashed = Vec::new();
+    for entry in entries {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let Some((trashed_at, filename)) = parse(&name).filter(|_| metadata.is_file()) else {
+            continue;
+        };
+        trashed.push(Trashed {
+            filename: filename.to_string(),
+            trashed_name: name.clone(),
+            trashed_at,
+            size_bytes: metadata.len(),
+            path: entry.path(),
+        });
+    }
+    trashed.sort_by(|a, b| b.trashed_at.cmp(&a.trashed_at).then_with(|| a.trashed_name.cmp(&b.trashed_name)));
+    Ok(trashed)
+}
+
+/// The trashed file called `name` in the trash, or else the latest deletion
+/// of a file originally called `name`, ignoring case.
+pub fn find(trash_dir: &Path, name: &str) -> std::io::Result<Option<Trashed>> {
+    let trashed = list(trash_dir)?;
+    if let Some(exact) = trashed.iter().find(|item| item.trashed_name == name) {
+        return Ok(Some(exact.clone()));
+    }
+    Ok(trashed.into_iter().find(|item| item.filename.eq_ignore_ascii_case(name)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_fs::prelude::*;
+
+    #[test]
+    fn keeps_every_deletion_and_finds_them_by_either_name() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        let trash = dir(temp.path(), None);
+        assert_eq!(trash, temp.path().join(".Trash"));
+        assert_eq!(dir(temp.path(), Some(Path::new("/srv/trash"))), PathBuf::from("/srv/trash"));
+
+        temp.child("a_b.jpg").write_binary(b"first").unwrap();
+        let first = put(&trash, temp.child("a_b.jpg").path()).unwrap();
+        temp.child("a_b.jpg").write_binary(b"second").unwrap();
+        put(&trash, temp.child("a_b.jpg").path()).unwrap();
+        assert!(!temp.child("a_b.jpg").path().exists());
+        temp.child(".Trash/stray.jpg").write_binary(b"stray").unwrap();
+
+        let listed = list(&trash).unwrap();
+        assert_eq!(listed.len(), 2);
+        assert!(listed.iter().all(|item| item.filename == "a_b.jpg"));
+        assert_eq!(std::fs::read(&listed[0].path).unwrap(), b"second");
+
+        let latest = find(&trash, "A_B.JPG").unwrap().unwrap();
+        assert_eq!(latest.trashed_name, listed[0].trashed_name);
+        let older = find(&trash, &crate::paths::file_name(&first)).unwrap().unwrap();
+        assert_eq!(std::fs::read(&older.path).unwrap(), b"first");
+        assert!(find(&trash, "stray.jpg").unwrap().is_none());
+
+        temp.child("a_b.jpg").write_binary(b"third").unwrap();
+        let taken = move_file(&older.path, temp.child("a_b.jpg").path()).unwrap_err();
+        assert_eq!(taken.kind(), std::io::ErrorKind::AlreadyExists);
+        assert_eq!(std::fs::read(temp.child("a_b.jpg").path()).unwrap(), b"third");
+        assert!(older.path.exists());
+
+        let at = parse("20240601T120000.000000Z_x.jpg").unwrap();
+        assert_eq!((at.0.to_rfc3339(), at.1), ("2024-06-01T12:00:00+00:00".to_string(), "x.jpg"));
+        assert!(parse("x.jpg").is_none());
+    }
+
+    #[test]
+    fn copies_across_filesystems_with_extended_attributes() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        temp.child("a.jpg").write_binary(b"image").unwrap();
+        let source = temp.child("a.jpg").path().to_path_buf();
+        if xattr::set(&source, crate::publish::PUBLISHED_XATTR, b"1").is_err() {
+            eprintln!("skipping: filesystem does not support extended attributes");
+            return;
+        }
+        let modified = std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_700_000_000);
+        std::fs::File::options().write(true).open(&source).unwrap().set_modified(modified).unwrap();
+
+        // A tmpfs with extended attributes makes move_file cross devices for
+        // real; without one, the copy it falls back to is tested directly
+        let shm = assert_fs::TempDir::new_in("/dev/shm")
+            .ok()
+            .filter(|dir| xattr::set(dir.path(), "user.probe", b"1").is_ok());
+        let cross_device = shm.is_some();
+        let other = shm.unwrap_or_else(|| assert_fs::TempDir::new().unwrap());
+        let target = other.path().join("b.jpg");
+        if cross_device {
+            move_file(&source, &target).unwrap();
+        } else {
+            copy_across(&source, &target).unwrap();
+        }
+
+        assert!(!source.exists());
+        assert_eq!(std::fs::read(&target).unwrap(), b"image");
+        assert_eq!(target.metadata().unwrap().modified().unwrap(), modified);
+        assert!(crate::publish::is_published(&target));
+    }
+}
@@ -0,0 +1,276 @@
+use actix_web::body::{BodySize, BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::web::Bytes;
+use actix_web::middleware::Next;
+use actix_web::{web, Error};
+use chrono::{DateTime, TimeZone, Utc};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::pin::Pin;
+use std::sync::Mutex;
+use std::task::{Context, Poll};
+use std::time::Instant;
+
+/// Header identifying the calling client or integration.
+pub const API_KEY_HEADER: &str = "X-API-Key";
+/// Requests without an API key are counted under this name.
+pub const ANONYMOUS: &str = "anonymous";
+/// Hours of usage kept, and so the longest reportable period.
+pub const RETENTION_HOURS: i64 = 7 * 24;
+/// Distinct keys counted per hour; keys first seen after that are counted as
+/// [`ANONYMOUS`], so clients inventing keys cannot grow the tracker without bound.
+pub const MAX_KEYS_PER_HOUR: usize = 1000;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct Usage {
+    pub requests: u64,
+    pub bytes_served: u64,
+    /// Wall time spent answering requests that resize, convert or thumbnail.
+    pub transform_secs: f64,
+    /// Requests answered with a 5xx status.
+    pub errors: u64,
+}
+
+impl Usage {
+    fn add(&mut self, other: &Usage) {
+        self.requests += other.requests;
+        self.bytes_served += other.bytes_served;
+        self.transform_secs += other.transform_secs;
+        self.errors += other.errors;
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct KeyUsage {
+    /// The API key, shortened so the report does not leak usable keys.
+    pub key: String,
+    #[serde(flatten)]
+    pub usage: Usage,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct UsageReport {
+    pub since: DateTime<Utc>,
+    /// Busiest keys first.
+    pub keys: Vec<KeyUsage>,
+}
+
+/// Per-API-key counters in hourly buckets, reset when the process restarts.
+#[derive(Default)]
+pub struct UsageTracker {
+    hours: Mutex<BTreeMap<i64, HashMap<String, Usage>>>,
+}
+
+fn hour_of(time: DateTime<Utc>) -> i64 {
+    time.timestamp().div_euclid(3600)
+}
+
+impl UsageTracker {
+    pub fn record(&self, key: &str, usage: Usage, at: DateTime<Utc>) {
+        let hour = hour_of(at);
+        let mut hours = self.hours.lock().unwrap();
+        let keys = hours.entry(hour).or_default();
+        let key = if keys.len() < MAX_KEYS_PER_HOUR || keys.contains_key(key) { key } else { ANONYMOUS };
+        keys.entry(key.to_string()).or_default().add(&usage);
+        let newest = hours.keys().next_back().copied().unwrap_or(hour);
+        hours.retain(|bucket, _| *bucket > newest - RETENTION_HOURS);
+    }
+
+    /// Usage over the last `period_hours` whole hours, including the current one.
+    pub fn report(&self, period_hours: i64, now: DateTime<Utc>) -> UsageReport {
+        let first = hour_of(now) - period_hours.clamp(1, RETENTION_HOURS) + 1;
+        let mut totals: HashMap<String, Usage> = HashMap::new();
+        for keys in self.hours.lock().unwrap().range(first..).map(|(_, keys)| keys) {
+            for (key, usage) in keys {
+                totals.entry(key.clone()).or_default().add(usage);
+            }
+        }
+
+        let mut keys: Vec<KeyUsage> = totals
+            .into_iter()
+            .map(|(key, usage)| KeyUsage { key: redact(&key), usage })
+            .collect();
+        keys.sort_by(|a, b| b.usage.requests.cmp(&a.usage.requests).then_with(|| a.key.cmp(&b.key)));
+        UsageReport {
+            since: Utc.timestamp_opt(first * 3600, 0).single().unwrap_or(now),
+            keys,
+        }
+    }
+
+    /// Usage of all keys together over the last `period_hours` whole hours.
+    pub fn total(&self, period_hours: i64, now: DateTime<Utc>) -> Usage {
+        let first = hour_of(now) - period_hours.clamp(1, RETENTION_HOURS) + 1;
+        let mut total = Usage::default();
+        for keys in self.hours.lock().unwrap().range(first..).map(|(_, keys)| keys) {
+            keys.values().for_each(|usage| total.add(usage));
+        }
+        total
+    }
+}
+
+/// Keeps enough of a key to tell clients apart without revealing it.
+fn redact(key: &str) -> String {
+    if key == ANONYMOUS || key.chars().count() <= 8 {
+        return key.to_string();
+    }
+    format!("{}…", key.chars().take(6).collect::<String>())
+}
+
+/// Parses a period such as `6h` or `7d` into hours.
+pub fn parse_period(period: &str) -> Option<i64> {
+    let period = period.trim();
+    let count = |digits: &str| digits.parse::<i64>().ok().filter(|count| *count > 0);
+    let hours = match (period.strip_suffix('h'), period.strip_suffix('d')) {
+        (Some(hours), _) => count(hours)?,
+        (_, Some(days)) => count(days)?.checked_mul(24)?,
+        _ => return None,
+    };
+    (hours <= RETENTION_HOURS).then_some(hours)
+}
+
+/// The request's `X-API-Key`, or [`ANONYMOUS`].
+pub fn api_key(req: &ServiceRequest) -> String {
+    req.headers()
+        .get(API_KEY_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(str::trim)
+        .filter(|key| !key.is_empty())
+        .unwrap_or(ANONYMOUS)
+        .to_string()
+}
+
+/// Whether the request resizes, converts or renders a variant.
+pub fn is_transform(req: &ServiceRequest) -> bool {
+    let path = req.path();
+    let query = req.query_string();
+    path.ends_with("/thumbnail")
+        || path.ends_with("/convert")
+        || path.ends_with("/frame")
+        || path.ends_with("/sprite.jpg")
+        || path == "/transform"
+        || (path.starts_with("/images/") && query
+            .split('&')
+            .any(|pair| pair.starts_with("w=") || pair.starts_with("h=") || pair.starts_with("preset=")))
+}
+
+/// Counts every request against its `X-API-Key`.
+///
+/// Bytes are counted as the body is sent rather than from its declared size,
+/// so streamed files, ranges and downloads cut short all count what actually
+/// left the server.
+pub async fn track(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let Some(tracker) = req.app_data::<web::Data<UsageTracker>>().cloned() else {
+        return next.call(req).await.map(ServiceResponse::map_into_boxed_body);
+    };
+    let key = api_key(&req);
+    let transform = is_transform(&req);
+    let started = Instant::now();
+
+    let response = next.call(req).await?.map_into_boxed_body();
+    tracker.record(
+        &key,
+        Usage {
+            requests: 1,
+            bytes_served: 0,
+            transform_secs: if transform { started.elapsed().as_secs_f64() } else { 0.0 },
+            errors: response.status().is_server_error().into(),
+        },
+        Utc::now(),
+    );
+    Ok(response.map_body(|_, body| BoxBody::new(CountedBody { body, tracker, key, sent: 0 })))
+}
+
+/// A response body that records the bytes it sent once it is finished or dropped.
+struct CountedBody {
+    body: BoxBody,
+    tracker: web::Data<UsageTracker>,
+    key: String,
+    sent: u64,
+}
+
+impl MessageBody for CountedBody {
+    type Error = Box<dyn std::error::Error>;
+
+    fn size(&self) -> BodySize {
+        self.body.size()
+    }
+
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Bytes, Self::Error>>> {
+        let poll = Pin::new(&mut self.body).poll_next(cx);
+        if let Poll::Ready(Some(Ok(chunk))) = &poll {
+            self.sent += chunk.len() as u64;
+        }
+        poll
+    }
+}
+
+impl Drop for CountedBody {
+    fn drop(&mut self) {
+        if self.sent > 0 {
+            let usage = Usage { bytes_served: self.sent, ..Usage::default() };
+            self.tracker.record(&self.key, usage, Utc::now());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    #[test]
+    fn reports_usage_within_the_period() {
+        let tracker = UsageTracker::default();
+        let now = Utc::now();
+        let request = |bytes| Usage { requests: 1, bytes_served: bytes, transform_secs: 0.5, errors: 0 };
+        tracker.record("batch-job-key-123", request(100), now);
+        tracker.record("batch-job-key-123", request(50), now - Duration::hours(3));
+        tracker.record(ANONYMOUS, request(10), now);
+        tracker.record("old", request(1), now - Duration::hours(RETENTION_HOURS + 1));
+
+        let report = tracker.report(1, now);
+        let keys: Vec<_> = report.keys.iter().map(|k| (k.key.as_str(), k.usage.bytes_served)).collect();
+        assert_eq!(keys, vec![("anonymous", 10), ("batch-…", 100)]);
+
+        let report = tracker.report(24, now);
+        assert_eq!(report.keys[0].key, "batch-…");
+        assert_eq!(report.keys[0].usage, Usage { requests: 2, bytes_served: 150, transform_secs: 1.0, errors: 0 });
+        assert!(report.keys.iter().all(|k| k.key != "old"));
+
+        tracker.record(ANONYMOUS, Usage { requests: 1, errors: 1, ..Usage::default() }, now);
+        let total = tracker.total(24, now);
+        assert_eq!((total.requests, total.bytes_served, total.errors), (4, 160, 1));
+    }
+
+    #[test]
+    fn folds_keys_past_the_hourly_cap_into_anonymous() {
+        let tracker = UsageTracker::default();
+        let now = Utc::now();
+        for i in 0..MAX_KEYS_PER_HOUR + 5 {
+            tracker.record(&format!("key-{}", i), Usage { requests: 1, ..Usage::default() }, now);
+        }
+        tracker.record("key-0", Usage { requests: 1, ..Usage::default() }, now);
+
+        let report = tracker.report(1, now);
+        assert_eq!(report.keys.len(), MAX_KEYS_PER_HOUR + 1);
+        assert_eq!(report.keys[0].usage.requests, 5);
+        assert_eq!(report.keys[0].key, ANONYMOUS);
+    }
+
+    #[test]
+    fn parses_periods() {
+        assert_eq!(parse_period("6h"), Some(6));
+        assert_eq!(parse_period("7d"), Some(168));
+        assert_eq!(parse_period("8d"), None);
+        assert_eq!(parse_period("0h"), None);
+        assert_eq!(parse_period("h"), None);
+        assert_eq!(parse_period("3w"), None);
+        assert_eq!(parse_period("7é"), None);
+    }
+}
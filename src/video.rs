@@ -0,0 +1,537 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+// Caps the sibling boxes walked at one level, so malformed input stays cheap
+const MAX_BOXES: usize = 4096;
+
+/// One ISO base media file format (MP4/QuickTime) box header.
+struct BoxHeader {
+    kind: [u8; 4],
+    /// Offset of the box payload.
+    start: u64,
+    /// Offset just past the box.
+    end: u64,
+}
+
+fn read_box(file: &mut File, offset: u64, limit: u64) -> std::io::Result<Option<BoxHeader>> {
+    if offset + 8 > limit {
+        return Ok(None);
+    }
+    file.seek(SeekFrom::Start(offset))?;
+    let mut header = [0u8; 8];
+    file.read_exact(&mut header)?;
+    let size = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as u64;
+    let kind = [header[4], header[5], header[6], header[7]];
+
+    let (start, end) = match size {
+        // Extends to the end of the enclosing box
+        0 => (offset + 8, limit),
+        // 64-bit size follows the type
+        1 => {
+            let mut large = [0u8; 8];
+            file.read_exact(&mut large)?;
+            (offset + 16, offset.saturating_add(u64::from_be_bytes(large)))
+        }
+        size => (offset + 8, offset.saturating_add(size)),
+    };
+    if end < start || end > limit {
+        return Ok(None);
+    }
+    Ok(Some(BoxHeader { kind, start, end }))
+}
+
+/// Finds the first child box of `kind` between `start` and `end`.
+fn find_box(file: &mut File, kind: &[u8; 4], start: u64, end: u64) -> std::io::Result<Option<BoxHeader>> {
+    let mut offset = start;
+    for _ in 0..MAX_BOXES {
+        let Some(header) = read_box(file, offset, end)? else {
+            return Ok(None);
+        };
+        if &header.kind == kind {
+            return Ok(Some(header));
+        }
+        offset = header.end;
+    }
+    Ok(None)
+}
+
+/// Every child box between `start` and `end`.
+fn children(file: &mut File, start: u64, end: u64) -> std::io::Result<Vec<BoxHeader>> {
+    let mut boxes = Vec::new();
+    let mut offset = start;
+    while boxes.len() < MAX_BOXES {
+        let Some(header) = read_box(file, offset, end)? else {
+            break;
+        };
+        offset = header.end;
+        boxes.push(header);
+    }
+    Ok(boxes)
+}
+
+/// Compression format of a video track, from its sample description.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VideoCodec {
+    H264,
+    Hevc,
+    ProRes,
+    Av1,
+    Vp9,
+    Mpeg4,
+    Other,
+}
+
+impl VideoCodec {
+    fn from_fourcc(fourcc: &[u8; 4]) -> Self {
+        match fourcc {
+            b"avc1" | b"avc3" => VideoCodec::H264,
+            b"hvc1" | b"hev1" => VideoCodec::Hevc,
+            b"apcn" | b"apch" | b"apcs" | b"apco" | b"ap4h" | b"ap4x" => VideoCodec::ProRes,
+            b"av01" => VideoCodec::Av1,
+            b"vp09" => VideoCodec::Vp9,
+            b"mp4v" => VideoCodec::Mpeg4,
+            _ => VideoCodec::Other,
+        }
+    }
+
+    /// Parses a client-supplied codec name such as `hevc` or `prores`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "h264" | "avc" => Some(VideoCodec::H264),
+            "hevc" | "h265" => Some(VideoCodec::Hevc),
+            "prores" => Some(VideoCodec::ProRes),
+            "av1" => Some(VideoCodec::Av1),
+            "vp9" => Some(VideoCodec::Vp9),
+            "mpeg4" => Some(VideoCodec::Mpeg4),
+            _ => None,
+        }
+    }
+}
+
+/// The `mdia` box of the first video track (`moov/trak` with a `vide` handler).
+fn video_media(file: &mut File) -> Option<BoxHeader> {
+    let len = file.metadata().ok()?.len();
+    let moov = find_box(file, b"moov", 0, len).ok()??;
+
+    for trak in children(file, moov.start, moov.end).ok()? {
+        if &trak.kind != b"trak" {
+            continue;
+        }
+        let Some(mdia) = find_box(file, b"mdia", trak.start, trak.end).ok()? else {
+            continue;
+        };
+        // hdlr payload: version/flags, pre_defined, then the handler type
+        let Some(hdlr) = find_box(file, b"hdlr", mdia.start, mdia.end).ok()? else {
+            continue;
+        };
+        let mut handler = [0u8; 12];
+        file.seek(SeekFrom::Start(hdlr.start)).ok()?;
+        file.read_exact(&mut handler).ok()?;
+        if &handler[8..12] == b"vide" {
+            return Some(mdia);
+        }
+    }
+    None
+}
+
+/// The sample table (`minf/stbl`) under a track's `mdia` box.
+fn sample_table(file: &mut File, mdia: &BoxHeader) -> Option<BoxHeader> {
+    let minf = find_box(file, b"minf", mdia.start, mdia.end).ok()??;
+    find_box(file, b"stbl", minf.start, minf.end).ok()?
+}
+
+/// Codec and, when the entry gives them, coded width and height of the first
+/// sample description of the first video track.
+fn sample_description(file: &mut File) -> Option<(VideoCodec, Option<(u32, u32)>)> {
+    let mdia = video_media(file)?;
+    let stbl = sample_table(file, &mdia)?;
+    let stsd = find_box(file, b"stsd", stbl.start, stbl.end).ok()??;
+
+    // stsd payload: version/flags, entry count, then the first entry's box
+    // header. A visual entry continues with 6 reserved bytes, a data reference
+    // index and 16 bytes of predefined and reserved fields before the 16-bit
+    // width and height.
+    let mut entry = [0u8; 44];
+    file.seek(SeekFrom::Start(stsd.start)).ok()?;
+    file.read_exact(&mut entry[..16]).ok()?;
+    let codec = VideoCodec::from_fourcc(&[entry[12], entry[13], entry[14], entry[15]]);
+    let dimensions = (stsd.start + entry.len() as u64 <= stsd.end)
+        .then(|| file.read_exact(&mut entry[16..]).ok())
+        .flatten()
+        .map(|()| {
+            let width = u16::from_be_bytes([entry[40], entry[41]]) as u32;
+            let height = u16::from_be_bytes([entry[42], entry[43]]) as u32;
+            (width, height)
+        })
+        .filter(|&(width, height)| width > 0 && height > 0);
+    Some((codec, dimensions))
+}
+
+/// Codec of the first video track of an MP4 or QuickTime file.
+pub fn codec(path: &Path) -> Option<VideoCodec> {
+    let mut file = File::open(path).ok()?;
+    sample_description(&mut file).map(|(codec, _)| codec)
+}
+
+/// Reads a full box's entry table: version/flags, a 32-bit entry count, then
+/// `count` entries of `width` 32-bit fields each.
+fn read_table(file: &mut File, header: &BoxHeader, width: usize) -> Option<Vec<u32>> {
+    let mut prefix = [0u8; 8];
+    file.seek(SeekFrom::Start(header.start)).ok()?;
+    file.read_exact(&mut prefix).ok()?;
+    let count = u32::from_be_bytes(prefix[4..8].try_into().ok()?) as u64;
+    let bytes = count.checked_mul(4 * width as u64)?;
+    if header.start + 8 + bytes > header.end {
+        return None;
+    }
+    let mut table = vec![0u8; bytes as usize];
+    file.read_exact(&mut table).ok()?;
+    Some(
+        table
+            .chunks_exact(4)
+            .map(|field| u32::from_be_bytes([field[0], field[1], field[2], field[3]]))
+            .collect(),
+    )
+}
+
+/// Decode timestamps, in seconds, of the sync samples (keyframes) of the first
+/// video track of an MP4 or QuickTime file, in order. A track without a sync
+/// sample table (`stss`) is all keyframes, as with intra-only codecs.
+pub fn keyframes(path: &Path) -> Option<Vec<f64>> {
+    let mut file = File::open(path).ok()?;
+    let mdia = video_media(&mut file)?;
+
+    // mdhd payload: version/flags, creation and modification times (64-bit in
+    // version 1), then the timescale
+    let mdhd = find_box(&mut file, b"mdhd", mdia.start, mdia.end).ok()??;
+    let mut fields = [0u8; 24];
+    file.seek(SeekFrom::Start(mdhd.start)).ok()?;
+    file.read_exact(&mut fields).ok()?;
+    let timescale_at = if fields[0] == 1 { 20 } else { 12 };
+    let timescale = u32::from_be_bytes(fields[timescale_at..timescale_at + 4].try_into().ok()?);
+    if timescale == 0 {
+        return None;
+    }
+
+    let stbl = sample_table(&mut file, &mdia)?;
+    let stts = find_box(&mut file, b"stts", stbl.start, stbl.end).ok()??;
+    // (sample count, sample duration) runs
+    let durations = read_table(&mut file, &stts, 2)?;
+    let sync = match find_box(&mut file, b"stss", stbl.start, stbl.end).ok()? {
+        Some(stss) => Some(read_table(&mut file, &stss, 1)?),
+        None => None,
+    };
+
+    let mut times = Vec::new();
+    let (mut sample, mut time) = (1u64, 0u64);
+    let mut sync_samples = sync.iter().flatten().map(|&n| n as u64).peekable();
+    for run in durations.chunks_exact(2) {
+        let (count, delta) = (run[0] as u64, run[1] as u64);
+        let end = sample + count;
+        match &sync {
+            Some(_) => {
+                while let Some(&n) = sync_samples.peek().filter(|&&n| n < end) {
+                    if n >= sample {
+                        times.push((time + (n - sample) * delta) as f64 / timescale as f64);
+                    }
+                    sync_samples.next();
+                }
+            }
+            None => times.extend((0..count).map(|i| (time + i * delta) as f64 / timescale as f64)),
+        }
+        time += count * delta;
+        sample = end;
+    }
+    Some(times)
+}
+
+/// Duration in seconds from the movie header (`moov/mvhd`) of an MP4 or
+/// QuickTime file, or `None` for other containers and malformed files.
+pub fn duration_secs(path: &Path) -> Option<f64> {
+    let mut file = File::open(path).ok()?;
+    let len = file.metadata().ok()?.len();
+
+    let moov = find_box(&mut file, b"moov", 0, len).ok()??;
+    let mvhd = find_box(&mut file, b"mvhd", moov.start, moov.end).ok()??;
+
+    file.seek(SeekFrom::Start(mvhd.start)).ok()?;
+    let mut version = [0u8; 4];
+    file.read_exact(&mut version).ok()?;
+    let (timescale, duration) = if version[0] == 1 {
+        let mut fields = [0u8; 28];
+        file.read_exact(&mut fields).ok()?;
+        let timescale = u32::from_be_bytes(fields[16..20].try_into().ok()?);
+        (timescale, u64::from_be_bytes(fields[20..28].try_into().ok()?))
+    } else {
+        let mut fields = [0u8; 16];
+        file.read_exact(&mut fields).ok()?;
+        let timescale = u32::from_be_bytes(fields[8..12].try_into().ok()?);
+        (timescale, u32::from_be_bytes(fields[12..16].try_into().ok()?) as u64)
+    };
+
+    (timescale > 0).then(|| duration as f64 / timescale as f64)
+}
+
+/// What a video's container headers tell without decoding anything. Only MP4
+/// and QuickTime headers are read; other containers just get their format
+/// and size.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct VideoInfo {
+    /// `mp4` or `quicktime` from the `ftyp` box, else the file extension
+    /// (`matroska` for `.mkv`).
+    pub container: String,
+    pub size_bytes: u64,
+    pub duration_secs: Option<f64>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub codec: Option<VideoCodec>,
+    /// Average bits per second over the whole file, audio included.
+    pub bitrate: Option<u64>,
+}
+
+/// Reads [`VideoInfo`] for the video at `path`.
+pub fn probe(path: &Path) -> std::io::Result<VideoInfo> {
+    let mut file = File::open(path)?;
+    let size_bytes = file.metadata()?.len();
+    let (codec, dimensions) = sample_description(&mut file).unzip();
+    let dimensions = dimensions.flatten();
+    let duration_secs = duration_secs(path);
+    Ok(VideoInfo {
+        container: container(&mut file, path),
+        size_bytes,
+        duration_secs,
+        width: dimensions.map(|(width, _)| width),
+        height: dimensions.map(|(_, height)| height),
+        codec,
+        bitrate: duration_secs
+            .filter(|&duration| duration > 0.0)
+            .map(|duration| (size_bytes as f64 * 8.0 / duration).round() as u64),
+    })
+}
+
+fn container(file: &mut File, path: &Path) -> String {
+    let mut head = [0u8; 12];
+    let read = file.seek(SeekFrom::Start(0)).and_then(|_| file.read_exact(&mut head));
+    if read.is_ok() && &head[4..8] == b"ftyp" {
+        return match &head[8..12] {
+            b"qt  " => "quicktime",
+            _ => "mp4",
+        }
+        .to_string();
+    }
+    let extension = path
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    match extension.as_str() {
+        "mkv" => "matroska".to_string(),
+        "mov" => "quicktime".to_string(),
+        _ => extension,
+    }
+}
+
+/// Probe results by path, reused while a file's size and modification time
+/// stay the same, so repeated requests do not reread headers.
+#[derive(Default)]
+pub struct ProbeCache {
+    entries: Mutex<HashMap<PathBuf, (Option<SystemTime>, VideoInfo)>>,
+}
+
+impl ProbeCache {
+    pub fn probe(&self, path: &Path) -> std::io::Result<VideoInfo> {
+        let metadata = std::fs::metadata(path)?;
+        let modified = metadata.modified().ok();
+        if let Some((cached_modified, info)) = self.entries.lock().unwrap().get(path) {
+            if *cached_modified == modified && info.size_bytes == metadata.len() {
+                return Ok(info.clone());
+            }
+        }
+        let info = probe(path)?;
+        self.entries
+            .lock()
+            .unwrap()
+            .insert(path.to_path_buf(), (modified, info.clone()));
+        Ok(info)
+    }
+}
+
+/// Parses a position in a video: seconds with an optional `s` suffix (`5`,
+/// `1.5s`) or `m:ss` / `h:mm:ss` (`1:05`, `0:01:05.5`).
+pub fn parse_timestamp(text: &str) -> Option<f64> {
+    let text = text.trim();
+    let secs = match text.strip_suffix('s') {
+        Some(secs) => secs.parse::<f64>().ok()?,
+        None => {
+            let parts: Vec<&str> = text.split(':').collect();
+            if parts.len() > 3 {
+                return None;
+            }
+            let (last, rest) = parts.split_last()?;
+            let mut secs = last.parse::<f64>().ok()?;
+            if !rest.is_empty() && !(0.0..60.0).contains(&secs) {
+                return None;
+            }
+            for (place, part) in rest.iter().rev().enumerate() {
+                let value: u32 = part.parse().ok()?;
+                secs += value as f64 * 60f64.powi(place as i32 + 1);
+            }
+            secs
+        }
+    };
+    (secs.is_finite() && secs >= 0.0).then_some(secs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_fs::prelude::*;
+
+    fn mp4_box(kind: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+        let mut bytes = ((payload.len() + 8) as u32).to_be_bytes().to_vec();
+        bytes.extend_from_slice(kind);
+        bytes.extend_from_slice(payload);
+        bytes
+    }
+
+    #[test]
+    fn reads_duration_from_movie_header() {
+        let temp = assert_fs::TempDir::new().unwrap();
+
+        // Version 0 mvhd: 90 seconds at a 600 timescale, with moov after mdat
+        let mut mvhd = vec![0u8; 4 + 8];
+        mvhd.extend_from_slice(&600u32.to_be_bytes());
+        mvhd.extend_from_slice(&54_000u32.to_be_bytes());
+        mvhd.extend_from_slice(&[0u8; 80]);
+        let mut file = mp4_box(b"ftyp", b"isom\0\0\0\0");
+        file.extend(mp4_box(b"mdat", &[0u8; 32]));
+        file.extend(mp4_box(b"moov", &mp4_box(b"mvhd", &mvhd)));
+        temp.child("clip.mp4").write_binary(&file).unwrap();
+        assert_eq!(duration_secs(temp.child("clip.mp4").path()), Some(90.0));
+
+        // Version 1 mvhd with 64-bit fields
+        let mut mvhd = vec![1u8, 0, 0, 0];
+        mvhd.extend_from_slice(&[0u8; 16]);
+        mvhd.extend_from_slice(&1000u32.to_be_bytes());
+        mvhd.extend_from_slice(&2_500u64.to_be_bytes());
+        temp.child("long.mov").write_binary(&mp4_box(b"moov", &mp4_box(b"mvhd", &mvhd))).unwrap();
+        assert_eq!(duration_secs(temp.child("long.mov").path()), Some(2.5));
+
+        temp.child("junk.mp4").write_binary(b"\0\0\0\xffmoov").unwrap();
+        assert_eq!(duration_secs(temp.child("junk.mp4").path()), None);
+    }
+
+    /// Full box payload: version/flags, entry count, then `width`-field entries.
+    fn table_of(width: usize, fields: &[u32]) -> Vec<u8> {
+        let mut bytes = vec![0u8; 4];
+        bytes.extend_from_slice(&((fields.len() / width) as u32).to_be_bytes());
+        fields.iter().for_each(|field| bytes.extend_from_slice(&field.to_be_bytes()));
+        bytes
+    }
+
+    fn track_with(handler: &[u8; 4], fourcc: &[u8; 4], samples: &[u8]) -> Vec<u8> {
+        let mut hdlr = vec![0u8; 8];
+        hdlr.extend_from_slice(handler);
+        hdlr.extend_from_slice(&[0u8; 12]);
+        let mut stsd = vec![0, 0, 0, 0, 0, 0, 0, 1];
+        // Visual sample entry coded at 1920x1080
+        let mut entry = vec![0u8; 24];
+        entry.extend_from_slice(&1920u16.to_be_bytes());
+        entry.extend_from_slice(&1080u16.to_be_bytes());
+        entry.extend_from_slice(&[0u8; 50]);
+        stsd.extend(mp4_box(fourcc, &entry));
+        let mut stbl = mp4_box(b"stsd", &stsd);
+        stbl.extend_from_slice(samples);
+        // Version 0 mdhd with a 600 timescale
+        let mut mdhd = vec![0u8; 12];
+        mdhd.extend_from_slice(&600u32.to_be_bytes());
+        mdhd.extend_from_slice(&[0u8; 8]);
+        let mut mdia = mp4_box(b"mdhd", &mdhd);
+        mdia.extend(mp4_box(b"hdlr", &hdlr));
+        mdia.extend(mp4_box(b"minf", &mp4_box(b"stbl", &stbl)));
+        mp4_box(b"trak", &mp4_box(b"mdia", &mdia))
+    }
+
+    fn track(handler: &[u8; 4], fourcc: &[u8; 4]) -> Vec<u8> {
+        track_with(handler, fourcc, &[])
+    }
+
+    #[test]
+    fn reads_codec_of_the_video_track() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        let mut moov = track(b"soun", b"mp4a");
+        moov.extend(track(b"vide", b"hvc1"));
+        temp.child("phone.mov").write_binary(&mp4_box(b"moov", &moov)).unwrap();
+        assert_eq!(codec(temp.child("phone.mov").path()), Some(VideoCodec::Hevc));
+
+        temp.child("audio.m4v").write_binary(&mp4_box(b"moov", &track(b"soun", b"mp4a"))).unwrap();
+        assert_eq!(codec(temp.child("audio.m4v").path()), None);
+        assert_eq!(VideoCodec::from_name(" HEVC "), Some(VideoCodec::Hevc));
+    }
+
+    #[test]
+    fn reads_keyframe_times_from_sample_tables() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        // 10 samples of 1/30s then 5 of 1/15s (at a 600 timescale), keyframes 1, 6, 11 and 14
+        let mut samples = mp4_box(b"stts", &table_of(2, &[10, 20, 5, 40]));
+        samples.extend(mp4_box(b"stss", &table_of(1, &[1, 6, 11, 14])));
+        temp.child("clip.mp4").write_binary(&mp4_box(b"moov", &track_with(b"vide", b"avc1", &samples))).unwrap();
+        let times = keyframes(temp.child("clip.mp4").path()).unwrap();
+        let expected = [0.0, 5.0 * 20.0 / 600.0, 200.0 / 600.0, (200.0 + 3.0 * 40.0) / 600.0];
+        assert_eq!(times.len(), expected.len());
+        times.iter().zip(expected).for_each(|(t, e)| assert!((t - e).abs() < 1e-9, "{} != {}", t, e));
+
+        // Without stss every sample is a keyframe
+        let samples = mp4_box(b"stts", &table_of(2, &[3, 300]));
+        temp.child("intra.mov").write_binary(&mp4_box(b"moov", &track_with(b"vide", b"apcn", &samples))).unwrap();
+        assert_eq!(keyframes(temp.child("intra.mov").path()), Some(vec![0.0, 0.5, 1.0]));
+
+        // A sample table claiming more entries than it holds is rejected
+        let mut samples = mp4_box(b"stts", &table_of(2, &[3, 300]));
+        samples.extend(mp4_box(b"stss", &[0, 0, 0, 0, 0, 0, 0, 9]));
+        temp.child("bad.mp4").write_binary(&mp4_box(b"moov", &track_with(b"vide", b"avc1", &samples))).unwrap();
+        assert_eq!(keyframes(temp.child("bad.mp4").path()), None);
+    }
+
+    #[test]
+    fn parses_timestamps() {
+        assert_eq!(parse_timestamp("5s"), Some(5.0));
+        assert_eq!(parse_timestamp("1.5"), Some(1.5));
+        assert_eq!(parse_timestamp("1:05"), Some(65.0));
+        assert_eq!(parse_timestamp("1:00:02.5"), Some(3602.5));
+        assert_eq!(parse_timestamp("1:75"), None);
+        assert_eq!(parse_timestamp("-2s"), None);
+        assert_eq!(parse_timestamp("5m"), None);
+        assert_eq!(parse_timestamp("1:2:3:4"), None);
+    }
+
+    #[test]
+    fn probes_container_headers() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        // 10 seconds at a 600 timescale
+        let mut mvhd = vec![0u8; 4 + 8];
+        mvhd.extend_from_slice(&600u32.to_be_bytes());
+        mvhd.extend_from_slice(&6_000u32.to_be_bytes());
+        let mut moov = mp4_box(b"mvhd", &mvhd);
+        moov.extend(track(b"vide", b"hvc1"));
+        let mut file = mp4_box(b"ftyp", b"qt  \0\0\0\0");
+        file.extend(mp4_box(b"moov", &moov));
+        temp.child("phone.mov").write_binary(&file).unwrap();
+
+        let info = probe(temp.child("phone.mov").path()).unwrap();
+        assert_eq!(info.container, "quicktime");
+        assert_eq!(info.duration_secs, Some(10.0));
+        assert_eq!((info.width, info.height), (Some(1920), Some(1080)));
+        assert_eq!(info.codec, Some(VideoCodec::Hevc));
+        assert_eq!(info.bitrate, Some(file.len() as u64 * 8 / 10));
+
+        temp.child("clip.mkv").write_binary(b"\x1a\x45\xdf\xa3").unwrap();
+        let info = ProbeCache::default().probe(temp.child("clip.mkv").path()).unwrap();
+        assert_eq!((info.container.as_str(), info.size_bytes, info.codec), ("matroska", 4, None));
+    }
+}
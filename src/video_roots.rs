@@ -0,0 +1,151 @@
+use chrono::{DateTime, Utc};
+use percent_encoding::{utf8_percent_encode, AsciiSet, CONTROLS};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::error::ApiError;
+use crate::galleryignore::IgnoreRules;
+use crate::{library, paths, volumes};
+
+/// The root under which videos in the images directory and referenced
+/// folders are addressed, as `/videos/library/{filename}/...`.
+pub const LIBRARY: &str = "library";
+
+// Library names never decode to contain a slash, so escaped keys cannot clash with them
+const CACHE_NAME: &AsciiSet = &CONTROLS.add(b'/').add(b'\\').add(b'%');
+
+/// Folders sort before files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EntryKind {
+    Folder,
+    File,
+}
+
+/// One entry of a folder inside a video root.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FolderEntry {
+    pub name: String,
+    pub kind: EntryKind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size_bytes: Option<u64>,
+    pub modified: Option<DateTime<Utc>>,
+}
+
+/// Checks that every root name works as one URL path segment and every
+/// folder is an absolute path.
+pub fn validate(roots: &HashMap<String, PathBuf>) -> Result<(), String> {
+    for (name, dir) in roots {
+        if name.is_empty() || name == "." || name == ".." || name.contains(['/', '\\']) {
+            return Err(format!("video root name {:?} must be a single path segment", name));
+        }
+        if name == LIBRARY {
+            return Err(format!("video root name {:?} is reserved for library videos", name));
+        }
+        if !dir.is_absolute() {
+            return Err(format!("video root {:?} must be an absolute path, not {:?}", name, dir));
+        }
+    }
+    Ok(())
+}
+
+/// The file or folder at `path` (slash-separated, possibly empty) inside the
+/// root named `root`. Every segment is checked like an image file name, and
+/// hidden entries and those excluded by a `.galleryignore` are not served.
+pub fn resolve(roots: &HashMap<String, PathBuf>, root: &str, path: &str) -> Result<PathBuf, ApiError> {
+    let dir = roots.get(root).ok_or_else(|| ApiError::not_found("Unknown video root"))?;
+    if !volumes::is_online(dir) {
+        return Err(ApiError::VolumeOffline(volumes::name(dir)));
+    }
+    let mut resolved = dir.clone();
+    for segment in path.split('/').filter(|segment| !segment.is_empty()) {
+        let parent = resolved;
+        resolved = paths::resolve(&parent, segment)?;
+        if segment.starts_with('.') || IgnoreRules::for_dir(dir, &parent).is_ignored(&resolved, resolved.is_dir()) {
+            return Err(ApiError::not_found("Not found"));
+        }
+    }
+    Ok(resolved)
+}
+
+/// One file name for the cached posters, previews and HLS segments of the
+/// video at `path` inside `root`, distinct from every library file name.
+pub fn cache_name(root: &str, path: &str) -> String {
+    utf8_percent_encode(&format!("{}/{}", root, path.trim_matches('/')), CACHE_NAME).to_string()
+}
+
+/// Subfolders and media files of `dir`, which is `root` or inside it, folders
+/// first, then by name. Hidden entries, other files and entries excluded by a
+/// `.galleryignore` in `dir` or a parent up to `root` are left out.
+pub fn list(root: &Path, dir: &Path) -> std::io::Result<Vec<FolderEntry>> {
+    let ignored = IgnoreRules::for_dir(root, dir);
+    let mut entries = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if name.starts_with('.') {
+            continue;
+        }
+        let Ok(metadata) = entry.metadata() else {
+            continue;
+        };
+        if ignored.is_ignored(&entry.path(), metadata.is_dir()) {
+            continue;
+        }
+        let modified = metadata.modified().ok().map(DateTime::<Utc>::from);
+        if metadata.is_dir() {
+            entries.push(FolderEntry { name, kind: EntryKind::Folder, size_bytes: None, modified });
+        } else if metadata.is_file() && library::is_media_file(&entry.path()) {
+            entries.push(FolderEntry { name, kind: EntryKind::File, size_bytes: Some(metadata.len()), modified });
+        }
+    }
+    entries.sort_by(|a, b| a.kind.cmp(&b.kind).then_with(|| a.name.cmp(&b.name)));
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_fs::prelude::*;
+
+    #[test]
+    fn validates_names_and_folders() {
+        let roots = |name: &str, dir: &str| HashMap::from([(name.to_string(), PathBuf::from(dir))]);
+        assert!(validate(&roots("haley-reed", "/Volumes/VideosNew/haley-reed")).is_ok());
+        for (name, dir) in [("", "/videos"), ("..", "/videos"), ("a/b", "/videos"), ("models", "videos/models"), (LIBRARY, "/videos")] {
+            assert!(validate(&roots(name, dir)).is_err(), "{:?} = {:?}", name, dir);
+        }
+    }
+
+    #[test]
+    fn resolves_and_lists_inside_a_root() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        temp.child("2024/clip.mp4").write_binary(b"movie").unwrap();
+        temp.child("2024/notes.txt").write_binary(b"text").unwrap();
+        temp.child("2024/.hidden.mp4").write_binary(b"movie").unwrap();
+        temp.child("2024/extras/b.mov").write_binary(b"movie").unwrap();
+        temp.child("2024/private/c.mp4").write_binary(b"movie").unwrap();
+        temp.child(".galleryignore").write_str("private/\n").unwrap();
+        let roots = HashMap::from([
+            ("haley-reed".to_string(), temp.path().to_path_buf()),
+            ("offline".to_string(), temp.path().join("missing")),
+        ]);
+
+        assert_eq!(resolve(&roots, "haley-reed", "2024/clip.mp4").unwrap(), temp.path().join("2024/clip.mp4"));
+        assert_eq!(resolve(&roots, "haley-reed", "").unwrap(), temp.path());
+        assert!(matches!(resolve(&roots, "haley-reed", "2024/../../etc"), Err(ApiError::Validation(_))));
+        assert!(matches!(resolve(&roots, "haley-reed", "2024/.hidden.mp4"), Err(ApiError::NotFound(_))));
+        assert!(matches!(resolve(&roots, "haley-reed", "2024/private/c.mp4"), Err(ApiError::NotFound(_))));
+        assert!(matches!(resolve(&roots, "other", "clip.mp4"), Err(ApiError::NotFound(_))));
+        assert!(matches!(resolve(&roots, "offline", "clip.mp4"), Err(ApiError::VolumeOffline(_))));
+        assert_eq!(cache_name("haley-reed", "2024/clip 1.mp4"), "haley-reed%2F2024%2Fclip 1.mp4");
+
+        let names: Vec<(String, EntryKind)> = list(temp.path(), &temp.path().join("2024"))
+            .unwrap()
+            .into_iter()
+            .map(|entry| (entry.name, entry.kind))
+            .collect();
+        assert_eq!(names, [("extras".to_string(), EntryKind::Folder), ("clip.mp4".to_string(), EntryKind::File)]);
+    }
+}
@@ -0,0 +1,94 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// How often the monitor looks for volumes going away or coming back.
+pub const CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// A folder the library is served from: the images directory or a referenced
+/// folder, often on a removable or network drive.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Volume {
+    pub name: String,
+    pub path: PathBuf,
+    pub online: bool,
+}
+
+/// Whether `path` is mounted and readable. An unmounted drive's mount point
+/// is usually gone (macOS `/Volumes/...`) or unreadable.
+pub fn is_online(path: &Path) -> bool {
+    std::fs::read_dir(path).is_ok()
+}
+
+/// Display name of the volume at `path`, e.g. `VideosNew` for `/Volumes/VideosNew`.
+pub fn name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| path.display().to_string())
+}
+
+pub fn status(path: &Path) -> Volume {
+    Volume {
+        name: name(path),
+        path: path.to_path_buf(),
+        online: is_online(path),
+    }
+}
+
+/// The first of `roots` that is offline, if any.
+pub fn first_offline<'a>(roots: impl IntoIterator<Item = &'a Path>) -> Option<&'a Path> {
+    roots.into_iter().find(|root| !is_online(root))
+}
+
+/// Remembers which volumes were online at the last check, to notice them
+/// coming back.
+#[derive(Default)]
+pub struct VolumeMonitor {
+    online: Mutex<HashMap<PathBuf, bool>>,
+}
+
+impl VolumeMonitor {
+    /// Checks every root, logging changes. Returns the statuses and whether
+    /// any volume came back online since the previous check.
+    pub fn check(&self, roots: &[PathBuf]) -> (Vec<Volume>, bool) {
+        let volumes: Vec<Volume> = roots.iter().map(|root| status(root)).collect();
+        let mut online = self.online.lock().unwrap();
+        let mut recovered = false;
+        for volume in &volumes {
+            match online.insert(volume.path.clone(), volume.online) {
+                Some(false) if volume.online => {
+                    log::info!("Volume {} is back online", volume.name);
+                    recovered = true;
+                }
+                Some(true) | None if !volume.online => log::warn!("Volume {} is offline", volume.name),
+                _ => {}
+            }
+        }
+        (volumes, recovered)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn notices_volumes_coming_back() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        let drive = temp.path().join("VideosNew");
+        let roots = vec![temp.path().to_path_buf(), drive.clone()];
+        let monitor = VolumeMonitor::default();
+
+        let (volumes, recovered) = monitor.check(&roots);
+        assert!(!recovered);
+        assert_eq!(volumes[1], Volume { name: "VideosNew".to_string(), path: drive.clone(), online: false });
+        assert_eq!(first_offline(roots.iter().map(PathBuf::as_path)), Some(drive.as_path()));
+
+        std::fs::create_dir(&drive).unwrap();
+        let (volumes, recovered) = monitor.check(&roots);
+        assert!(recovered && volumes.iter().all(|volume| volume.online));
+        assert!(!monitor.check(&roots).1);
+    }
+}
@@ -0,0 +1,134 @@
+use notify::{EventKind, RecursiveMode, Watcher};
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc};
+
+use crate::cache::{ImageCache, VariantCache};
+use crate::indexer::LibraryIndex;
+use crate::suggest::SuggestIndex;
+use crate::thumbnails;
+
+/// Everything derived from files in the images directory and referenced
+/// folders that must follow them when they change on disk.
+#[derive(Clone)]
+pub struct LibraryWatcher {
+    pub images_dir: PathBuf,
+    pub referenced_dirs: Vec<PathBuf>,
+    pub index: Arc<LibraryIndex>,
+    pub images: Arc<ImageCache>,
+    pub variants: Arc<VariantCache>,
+    pub suggestions: Arc<SuggestIndex>,
+}
+
+impl LibraryWatcher {
+    /// Forgets everything cached for `filename` and re-reads its index entry.
+    pub fn refresh(&self, filename: &str) {
+        log::debug!("Refreshing {} after a change on disk", filename);
+        self.images.invalidate(filename);
+        self.variants.invalidate(filename);
+        if let Err(e) = thumbnails::remove(&self.images_dir, filename) {
+            log::warn!("Failed to remove thumbnails of {}: {}", filename, e);
+        }
+        self.index.refresh(&self.images_dir, &self.referenced_dirs, filename);
+        // Tags edited in Finder leave the directory times alone
+        self.suggestions.invalidate();
+    }
+
+    /// Name of a file directly inside the images directory or a referenced
+    /// folder; hidden entries such as the thumbnail cache are not ours to track.
+    fn tracked_name(&self, path: &Path) -> Option<String> {
+        let parent = path.parent()?;
+        if parent != self.images_dir && !self.referenced_dirs.iter().any(|dir| dir == parent) {
+            return None;
+        }
+        let name = path.file_name()?.to_str()?;
+        (!name.starts_with('.')).then(|| name.to_string())
+    }
+
+    /// Watches the images directory and referenced folders on a background
+    /// thread for as long as the process runs. A referenced folder that cannot
+    /// be watched, such as an unmounted drive, is skipped with a warning.
+    /// Renames arrive as a removal plus a creation (or one event carrying both
+    /// paths), so each affected name is simply refreshed.
+    pub fn spawn(self) -> notify::Result<()> {
+        let (tx, rx) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(tx)?;
+        watcher.watch(&self.images_dir, RecursiveMode::NonRecursive)?;
+        for dir in &self.referenced_dirs {
+            if let Err(e) = watcher.watch(dir, RecursiveMode::NonRecursive) {
+                log::warn!("Not watching referenced folder {:?}: {}", dir, e);
+            }
+        }
+
+        std::thread::Builder::new()
+            .name("images-watcher".to_string())
+            .spawn(move || {
+                // The watcher stops when dropped, so this thread owns it
+                let _watcher = watcher;
+                for event in rx {
+                    match event {
+                        Ok(event) if matches!(event.kind, EventKind::Access(_)) => {}
+                        Ok(event) => {
+                            for name in event.paths.iter().filter_map(|path| self.tracked_name(path)) {
+                                self.refresh(&name);
+                            }
+                        }
+                        Err(e) => log::warn!("File watcher error: {}", e),
+                    }
+                }
+            })?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::VariantKey;
+    use crate::processor::{EncodedImage, FitMode};
+    use assert_fs::prelude::*;
+
+    #[test]
+    fn refresh_drops_caches_and_updates_the_index() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        temp.child("a.jpg").write_binary(b"old").unwrap();
+        let watcher = LibraryWatcher {
+            images_dir: temp.path().to_path_buf(),
+            referenced_dirs: Vec::new(),
+            index: Arc::new(LibraryIndex::default()),
+            images: Arc::new(ImageCache::default()),
+            variants: Arc::new(VariantCache::default()),
+            suggestions: Arc::new(SuggestIndex::default()),
+        };
+        let key = VariantKey {
+            filename: "a.jpg".to_string(),
+            width: Some(10),
+            height: None,
+            fit: FitMode::Contain,
+            respect_orientation: true,
+            convert: None,
+        };
+        let modified = Some(std::time::SystemTime::now());
+        watcher.images.insert("a.jpg", modified, "old".into());
+        watcher.variants.insert(key.clone(), modified, EncodedImage { bytes: "v".into(), format: image::ImageFormat::Jpeg });
+        temp.child(".thumbnails/256/a.jpg").write_binary(b"t").unwrap();
+
+        let policy = crate::policy::ContentPolicy::default();
+        let suggested = watcher.suggestions.get(temp.path(), &policy).unwrap();
+
+        temp.child("a.jpg").write_binary(b"newer").unwrap();
+        watcher.refresh("a.jpg");
+        assert!(!Arc::ptr_eq(&suggested, &watcher.suggestions.get(temp.path(), &policy).unwrap()));
+        assert!(watcher.images.get("a.jpg", modified).is_none());
+        assert!(watcher.variants.get(&key, modified).is_none());
+        assert!(!temp.child(".thumbnails/256/a.jpg").path().exists());
+        assert_eq!(watcher.index.get("a.jpg").unwrap().size_bytes, 5);
+
+        std::fs::remove_file(temp.child("a.jpg").path()).unwrap();
+        watcher.refresh("a.jpg");
+        assert!(watcher.index.get("a.jpg").is_none());
+
+        assert_eq!(watcher.tracked_name(&temp.path().join("b.png")).as_deref(), Some("b.png"));
+        assert_eq!(watcher.tracked_name(&temp.path().join(".thumbnails")), None);
+        assert_eq!(watcher.tracked_name(Path::new("/elsewhere/b.png")), None);
+    }
+}
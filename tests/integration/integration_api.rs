@@ -1,5 +1,6 @@
 use actix_web::{test, App};
 use assert_fs::prelude::*;
+use images_api::config::Config;
 use images_api::startup;  // You'll need to create this module
 use std::time::Duration;
 use wiremock::{Mock, MockServer, ResponseTemplate};
@@ -14,7 +15,11 @@ async fn test_full_image_workflow() {
     test_image.write_binary(b"fake image content").unwrap();
 
     // Start the application
-    let app = startup::run(temp.path().to_path_buf()).await.expect("Failed to start application");
+    let config = Config {
+        images_dir: temp.path().to_path_buf(),
+        ..Config::default()
+    };
+    let app = startup::run(config).await.expect("Failed to start application");
     
     // Create a test client
     let client = reqwest::Client::builder()
@@ -1,6 +1,9 @@
 use actix_web::{test, web, App};
 use assert_fs::prelude::*;
-use images_api::handlers::*;  // Update this with your actual handler module
+use images_api::cache::{ImageCache, VariantCache};
+use images_api::config::Config;
+use images_api::handlers::*;
+use images_api::stats::ViewStats;
 use predicates::prelude::*;
 
 #[actix_rt::test]
@@ -14,6 +17,10 @@ async fn test_get_image_success() {
     let app = test::init_service(
         App::new()
             .app_data(web::Data::new(temp.path().to_path_buf()))
+            .app_data(web::Data::new(Config::default()))
+            .app_data(web::Data::new(ViewStats::default()))
+            .app_data(web::Data::new(ImageCache::default()))
+            .app_data(web::Data::new(VariantCache::default()))
             .service(serve_image),
     )
     .await;
@@ -37,6 +44,10 @@ async fn test_get_image_not_found() {
     let app = test::init_service(
         App::new()
             .app_data(web::Data::new(temp.path().to_path_buf()))
+            .app_data(web::Data::new(Config::default()))
+            .app_data(web::Data::new(ViewStats::default()))
+            .app_data(web::Data::new(ImageCache::default()))
+            .app_data(web::Data::new(VariantCache::default()))
             .service(serve_image),
     )
     .await;